@@ -0,0 +1,43 @@
+/* Generated by cbindgen from lustre-collector-ffi/src/lib.rs, do not edit. */
+
+#ifndef LUSTRE_COLLECTOR_H
+#define LUSTRE_COLLECTOR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Parses `lctl get_param` output, returning the records as a JSON array.
+//
+// Returns `NULL` on failure, see [`lustre_collector_last_error`].
+//
+// # Safety
+//
+// `input` must be `NULL` or valid for reads of `len` bytes.
+char *lustre_collector_parse_lctl_output(const uint8_t *input, size_t len);
+
+// Parses `lnetctl net show -v 4`, `lnetctl stats show` or `lnetctl global show`
+// output, returning the records as a JSON array.
+//
+// Returns `NULL` on failure, see [`lustre_collector_last_error`].
+//
+// # Safety
+//
+// `input` must be `NULL` or valid for reads of `len` bytes.
+char *lustre_collector_parse_lnetctl_output(const uint8_t *input, size_t len);
+
+// The error from the last failed call on this thread, or `NULL` if the last
+// call succeeded.
+//
+// The string is owned by the library and is valid until the next parse call
+// on this thread. It must not be passed to [`lustre_collector_string_free`].
+const char *lustre_collector_last_error(void);
+
+// Frees a string returned by one of the parse functions. `NULL` is ignored.
+//
+// # Safety
+//
+// `s` must be `NULL` or a pointer returned by a parse function that has not
+// already been freed.
+void lustre_collector_string_free(char *s);
+
+#endif /* LUSTRE_COLLECTOR_H */
@@ -0,0 +1,210 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! A C ABI over the `lustre_collector` parsers.
+//!
+//! Each parse function takes a buffer of raw `lctl get_param` or `lnetctl`
+//! output and returns the parsed records serialized as a JSON array, in the
+//! same shape `lustre_collector --format json` prints. Returned strings are
+//! owned by the caller and must be released with [`lustre_collector_string_free`].
+//!
+//! On failure a parse function returns `NULL` and the reason can be read with
+//! [`lustre_collector_last_error`] from the same thread.
+
+use lustre_collector::{parse_lctl_output, parse_lnetctl_output, LustreCollectorError, Record};
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+    panic, ptr, slice, str,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: impl Into<Vec<u8>>) {
+    let mut msg = msg.into();
+    msg.retain(|x| *x != 0);
+
+    LAST_ERROR.with(|x| *x.borrow_mut() = CString::new(msg).ok());
+}
+
+/// Borrows `len` bytes from `input`, treating a `NULL` pointer as empty.
+///
+/// # Safety
+///
+/// `input` must be `NULL` or valid for reads of `len` bytes.
+unsafe fn input<'a>(input: *const u8, len: usize) -> &'a [u8] {
+    if input.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(input, len)
+    }
+}
+
+/// Runs `f`, serializing its records as JSON and recording any error or panic
+/// instead of letting it cross the C boundary.
+fn to_json(
+    f: impl FnOnce() -> Result<Vec<Record>, LustreCollectorError> + panic::UnwindSafe,
+) -> *mut c_char {
+    let json = match panic::catch_unwind(f) {
+        Ok(Ok(records)) => serde_json::to_vec(&records).map_err(LustreCollectorError::from),
+        Ok(Err(e)) => Err(e),
+        Err(_) => {
+            set_last_error("lustre_collector panicked while parsing");
+
+            return ptr::null_mut();
+        }
+    };
+
+    match json.map(CString::new) {
+        Ok(Ok(x)) => {
+            LAST_ERROR.with(|x| x.borrow_mut().take());
+
+            x.into_raw()
+        }
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+
+            ptr::null_mut()
+        }
+        Err(e) => {
+            set_last_error(e.to_string());
+
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Parses `lctl get_param` output, returning the records as a JSON array.
+///
+/// Returns `NULL` on failure, see [`lustre_collector_last_error`].
+///
+/// # Safety
+///
+/// `input` must be `NULL` or valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lustre_collector_parse_lctl_output(
+    input: *const u8,
+    len: usize,
+) -> *mut c_char {
+    let input = self::input(input, len);
+
+    to_json(|| parse_lctl_output(input))
+}
+
+/// Parses `lnetctl net show -v 4`, `lnetctl stats show` or `lnetctl global show`
+/// output, returning the records as a JSON array.
+///
+/// Returns `NULL` on failure, see [`lustre_collector_last_error`].
+///
+/// # Safety
+///
+/// `input` must be `NULL` or valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lustre_collector_parse_lnetctl_output(
+    input: *const u8,
+    len: usize,
+) -> *mut c_char {
+    let input = self::input(input, len);
+
+    to_json(|| parse_lnetctl_output(str::from_utf8(input)?))
+}
+
+/// The error from the last failed call on this thread, or `NULL` if the last
+/// call succeeded.
+///
+/// The string is owned by the library and is valid until the next parse call
+/// on this thread. It must not be passed to [`lustre_collector_string_free`].
+#[no_mangle]
+pub extern "C" fn lustre_collector_last_error() -> *const c_char {
+    LAST_ERROR.with(|x| x.borrow().as_ref().map_or(ptr::null(), |x| x.as_ptr()))
+}
+
+/// Frees a string returned by one of the parse functions. `NULL` is ignored.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a pointer returned by a parse function that has not
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lustre_collector_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn parse(
+        f: unsafe extern "C" fn(*const u8, usize) -> *mut c_char,
+        input: &[u8],
+    ) -> Result<serde_json::Value, String> {
+        unsafe {
+            let x = f(input.as_ptr(), input.len());
+
+            if x.is_null() {
+                let e = lustre_collector_last_error();
+
+                return Err(CStr::from_ptr(e).to_string_lossy().into_owned());
+            }
+
+            let json = serde_json::from_slice(CStr::from_ptr(x).to_bytes()).unwrap();
+
+            lustre_collector_string_free(x);
+
+            assert!(lustre_collector_last_error().is_null());
+
+            Ok(json)
+        }
+    }
+
+    #[test]
+    fn parses_lctl_output() {
+        let json = parse(
+            lustre_collector_parse_lctl_output,
+            include_bytes!("../../lustre-collector/src/fixtures/valid/valid.txt"),
+        )
+        .unwrap();
+
+        assert!(!json.as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn parses_lnetctl_output() {
+        let json = parse(
+            lustre_collector_parse_lnetctl_output,
+            include_bytes!("../../lustre-collector/src/fixtures/lnetctl_net_show.txt"),
+        )
+        .unwrap();
+
+        assert!(!json.as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_errors() {
+        let e = parse(lustre_collector_parse_lctl_output, b"\xff\xfe").unwrap_err();
+
+        assert!(e.contains("utf-8"), "{e}");
+
+        let json = parse(lustre_collector_parse_lnetctl_output, b"").unwrap();
+
+        assert_eq!(json, serde_json::json!([]));
+    }
+
+    #[test]
+    fn accepts_null() {
+        unsafe {
+            let x = lustre_collector_parse_lnetctl_output(ptr::null(), 0);
+
+            assert_eq!(CStr::from_ptr(x).to_str().unwrap(), "[]");
+
+            lustre_collector_string_free(x);
+            lustre_collector_string_free(ptr::null_mut());
+        }
+    }
+}
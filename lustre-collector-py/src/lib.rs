@@ -0,0 +1,144 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Python bindings for the `lustre_collector` parsers.
+//!
+//! Records come back as plain dicts and lists in the same shape
+//! `lustre_collector --format json` prints, so scripts can move from
+//! `json.loads` of the CLI output to calling the parser in process.
+//!
+//! ```python
+//! import subprocess, lustre_collector
+//!
+//! out = subprocess.run(["lctl", "get_param", "obdfilter.*.stats"], capture_output=True).stdout
+//! records = lustre_collector.parse_lctl_output(out)
+//! ```
+
+use lustre_collector::{LustreCollectorError, Record};
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+use serde_json::Value;
+
+fn to_py_err(e: LustreCollectorError) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+fn to_py(py: Python<'_>, x: &Value) -> PyResult<PyObject> {
+    Ok(match x {
+        Value::Null => py.None(),
+        Value::Bool(x) => x.into_py(py),
+        Value::Number(x) => {
+            if let Some(x) = x.as_u64() {
+                x.into_py(py)
+            } else if let Some(x) = x.as_i64() {
+                x.into_py(py)
+            } else {
+                x.as_f64().into_py(py)
+            }
+        }
+        Value::String(x) => x.into_py(py),
+        Value::Array(xs) => {
+            let list = PyList::empty_bound(py);
+
+            for x in xs {
+                list.append(to_py(py, x)?)?;
+            }
+
+            list.into_py(py)
+        }
+        Value::Object(xs) => {
+            let dict = PyDict::new_bound(py);
+
+            for (k, v) in xs {
+                dict.set_item(k, to_py(py, v)?)?;
+            }
+
+            dict.into_py(py)
+        }
+    })
+}
+
+fn records_to_py(py: Python<'_>, records: Vec<Record>) -> PyResult<PyObject> {
+    let value = serde_json::to_value(records)
+        .map_err(LustreCollectorError::from)
+        .map_err(to_py_err)?;
+
+    to_py(py, &value)
+}
+
+/// Parses `lctl get_param` output, returning a list of records.
+///
+/// Raises `ValueError` if the output cannot be parsed.
+// The `#[pyfunction]` expansion in pyo3 0.22 converts `PyErr` into itself
+#[allow(clippy::useless_conversion)]
+#[pyfunction]
+fn parse_lctl_output(py: Python<'_>, output: &[u8]) -> PyResult<PyObject> {
+    let records = py
+        .allow_threads(|| lustre_collector::parse_lctl_output(output))
+        .map_err(to_py_err)?;
+
+    records_to_py(py, records)
+}
+
+/// Parses `lnetctl net show -v 4`, `lnetctl stats show` or `lnetctl global show`
+/// output, returning a list of records.
+///
+/// Raises `ValueError` if the output cannot be parsed.
+// The `#[pyfunction]` expansion in pyo3 0.22 converts `PyErr` into itself
+#[allow(clippy::useless_conversion)]
+#[pyfunction]
+fn parse_lnetctl_output(py: Python<'_>, output: &str) -> PyResult<PyObject> {
+    let records = py
+        .allow_threads(|| lustre_collector::parse_lnetctl_output(output))
+        .map_err(to_py_err)?;
+
+    records_to_py(py, records)
+}
+
+#[pymodule]
+#[pyo3(name = "lustre_collector")]
+fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_function(wrap_pyfunction!(parse_lctl_output, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_lnetctl_output, m)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_into_python_objects() {
+        Python::with_gil(|py| {
+            let m = PyModule::new_bound(py, "lustre_collector").unwrap();
+            init(&m).unwrap();
+
+            let records = m
+                .getattr("parse_lctl_output")
+                .unwrap()
+                .call1(
+                    (&include_bytes!("../../lustre-collector/src/fixtures/valid/valid.txt")[..],),
+                )
+                .unwrap();
+
+            let records = records.downcast::<PyList>().unwrap();
+
+            assert!(!records.is_empty());
+            assert!(records.get_item(0).unwrap().is_instance_of::<PyDict>());
+
+            let e = m
+                .getattr("parse_lnetctl_output")
+                .unwrap()
+                .call1(("net: [",))
+                .unwrap_err();
+
+            assert!(e.is_instance_of::<PyValueError>(py));
+        });
+    }
+}
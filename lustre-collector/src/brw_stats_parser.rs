@@ -67,7 +67,7 @@ where
     })
 }
 
-fn bucket<I>() -> impl Parser<I, Output = BrwStatsBucket>
+pub(crate) fn bucket<I>() -> impl Parser<I, Output = BrwStatsBucket>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
@@ -0,0 +1,109 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{
+    base_parsers::{digits, param},
+    types::{Device, HostStat, HostStats, Record},
+};
+use combine::{
+    attempt,
+    error::ParseError,
+    many, many1,
+    parser::char::{newline, spaces},
+    satisfy, skip_many1,
+    stream::Stream,
+    token, Parser,
+};
+
+pub(crate) const DEVICES: &str = "devices";
+
+pub(crate) fn params() -> Vec<String> {
+    vec![DEVICES.to_string()]
+}
+
+/// A column of the device list, which may hold any character but a space.
+fn field<I>() -> impl Parser<I, Output = String>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    many1(satisfy(|c: char| !c.is_whitespace()))
+}
+
+fn separator<I>() -> impl Parser<I, Output = ()>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    skip_many1(token(' '))
+}
+
+/// A `  0 UP osd-ldiskfs fs-MDT0000-osd fs-MDT0000-osd_UUID 9` line, as printed by `lctl dl`.
+fn device<I>() -> impl Parser<I, Output = Device>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    attempt((
+        spaces().with(digits()).skip(separator()),
+        field().skip(separator()),
+        field().skip(separator()),
+        field().skip(separator()),
+        field().skip(separator()),
+        digits(),
+    ))
+    .skip(newline())
+    .map(|(index, status, kind, name, uuid, refcount)| Device {
+        index,
+        status,
+        kind,
+        name,
+        uuid,
+        refcount,
+    })
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (param(DEVICES).skip(newline()), many(device()))
+        .map(|(param, value)| Record::Host(HostStats::Devices(HostStat { param, value })))
+        .message("while parsing devices")
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn test_devices() {
+        static FIXTURE: &str = include_str!("fixtures/valid/devices.txt");
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+
+    #[test]
+    fn test_no_devices() {
+        let (result, rest) = parse().easy_parse("devices=\n").unwrap();
+
+        assert_eq!(
+            result,
+            Record::Host(HostStats::Devices(HostStat {
+                param: crate::types::Param(DEVICES.to_string()),
+                value: vec![]
+            }))
+        );
+        assert_eq!(rest, "");
+    }
+}
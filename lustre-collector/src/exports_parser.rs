@@ -3,19 +3,23 @@
 // license that can be found in the LICENSE file.
 
 use crate::{
-    base_parsers::{equals, period},
+    base_parsers::{digits, equals, period},
     stats_parser::stats,
-    ExportStats,
+    ExportGrant, ExportStats,
 };
 use combine::{
-    attempt,
+    attempt, choice,
     error::ParseError,
     many, many1,
-    parser::char::{alpha_num, string},
+    parser::char::{alpha_num, newline, string},
     stream::Stream,
     token, Parser,
 };
 
+pub(crate) const DIRTY: &str = "dirty";
+pub(crate) const GRANTED: &str = "granted";
+pub(crate) const PENDING: &str = "pending";
+
 /// Parses a single nid
 pub(crate) fn nid<I>() -> impl Parser<I, Output = String>
 where
@@ -46,6 +50,25 @@ where
     .message("while parsing export_stats")
 }
 
+/// Parses a single obdfilter.*OST*.exports.*.{dirty,granted,pending} line
+pub(crate) fn export_grant<I>() -> impl Parser<I, Output = ExportGrant>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    attempt((
+        nid().skip(period()),
+        choice((string(DIRTY), string(GRANTED), string(PENDING))).skip(equals()),
+        digits().skip(newline()),
+    ))
+    .map(|(nid, kind, bytes)| ExportGrant {
+        nid,
+        kind: kind.to_string(),
+        bytes,
+    })
+    .message("while parsing export_grant")
+}
+
 /// Parses multiple obdfilter.*OST*.exports.*.stats lines
 pub(crate) fn exports_stats<I>() -> impl Parser<I, Output = Vec<ExportStats>>
 where
@@ -0,0 +1,150 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{
+    base_parsers::{equals, period},
+    types::{ImportStat, ImportState, ImportVariant, Param, Record, Target, TargetStats},
+};
+use combine::{
+    attempt, choice, eof,
+    error::{ParseError, StreamError},
+    many1, optional,
+    parser::{
+        char::{alpha_num, newline, string},
+        repeat::take_until,
+    },
+    satisfy,
+    stream::{Stream, StreamErrorFor},
+    Parser,
+};
+
+pub(crate) const IMPORT: &str = "import";
+
+pub(crate) fn params() -> Vec<String> {
+    ["mdc", "osc"]
+        .into_iter()
+        .map(|x| format!("{x}.*.{IMPORT}"))
+        .collect()
+}
+
+/// Parses `mdc.<import>.<param>=`. `mgc` imports are named after a NID,
+/// like `MGC10.73.20.11@tcp`, so the name runs up to the param.
+pub(crate) fn import<I>(
+    param: &'static str,
+) -> impl Parser<I, Output = (ImportVariant, Target, Param)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    attempt((
+        choice((
+            attempt(string("mdc")).map(|_| ImportVariant::Mdc),
+            attempt(string("osc")).map(|_| ImportVariant::Osc),
+            attempt(string("mgc")).map(|_| ImportVariant::Mgc),
+        ))
+        .skip(period()),
+        many1(satisfy(|c| c != '=' && c != '\n')).and_then(move |x: String| {
+            x.strip_suffix(&format!(".{param}"))
+                .map(|x| Target(x.to_string()))
+                .ok_or_else(|| StreamErrorFor::<I>::expected_static_message(param))
+        }),
+        equals().skip(newline()),
+    ))
+    .map(move |(kind, target, _)| (kind, target, Param(param.to_string())))
+}
+
+#[derive(serde::Deserialize)]
+struct ImportYaml {
+    import: ImportBlock,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportBlock {
+    target: String,
+    state: String,
+    #[serde(default)]
+    connect_flags: Vec<String>,
+    #[serde(default)]
+    connection: Connection,
+}
+
+#[derive(Default, serde::Deserialize)]
+struct Connection {
+    current_connection: Option<String>,
+    #[serde(default)]
+    connection_attempts: u64,
+}
+
+impl From<ImportYaml> for ImportState {
+    fn from(ImportYaml { import: x }: ImportYaml) -> Self {
+        let remote = x
+            .target
+            .strip_suffix("_UUID")
+            .map(str::to_string)
+            .unwrap_or(x.target);
+
+        ImportState {
+            remote: Target(remote),
+            state: x.state,
+            connect_flags: x.connect_flags,
+            current_connection: x.connection.current_connection,
+            connection_attempts: x.connection.connection_attempts,
+        }
+    }
+}
+
+/// The yaml block under `import:`, up to the next param.
+fn import_state<I>() -> impl Parser<I, Output = ImportState>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        string("import:"),
+        take_until(attempt((newline(), alpha_num()).map(drop).or(eof()))),
+    )
+        .skip(optional(newline()))
+        .and_then(|(_, x): (_, String)| {
+            serde_yaml::from_str::<ImportYaml>(&format!("import:{x}"))
+                .map(ImportState::from)
+                .map_err(StreamErrorFor::<I>::other)
+        })
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (import(IMPORT), import_state())
+        .map(|((kind, target, param), value)| {
+            Record::Target(TargetStats::Import(ImportStat {
+                kind,
+                param,
+                target,
+                value,
+            }))
+        })
+        .message("while parsing import")
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        static FIXTURE: &str = include_str!("fixtures/valid/import.txt");
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+}
@@ -3,14 +3,18 @@
 // license that can be found in the LICENSE file.
 
 use crate::{
-    base_parsers::{digits, param, period, target},
+    base_parsers::{digits, equals, param, period, target},
     ldlm::LDLM,
-    types::{Param, Record, Target, TargetStat, TargetStats, TargetVariant},
+    types::{
+        ImportStat, ImportVariant, Param, Record, Target, TargetStat, TargetStats, TargetVariant,
+    },
 };
 use combine::{
     attempt, choice,
     error::{ParseError, StreamError},
+    many1,
     parser::char::{newline, string},
+    satisfy,
     stream::{Stream, StreamErrorFor},
     Parser,
 };
@@ -27,7 +31,12 @@ pub(crate) const LRU_SIZE: &str = "lru_size";
 pub(crate) const MAX_NOLOCK_BYTES: &str = "max_nolock_bytes";
 pub(crate) const MAX_PARALLEL_AST: &str = "max_parallel_ast";
 pub(crate) const RESOURCE_COUNT: &str = "resource_count";
-pub(crate) const LDLM_STATS: [&str; 12] = [
+pub(crate) const POOL_GRANTED: &str = "pool.granted";
+pub(crate) const POOL_GRANT_RATE: &str = "pool.grant_rate";
+pub(crate) const POOL_CANCEL_RATE: &str = "pool.cancel_rate";
+pub(crate) const POOL_LIMIT: &str = "pool.limit";
+pub(crate) const POOL_SLV: &str = "pool.slv";
+pub(crate) const LDLM_STATS: [&str; 17] = [
     CONTENDED_LOCKS,
     CONTENTION_SECONDS,
     CTIME_AGE_LIMIT,
@@ -40,8 +49,16 @@ pub(crate) const LDLM_STATS: [&str; 12] = [
     MAX_NOLOCK_BYTES,
     MAX_PARALLEL_AST,
     RESOURCE_COUNT,
+    POOL_GRANTED,
+    POOL_GRANT_RATE,
+    POOL_CANCEL_RATE,
+    POOL_LIMIT,
+    POOL_SLV,
 ];
 
+/// The stats read from the lock namespaces of client imports
+pub(crate) const IMPORT_STATS: [&str; 4] = [LOCK_COUNT, LOCK_UNUSED_COUNT, LRU_MAX_AGE, LRU_SIZE];
+
 pub(crate) const NAMESPACES: &str = "namespaces";
 
 /// Takes LDLM_STATS and produces a list of params for
@@ -53,30 +70,36 @@ pub(crate) fn params() -> Vec<String> {
         .collect()
 }
 
+/// Takes IMPORT_STATS and produces a list of params for the lock namespaces
+/// of the `mdc`, `osc` and `mgc` imports.
+pub(crate) fn import_params() -> Vec<String> {
+    IMPORT_STATS
+        .iter()
+        .map(|x| format!("{LDLM}.{NAMESPACES}.{{*-mdc-*,*-osc-*,MGC*}}.{x}"))
+        .collect()
+}
+
 /// Parses the name of the target
 pub(crate) fn ldlm_target<I>() -> impl Parser<I, Output = (TargetVariant, Target)>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    (
-        attempt((string(NAMESPACES), period())),
-        choice((
-            string("mdt-").map(|_| TargetVariant::Mdt),
-            string("filter-").map(|_| TargetVariant::Ost),
-        ))
-        .and(target()),
-    )
-        .and_then(|(_, (kind, Target(x)))| {
-            let xs: Vec<&str> = x.split("_UUID").collect();
+    choice((
+        attempt(string("mdt-")).map(|_| TargetVariant::Mdt),
+        attempt(string("filter-")).map(|_| TargetVariant::Ost),
+    ))
+    .and(target())
+    .and_then(|(kind, Target(x))| {
+        let xs: Vec<&str> = x.split("_UUID").collect();
 
-            match xs.as_slice() {
-                [y, _] => Ok((kind, Target((*y).to_string()))),
-                _ => Err(StreamErrorFor::<I>::expected_static_message("_UUID")),
-            }
-        })
-        .skip(period())
-        .message("while parsing lock_namespaces")
+        match xs.as_slice() {
+            [y, _] => Ok((kind, Target((*y).to_string()))),
+            _ => Err(StreamErrorFor::<I>::expected_static_message("_UUID")),
+        }
+    })
+    .skip(period())
+    .message("while parsing lock_namespaces")
 }
 
 pub(crate) fn ldlm_stat<I>() -> impl Parser<I, Output = (Param, u64)>
@@ -97,92 +120,174 @@ where
         (param(MAX_NOLOCK_BYTES), digits().skip(newline())),
         (param(MAX_PARALLEL_AST), digits().skip(newline())),
         (param(RESOURCE_COUNT), digits().skip(newline())),
+        (
+            choice((
+                param(POOL_GRANTED),
+                param(POOL_GRANT_RATE),
+                param(POOL_CANCEL_RATE),
+                param(POOL_LIMIT),
+                param(POOL_SLV),
+            )),
+            digits().skip(newline()),
+        ),
     ))
 }
 
-pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+/// The kind of import a lock namespace belongs to, from its name, like
+/// `fs-MDT0000-mdc-ffff8b4d3c2a1000` or `MGC10.73.20.11@tcp`.
+fn import_kind(namespace: &str) -> Option<ImportVariant> {
+    if namespace.starts_with("MGC") {
+        Some(ImportVariant::Mgc)
+    } else if namespace.contains("-mdc-") {
+        Some(ImportVariant::Mdc)
+    } else if namespace.contains("-osc-") {
+        Some(ImportVariant::Osc)
+    } else {
+        None
+    }
+}
+
+/// Parses a stat of the lock namespace of a client import. `MGC` namespaces
+/// are named after a NID, so the name runs up to the last period.
+fn import_stat<I>() -> impl Parser<I, Output = TargetStats>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    (ldlm_target(), ldlm_stat())
-        .and_then(|((kind, target), (Param(p), value))| match p.as_ref() {
-            CONTENDED_LOCKS => Ok(TargetStats::ContendedLocks(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            CONTENTION_SECONDS => Ok(TargetStats::ContentionSeconds(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            CTIME_AGE_LIMIT => Ok(TargetStats::CtimeAgeLimit(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            EARLY_LOCK_CANCEL => Ok(TargetStats::EarlyLockCancel(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            LOCK_COUNT => Ok(TargetStats::LockCount(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            LOCK_TIMEOUTS => Ok(TargetStats::LockTimeouts(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            LOCK_UNUSED_COUNT => Ok(TargetStats::LockUnusedCount(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            LRU_MAX_AGE => Ok(TargetStats::LruMaxAge(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            LRU_SIZE => Ok(TargetStats::LruSize(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            MAX_NOLOCK_BYTES => Ok(TargetStats::MaxNolockBytes(TargetStat {
-                kind,
-                target,
-                param: Param(p),
-                value,
-            })),
-            MAX_PARALLEL_AST => Ok(TargetStats::MaxParallelAst(TargetStat {
+    (
+        attempt(
+            many1(satisfy(|c| c != '=' && c != '\n'))
+                .skip(equals())
+                .and_then(|x: String| {
+                    let (namespace, param) = x
+                        .rsplit_once('.')
+                        .filter(|(_, param)| IMPORT_STATS.contains(param))
+                        .ok_or_else(|| {
+                            StreamErrorFor::<I>::expected_static_message("import lock stat")
+                        })?;
+
+                    let kind = import_kind(namespace).ok_or_else(|| {
+                        StreamErrorFor::<I>::expected_static_message("import namespace")
+                    })?;
+
+                    Ok::<_, StreamErrorFor<I>>((
+                        kind,
+                        Target(namespace.to_string()),
+                        Param(param.to_string()),
+                    ))
+                }),
+        ),
+        digits().skip(newline()),
+    )
+        .map(|((kind, target, param), value)| {
+            TargetStats::ImportLdlm(ImportStat {
                 kind,
+                param,
                 target,
-                param: Param(p),
                 value,
-            })),
-            RESOURCE_COUNT => Ok(TargetStats::ResourceCount(TargetStat {
+            })
+        })
+}
+
+fn target_stat<I>() -> impl Parser<I, Output = TargetStats>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (ldlm_target(), ldlm_stat()).and_then(|((kind, target), (Param(p), value))| match p.as_ref() {
+        CONTENDED_LOCKS => Ok(TargetStats::ContendedLocks(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        CONTENTION_SECONDS => Ok(TargetStats::ContentionSeconds(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        CTIME_AGE_LIMIT => Ok(TargetStats::CtimeAgeLimit(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        EARLY_LOCK_CANCEL => Ok(TargetStats::EarlyLockCancel(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        LOCK_COUNT => Ok(TargetStats::LockCount(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        LOCK_TIMEOUTS => Ok(TargetStats::LockTimeouts(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        LOCK_UNUSED_COUNT => Ok(TargetStats::LockUnusedCount(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        LRU_MAX_AGE => Ok(TargetStats::LruMaxAge(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        LRU_SIZE => Ok(TargetStats::LruSize(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        MAX_NOLOCK_BYTES => Ok(TargetStats::MaxNolockBytes(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        MAX_PARALLEL_AST => Ok(TargetStats::MaxParallelAst(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        RESOURCE_COUNT => Ok(TargetStats::ResourceCount(TargetStat {
+            kind,
+            target,
+            param: Param(p),
+            value,
+        })),
+        POOL_GRANTED | POOL_GRANT_RATE | POOL_CANCEL_RATE | POOL_LIMIT | POOL_SLV => {
+            Ok(TargetStats::LdlmPool(TargetStat {
                 kind,
                 target,
                 param: Param(p),
                 value,
-            })),
-            _ => Err(StreamErrorFor::<I>::unexpected_static_message(
-                "Unexpected top-level param",
-            )),
-        })
+            }))
+        }
+        _ => Err(StreamErrorFor::<I>::unexpected_static_message(
+            "Unexpected top-level param",
+        )),
+    })
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    attempt((string(NAMESPACES), period()))
+        .with(target_stat().or(import_stat()))
         .map(Record::Target)
         .message("while parsing ldlm.namepsaces")
 }
@@ -208,6 +313,11 @@ mod tests {
                 "ldlm.namespaces.{mdt-,filter-}*.max_nolock_bytes".to_string(),
                 "ldlm.namespaces.{mdt-,filter-}*.max_parallel_ast".to_string(),
                 "ldlm.namespaces.{mdt-,filter-}*.resource_count".to_string(),
+                "ldlm.namespaces.{mdt-,filter-}*.pool.granted".to_string(),
+                "ldlm.namespaces.{mdt-,filter-}*.pool.grant_rate".to_string(),
+                "ldlm.namespaces.{mdt-,filter-}*.pool.cancel_rate".to_string(),
+                "ldlm.namespaces.{mdt-,filter-}*.pool.limit".to_string(),
+                "ldlm.namespaces.{mdt-,filter-}*.pool.slv".to_string(),
             ]
         )
     }
@@ -220,4 +330,13 @@ mod tests {
 
         assert_eq!(result, r);
     }
+
+    #[test]
+    fn test_pool_stat() {
+        let result = ldlm_stat().parse("pool.slv=3355430400\n");
+
+        let r = Ok(((Param(POOL_SLV.to_string()), 3_355_430_400), ""));
+
+        assert_eq!(result, r);
+    }
 }
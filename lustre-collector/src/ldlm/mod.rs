@@ -5,7 +5,7 @@
 use crate::{base_parsers::period, Record};
 use combine::{attempt, parser::char::string, ParseError, Parser, Stream};
 
-mod ldlm_namespace_parser;
+pub(crate) mod ldlm_namespace_parser;
 mod ldlm_service_parser;
 
 pub(crate) const LDLM: &str = "ldlm";
@@ -13,6 +13,7 @@ pub(crate) const LDLM: &str = "ldlm";
 pub(crate) fn params() -> Vec<String> {
     ldlm_namespace_parser::params()
         .into_iter()
+        .chain(ldlm_namespace_parser::import_params())
         .chain(ldlm_service_parser::params())
         .collect()
 }
@@ -4,8 +4,10 @@
 
 mod base_parsers;
 pub(crate) mod brw_stats_parser;
+mod device_parser;
 pub mod error;
 pub(crate) mod exports_parser;
+mod import_parser;
 pub(crate) mod ldlm;
 pub(crate) mod llite;
 mod lnetctl_parser;
@@ -13,23 +15,43 @@ mod mdd_parser;
 mod mds;
 pub mod mgs;
 mod node_stats_parsers;
+mod nodemap_parser;
 mod osd_parser;
+mod osp_parser;
 mod oss;
 pub mod parser;
+mod pool_parser;
 pub(crate) mod quota;
 pub mod recovery_status_parser;
+mod rpc_stats_parser;
+mod srpc_parser;
 mod stats_parser;
+pub mod support;
+mod threads_parser;
 mod time;
 mod top_level_parser;
 pub mod types;
+#[cfg(feature = "winnow")]
+mod winnow_parser;
+pub mod zfs_parser;
 
 pub use crate::error::LustreCollectorError;
 use combine::parser::EasyParser;
 pub use lnetctl_parser::parse as parse_lnetctl_output;
+pub use lnetctl_parser::parse_lnetctl_peer_stats;
 pub use lnetctl_parser::parse_lnetctl_stats;
+pub use lnetctl_parser::{
+    parse_lnetctl_global, parse_lnetctl_routes, parse_lnetctl_routing, parse_lnetctl_udsp,
+};
+pub use mgs::mgs_live_parser::{parse_mgs_live_output, parse_pool_list_output};
 pub use node_stats_parsers::{parse_cpustats_output, parse_meminfo_output};
-use std::{io, str};
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead},
+    str,
+};
 pub use types::*;
+pub use zfs_parser::{parse_arcstats_output, parse_zpool_fragmentation_output};
 
 fn check_output(records: Vec<Record>, state: &str) -> Result<Vec<Record>, LustreCollectorError> {
     let params = crate::parser::params().join(" ");
@@ -49,13 +71,192 @@ fn check_output(records: Vec<Record>, state: &str) -> Result<Vec<Record>, Lustre
 pub fn parse_lctl_output(lctl_output: &[u8]) -> Result<Vec<Record>, LustreCollectorError> {
     let lctl_stats = str::from_utf8(lctl_output)?;
 
-    let (lctl_record, state) = parser::parse()
-        .easy_parse(lctl_stats)
-        .map_err(|err| err.map_position(|p| p.translate_position(lctl_stats)))?;
+    let (lctl_record, state) = parser::parse_output(lctl_stats)?;
 
     check_output(lctl_record, state)
 }
 
+/// Returns the param name if `line` starts a `param=value` in `lctl get_param` output.
+fn param_name(line: &str) -> Option<&str> {
+    let (name, _) = line.split_once('=')?;
+
+    (!name.is_empty() && !name.contains(char::is_whitespace)).then_some(name)
+}
+
+/// Returns the byte offset of every `param=value` line in `lctl get_param` output,
+/// along with the param name.
+fn param_offsets(lctl_stats: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+    let mut xs = vec![];
+
+    for line in lctl_stats.split_inclusive('\n') {
+        if let Some(name) = param_name(line) {
+            xs.push((offset, name));
+        }
+
+        offset += line.len();
+    }
+
+    xs
+}
+
+/// Whether `name` continues the same record as `prev`, so the two cannot be parsed apart.
+///
+/// The exports of an MDT are counted together into its connected clients.
+fn same_record(prev: &str, name: &str) -> bool {
+    let group = |x: &str| x.find(".exports.").map(|i| x[..i].to_string());
+
+    group(prev).is_some() && group(prev) == group(name)
+}
+
+/// Like `parse_lctl_output`, but reads `lctl get_param` output from `reader` and
+/// yields records as each param is parsed, without waiting for the rest of the output.
+///
+/// Params that fail to parse are buffered along with the ones that follow until they do,
+/// so an error is only yielded once the output is exhausted. No records follow an error.
+pub fn parse_lctl_output_iter<R: BufRead>(reader: R) -> LctlRecords<R> {
+    LctlRecords {
+        reader,
+        chunk: String::new(),
+        param: None,
+        records: VecDeque::new(),
+        done: false,
+    }
+}
+
+/// The records of `lctl get_param` output, as returned by [`parse_lctl_output_iter`].
+#[derive(Debug)]
+pub struct LctlRecords<R> {
+    reader: R,
+    /// The params read that have not been parsed yet
+    chunk: String,
+    /// The name of the last param in `chunk`
+    param: Option<String>,
+    records: VecDeque<Record>,
+    done: bool,
+}
+
+impl<R: BufRead> LctlRecords<R> {
+    /// Reads params until those read so far parse, or the output is exhausted.
+    fn fill(&mut self) -> Result<(), LustreCollectorError> {
+        loop {
+            let mut line = String::new();
+
+            if self.reader.read_line(&mut line)? == 0 {
+                self.done = true;
+
+                let chunk = std::mem::take(&mut self.chunk);
+
+                self.records.extend(parse_lctl_output(chunk.as_bytes())?);
+
+                return Ok(());
+            }
+
+            if let Some(name) = param_name(&line) {
+                let name = name.to_string();
+
+                let records = match self.param.replace(name.clone()) {
+                    Some(prev) if !same_record(&prev, &name) => {
+                        match parser::parse_output(&self.chunk) {
+                            Ok((records, "")) => Some(records),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+
+                if let Some(records) = records {
+                    self.records.extend(records);
+                    self.chunk = line;
+
+                    return Ok(());
+                }
+            }
+
+            self.chunk.push_str(&line);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LctlRecords<R> {
+    type Item = Result<Record, LustreCollectorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(x) = self.records.pop_front() {
+                return Some(Ok(x));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            if let Err(e) = self.fill() {
+                self.done = true;
+
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// How many characters of a param that could not be parsed are kept in its [`ParseIssue`].
+const SNIPPET_LEN: usize = 200;
+
+/// A param `parse_lctl_output_lossy` could not parse and left out.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ParseIssue {
+    /// The name of the param, as printed by `lctl get_param`
+    pub param: String,
+    /// The start of the output of the param
+    pub snippet: String,
+}
+
+/// Like `parse_lctl_output`, but drops any param that fails to parse instead of failing
+/// the whole output.
+///
+/// Params can vanish between being listed and being read, for example while a target
+/// is unmounted during failover. Returns the parsed records along with the params that
+/// were skipped.
+pub fn parse_lctl_output_lossy(
+    lctl_output: &[u8],
+) -> Result<(Vec<Record>, Vec<ParseIssue>), LustreCollectorError> {
+    let mut lctl_stats = str::from_utf8(lctl_output)?.to_string();
+    let mut skipped = vec![];
+
+    loop {
+        // A parser that errors has already consumed its own param and failed on
+        // whatever follows, while leftover input starts at the param that could not be parsed.
+        let (offset, consumed) = match parser::parse_output(&lctl_stats) {
+            Ok((records, "")) => return Ok((records, skipped)),
+            Ok((_, state)) => (lctl_stats.len() - state.len(), false),
+            Err(err) => (err.position, true),
+        };
+
+        let offsets = param_offsets(&lctl_stats);
+
+        let idx = match offsets.iter().rposition(|(x, _)| *x <= offset) {
+            Some(idx) if consumed && idx > 0 && offsets[idx].0 == offset => idx - 1,
+            Some(idx) => idx,
+            // Nothing recognizable as a param, so report the original failure.
+            None => return Ok((parse_lctl_output(lctl_stats.as_bytes())?, skipped)),
+        };
+
+        let start = offsets[idx].0;
+        let end = offsets
+            .get(idx + 1)
+            .map(|(x, _)| *x)
+            .unwrap_or(lctl_stats.len());
+
+        skipped.push(ParseIssue {
+            param: offsets[idx].1.to_string(),
+            snippet: lctl_stats[start..end].chars().take(SNIPPET_LEN).collect(),
+        });
+
+        lctl_stats.replace_range(start..end, "");
+    }
+}
+
 pub fn parse_mgs_fs_output(mgs_fs_output: &[u8]) -> Result<Vec<Record>, LustreCollectorError> {
     let mgs_fs = str::from_utf8(mgs_fs_output)?;
 
@@ -81,7 +282,7 @@ pub fn parse_recovery_status_output(
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_lctl_output, Record};
+    use super::{parse_lctl_output, parse_lctl_output_iter, parse_lctl_output_lossy, Record};
 
     #[test]
     fn ex8761_job_stats() {
@@ -105,6 +306,53 @@ mod tests {
         assert_eq!(expected, z);
     }
 
+    #[test]
+    fn lossy_skips_vanished_params() {
+        let xs = include_bytes!("./fixtures/vanished_params.txt");
+
+        assert!(parse_lctl_output(xs).is_err());
+
+        let (records, skipped) = parse_lctl_output_lossy(xs).unwrap();
+
+        insta::assert_debug_snapshot!(skipped);
+        insta::assert_debug_snapshot!(records);
+    }
+
+    #[test]
+    fn lossy_matches_strict_on_valid_output() {
+        let xs = include_bytes!("./fixtures/valid/valid.txt");
+
+        let (records, skipped) = parse_lctl_output_lossy(xs).unwrap();
+
+        assert!(skipped.is_empty());
+        assert_eq!(records, parse_lctl_output(xs).unwrap());
+    }
+
+    #[test]
+    fn iter_matches_strict_on_valid_output() {
+        for xs in [
+            &include_bytes!("./fixtures/valid/valid.txt")[..],
+            include_bytes!("./fixtures/valid/valid_mds.txt"),
+            include_bytes!("./fixtures/valid/params-6.2.0-r9.txt"),
+            include_bytes!("./fixtures/valid/valid_no_newline.txt"),
+        ] {
+            let records = parse_lctl_output_iter(xs).collect::<Result<Vec<_>, _>>();
+
+            assert_eq!(records.unwrap(), parse_lctl_output(xs).unwrap());
+        }
+    }
+
+    #[test]
+    fn iter_yields_records_before_an_error() {
+        let xs = include_bytes!("./fixtures/vanished_params.txt");
+
+        let records = parse_lctl_output_iter(&xs[..]).collect::<Vec<_>>();
+
+        assert!(records.len() > 1);
+        assert!(records[..records.len() - 1].iter().all(Result::is_ok));
+        assert!(records.last().unwrap().is_err());
+    }
+
     #[test]
     fn params() {
         let xs = super::parser::params();
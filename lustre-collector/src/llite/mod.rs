@@ -3,17 +3,29 @@
 // license that can be found in the LICENSE file.
 
 use crate::{
-    base_parsers::{param, period, target},
+    base_parsers::{digits, param, period, target, till_newline},
     stats_parser::stats,
-    Param, Record, Stat, Target, TargetStats,
+    time::time_triple,
+    LliteCacheStat, Param, Record, Stat, Target, TargetStats,
+};
+use combine::{
+    attempt, choice,
+    error::StreamError,
+    many, many1, optional,
+    parser::char::{letter, newline, string},
+    skip_many,
+    stream::StreamErrorFor,
+    token, ParseError, Parser, Stream,
 };
-use combine::{parser::char::string, ParseError, Parser, Stream};
 
 pub(crate) const LLITE: &str = "llite";
 pub(crate) const STATS: &str = "stats";
+pub(crate) const READ_AHEAD_STATS: &str = "read_ahead_stats";
+pub(crate) const MAX_CACHED_MB: &str = "max_cached_mb";
+pub(crate) const UNSTABLE_STATS: &str = "unstable_stats";
 
 pub(crate) fn params() -> Vec<String> {
-    [STATS]
+    [STATS, READ_AHEAD_STATS, MAX_CACHED_MB, UNSTABLE_STATS]
         .into_iter()
         .map(|x| format!("{LLITE}.*.{x}"))
         .collect()
@@ -31,6 +43,66 @@ where
 
 enum LliteStat {
     Stats(Vec<Stat>),
+    Values(Vec<(String, u64)>),
+}
+
+/// Parses a `name: value` line, as found in `max_cached_mb` and `unstable_stats`
+fn name_value<I>() -> impl Parser<I, Output = (String, u64)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        attempt(many1(letter().or(token('_'))).skip(token(':'))),
+        skip_many(token(' ')).with(digits()).skip(newline()),
+    )
+}
+
+/// Splits a `<name> <samples> samples [<units>]` line of `read_ahead_stats`.
+///
+/// Older releases name events with spaces and dashes, like `hit max r-a issue`,
+/// which `stats` can't parse. Those are normalized to the underscored names
+/// of newer releases.
+fn read_ahead_event(line: &str) -> Option<Stat> {
+    let (head, rest) = line.split_once(" samples [")?;
+    let (name, samples) = head.trim_end().rsplit_once(' ')?;
+    let (units, _) = rest.split_once(']')?;
+
+    let name = name.trim_end();
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(Stat {
+        name: name.replace([' ', '-'], "_"),
+        units: units.to_string(),
+        samples: samples.parse().ok()?,
+        min: None,
+        max: None,
+        sum: None,
+        sumsquare: None,
+    })
+}
+
+fn read_ahead_stats<I>() -> impl Parser<I, Output = Vec<Stat>>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        optional(newline()).with(time_triple()),
+        many(
+            attempt(till_newline().and_then(|x: String| {
+                read_ahead_event(&x).ok_or_else(|| {
+                    StreamErrorFor::<I>::expected_static_message("read_ahead_stats event")
+                })
+            }))
+            .skip(newline()),
+        ),
+    )
+        .map(|(_, xs)| xs)
+        .message("while parsing read_ahead_stats")
 }
 
 fn llite_stat<I>() -> impl Parser<I, Output = (Param, LliteStat)>
@@ -38,7 +110,22 @@ where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    (param(STATS), stats().map(LliteStat::Stats)).message("while parsing llite_stat")
+    choice((
+        (param(STATS), stats().map(LliteStat::Stats)),
+        (
+            param(READ_AHEAD_STATS),
+            read_ahead_stats().map(LliteStat::Stats),
+        ),
+        (
+            param(MAX_CACHED_MB),
+            newline().with(many1(name_value())).map(LliteStat::Values),
+        ),
+        (
+            param(UNSTABLE_STATS),
+            newline().with(many1(name_value())).map(LliteStat::Values),
+        ),
+    ))
+    .message("while parsing llite_stat")
 }
 
 pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
@@ -53,6 +140,11 @@ where
                 param,
                 stats,
             }),
+            LliteStat::Values(values) => TargetStats::LliteCache(LliteCacheStat {
+                target,
+                param,
+                values,
+            }),
         })
         .map(Record::Target)
         .message("while parsing llite")
@@ -87,4 +179,44 @@ openclosetime             6906208 samples [usec] 2225920 34405427 16316964115525
 
         assert_debug_snapshot!(result)
     }
+
+    #[test]
+    fn test_read_ahead_stats() {
+        let x = r#"llite.fs-ffff9440f1003000.read_ahead_stats=
+snapshot_time             1689697369.331040915 secs.nsecs
+hits                      2127541 samples [pages]
+misses                    34712 samples [pages]
+readpage not consecutive  1651 samples [pages]
+miss inside window        12 samples [pages]
+failed grab_cache_page    5 samples [pages]
+read but discarded        38 samples [pages]
+zero size window          812 samples [pages]
+read-ahead to EOF         97 samples [pages]
+hit max r-a issue         3 samples [pages]
+failed to reach end       231 samples [pages]
+llite.fs-ffff9440f1003000.max_cached_mb=
+users: 5
+max_cached_mb: 64000
+used_mb: 12877
+unused_mb: 51123
+reclaim_count: 3
+"#;
+
+        let result: (Vec<_>, _) = many(parse()).parse(x).unwrap();
+
+        assert_debug_snapshot!(result)
+    }
+
+    #[test]
+    fn test_read_ahead_event() {
+        assert_eq!(
+            read_ahead_event("hit max r-a issue         3 samples [pages]").map(|x| x.name),
+            Some("hit_max_r_a_issue".to_string())
+        );
+        assert_eq!(read_ahead_event("users: 5"), None);
+        assert_eq!(
+            read_ahead_event("                  3 samples [pages]"),
+            None
+        );
+    }
 }
@@ -3,10 +3,11 @@
 // license that can be found in the LICENSE file.
 
 use crate::{
-    lnet_exports::LNetStatsStatistics,
+    lnet_exports::{Global, LNetStatsStatistics, Peer, Route, Routing, Udsp},
     types::{lnet_exports::Net, LNetStat, LNetStats, Param, Record},
-    LNetStatGlobal, LustreCollectorError,
+    LNetRoute, LNetRouterBuffers, LNetStatGlobal, LNetUdspPolicies, LustreCollectorError,
 };
+use std::collections::BTreeMap;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct LnetNetStats {
@@ -17,7 +18,9 @@ pub(crate) fn build_lnet_stats(x: &Net) -> Vec<Record> {
     x.local_nis
         .iter()
         .flat_map(|y| {
-            vec![
+            let health = &y.health_stats;
+
+            let mut xs = vec![
                 LNetStats::SendCount(LNetStat {
                     nid: y.nid.to_string(),
                     param: Param("send_count".to_string()),
@@ -33,7 +36,52 @@ pub(crate) fn build_lnet_stats(x: &Net) -> Vec<Record> {
                     param: Param("drop_count".to_string()),
                     value: y.statistics.drop_count,
                 }),
-            ]
+                LNetStats::NiHealthValue(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("health_value".to_string()),
+                    value: health.health_value,
+                }),
+                LNetStats::NiInterrupts(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("interrupts".to_string()),
+                    value: health.interrupts,
+                }),
+                LNetStats::NiDropped(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("dropped".to_string()),
+                    value: health.dropped,
+                }),
+                LNetStats::NiTimeouts(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("timeouts".to_string()),
+                    value: health.timeouts,
+                }),
+                LNetStats::NiCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("credits".to_string()),
+                    value: y.tunables.credits,
+                }),
+                LNetStats::NiPeerCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("peer_credits".to_string()),
+                    value: y.tunables.peer_credits,
+                }),
+                LNetStats::NiPeerBufferCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("peer_buffer_credits".to_string()),
+                    value: y.tunables.peer_buffer_credits,
+                }),
+            ];
+
+            if let Some(value) = health.fatal_error {
+                xs.push(LNetStats::NiFatalError(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("fatal_error".to_string()),
+                    value,
+                }));
+            }
+
+            xs
         })
         .map(Record::LNetStat)
         .collect()
@@ -89,6 +137,258 @@ pub fn parse_lnetctl_stats(x: &str) -> Result<Vec<Record>, LustreCollectorError>
         .unwrap_or_default())
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LnetPeerStats {
+    peer: Option<Vec<Peer>>,
+}
+
+const LNET_PEER_NO_DISCOVERY: u64 = 1 << 1;
+const LNET_PEER_DISCOVERED: u64 = 1 << 3;
+const LNET_PEER_DISCOVERING: u64 = 1 << 5;
+const LNET_PEER_PING_FAILED: u64 = 1 << 10;
+const LNET_PEER_PUSH_FAILED: u64 = 1 << 11;
+
+/// The discovery state of a peer, from its `peer state` flags.
+fn discovery_state(peer_state: u64) -> &'static str {
+    if peer_state & LNET_PEER_NO_DISCOVERY != 0 {
+        "disabled"
+    } else if peer_state & (LNET_PEER_PING_FAILED | LNET_PEER_PUSH_FAILED) != 0 {
+        "failed"
+    } else if peer_state & LNET_PEER_DISCOVERING != 0 {
+        "discovering"
+    } else if peer_state & LNET_PEER_DISCOVERED != 0 {
+        "discovered"
+    } else {
+        "undiscovered"
+    }
+}
+
+pub(crate) fn build_lnetctl_peer_stats(x: &Peer) -> Vec<Record> {
+    let discovery = x.peer_state.map(|state| {
+        LNetStats::PeerDiscoveryState(LNetStat {
+            nid: x.primary_nid.to_string(),
+            param: Param("peer_state".to_string()),
+            value: discovery_state(state).to_string(),
+        })
+    });
+
+    x.peer_ni
+        .iter()
+        .flat_map(|y| {
+            vec![
+                LNetStats::PeerSendCount(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("send_count".to_string()),
+                    value: y.statistics.send_count,
+                }),
+                LNetStats::PeerRecvCount(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("recv_count".to_string()),
+                    value: y.statistics.recv_count,
+                }),
+                LNetStats::PeerDropCount(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("drop_count".to_string()),
+                    value: y.statistics.drop_count,
+                }),
+                LNetStats::PeerHealthValue(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("health_value".to_string()),
+                    value: y.health_stats.health_value,
+                }),
+                LNetStats::PeerMaxTxCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("max_ni_tx_credits".to_string()),
+                    value: y.max_ni_tx_credits,
+                }),
+                LNetStats::PeerAvailableTxCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("available_tx_credits".to_string()),
+                    value: y.available_tx_credits,
+                }),
+                LNetStats::PeerMinTxCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("min_tx_credits".to_string()),
+                    value: y.min_tx_credits,
+                }),
+                LNetStats::PeerAvailableRtrCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("available_rtr_credits".to_string()),
+                    value: y.available_rtr_credits,
+                }),
+                LNetStats::PeerMinRtrCredits(LNetStat {
+                    nid: y.nid.to_string(),
+                    param: Param("min_rtr_credits".to_string()),
+                    value: y.min_rtr_credits,
+                }),
+            ]
+        })
+        .chain(discovery)
+        .map(Record::LNetStat)
+        .collect()
+}
+
+/// Must be called with output of `lnetctl peer show -v 4`
+pub fn parse_lnetctl_peer_stats(x: &str) -> Result<Vec<Record>, LustreCollectorError> {
+    let x = x.trim();
+
+    if x.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let y: LnetPeerStats = serde_yaml::from_str(x)?;
+
+    Ok(y.peer
+        .map(|x| x.iter().flat_map(build_lnetctl_peer_stats).collect())
+        .unwrap_or_default())
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LnetRoutes {
+    route: Option<Vec<Route>>,
+}
+
+/// Must be called with output of `lnetctl route show -v`
+pub fn parse_lnetctl_routes(x: &str) -> Result<Vec<Record>, LustreCollectorError> {
+    let x = x.trim();
+
+    if x.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let y: LnetRoutes = serde_yaml::from_str(x)?;
+
+    Ok(y.route
+        .unwrap_or_default()
+        .into_iter()
+        .map(|x| {
+            Record::LNetStat(LNetStats::Route(LNetRoute {
+                up: x.state == "up",
+                net: x.net,
+                gateway: x.gateway,
+                hop: x.hop,
+                priority: x.priority,
+            }))
+        })
+        .collect())
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LnetRouting {
+    routing: Option<Vec<Routing>>,
+}
+
+/// Must be called with output of `lnetctl routing show`
+pub fn parse_lnetctl_routing(x: &str) -> Result<Vec<Record>, LustreCollectorError> {
+    let x = x.trim();
+
+    if x.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let y: LnetRouting = serde_yaml::from_str(x)?;
+
+    let mut records = vec![];
+    let mut buffers: BTreeMap<String, LNetRouterBuffers> = BTreeMap::new();
+
+    for entry in y.routing.unwrap_or_default() {
+        match entry {
+            Routing::Enable { enable } => {
+                records.push(Record::LNetStat(LNetStats::RoutingEnabled(
+                    LNetStatGlobal {
+                        param: Param("enable".to_string()),
+                        value: enable != 0,
+                    },
+                )));
+            }
+            Routing::Cpt(cpts) => {
+                for (size, pool) in cpts.into_values().flatten() {
+                    let x = buffers
+                        .entry(size.clone())
+                        .or_insert_with(|| LNetRouterBuffers {
+                            size,
+                            buffers: 0,
+                            credits: 0,
+                            min_credits: 0,
+                        });
+
+                    x.buffers += pool.nbuffers;
+                    x.credits += pool.credits;
+                    x.min_credits += pool.mincredits;
+                }
+            }
+        }
+    }
+
+    records.extend(
+        buffers
+            .into_values()
+            .map(|x| Record::LNetStat(LNetStats::RouterBuffers(x))),
+    );
+
+    Ok(records)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LnetUdsps {
+    udsp: Option<Vec<Udsp>>,
+}
+
+/// Must be called with output of `lnetctl udsp show`
+pub fn parse_lnetctl_udsp(x: &str) -> Result<Vec<Record>, LustreCollectorError> {
+    let x = x.trim();
+
+    if x.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let y: LnetUdsps = serde_yaml::from_str(x)?;
+
+    let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+
+    for action in y
+        .udsp
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|x| x.action.into_keys())
+    {
+        *counts.entry(action).or_default() += 1;
+    }
+
+    Ok(counts
+        .into_iter()
+        .map(|(action, count)| {
+            Record::LNetStat(LNetStats::UdspPolicies(LNetUdspPolicies { action, count }))
+        })
+        .collect())
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LnetGlobal {
+    global: Option<Global>,
+}
+
+/// Must be called with output of `lnetctl global show`
+pub fn parse_lnetctl_global(x: &str) -> Result<Vec<Record>, LustreCollectorError> {
+    let x = x.trim();
+
+    if x.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let y: LnetGlobal = serde_yaml::from_str(x)?;
+
+    Ok(y.global
+        .map(|x| {
+            Record::LNetStat(LNetStats::DiscoveryEnabled(LNetStatGlobal {
+                param: Param("discovery".to_string()),
+                value: x.discovery != 0,
+            }))
+        })
+        .into_iter()
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -494,4 +794,82 @@ mod tests {
 
         assert_debug_snapshot!(x);
     }
+
+    #[test]
+    fn test_lnet_udsp_parse() {
+        let x = parse_lnetctl_udsp(include_str!("fixtures/lnetctl_udsp_show.txt")).unwrap();
+
+        assert_debug_snapshot!(x);
+    }
+
+    #[test]
+    fn test_lnet_global_parse() {
+        let x = parse_lnetctl_global(
+            r#"global:
+    numa_range: 0
+    max_interfaces: 200
+    discovery: 1
+    drop_asym_route: 0
+    retry_count: 2
+    transaction_timeout: 50
+    health_sensitivity: 100
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            x,
+            vec![Record::LNetStat(LNetStats::DiscoveryEnabled(
+                LNetStatGlobal {
+                    param: Param("discovery".to_string()),
+                    value: true,
+                }
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_discovery_state() {
+        assert_eq!(discovery_state(137), "discovered");
+        assert_eq!(discovery_state(1 << 5 | 1), "discovering");
+        assert_eq!(discovery_state(1 << 10 | 1 << 3), "failed");
+        assert_eq!(discovery_state(1 << 1 | 1 << 3), "disabled");
+        assert_eq!(discovery_state(0), "undiscovered");
+    }
+
+    #[test]
+    fn test_lnet_routes_parse() {
+        let x = parse_lnetctl_routes(include_str!("fixtures/lnetctl_route_show.txt")).unwrap();
+
+        assert_debug_snapshot!(x);
+    }
+
+    #[test]
+    fn test_lnet_routing_parse() {
+        let x = parse_lnetctl_routing(include_str!("fixtures/lnetctl_routing_show.txt")).unwrap();
+
+        assert_debug_snapshot!(x);
+    }
+
+    #[test]
+    fn test_lnet_routing_disabled() {
+        let x = parse_lnetctl_routing("routing:\n    - enable: 0\n").unwrap();
+
+        assert_eq!(
+            x,
+            vec![Record::LNetStat(LNetStats::RoutingEnabled(
+                LNetStatGlobal {
+                    param: Param("enable".to_string()),
+                    value: false,
+                }
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_lnet_peer_parse() {
+        let x = parse_lnetctl_peer_stats(include_str!("fixtures/lnetctl_peer_show.txt")).unwrap();
+
+        assert_debug_snapshot!(x);
+    }
 }
@@ -2,19 +2,24 @@
 // Use of this source code is governed by a MIT-style
 // license that can be found in the LICENSE file.
 
-use clap::{value_parser, Arg, ValueEnum};
+use clap::{value_parser, Arg, ArgAction, ValueEnum};
 use lustre_collector::{
-    error::LustreCollectorError, mgs::mgs_fs_parser, parse_lctl_output, parse_lnetctl_output,
-    parse_lnetctl_stats, parse_mgs_fs_output, parse_recovery_status_output, parser,
-    recovery_status_parser, types::Record,
+    error::LustreCollectorError, mgs::mgs_fs_parser, parse_arcstats_output,
+    parse_lctl_output_lossy, parse_lnetctl_global, parse_lnetctl_output, parse_lnetctl_peer_stats,
+    parse_lnetctl_routes, parse_lnetctl_routing, parse_lnetctl_stats, parse_lnetctl_udsp,
+    parse_mgs_fs_output, parse_recovery_status_output, parse_zpool_fragmentation_output, parser,
+    recovery_status_parser, types::Record, zfs_parser,
 };
 use std::{
-    fmt, panic,
+    fmt, fs,
+    io::{self, Write},
+    panic,
     process::{Command, ExitCode},
     str::{self, FromStr},
     thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tracing::debug;
+use tracing::{debug, warn};
 
 #[derive(ValueEnum, PartialEq, Debug, Clone, Copy)]
 enum Format {
@@ -81,6 +86,59 @@ fn get_lnetctl_stats_output() -> Result<Vec<u8>, LustreCollectorError> {
     Ok(r.stdout)
 }
 
+fn get_lnetctl_peer_output() -> Result<Vec<u8>, LustreCollectorError> {
+    let r = Command::new("lnetctl")
+        .args(["peer", "show", "-v", "4"])
+        .output()?;
+
+    Ok(r.stdout)
+}
+
+fn get_lnetctl_route_records() -> Result<Vec<Record>, LustreCollectorError> {
+    let r = Command::new("lnetctl")
+        .args(["route", "show", "-v"])
+        .output()?;
+
+    let mut records = parse_lnetctl_routes(str::from_utf8(&r.stdout)?)?;
+
+    let r = Command::new("lnetctl").args(["routing", "show"]).output()?;
+
+    records.append(&mut parse_lnetctl_routing(str::from_utf8(&r.stdout)?)?);
+
+    Ok(records)
+}
+
+fn get_lnetctl_discovery_records() -> Result<Vec<Record>, LustreCollectorError> {
+    let r = Command::new("lnetctl").args(["global", "show"]).output()?;
+
+    let mut records = parse_lnetctl_global(str::from_utf8(&r.stdout)?)?;
+
+    let r = Command::new("lnetctl").args(["udsp", "show"]).output()?;
+
+    records.append(&mut parse_lnetctl_udsp(str::from_utf8(&r.stdout)?)?);
+
+    Ok(records)
+}
+
+fn get_zfs_records() -> Result<Vec<Record>, LustreCollectorError> {
+    let arcstats = match fs::read(zfs_parser::ARCSTATS) {
+        Ok(x) => x,
+        // Not a ZFS backed server
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut records = parse_arcstats_output(&arcstats)?;
+
+    let r = Command::new("zpool")
+        .args(zfs_parser::ZPOOL_FRAGMENTATION_ARGS)
+        .output()?;
+
+    records.append(&mut parse_zpool_fragmentation_output(&r.stdout)?);
+
+    Ok(records)
+}
+
 fn main() -> ExitCode {
     match run() {
         Ok(()) => ExitCode::SUCCESS,
@@ -92,31 +150,23 @@ fn main() -> ExitCode {
     }
 }
 
-fn run() -> Result<(), LustreCollectorError> {
-    tracing_subscriber::fmt::init();
-
-    let matches = clap::Command::new(env!("CARGO_PKG_NAME"))
-        .version(env!("CARGO_PKG_VERSION"))
-        .author("Whamcloud")
-        .about("Grabs various Lustre statistics for display in JSON or YAML")
-        .arg(
-            Arg::new("format")
-                .short('f')
-                .long("format")
-                .value_parser(value_parser!(Format))
-                .default_value("json")
-                .help("Sets the output formatting"),
-        )
-        .get_matches();
-
-    let format = matches
-        .get_one::<Format>("format")
-        .expect("Required argument `format` missing");
+/// A record as emitted by `--watch`, stamped with the time it was collected.
+#[derive(serde::Serialize)]
+struct WatchRecord<'a> {
+    /// Seconds since the epoch
+    timestamp: u64,
+    record: &'a Record,
+}
 
+fn collect() -> Result<Vec<Record>, LustreCollectorError> {
     let handle = thread::spawn(move || -> Result<Vec<Record>, LustreCollectorError> {
         let lctl_output = get_lctl_output()?;
 
-        let lctl_record = parse_lctl_output(&lctl_output)?;
+        let (lctl_record, skipped) = parse_lctl_output_lossy(&lctl_output)?;
+
+        for x in skipped {
+            warn!("Skipped unparsable param {}: {}", x.param, x.snippet);
+        }
 
         Ok(lctl_record)
     });
@@ -136,6 +186,21 @@ fn run() -> Result<(), LustreCollectorError> {
             Ok(lnetctl_stats_record)
         });
 
+    let lnetctl_peer_handle =
+        thread::spawn(move || -> Result<Vec<Record>, LustreCollectorError> {
+            let lnetctl_peer_output = get_lnetctl_peer_output()?;
+            let lnetctl_peer_record =
+                parse_lnetctl_peer_stats(str::from_utf8(&lnetctl_peer_output)?)?;
+
+            Ok(lnetctl_peer_record)
+        });
+
+    let lnetctl_route_handle = thread::spawn(get_lnetctl_route_records);
+
+    let lnetctl_discovery_handle = thread::spawn(get_lnetctl_discovery_records);
+
+    let zfs_handle = thread::spawn(get_zfs_records);
+
     let recovery_status_handle =
         thread::spawn(move || -> Result<Vec<Record>, LustreCollectorError> {
             let recovery_status_output = get_recovery_status_output()?;
@@ -146,14 +211,11 @@ fn run() -> Result<(), LustreCollectorError> {
 
     let lnetctl_net_show_output = Command::new("lnetctl")
         .args(["net", "show", "-v", "4"])
-        .output()
-        .expect("failed to get lnetctl stats");
+        .output()?;
 
-    let lnetctl_net_show_stats = str::from_utf8(&lnetctl_net_show_output.stdout)
-        .expect("while converting 'lnetctl net show -v 4' stdout from utf8");
+    let lnetctl_net_show_stats = str::from_utf8(&lnetctl_net_show_output.stdout)?;
 
-    let mut lnet_record = parse_lnetctl_output(lnetctl_net_show_stats)
-        .expect("while parsing 'lnetctl net show -v 4' stats");
+    let mut lnet_record = parse_lnetctl_output(lnetctl_net_show_stats)?;
 
     let mut lctl_record = match handle.join() {
         Ok(r) => r?,
@@ -178,7 +240,109 @@ fn run() -> Result<(), LustreCollectorError> {
     lctl_record.append(&mut lnet_record);
     lctl_record.append(&mut mgs_fs_record);
     lctl_record.append(&mut recovery_status_records);
+    let mut lnetctl_peer_record = match lnetctl_peer_handle.join() {
+        Ok(r) => r.unwrap_or_default(),
+        Err(e) => panic::resume_unwind(e),
+    };
+
+    let mut lnetctl_route_record = match lnetctl_route_handle.join() {
+        Ok(r) => r.unwrap_or_default(),
+        Err(e) => panic::resume_unwind(e),
+    };
+
+    let mut lnetctl_discovery_record = match lnetctl_discovery_handle.join() {
+        Ok(r) => r.unwrap_or_default(),
+        Err(e) => panic::resume_unwind(e),
+    };
+
+    let mut zfs_record = match zfs_handle.join() {
+        Ok(r) => r.unwrap_or_default(),
+        Err(e) => panic::resume_unwind(e),
+    };
+
     lctl_record.append(&mut lnetctl_stats_record);
+    lctl_record.append(&mut lnetctl_peer_record);
+    lctl_record.append(&mut lnetctl_route_record);
+    lctl_record.append(&mut lnetctl_discovery_record);
+    lctl_record.append(&mut zfs_record);
+
+    Ok(lctl_record)
+}
+
+/// Collects every `interval` and writes each record as a line of JSON to stdout.
+fn watch(interval: Duration) -> Result<(), LustreCollectorError> {
+    loop {
+        let start = Instant::now();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        match collect() {
+            Ok(records) => {
+                let mut stdout = io::stdout().lock();
+
+                for record in &records {
+                    serde_json::to_writer(&mut stdout, &WatchRecord { timestamp, record })?;
+                    writeln!(stdout)?;
+                }
+
+                stdout.flush()?;
+            }
+            Err(e) => warn!("Failed to collect stats: {e}"),
+        }
+
+        thread::sleep(interval.saturating_sub(start.elapsed()));
+    }
+}
+
+fn run() -> Result<(), LustreCollectorError> {
+    tracing_subscriber::fmt::init();
+
+    let matches = clap::Command::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .author("Whamcloud")
+        .about("Grabs various Lustre statistics for display in JSON or YAML")
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .value_parser(value_parser!(Format))
+                .default_value("json")
+                .help("Sets the output formatting"),
+        )
+        .arg(
+            Arg::new("watch")
+                .short('w')
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .help("Keeps collecting and writes each record as a line of JSON with a timestamp"),
+        )
+        .arg(
+            Arg::new("interval")
+                .short('i')
+                .long("interval")
+                .value_parser(humantime::parse_duration)
+                .default_value("10s")
+                .requires("watch")
+                .help("How often to collect in watch mode, e.g. 10s or 1m"),
+        )
+        .get_matches();
+
+    if matches.get_flag("watch") {
+        let interval = matches
+            .get_one::<Duration>("interval")
+            .expect("Required argument `interval` missing");
+
+        return watch(*interval);
+    }
+
+    let format = matches
+        .get_one::<Format>("format")
+        .expect("Required argument `format` missing");
+
+    let lctl_record = collect()?;
 
     let x = match format {
         Format::Json => serde_json::to_string(&lctl_record)?,
@@ -29,26 +29,27 @@ where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    many1(interface_clients())
-        .map(|xs: Vec<_>| {
-            xs.into_iter().fold(BTreeMap::new(), |mut acc, (k, v)| {
-                acc.entry(k).and_modify(|x| *x += v).or_insert(v);
+    many1(interface_clients()).map(records)
+}
 
-                acc
-            })
-        })
-        .map(|hm| {
-            hm.into_iter()
-                .map(|(k, value)| TargetStat {
-                    kind: TargetVariant::Mdt,
-                    target: Target(k),
-                    param: Param("connected_clients".into()),
-                    value,
-                })
-                .map(TargetStats::ConnectedClients)
-                .map(Record::Target)
-                .collect()
+/// Sums the clients of each MDT over its exports.
+pub(crate) fn records(xs: Vec<(String, u64)>) -> Vec<Record> {
+    let hm = xs.into_iter().fold(BTreeMap::new(), |mut acc, (k, v)| {
+        acc.entry(k).and_modify(|x| *x += v).or_insert(v);
+
+        acc
+    });
+
+    hm.into_iter()
+        .map(|(k, value)| TargetStat {
+            kind: TargetVariant::Mdt,
+            target: Target(k),
+            param: Param("connected_clients".into()),
+            value,
         })
+        .map(TargetStats::ConnectedClients)
+        .map(Record::Target)
+        .collect()
 }
 
 fn interface_clients<I>() -> impl Parser<I, Output = (String, u64)>
@@ -5,7 +5,8 @@
 use crate::{
     base_parsers::{equals, period, target},
     stats_parser::stats,
-    types::{Param, Record, Stat, Target, TargetStats},
+    threads_parser::{threads, threads_stat, Threads, THREADS},
+    types::{Param, Record, Stat, Target, TargetStats, TargetVariant},
     MdsStat,
 };
 use combine::{attempt, choice, error::ParseError, parser::char::string, stream::Stream, Parser};
@@ -39,7 +40,8 @@ pub(crate) const MDT_STATS: [&str; 8] = [
 pub(crate) fn params() -> Vec<String> {
     MDT_STATS
         .iter()
-        .map(|x| format!("{MDS}.{MDS_UPPER}.{x}.{STATS}"))
+        .flat_map(|x| [STATS].into_iter().chain(THREADS).map(move |y| (x, y)))
+        .map(|(x, y)| format!("{MDS}.{MDS_UPPER}.{x}.{y}"))
         .collect()
 }
 
@@ -53,34 +55,42 @@ where
         .message("while parsing `mds_prefix`")
 }
 
-fn param_non_final<I>(x: &'static str) -> impl Parser<I, Output = Param>
+fn service<I>(x: &'static str) -> impl Parser<I, Output = &'static str>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    attempt(string(x).skip(period()))
-        .skip(string(STATS).skip(equals()))
-        .map(|x| Param(x.to_string()))
-        .message("while parsing `mds_suffix`")
+    attempt(string(x).skip(period())).message("while parsing `mds_suffix`")
 }
 
-fn mds_stat<I>() -> impl Parser<I, Output = (Param, Vec<Stat>)>
+enum ServiceStat {
+    Stats(Vec<Stat>),
+    Threads(Threads),
+}
+
+fn mds_stat<I>() -> impl Parser<I, Output = (&'static str, ServiceStat)>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
     (
         choice((
-            param_non_final(MDT),
-            param_non_final(MDT_FLD),
-            param_non_final(MDT_IO),
-            param_non_final(MDT_OUT),
-            param_non_final(MDT_READ),
-            param_non_final(MDT_SEQM),
-            param_non_final(MDT_SEQS),
-            param_non_final(MDT_SETATTR),
+            service(MDT),
+            service(MDT_FLD),
+            service(MDT_IO),
+            service(MDT_OUT),
+            service(MDT_READ),
+            service(MDT_SEQM),
+            service(MDT_SEQS),
+            service(MDT_SETATTR),
+        )),
+        choice((
+            string(STATS)
+                .skip(equals())
+                .with(stats())
+                .map(ServiceStat::Stats),
+            threads().map(ServiceStat::Threads),
         )),
-        stats(),
     )
         .message("while parsing `mds_stat`")
 }
@@ -90,9 +100,14 @@ where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    mds_prefix()
-        .with(mds_stat())
-        .map(|(param, stats)| TargetStats::Mds(MdsStat { param, stats }))
+    (mds_prefix(), mds_stat())
+        .map(|(target, (service, x))| match x {
+            ServiceStat::Stats(stats) => TargetStats::Mds(MdsStat {
+                param: Param(service.to_string()),
+                stats,
+            }),
+            ServiceStat::Threads(x) => threads_stat(TargetVariant::Mdt, target, service, x),
+        })
         .map(Record::Target)
         .message("while parsing mds")
 }
@@ -6,7 +6,7 @@ use crate::{
     base_parsers::{digits, param, param_period, period, target},
     exports_parser::exports_stats,
     oss::obdfilter_parser::{EXPORTS, EXPORTS_PARAMS},
-    stats_parser::stats,
+    stats_parser::timed_stats,
     types::{Param, Record, Stat, Target, TargetStat, TargetStats, TargetVariant},
     ExportStats,
 };
@@ -20,10 +20,17 @@ use combine::{
 
 pub(crate) const STATS: &str = "md_stats";
 pub(crate) const NUM_EXPORTS: &str = "num_exports";
+pub(crate) const NUM_OPEN_FILES: &str = "num_open_files";
+pub(crate) const EVICTION_COUNT: &str = "eviction_count";
+pub(crate) const IDENTITY_EXPIRE: &str = "identity_expire";
+pub(crate) const IDENTITY_ACQUIRE_EXPIRE: &str = "identity_acquire_expire";
 
-enum MdtStat {
-    Stats(Vec<Stat>),
+pub(crate) enum MdtStat {
+    Stats(Option<u64>, Vec<Stat>),
     NumExports(u64),
+    OpenFiles(u64),
+    Evictions(u64),
+    Tunable(u64),
     ExportStats(Vec<ExportStats>),
 }
 
@@ -37,7 +44,23 @@ where
             param(NUM_EXPORTS),
             digits().skip(newline()).map(MdtStat::NumExports),
         ),
-        (param(STATS), stats().map(MdtStat::Stats)).message("while parsing mdt_stat"),
+        (
+            param(NUM_OPEN_FILES),
+            digits().skip(newline()).map(MdtStat::OpenFiles),
+        ),
+        (
+            param(EVICTION_COUNT),
+            digits().skip(newline()).map(MdtStat::Evictions),
+        ),
+        (
+            choice((param(IDENTITY_EXPIRE), param(IDENTITY_ACQUIRE_EXPIRE))),
+            digits().skip(newline()).map(MdtStat::Tunable),
+        ),
+        (
+            param(STATS),
+            timed_stats().map(|(time, xs)| MdtStat::Stats(time, xs)),
+        )
+            .message("while parsing mdt_stat"),
         (
             param_period(EXPORTS),
             exports_stats().map(MdtStat::ExportStats),
@@ -49,6 +72,10 @@ pub(crate) fn params() -> Vec<String> {
     [
         format!("mdt.*.{STATS}"),
         format!("mdt.*MDT*.{NUM_EXPORTS}"),
+        format!("mdt.*MDT*.{NUM_OPEN_FILES}"),
+        format!("mdt.*MDT*.{EVICTION_COUNT}"),
+        format!("mdt.*MDT*.{IDENTITY_EXPIRE}"),
+        format!("mdt.*MDT*.{IDENTITY_ACQUIRE_EXPIRE}"),
         format!("mdt.*MDT*.{EXPORTS_PARAMS}"),
     ]
     .into_iter()
@@ -68,32 +95,69 @@ where
         .message("while parsing target_name")
 }
 
-pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Vec<Record>>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
     (target_name(), mdt_stat())
-        .map(|(target, (param, value))| match value {
-            MdtStat::Stats(value) => TargetStats::Stats(TargetStat {
-                kind: TargetVariant::Mdt,
-                target,
-                param,
-                value,
-            }),
-            MdtStat::NumExports(value) => TargetStats::NumExports(TargetStat {
-                kind: TargetVariant::Mdt,
-                target,
-                param,
-                value,
-            }),
-            MdtStat::ExportStats(value) => TargetStats::ExportStats(TargetStat {
-                kind: TargetVariant::Mdt,
-                target,
-                param,
-                value,
-            }),
-        })
-        .map(Record::Target)
+        .map(|(target, (param, value))| records(target, param, value))
         .message("while parsing mdt")
 }
+
+pub(crate) fn records(target: Target, param: Param, value: MdtStat) -> Vec<Record> {
+    let snapshot_time = match &value {
+        MdtStat::Stats(Some(x), _) => Some(TargetStats::SnapshotTime(TargetStat {
+            kind: TargetVariant::Mdt,
+            target: target.clone(),
+            param: param.clone(),
+            value: *x,
+        })),
+        _ => None,
+    };
+
+    let x = match value {
+        MdtStat::Stats(_, value) => TargetStats::Stats(TargetStat {
+            kind: TargetVariant::Mdt,
+            target,
+            param,
+            value,
+        }),
+        MdtStat::NumExports(value) => TargetStats::NumExports(TargetStat {
+            kind: TargetVariant::Mdt,
+            target,
+            param,
+            value,
+        }),
+        MdtStat::OpenFiles(value) => TargetStats::OpenFiles(TargetStat {
+            kind: TargetVariant::Mdt,
+            target,
+            param,
+            value,
+        }),
+        MdtStat::Evictions(value) => TargetStats::Evictions(TargetStat {
+            kind: TargetVariant::Mdt,
+            target,
+            param,
+            value,
+        }),
+        MdtStat::Tunable(value) => TargetStats::Tunable(TargetStat {
+            kind: TargetVariant::Mdt,
+            target,
+            param,
+            value,
+        }),
+        MdtStat::ExportStats(value) => TargetStats::ExportStats(TargetStat {
+            kind: TargetVariant::Mdt,
+            target,
+            param,
+            value,
+        }),
+    };
+
+    snapshot_time
+        .into_iter()
+        .chain([x])
+        .map(Record::Target)
+        .collect()
+}
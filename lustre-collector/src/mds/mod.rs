@@ -16,12 +16,12 @@ pub(crate) fn params() -> Vec<String> {
         .collect()
 }
 
-pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Vec<Record>>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    attempt(mds_parser::parse()).or(attempt(mdt_parser::parse()))
+    attempt(mds_parser::parse().map(|x| vec![x])).or(attempt(mdt_parser::parse()))
 }
 
 #[cfg(test)]
@@ -109,7 +109,8 @@ mds.MDS.mdt_setattr.stats=
 snapshot_time             1689062826.416952373 secs.nsecs
 "#;
 
-        let result: (Vec<_>, _) = many(parse()).easy_parse(x).unwrap();
+        let result: (Vec<Vec<_>>, _) = many(parse()).easy_parse(x).unwrap();
+        let result: (Vec<_>, _) = (result.0.into_iter().flatten().collect(), result.1);
 
         assert_debug_snapshot!(result)
     }
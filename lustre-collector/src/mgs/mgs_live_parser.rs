@@ -0,0 +1,139 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{
+    error::LustreCollectorError,
+    types::{FsName, MgsFsStat, Param, Record, Target, TargetStats},
+};
+use std::str;
+
+/// The params holding the live state of each filesystem registered with the MGS `target`.
+pub fn params(target: &Target, fsnames: &[FsName]) -> Vec<String> {
+    fsnames
+        .iter()
+        .map(|FsName(fs)| format!("mgs.{}.live.{fs}", target.0))
+        .collect()
+}
+
+/// The `lctl` args listing the OST pools of `fs`.
+pub fn pool_list_args(fs: &FsName) -> [&str; 2] {
+    ["pool_list", &fs.0]
+}
+
+fn fs_stat<T>(target: &Target, fs: &FsName, param: &str, value: T) -> MgsFsStat<T> {
+    MgsFsStat {
+        target: target.clone(),
+        fs: fs.clone(),
+        param: Param(param.to_string()),
+        value,
+    }
+}
+
+fn parse_u64(param: &str, value: &str) -> Result<u64, LustreCollectorError> {
+    value.parse().map_err(|_| {
+        LustreCollectorError::ConversionError(format!("Could not parse mgs live {param}"))
+    })
+}
+
+/// Parses the imperative recovery state out of `lctl get_param mgs.*.live.<fs>` output.
+pub fn parse_mgs_live_output(output: &[u8]) -> Result<Vec<Record>, LustreCollectorError> {
+    let output = str::from_utf8(output)?;
+
+    let mut records = vec![];
+    let mut current = None;
+
+    for line in output.lines() {
+        if let Some(x) = line.strip_prefix("mgs.").and_then(|x| x.strip_suffix('=')) {
+            current = x
+                .split_once(".live.")
+                .map(|(target, fs)| (Target(target.to_string()), FsName(fs.to_string())));
+
+            continue;
+        }
+
+        let Some((target, fs)) = &current else {
+            continue;
+        };
+
+        let Some((name, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+
+        let value = value.trim();
+
+        let stat = match name {
+            "state" => TargetStats::MgsIrState(fs_stat(target, fs, name, value.to_string())),
+            "nonir_clientcount" => {
+                TargetStats::MgsIrNonirClients(fs_stat(target, fs, name, parse_u64(name, value)?))
+            }
+            "nidtbl_version" => {
+                TargetStats::MgsNidtblVersion(fs_stat(target, fs, name, parse_u64(name, value)?))
+            }
+            "notify_count" => {
+                TargetStats::MgsIrNotifyCount(fs_stat(target, fs, name, parse_u64(name, value)?))
+            }
+            _ => continue,
+        };
+
+        records.push(Record::Target(stat));
+    }
+
+    Ok(records)
+}
+
+/// Counts the pools in `lctl pool_list <fs>` output, run on the MGS `target`.
+pub fn parse_pool_list_output(
+    target: &Target,
+    fs: &FsName,
+    output: &[u8],
+) -> Result<Vec<Record>, LustreCollectorError> {
+    let output = str::from_utf8(output)?;
+
+    let prefix = format!("{}.", fs.0);
+
+    let count = output
+        .lines()
+        .filter(|x| x.trim().starts_with(&prefix))
+        .count();
+
+    Ok(vec![Record::Target(TargetStats::MgsPoolCount(fs_stat(
+        target,
+        fs,
+        "pool_count",
+        count as u64,
+    )))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_debug_snapshot;
+
+    #[test]
+    fn test_mgs_live() {
+        let result = parse_mgs_live_output(include_bytes!("../fixtures/mgs_live.txt")).unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+
+    #[test]
+    fn test_pool_list() {
+        let result = parse_pool_list_output(
+            &Target("MGS".into()),
+            &FsName("fs".into()),
+            b"Pools from fs:\nfs.flash\nfs.archive\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            vec![Record::Target(TargetStats::MgsPoolCount(MgsFsStat {
+                target: Target("MGS".into()),
+                fs: FsName("fs".into()),
+                param: Param("pool_count".into()),
+                value: 2,
+            }))]
+        );
+    }
+}
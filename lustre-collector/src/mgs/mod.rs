@@ -3,4 +3,5 @@
 // license that can be found in the LICENSE file.
 
 pub mod mgs_fs_parser;
+pub mod mgs_live_parser;
 pub mod mgs_parser;
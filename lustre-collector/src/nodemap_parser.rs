@@ -0,0 +1,133 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{
+    base_parsers::{digits, param, period, word},
+    types::{HostStat, NodemapStat, NodemapStats, Param, Record},
+};
+use combine::{
+    attempt, choice,
+    error::ParseError,
+    parser::char::{newline, string},
+    stream::Stream,
+    Parser,
+};
+
+pub(crate) const NODEMAP: &str = "nodemap";
+pub(crate) const ACTIVE: &str = "active";
+pub(crate) const ID: &str = "id";
+pub(crate) const ADMIN_NODEMAP: &str = "admin_nodemap";
+pub(crate) const TRUSTED_NODEMAP: &str = "trusted_nodemap";
+pub(crate) const SQUASH_UID: &str = "squash_uid";
+pub(crate) const SQUASH_GID: &str = "squash_gid";
+
+pub(crate) fn params() -> Vec<String> {
+    vec![
+        format!("{NODEMAP}.{ACTIVE}"),
+        format!("{NODEMAP}.*.{ID}"),
+        format!("{NODEMAP}.*.{ADMIN_NODEMAP}"),
+        format!("{NODEMAP}.*.{TRUSTED_NODEMAP}"),
+        format!("{NODEMAP}.*.{SQUASH_UID}"),
+        format!("{NODEMAP}.*.{SQUASH_GID}"),
+    ]
+}
+
+#[derive(Debug)]
+enum NodemapValue {
+    Id(u64),
+    AdminNodemap(bool),
+    TrustedNodemap(bool),
+    SquashUid(u64),
+    SquashGid(u64),
+}
+
+fn flag<I>() -> impl Parser<I, Output = bool>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    digits().map(|x| x != 0)
+}
+
+fn nodemap_stat<I>() -> impl Parser<I, Output = (Param, NodemapValue)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    choice((
+        (param(ID), digits().map(NodemapValue::Id)),
+        (param(ADMIN_NODEMAP), flag().map(NodemapValue::AdminNodemap)),
+        (
+            param(TRUSTED_NODEMAP),
+            flag().map(NodemapValue::TrustedNodemap),
+        ),
+        (param(SQUASH_UID), digits().map(NodemapValue::SquashUid)),
+        (param(SQUASH_GID), digits().map(NodemapValue::SquashGid)),
+    ))
+    .skip(newline())
+    .message("while parsing nodemap stat")
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        attempt(string(NODEMAP).skip(period())),
+        choice((
+            (param(ACTIVE), flag().skip(newline()))
+                .map(|(param, value)| NodemapStats::Active(HostStat { param, value })),
+            (word().skip(period()), nodemap_stat()).map(|(nodemap, (param, value))| match value {
+                NodemapValue::Id(value) => NodemapStats::Id(NodemapStat {
+                    nodemap,
+                    param,
+                    value,
+                }),
+                NodemapValue::AdminNodemap(value) => NodemapStats::AdminNodemap(NodemapStat {
+                    nodemap,
+                    param,
+                    value,
+                }),
+                NodemapValue::TrustedNodemap(value) => NodemapStats::TrustedNodemap(NodemapStat {
+                    nodemap,
+                    param,
+                    value,
+                }),
+                NodemapValue::SquashUid(value) => NodemapStats::SquashUid(NodemapStat {
+                    nodemap,
+                    param,
+                    value,
+                }),
+                NodemapValue::SquashGid(value) => NodemapStats::SquashGid(NodemapStat {
+                    nodemap,
+                    param,
+                    value,
+                }),
+            }),
+        )),
+    )
+        .map(|(_, x)| Record::Nodemap(x))
+        .message("while parsing nodemap")
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn test_nodemap_stats() {
+        static FIXTURE: &str = include_str!("fixtures/valid/nodemap.txt");
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+}
@@ -7,14 +7,16 @@ use crate::{
     brw_stats_parser::brw_stats,
     quota::quota_parser::quota_stats_osd,
     types::{BrwStats, Param, Record, Target, TargetStat, TargetStats, TargetVariant},
-    QuotaKind, QuotaStatsOsd,
+    QuotaEnforcement, QuotaKind, QuotaStatsOsd,
 };
 use combine::{
     attempt, choice,
     error::{ParseError, StreamError},
-    parser::char::{newline, string},
+    many1,
+    parser::char::{letter, newline, string},
+    skip_many,
     stream::{Stream, StreamErrorFor},
-    Parser,
+    token, Parser,
 };
 
 pub(crate) const FILES_FREE: &str = "filesfree";
@@ -29,6 +31,7 @@ pub(crate) const BRW_STATS: &str = "brw_stats";
 pub(crate) const QUOTA_ACCT_GRP: &str = "quota_slave.acct_group";
 pub(crate) const QUOTA_ACCT_USR: &str = "quota_slave.acct_user";
 pub(crate) const QUOTA_ACCT_PRJ: &str = "quota_slave.acct_project";
+pub(crate) const QUOTA_SLAVE_INFO: &str = "quota_slave.info";
 
 pub(crate) fn params() -> Vec<String> {
     vec![
@@ -42,6 +45,7 @@ pub(crate) fn params() -> Vec<String> {
         format!("osd-*.*.{QUOTA_ACCT_GRP}"),
         format!("osd-*.*.{QUOTA_ACCT_USR}"),
         format!("osd-*.*.{QUOTA_ACCT_PRJ}"),
+        format!("osd-*.*.{QUOTA_SLAVE_INFO}"),
     ]
 }
 
@@ -61,6 +65,7 @@ enum OsdStat {
     KBytesTotal(u64),
     BrwStats(Vec<BrwStats>),
     QuotaStats(QuotaStatsOsd),
+    QuotaEnforcement(QuotaEnforcement),
 }
 
 fn target_and_variant<I>() -> impl Parser<I, Output = (Target, TargetVariant)>
@@ -83,6 +88,45 @@ where
         .message("while parsing target_and_variant")
 }
 
+/// Parses a `name: value` line of `quota_slave.info`, where the name may contain spaces
+fn quota_slave_info_line<I>() -> impl Parser<I, Output = (String, String)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        attempt(many1(letter().or(token(' '))).skip(token(':'))),
+        skip_many(token(' ')).with(till_newline()).skip(newline()),
+    )
+}
+
+/// Parses `quota_slave.info`, keeping only which quota kinds are enforced
+fn quota_slave_info<I>() -> impl Parser<I, Output = QuotaEnforcement>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    newline()
+        .with(many1(quota_slave_info_line()))
+        .map(|xs: Vec<(String, String)>| {
+            let enabled = xs
+                .into_iter()
+                .find(|(name, _)| name == "quota enabled")
+                .map(|(_, x)| x)
+                .unwrap_or_default();
+
+            // Either a combination of `u`, `g` and `p`, or `none`
+            let enabled = if enabled == "none" { "" } else { &enabled };
+
+            QuotaEnforcement {
+                usr: enabled.contains('u'),
+                grp: enabled.contains('g'),
+                prj: enabled.contains('p'),
+            }
+        })
+        .message("while parsing quota_slave.info")
+}
+
 fn osd_stat<I>() -> impl Parser<I, Output = (Param, OsdStat)>
 where
     I: Stream<Token = char>,
@@ -144,6 +188,10 @@ where
                 })
             }),
         ),
+        (
+            param(QUOTA_SLAVE_INFO),
+            quota_slave_info().map(OsdStat::QuotaEnforcement),
+        ),
     ))
 }
 
@@ -202,6 +250,12 @@ where
                 param,
                 value,
             }),
+            OsdStat::QuotaEnforcement(value) => TargetStats::QuotaEnforcement(TargetStat {
+                kind,
+                target,
+                param,
+                value,
+            }),
         })
         .map(Record::Target)
         .message("while parsing osd")
@@ -0,0 +1,127 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Object precreation state of the `osp` devices an MDT uses to reach each
+//! OST, like `osp.fs-OST0000-osc-MDT0000.prealloc_next_id`.
+
+use crate::{
+    base_parsers::{digits, equals, period, target},
+    types::{OspStat, Param, Record, Target, TargetStats},
+};
+use combine::{
+    attempt, choice,
+    error::{ParseError, StreamError},
+    optional,
+    parser::char::{newline, string},
+    stream::{Stream, StreamErrorFor},
+    token, Parser,
+};
+
+pub(crate) const OSP: &str = "osp";
+
+pub(crate) const PREALLOC_NEXT_ID: &str = "prealloc_next_id";
+pub(crate) const PREALLOC_LAST_ID: &str = "prealloc_last_id";
+pub(crate) const PREALLOC_STATUS: &str = "prealloc_status";
+pub(crate) const CREATE_COUNT: &str = "create_count";
+
+pub(crate) const OSP_STATS: [&str; 4] = [
+    PREALLOC_NEXT_ID,
+    PREALLOC_LAST_ID,
+    PREALLOC_STATUS,
+    CREATE_COUNT,
+];
+
+/// Only the `osp` devices of OSTs precreate objects, MDT to MDT ones do not.
+pub(crate) fn params() -> Vec<String> {
+    OSP_STATS
+        .iter()
+        .map(|x| format!("{OSP}.*OST*.{x}"))
+        .collect()
+}
+
+/// Splits a `<fs>-OST0000-osc-MDT0000` device into the OST and the MDT.
+fn split_device(device: &str) -> Option<(Target, Target)> {
+    let (ost, mdt) = device.split_once("-osc-")?;
+    let (fs, _) = ost.rsplit_once('-')?;
+
+    Some((Target(ost.to_string()), Target(format!("{fs}-{mdt}"))))
+}
+
+fn device<I>() -> impl Parser<I, Output = (Target, Target)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    target().and_then(|Target(x)| {
+        split_device(&x).ok_or_else(|| StreamErrorFor::<I>::expected_static_message("osp device"))
+    })
+}
+
+/// `prealloc_status` is 0 or a negative errno, like `-28` when the OST is full.
+fn signed_digits<I>() -> impl Parser<I, Output = i64>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (optional(token('-')), digits()).and_then(|(sign, x)| {
+        i64::try_from(x)
+            .map(|x| if sign.is_some() { -x } else { x })
+            .map_err(StreamErrorFor::<I>::other)
+    })
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        attempt((string(OSP), period()))
+            .with(device())
+            .skip(period()),
+        choice(OSP_STATS.map(|x| attempt(string(x)))).skip(equals()),
+        signed_digits().skip(newline()),
+    )
+        .map(|((target, mdt), param, value)| {
+            Record::Target(TargetStats::OspPrealloc(OspStat {
+                target,
+                mdt,
+                param: Param(param.to_string()),
+                value,
+            }))
+        })
+        .message("while parsing osp")
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn test_osp() {
+        static FIXTURE: &str = include_str!("fixtures/valid/osp_prealloc.txt");
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+
+    #[test]
+    fn test_split_device() {
+        assert_eq!(
+            split_device("ai400x2-OST0003-osc-MDT0001"),
+            Some((
+                Target("ai400x2-OST0003".into()),
+                Target("ai400x2-MDT0001".into())
+            ))
+        );
+        assert_eq!(split_device("ai400x2-MDT0001-osp-MDT0000"), None);
+    }
+}
@@ -15,12 +15,12 @@ pub(crate) fn params() -> Vec<String> {
         .collect()
 }
 
-pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Vec<Record>>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    attempt(obdfilter_parser::parse()).or(attempt(oss_parser::parse()))
+    attempt(obdfilter_parser::parse()).or(attempt(oss_parser::parse().map(|x| vec![x])))
 }
 
 #[cfg(test)]
@@ -77,7 +77,8 @@ ost_write                 951033049 samples [usec] 59 1247713 2749050524782 1000
 ost_punch                 1515 samples [usec] 16 4883 63967 29511205
 "#;
 
-        let result: (Vec<_>, _) = many(parse()).parse(x).unwrap();
+        let result: (Vec<Vec<_>>, _) = many(parse()).parse(x).unwrap();
+        let result: (Vec<_>, _) = (result.0.into_iter().flatten().collect(), result.1);
 
         assert_debug_snapshot!(result)
     }
@@ -4,10 +4,10 @@
 
 use crate::{
     base_parsers::{digits, param, param_period, period, target},
-    exports_parser::exports_stats,
-    stats_parser::stats,
+    exports_parser::{export_grant, exports_stats},
+    stats_parser::timed_stats,
     types::{Param, Record, Stat, Target, TargetStat, TargetStats, TargetVariant},
-    ExportStats,
+    ExportGrant, ExportStats,
 };
 use combine::{
     choice,
@@ -23,17 +23,28 @@ pub(crate) const NUM_EXPORTS: &str = "num_exports";
 pub(crate) const TOT_DIRTY: &str = "tot_dirty";
 pub(crate) const TOT_GRANTED: &str = "tot_granted";
 pub(crate) const TOT_PENDING: &str = "tot_pending";
+pub(crate) const BRW_SIZE: &str = "brw_size";
+
+pub(crate) const MAX_DIRTY_MB: &str = "max_dirty_mb";
+pub(crate) const READCACHE_MAX_FILESIZE: &str = "readcache_max_filesize";
+pub(crate) const SYNC_JOURNAL: &str = "sync_journal";
 
 pub(crate) const EXPORTS: &str = "exports";
 pub(crate) const EXPORTS_PARAMS: &str = "exports.*.stats";
+pub(crate) const EXPORTS_GRANT_PARAMS: &str = "exports.*.{dirty,granted,pending}";
 
-pub(crate) const OBD_STATS: [&str; 6] = [
+pub(crate) const OBD_STATS: [&str; 11] = [
     STATS,
     NUM_EXPORTS,
     TOT_DIRTY,
     TOT_GRANTED,
     TOT_PENDING,
+    BRW_SIZE,
+    MAX_DIRTY_MB,
+    READCACHE_MAX_FILESIZE,
+    SYNC_JOURNAL,
     EXPORTS_PARAMS,
+    EXPORTS_GRANT_PARAMS,
 ];
 
 /// Takes OBD_STATS and produces a list of params for
@@ -57,13 +68,16 @@ where
 }
 
 #[derive(Debug)]
-enum ObdfilterStat {
-    Stats(Vec<Stat>),
+pub(crate) enum ObdfilterStat {
+    Stats(Option<u64>, Vec<Stat>),
     ExportStats(Vec<ExportStats>),
+    ExportGrant(ExportGrant),
     NumExports(u64),
     TotDirty(u64),
     TotGranted(u64),
     TotPending(u64),
+    BrwSize(u64),
+    Tunable(u64),
 }
 
 fn obdfilter_stat<I>() -> impl Parser<I, Output = (Param, ObdfilterStat)>
@@ -72,7 +86,10 @@ where
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
     choice((
-        (param(STATS), stats().map(ObdfilterStat::Stats)),
+        (
+            param(STATS),
+            timed_stats().map(|(time, xs)| ObdfilterStat::Stats(time, xs)),
+        ),
         (
             param(NUM_EXPORTS),
             digits().skip(newline()).map(ObdfilterStat::NumExports),
@@ -89,62 +106,114 @@ where
             param(TOT_PENDING),
             digits().skip(newline()).map(ObdfilterStat::TotPending),
         ),
+        (
+            param(BRW_SIZE),
+            digits().skip(newline()).map(ObdfilterStat::BrwSize),
+        ),
+        (
+            choice((
+                param(MAX_DIRTY_MB),
+                param(READCACHE_MAX_FILESIZE),
+                param(SYNC_JOURNAL),
+            )),
+            digits().skip(newline()).map(ObdfilterStat::Tunable),
+        ),
         (
             param_period(EXPORTS),
-            exports_stats().map(ObdfilterStat::ExportStats),
+            choice((
+                export_grant().map(ObdfilterStat::ExportGrant),
+                exports_stats().map(ObdfilterStat::ExportStats),
+            )),
         ),
     ))
     .message("while parsing obdfilter")
 }
 
-pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Vec<Record>>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
     (target_name(), obdfilter_stat())
-        .map(|(target, (param, value))| match value {
-            ObdfilterStat::Stats(value) => TargetStats::Stats(TargetStat {
-                kind: TargetVariant::Ost,
-                target,
-                param,
-                value,
-            }),
-            ObdfilterStat::NumExports(value) => TargetStats::NumExports(TargetStat {
-                kind: TargetVariant::Ost,
-                target,
-                param,
-                value,
-            }),
-            ObdfilterStat::TotDirty(value) => TargetStats::TotDirty(TargetStat {
-                kind: TargetVariant::Ost,
-                target,
-                param,
-                value,
-            }),
-            ObdfilterStat::TotGranted(value) => TargetStats::TotGranted(TargetStat {
-                kind: TargetVariant::Ost,
-                target,
-                param,
-                value,
-            }),
-            ObdfilterStat::TotPending(value) => TargetStats::TotPending(TargetStat {
-                kind: TargetVariant::Ost,
-                target,
-                param,
-                value,
-            }),
-            ObdfilterStat::ExportStats(value) => TargetStats::ExportStats(TargetStat {
-                kind: TargetVariant::Ost,
-                target,
-                param,
-                value,
-            }),
-        })
-        .map(Record::Target)
+        .map(|(target, (param, value))| records(target, param, value))
         .message("while parsing obdfilter")
 }
 
+pub(crate) fn records(target: Target, param: Param, value: ObdfilterStat) -> Vec<Record> {
+    let snapshot_time = match &value {
+        ObdfilterStat::Stats(Some(x), _) => Some(TargetStats::SnapshotTime(TargetStat {
+            kind: TargetVariant::Ost,
+            target: target.clone(),
+            param: param.clone(),
+            value: *x,
+        })),
+        _ => None,
+    };
+
+    let x = match value {
+        ObdfilterStat::Stats(_, value) => TargetStats::Stats(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::NumExports(value) => TargetStats::NumExports(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::TotDirty(value) => TargetStats::TotDirty(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::TotGranted(value) => TargetStats::TotGranted(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::TotPending(value) => TargetStats::TotPending(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::BrwSize(value) => TargetStats::BrwSize(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::Tunable(value) => TargetStats::Tunable(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::ExportStats(value) => TargetStats::ExportStats(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+        ObdfilterStat::ExportGrant(value) => TargetStats::ExportGrant(TargetStat {
+            kind: TargetVariant::Ost,
+            target,
+            param,
+            value,
+        }),
+    };
+
+    snapshot_time
+        .into_iter()
+        .chain([x])
+        .map(Record::Target)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,7 +5,8 @@
 use crate::{
     base_parsers::{equals, period},
     stats_parser::stats,
-    types::{Param, Record, Stat, TargetStats},
+    threads_parser::{threads, threads_stat, Threads, THREADS},
+    types::{Param, Record, Stat, Target, TargetStats, TargetVariant},
     OssStat,
 };
 use combine::{attempt, choice, error::ParseError, parser::char::string, stream::Stream, Parser};
@@ -26,7 +27,8 @@ pub(crate) const OST_STATS: [&str; 5] = [OST, OST_IO, OST_CREATE, OST_OUT, OST_S
 pub(crate) fn params() -> Vec<String> {
     OST_STATS
         .iter()
-        .map(|x| format!("{OST}.{OSS}.{x}.{STATS}"))
+        .flat_map(|x| [STATS].into_iter().chain(THREADS).map(move |y| (x, y)))
+        .map(|(x, y)| format!("{OST}.{OSS}.{x}.{y}"))
         .collect()
 }
 
@@ -41,31 +43,39 @@ where
         .message("while parsing `oss_prefix`")
 }
 
-fn param_non_final<I>(x: &'static str) -> impl Parser<I, Output = Param>
+fn service<I>(x: &'static str) -> impl Parser<I, Output = &'static str>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    attempt(string(x).skip(period()))
-        .skip(string(STATS).skip(equals()))
-        .map(|x| Param(x.to_string()))
-        .message("while parsing `oss_suffix`")
+    attempt(string(x).skip(period())).message("while parsing `oss_suffix`")
 }
 
-fn oss_stat<I>() -> impl Parser<I, Output = (Param, Vec<Stat>)>
+enum ServiceStat {
+    Stats(Vec<Stat>),
+    Threads(Threads),
+}
+
+fn oss_stat<I>() -> impl Parser<I, Output = (&'static str, ServiceStat)>
 where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
     (
         choice((
-            param_non_final(OST),
-            param_non_final(OST_IO),
-            param_non_final(OST_CREATE),
-            param_non_final(OST_OUT),
-            param_non_final(OST_SEQ),
+            service(OST),
+            service(OST_IO),
+            service(OST_CREATE),
+            service(OST_OUT),
+            service(OST_SEQ),
+        )),
+        choice((
+            string(STATS)
+                .skip(equals())
+                .with(stats())
+                .map(ServiceStat::Stats),
+            threads().map(ServiceStat::Threads),
         )),
-        stats(),
     )
         .message("while parsing `oss_stat`")
 }
@@ -77,7 +87,15 @@ where
 {
     oss_prefix()
         .with(oss_stat())
-        .map(|(param, stats)| TargetStats::Oss(OssStat { param, stats }))
+        .map(|(service, x)| match x {
+            ServiceStat::Stats(stats) => TargetStats::Oss(OssStat {
+                param: Param(service.to_string()),
+                stats,
+            }),
+            ServiceStat::Threads(x) => {
+                threads_stat(TargetVariant::Ost, Target(OSS.to_string()), service, x)
+            }
+        })
         .map(Record::Target)
         .message("while parsing oss")
 }
@@ -3,11 +3,13 @@
 // license that can be found in the LICENSE file.
 
 use crate::{
-    ldlm, llite, mdd_parser,
+    device_parser, import_parser, ldlm, llite, mdd_parser,
     mds::{self, client_count_parser},
     mgs::mgs_parser,
-    osd_parser, oss, quota, top_level_parser,
+    nodemap_parser, osd_parser, osp_parser, oss, pool_parser, quota, rpc_stats_parser, srpc_parser,
+    top_level_parser,
     types::Record,
+    zfs_parser,
 };
 use combine::{choice, error::ParseError, many, Parser, Stream};
 
@@ -15,7 +17,9 @@ pub fn params() -> Vec<String> {
     top_level_parser::top_level_params()
         .into_iter()
         .chain(client_count_parser::params())
+        .chain(device_parser::params())
         .chain(osd_parser::params())
+        .chain(zfs_parser::params())
         .chain(mgs_parser::params())
         .chain(oss::params())
         .chain(mds::params())
@@ -23,6 +27,12 @@ pub fn params() -> Vec<String> {
         .chain(llite::params())
         .chain(mdd_parser::params())
         .chain(quota::params())
+        .chain(nodemap_parser::params())
+        .chain(pool_parser::params())
+        .chain(osp_parser::params())
+        .chain(srpc_parser::params())
+        .chain(import_parser::params())
+        .chain(rpc_stats_parser::params())
         .collect()
 }
 
@@ -31,21 +41,53 @@ where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    many(choice((
+    many(record()).map(|xs: Vec<_>| xs.into_iter().flatten().collect())
+}
+
+/// Parses a single param, or a run of params that make up one record.
+pub(crate) fn record<I>() -> impl Parser<I, Output = Vec<Record>>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    choice((
         top_level_parser::parse().map(|x| vec![x]),
         client_count_parser::parse(),
+        device_parser::parse().map(|x| vec![x]),
+        zfs_parser::parse().map(|x| vec![x]),
         osd_parser::parse().map(|x| vec![x]),
         mgs_parser::parse().map(|x| vec![x]),
-        oss::parse().map(|x| vec![x]),
-        mds::parse().map(|x| vec![x]),
+        oss::parse(),
+        mds::parse(),
         ldlm::parse().map(|x| vec![x]),
+        pool_parser::parse().map(|x| vec![x]),
+        osp_parser::parse().map(|x| vec![x]),
         llite::parse().map(|x| vec![x]),
         mdd_parser::parse().map(|x| vec![x]),
         quota::parse().map(|x| vec![x]),
-    )))
-    .map(|xs: Vec<_>| xs.into_iter().flatten().collect())
+        nodemap_parser::parse().map(|x| vec![x]),
+        srpc_parser::parse().map(|x| x.into_iter().collect()),
+        import_parser::parse().map(|x| vec![x]),
+        rpc_stats_parser::parse().map(|x| vec![x]),
+    ))
 }
 
+/// Parses `lctl get_param` output, returning the records and whatever input
+/// could not be parsed. Error positions are byte offsets into `input`.
+#[cfg(not(feature = "winnow"))]
+pub(crate) fn parse_output(
+    input: &str,
+) -> Result<(Vec<Record>, &str), combine::easy::Errors<char, &str, usize>> {
+    use combine::EasyParser;
+
+    parse()
+        .easy_parse(input)
+        .map_err(|err| err.map_position(|p| p.translate_position(input)))
+}
+
+#[cfg(feature = "winnow")]
+pub(crate) use crate::winnow_parser::parse as parse_output;
+
 #[cfg(test)]
 mod tests {
     use super::*;
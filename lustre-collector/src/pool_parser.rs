@@ -0,0 +1,107 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{
+    base_parsers::{equals, period, target},
+    types::{FsName, Param, PoolStat, Record, Target, TargetStats},
+};
+use combine::{
+    attempt, choice,
+    error::ParseError,
+    many,
+    parser::char::{newline, string},
+    stream::Stream,
+    Parser,
+};
+
+pub(crate) const LOD: &str = "lod";
+pub(crate) const LOV: &str = "lov";
+pub(crate) const POOLS: &str = "pools";
+
+/// Pools are listed by the `lod` devices of MDTs and the `lov` devices of clients.
+pub(crate) fn params() -> Vec<String> {
+    [LOD, LOV]
+        .into_iter()
+        .map(|x| format!("{x}.*.{POOLS}.*"))
+        .collect()
+}
+
+/// The filesystem of a `<fs>-MDT0000-mdtlov` or `<fs>-clilov-<id>` device.
+fn fs_name(device: &Target) -> FsName {
+    let fs = device
+        .0
+        .rsplit_once("-MDT")
+        .or_else(|| device.0.rsplit_once("-clilov"))
+        .map_or(device.0.as_str(), |(x, _)| x);
+
+    FsName(fs.to_string())
+}
+
+/// A `<fs>-OST0000_UUID` line naming a member of the pool.
+fn member<I>() -> impl Parser<I, Output = Target>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    attempt(target().skip(newline()))
+        .map(|Target(x)| Target(x.strip_suffix("_UUID").map(str::to_string).unwrap_or(x)))
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        attempt((
+            choice((attempt(string(LOD)), attempt(string(LOV)))).skip(period()),
+            target().skip(period()),
+            string(POOLS).skip(period()),
+        )),
+        target().skip(equals()).skip(newline()),
+        many(member()),
+    )
+        .map(|((_, device, _), Target(pool), members)| {
+            Record::Target(TargetStats::Pool(PoolStat {
+                fs: fs_name(&device),
+                target: device,
+                param: Param(POOLS.to_string()),
+                pool,
+                members,
+            }))
+        })
+        .message("while parsing pools")
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn test_pools() {
+        static FIXTURE: &str = include_str!("fixtures/valid/pools.txt");
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+
+    #[test]
+    fn test_fs_name() {
+        assert_eq!(
+            fs_name(&Target("ai400x2-MDT0000-mdtlov".into())),
+            FsName("ai400x2".into())
+        );
+        assert_eq!(
+            fs_name(&Target("fs-clilov-ffff8b1e4e1c9000".into())),
+            FsName("fs".into())
+        );
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! The `rpc_stats` of `osc` imports: RPCs currently in flight, pages waiting
+//! to be sent and histograms of the RPCs sent so far.
+
+use crate::{
+    base_parsers::{digits, string_to, till_newline, word},
+    brw_stats_parser::bucket,
+    import_parser::import,
+    time::time_triple,
+    types::{BrwStats, ImportStat, OscRpcStats, Record, TargetStats},
+};
+use combine::{
+    attempt, choice,
+    error::ParseError,
+    many, many1,
+    parser::char::{newline, spaces, string},
+    satisfy,
+    stream::Stream,
+    token, Parser,
+};
+use std::collections::BTreeMap;
+
+pub(crate) const RPC_STATS: &str = "rpc_stats";
+
+pub(crate) fn params() -> Vec<String> {
+    vec![format!("osc.*.{RPC_STATS}")]
+}
+
+/// A `read RPCs in flight:  0` or `pending write pages:  12` line
+fn counter<I>() -> impl Parser<I, Output = (String, u64)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    attempt((
+        many1(satisfy(|c: char| c.is_ascii_alphabetic() || c == ' ')),
+        token(':'),
+        spaces().with(digits()),
+        newline(),
+    ))
+    .map(|(name, _, value, _): (String, _, _, _)| (name, value))
+}
+
+/// A `read write` table, like `pages per rpc` with a bucket per power of two.
+fn section<I>() -> impl Parser<I, Output = BrwStats>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    let keys = choice([
+        attempt(string_to("pages per rpc", "pages")),
+        attempt(string_to("rpcs in flight", "rpcs_in_flight")),
+        attempt(string_to("offset", "offset")),
+    ]);
+
+    (
+        attempt((
+            spaces(),
+            string("read"),
+            spaces(),
+            string("write"),
+            newline(),
+        )),
+        keys.skip(spaces()),
+        word().skip(till_newline()).skip(newline()),
+        many(bucket().skip(newline())),
+    )
+        .map(|(_, name, unit, buckets)| BrwStats {
+            name,
+            unit,
+            buckets,
+        })
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        import(RPC_STATS),
+        time_triple(),
+        many(counter()),
+        many(section()),
+    )
+        .map(
+            |((kind, target, param), snapshot_time, counters, histograms)| {
+                let counters: BTreeMap<String, u64> = counters;
+                let counter = |x: &str| counters.get(x).copied().unwrap_or_default();
+
+                Record::Target(TargetStats::OscRpcStats(ImportStat {
+                    kind,
+                    param,
+                    target,
+                    value: OscRpcStats {
+                        snapshot_time,
+                        read_rpcs_in_flight: counter("read RPCs in flight"),
+                        write_rpcs_in_flight: counter("write RPCs in flight"),
+                        pending_read_pages: counter("pending read pages"),
+                        pending_write_pages: counter("pending write pages"),
+                        histograms,
+                    },
+                }))
+            },
+        )
+        .message("while parsing rpc_stats")
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn test_rpc_stats() {
+        static FIXTURE: &str = include_str!("fixtures/valid/osc_rpc_stats.txt");
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{
+    import_parser::import,
+    types::{ImportStat, Record, SrpcInfo, TargetStats},
+};
+use combine::{
+    attempt, error::ParseError, many, many1, optional, parser::char::newline, satisfy,
+    stream::Stream, token, Parser,
+};
+use std::collections::BTreeMap;
+
+pub(crate) const SRPC_INFO: &str = "srpc_info";
+
+pub(crate) fn params() -> Vec<String> {
+    ["mdc", "osc", "mgc"]
+        .into_iter()
+        .map(|x| format!("{x}.*.{SRPC_INFO}"))
+        .collect()
+}
+
+/// A `rpc flavor:    krb5p` or `gc next        3342` line
+fn field<I>() -> impl Parser<I, Output = (String, String)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    attempt((
+        many1(satisfy(|c: char| c.is_ascii_lowercase() || c == ' ')),
+        optional(token(':')),
+        many(satisfy(|c| c != '=' && c != '\n')),
+        newline(),
+    ))
+    .map(|(name, _, value, _): (String, _, String, _)| {
+        (name.trim().to_string(), value.trim().to_string())
+    })
+}
+
+fn srpc_info(mut fields: BTreeMap<String, String>) -> Option<SrpcInfo> {
+    Some(SrpcInfo {
+        rpc_flavor: fields.remove("rpc flavor")?,
+        bulk_flavor: fields.remove("bulk flavor").unwrap_or_default(),
+        flags: fields.remove("flags").unwrap_or_default(),
+        contexts: fields
+            .get("nctx")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or_default(),
+    })
+}
+
+/// Parses a `srpc_info` param. Imports without a security policy print
+/// nothing, and give no record.
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Option<Record>>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (import(SRPC_INFO), many(field()))
+        .map(|((kind, target, param), fields)| {
+            let value = srpc_info(fields)?;
+
+            Some(Record::Target(TargetStats::SrpcInfo(ImportStat {
+                kind,
+                param,
+                target,
+                value,
+            })))
+        })
+        .message("while parsing srpc_info")
+}
+
+#[cfg(test)]
+mod tests {
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    use super::*;
+
+    #[test]
+    fn test_srpc_info() {
+        static FIXTURE: &str = include_str!("fixtures/valid/srpc_info.txt");
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+
+    #[test]
+    fn test_stops_at_next_param() {
+        let x = "osc.fs-OST0000-osc-ffff8b4d3c2a1000.srpc_info=\nrpc flavor:    null\nmemused=1\n";
+
+        let (result, rest) = parse().easy_parse(x).unwrap();
+
+        assert!(result.is_some());
+        assert_eq!(rest, "memused=1\n");
+    }
+}
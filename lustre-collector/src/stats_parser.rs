@@ -112,7 +112,20 @@ where
     I: Stream<Token = char>,
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
-    (optional(newline()).with(time_triple()), many(stat())).map(|(_, xs)| xs)
+    timed_stats().map(|(_, xs)| xs)
+}
+
+/// Like [`stats`], also returning the `snapshot_time` in milliseconds since the epoch.
+pub(crate) fn timed_stats<I>() -> impl Parser<I, Output = (Option<u64>, Vec<Stat>)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (optional(newline()).with(time_triple()), many(stat())).map(|(time, xs)| {
+        let millis = time.parse::<f64>().ok().map(|x| (x * 1_000.0) as u64);
+
+        (millis, xs)
+    })
 }
 
 #[cfg(test)]
@@ -215,6 +228,19 @@ statfs                    16360 samples [reqs]
         assert_debug_snapshot!(result);
     }
 
+    #[test]
+    fn test_timed_stats() {
+        let x = r#"
+snapshot_time             1566007540.707634939 secs.nsecs
+statfs                    16360 samples [reqs]
+"#;
+
+        let ((time, stats), _) = timed_stats().parse(x).unwrap();
+
+        assert_eq!(time, Some(1_566_007_540_707));
+        assert_eq!(stats.len(), 1);
+    }
+
     #[test]
     fn test_empty_mdstats() {
         let x = r#"
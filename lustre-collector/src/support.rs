@@ -0,0 +1,83 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! What this crate knows how to parse, so a Lustre release can be checked
+//! against it before reporting a missing metric.
+
+use crate::{
+    ldlm::ldlm_namespace_parser::LDLM_STATS,
+    mds::mds_parser::MDT_STATS,
+    mgs::mgs_fs_parser,
+    oss::{obdfilter_parser::OBD_STATS, oss_parser::OST_STATS},
+    parser,
+    quota::quota_parser::QMT_STATS,
+    recovery_status_parser,
+    top_level_parser::TOP_LEVEL_PARAMS,
+};
+use std::collections::BTreeMap;
+
+/// Lustre releases the fixtures in `src/fixtures/valid` were captured from.
+pub const TESTED_VERSIONS: [&str; 4] = ["2.14.0", "2.14.0_ddn133", "2.14.0_ddn145", "6.2.0-r9"];
+
+#[derive(Debug, serde::Serialize)]
+pub struct SupportMatrix {
+    pub versions: Vec<&'static str>,
+    /// The `lctl get_param` globs that are read
+    pub params: Vec<String>,
+    /// The stats read for each kind of target or service
+    pub stats: BTreeMap<&'static str, Vec<&'static str>>,
+}
+
+pub fn support_matrix() -> SupportMatrix {
+    let params = parser::params()
+        .into_iter()
+        .chain(recovery_status_parser::params())
+        .chain(mgs_fs_parser::params())
+        .collect();
+
+    let stats = [
+        ("host", &TOP_LEVEL_PARAMS[..]),
+        ("ldlm", &LDLM_STATS[..]),
+        ("mdt", &MDT_STATS[..]),
+        ("ost", &OBD_STATS[..]),
+        ("oss", &OST_STATS[..]),
+        ("qmt", &QMT_STATS[..]),
+    ]
+    .into_iter()
+    .map(|(kind, xs)| (kind, xs.to_vec()))
+    .collect();
+
+    SupportMatrix {
+        versions: TESTED_VERSIONS.to_vec(),
+        params,
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{support_matrix, TESTED_VERSIONS};
+    use include_dir::{include_dir, Dir};
+
+    static VALID_FIXTURES: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/src/fixtures/valid/");
+
+    #[test]
+    fn test_tested_versions_have_fixtures() {
+        for version in TESTED_VERSIONS {
+            assert!(
+                !VALID_FIXTURES
+                    .find(&format!("**/*{version}*"))
+                    .unwrap()
+                    .collect::<Vec<_>>()
+                    .is_empty(),
+                "No fixture for {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_support_matrix() {
+        insta::assert_debug_snapshot!(support_matrix());
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! The `threads_min`, `threads_max` and `threads_started` params of the
+//! ptlrpc services under `ost.OSS` and `mds.MDS`.
+
+use crate::{
+    base_parsers::{digits, param},
+    mgs::mgs_parser::{THREADS_MAX, THREADS_MIN, THREADS_STARTED},
+    types::{Param, Target, TargetStat, TargetStats, TargetVariant},
+};
+use combine::{choice, error::ParseError, parser::char::newline, stream::Stream, Parser};
+
+pub(crate) const THREADS: [&str; 3] = [THREADS_MIN, THREADS_MAX, THREADS_STARTED];
+
+pub(crate) enum Threads {
+    Min(u64),
+    Max(u64),
+    Started(u64),
+}
+
+/// Parses `threads_min=4`, `threads_max=` or `threads_started=` of a service.
+pub(crate) fn threads<I>() -> impl Parser<I, Output = Threads>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    choice((
+        param(THREADS_MIN).with(digits().map(Threads::Min)),
+        param(THREADS_MAX).with(digits().map(Threads::Max)),
+        param(THREADS_STARTED).with(digits().map(Threads::Started)),
+    ))
+    .skip(newline())
+    .message("while parsing service threads")
+}
+
+/// The record of a thread count of `service`. The param is prefixed with the
+/// service, like `ost_io.threads_started`.
+pub(crate) fn threads_stat(
+    kind: TargetVariant,
+    target: Target,
+    service: &str,
+    x: Threads,
+) -> TargetStats {
+    let stat = |param: &str, value| TargetStat {
+        kind,
+        target,
+        param: Param(format!("{service}.{param}")),
+        value,
+    };
+
+    match x {
+        Threads::Min(x) => TargetStats::ThreadsMin(stat(THREADS_MIN, x)),
+        Threads::Max(x) => TargetStats::ThreadsMax(stat(THREADS_MAX, x)),
+        Threads::Started(x) => TargetStats::ThreadsStarted(stat(THREADS_STARTED, x)),
+    }
+}
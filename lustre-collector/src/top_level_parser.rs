@@ -3,7 +3,7 @@
 // license that can be found in the LICENSE file.
 
 use crate::{
-    base_parsers::{digits, param, target},
+    base_parsers::{digits, param, target, till_newline},
     types::{HostStat, HostStats, Param, Record},
     HealthCheckStat, Target,
 };
@@ -20,18 +20,21 @@ pub(crate) const MEMUSED_MAX: &str = "memused_max";
 pub(crate) const MEMUSED: &str = "memused";
 pub(crate) const LNET_MEMUSED: &str = "lnet_memused";
 pub(crate) const HEALTH_CHECK: &str = "health_check";
+pub(crate) const VERSION: &str = "version";
 
-pub(crate) const TOP_LEVEL_PARAMS: [&str; 4] = [MEMUSED, MEMUSED_MAX, LNET_MEMUSED, HEALTH_CHECK];
+pub(crate) const TOP_LEVEL_PARAMS: [&str; 5] =
+    [MEMUSED, MEMUSED_MAX, LNET_MEMUSED, HEALTH_CHECK, VERSION];
 
 pub(crate) fn top_level_params() -> Vec<String> {
     TOP_LEVEL_PARAMS.iter().map(|x| (*x).to_string()).collect()
 }
 
-enum TopLevelStat {
+pub(crate) enum TopLevelStat {
     Memused(u64),
     MemusedMax(u64),
     LnetMemused(u64),
     HealthCheck(HealthCheckStat),
+    Version(String),
 }
 
 fn target_health<I>() -> impl Parser<I, Output = Target>
@@ -104,6 +107,10 @@ where
             param(HEALTH_CHECK),
             health_stats().map(TopLevelStat::HealthCheck),
         ),
+        (
+            param(VERSION),
+            till_newline().map(|x| TopLevelStat::Version(x.trim().to_string())),
+        ),
     ))
     .skip(newline())
 }
@@ -114,16 +121,22 @@ where
     I::Error: ParseError<I::Token, I::Range, I::Position>,
 {
     top_level_stat()
-        .map(|(param, v)| match v {
-            TopLevelStat::Memused(value) => HostStats::Memused(HostStat { param, value }),
-            TopLevelStat::MemusedMax(value) => HostStats::MemusedMax(HostStat { param, value }),
-            TopLevelStat::LnetMemused(value) => HostStats::LNetMemUsed(HostStat { param, value }),
-            TopLevelStat::HealthCheck(value) => HostStats::HealthCheck(HostStat { param, value }),
-        })
-        .map(Record::Host)
+        .map(|(param, v)| record(param, v))
         .message("while parsing top_level_param")
 }
 
+pub(crate) fn record(param: Param, v: TopLevelStat) -> Record {
+    let x = match v {
+        TopLevelStat::Memused(value) => HostStats::Memused(HostStat { param, value }),
+        TopLevelStat::MemusedMax(value) => HostStats::MemusedMax(HostStat { param, value }),
+        TopLevelStat::LnetMemused(value) => HostStats::LNetMemUsed(HostStat { param, value }),
+        TopLevelStat::HealthCheck(value) => HostStats::HealthCheck(HostStat { param, value }),
+        TopLevelStat::Version(value) => HostStats::Version(HostStat { param, value }),
+    };
+
+    Record::Host(x)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -139,10 +152,27 @@ mod tests {
                 "memused_max".to_string(),
                 "lnet_memused".to_string(),
                 "health_check".to_string(),
+                "version".to_string(),
             ]
         )
     }
 
+    #[test]
+    fn test_version() {
+        let result = parse().parse("version=2.15.4\n");
+
+        assert_eq!(
+            result,
+            Ok((
+                Record::Host(HostStats::Version(HostStat {
+                    param: Param(VERSION.to_string()),
+                    value: "2.15.4".to_string()
+                })),
+                ""
+            ))
+        )
+    }
+
     #[test]
     fn test_row() {
         let result = parse().parse("memused_max=77991501\n");
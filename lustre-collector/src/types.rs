@@ -41,13 +41,13 @@ impl Deref for Param {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ReqsStat {
     pub samples: i64,
     pub unit: String,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct BytesStat {
     pub samples: i64,
     pub unit: String,
@@ -56,12 +56,20 @@ pub struct BytesStat {
     pub sum: i64,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ExportStats {
     pub nid: String,
     pub stats: Vec<Stat>,
 }
 
+/// Bytes of one kind of grant held by a single export: `dirty`, `granted` or `pending`.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ExportGrant {
+    pub nid: String,
+    pub kind: String,
+    pub bytes: u64,
+}
+
 /// Used to represent an unsigned timestamp in Lustre.
 ///
 /// Only use this field when you are sure that the timestamp is unsigned.
@@ -166,26 +174,28 @@ pub mod lnet_exports {
 
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct HealthStats {
+        /// Set once the NI has hit an unrecoverable error, missing before Lustre 2.15
+        pub fatal_error: Option<i64>,
         #[serde(rename = "health value")]
-        health_value: i64,
-        interrupts: i64,
-        dropped: i64,
-        aborted: i64,
+        pub health_value: i64,
+        pub interrupts: i64,
+        pub dropped: i64,
+        pub aborted: i64,
         #[serde(rename = "no route")]
-        no_route: i64,
-        timeouts: i64,
-        error: i64,
+        pub no_route: i64,
+        pub timeouts: i64,
+        pub error: i64,
     }
 
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct HealthStatsPeer {
         #[serde(rename = "health value")]
-        health_value: i64,
-        dropped: i64,
-        timeout: i64,
-        error: i64,
+        pub health_value: i64,
+        pub dropped: i64,
+        pub timeout: i64,
+        pub error: i64,
         #[serde(rename = "network timeout")]
-        network_timeout: i64,
+        pub network_timeout: i64,
     }
 
     #[derive(serde::Serialize, serde::Deserialize)]
@@ -199,9 +209,46 @@ pub mod lnet_exports {
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct Global {
         numa_range: Option<i64>,
-        max_intf: i64,
-        discovery: i64,
-        drop_asym_route: i64,
+        /// Renamed to `max_interfaces` in 2.13
+        max_intf: Option<i64>,
+        pub discovery: i64,
+        drop_asym_route: Option<i64>,
+    }
+
+    /// A User Defined Selection Policy, as listed by `lnetctl udsp show`.
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct Udsp {
+        pub idx: i64,
+        pub src: String,
+        pub dst: String,
+        pub rte: String,
+        /// A single action, such as `priority` or `pref`
+        pub action: HashMap<String, serde_yaml::Value>,
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct Route {
+        pub net: String,
+        pub gateway: String,
+        pub hop: i64,
+        pub priority: i64,
+        pub state: String,
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct RouterBufferPool {
+        pub npages: i64,
+        pub nbuffers: i64,
+        pub credits: i64,
+        pub mincredits: i64,
+    }
+
+    /// An entry of `lnetctl routing show`, either whether routing is enabled or the buffers of a CPT.
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(untagged)]
+    pub enum Routing {
+        Enable { enable: i64 },
+        Cpt(HashMap<String, HashMap<String, RouterBufferPool>>),
     }
 
     #[derive(serde::Serialize, serde::Deserialize)]
@@ -210,27 +257,30 @@ pub mod lnet_exports {
         pub primary_nid: String,
         #[serde(rename = "Multi-Rail")]
         pub multi_rail: String,
+        /// The `LNET_PEER_*` flags of the peer, see lib-types.h
+        #[serde(rename = "peer state")]
+        pub peer_state: Option<u64>,
         #[serde(rename = "peer ni")]
         pub peer_ni: Vec<PeerNi>,
     }
 
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct PeerNi {
-        nid: String,
-        state: String,
-        max_ni_tx_credits: i64,
-        available_tx_credits: i64,
-        min_tx_credits: i64,
-        tx_q_num_of_buf: i64,
-        available_rtr_credits: i64,
-        min_rtr_credits: i64,
-        refcount: i64,
-        statistics: LNetStatistics,
-        sent_stats: Stats,
-        received_stats: Stats,
-        dropped_stats: Stats,
+        pub nid: String,
+        pub state: String,
+        pub max_ni_tx_credits: i64,
+        pub available_tx_credits: i64,
+        pub min_tx_credits: i64,
+        pub tx_q_num_of_buf: i64,
+        pub available_rtr_credits: i64,
+        pub min_rtr_credits: i64,
+        pub refcount: i64,
+        pub statistics: LNetStatistics,
+        pub sent_stats: Stats,
+        pub received_stats: Stats,
+        pub dropped_stats: Stats,
         #[serde(rename = "health stats")]
-        health_stats: HealthStatsPeer,
+        pub health_stats: HealthStatsPeer,
     }
 
     #[derive(serde::Serialize, serde::Deserialize)]
@@ -277,7 +327,7 @@ pub mod lnet_exports {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Stat {
     pub name: String,
     pub units: String,
@@ -288,7 +338,7 @@ pub struct Stat {
     pub sumsquare: Option<u64>,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// A Stat specific to a host.
 pub struct HostStat<T> {
     pub param: Param,
@@ -324,7 +374,7 @@ impl Deref for TargetVariant {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Stats specific to a target.
 pub struct TargetStat<T> {
     pub kind: TargetVariant,
@@ -333,14 +383,88 @@ pub struct TargetStat<T> {
     pub value: T,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+/// The kind of client obd an import belongs to
+#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize, Clone, Copy)]
+pub enum ImportVariant {
+    Mdc,
+    Osc,
+    Mgc,
+}
+
+impl fmt::Display for ImportVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl Deref for ImportVariant {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        match *self {
+            ImportVariant::Mdc => "mdc",
+            ImportVariant::Osc => "osc",
+            ImportVariant::Mgc => "mgc",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// Stats of a client import, like `mdc.fs-MDT0000-mdc-ffff8b4d.srpc_info`
+pub struct ImportStat<T> {
+    pub kind: ImportVariant,
+    pub param: Param,
+    pub target: Target,
+    pub value: T,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// The security flavor an import sends RPCs with, from `srpc_info`
+pub struct SrpcInfo {
+    /// `null`, `plain`, `krb5n`, `krb5a`, `krb5i`, `krb5p`, `skn`, `ska`, `ski` or `skpi`
+    pub rpc_flavor: String,
+    pub bulk_flavor: String,
+    /// Comma separated flags like `rootonly,bulk,`, `-,` if none are set
+    pub flags: String,
+    /// Security contexts currently held by the import
+    pub contexts: u64,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// The connection state of an import, from its `import` param
+pub struct ImportState {
+    /// The server target the import connects to, like `fs-OST0000`
+    pub remote: Target,
+    /// `FULL` once connected, otherwise `DISCONN`, `CONNECTING`, `EVICTED`, `IDLE` and the like
+    pub state: String,
+    pub connect_flags: Vec<String>,
+    /// The NID of the server the import is currently connected through
+    pub current_connection: Option<String>,
+    /// Times the import has tried to (re)connect to the server
+    pub connection_attempts: u64,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// RPCs of an `osc` import, from its `rpc_stats` param
+pub struct OscRpcStats {
+    pub snapshot_time: String,
+    pub read_rpcs_in_flight: u64,
+    pub write_rpcs_in_flight: u64,
+    /// Dirty pages waiting to be sent
+    pub pending_write_pages: u64,
+    pub pending_read_pages: u64,
+    /// The `pages` per RPC, `rpcs_in_flight` when each RPC was sent and `offset` histograms
+    pub histograms: Vec<BrwStats>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Stats from parsing `ost.OSS.<PARAM>.stats`
 pub struct OssStat {
     pub param: Param,
     pub stats: Vec<Stat>,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Stats from parsing `llite.*.stats`
 pub struct LliteStat {
     pub target: Target,
@@ -348,14 +472,23 @@ pub struct LliteStat {
     pub stats: Vec<Stat>,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// Stats from parsing `llite.*.max_cached_mb` and `llite.*.unstable_stats`,
+/// which are lists of `name: value` pairs
+pub struct LliteCacheStat {
+    pub target: Target,
+    pub param: Param,
+    pub values: Vec<(String, u64)>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Stats from parsing `mds.MDS.<PARAM>.stats`
 pub struct MdsStat {
     pub param: Param,
     pub stats: Vec<Stat>,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Stats specific to a quota target.
 pub struct TargetQuotaStat<T> {
     pub pool: String,
@@ -365,7 +498,7 @@ pub struct TargetQuotaStat<T> {
     pub value: T,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Stats specific to a LNet Nid.
 pub struct LNetStat<T> {
     pub nid: String,
@@ -373,21 +506,57 @@ pub struct LNetStat<T> {
     pub value: T,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Stats global across LNet Nids.
 pub struct LNetStatGlobal<T> {
     pub param: Param,
     pub value: T,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// A route to a remote net through a gateway.
+pub struct LNetRoute {
+    pub net: String,
+    pub gateway: String,
+    pub hop: i64,
+    pub priority: i64,
+    /// Whether the gateway is up
+    pub up: bool,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// The router buffers of one size, summed over the CPTs of a router.
+pub struct LNetRouterBuffers {
+    /// `tiny`, `small` or `large`
+    pub size: String,
+    pub buffers: i64,
+    pub credits: i64,
+    pub min_credits: i64,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// The number of UDSPs with an action, such as `priority`.
+pub struct LNetUdspPolicies {
+    pub action: String,
+    pub count: u64,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+/// Stats specific to a nodemap.
+pub struct NodemapStat<T> {
+    pub nodemap: String,
+    pub param: Param,
+    pub value: T,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Changelog stats from parsing `mdd.*.changelog_users`.
 pub struct ChangelogStat {
     pub current_index: u64,
     pub users: Vec<ChangeLogUser>,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ChangeLogUser {
     pub user: String,
     pub index: u64,
@@ -417,14 +586,14 @@ impl TryFrom<&Target> for TargetVariant {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct BrwStatsBucket {
     pub name: u64,
     pub read: u64,
     pub write: u64,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct BrwStats {
     pub name: String,
     pub unit: String,
@@ -441,28 +610,44 @@ pub enum RecoveryStatus {
     Unknown,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum HostStats {
     MemusedMax(HostStat<u64>),
     Memused(HostStat<u64>),
     LNetMemUsed(HostStat<u64>),
     HealthCheck(HostStat<HealthCheckStat>),
+    Devices(HostStat<Vec<Device>>),
+    /// The Lustre release the loaded modules are from
+    Version(HostStat<String>),
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+/// A Lustre device configured on the node, as listed by `lctl dl`.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Device {
+    pub index: u64,
+    /// `UP`, or `ST` while the device is stopping
+    pub status: String,
+    /// The device type, e.g. `osd-ldiskfs` or `mdt`
+    pub kind: String,
+    pub name: String,
+    pub uuid: String,
+    pub refcount: u64,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct HealthCheckStat {
     pub healthy: bool,
     pub targets: Vec<Target>,
 }
 
 /// A Stat specific to a node.
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct NodeStat<T> {
     pub param: Param,
     pub value: T,
 }
 /// Top level node stats (not directly Lustre related)
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum NodeStats {
     CpuUser(NodeStat<u64>),
     CpuSystem(NodeStat<u64>),
@@ -477,8 +662,48 @@ pub enum NodeStats {
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct FsName(pub String);
 
+impl Deref for FsName {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A Stat specific to a filesystem registered with an MGS.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct MgsFsStat<T> {
+    pub target: Target,
+    pub fs: FsName,
+    pub param: Param,
+    pub value: T,
+}
+
+/// The members of an OST pool, as listed by an MDT or client `lov` device.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PoolStat {
+    /// The `lod` or `lov` device listing the pool
+    pub target: Target,
+    pub fs: FsName,
+    pub param: Param,
+    pub pool: String,
+    /// The OSTs in the pool
+    pub members: Vec<Target>,
+}
+
+/// An object precreation stat of the `osp` device an MDT uses to reach an OST.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OspStat<T> {
+    /// The OST objects are precreated on
+    pub target: Target,
+    /// The MDT the `osp` device belongs to
+    pub mdt: Target,
+    pub param: Param,
+    pub value: T,
+}
+
 /// The target stats currently collected
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum TargetStats {
     /// Operations per OST. Read and write data is particularly interesting
     Stats(TargetStat<Vec<Stat>>),
@@ -499,12 +724,28 @@ pub enum TargetStats {
     TotDirty(TargetStat<u64>),
     TotGranted(TargetStat<u64>),
     TotPending(TargetStat<u64>),
+    /// Maximum bulk RPC size in MiB
+    BrwSize(TargetStat<u64>),
+    /// A performance tunable, like `max_dirty_mb` or `sync_journal`, named by its param
+    Tunable(TargetStat<u64>),
+    /// Whether the ZFS backed target is on non-rotational storage
+    ZfsNonrotational(TargetStat<bool>),
     ContendedLocks(TargetStat<u64>),
     ContentionSeconds(TargetStat<u64>),
     ConnectedClients(TargetStat<u64>),
     CtimeAgeLimit(TargetStat<u64>),
     EarlyLockCancel(TargetStat<u64>),
     FsNames(TargetStat<Vec<FsName>>),
+    /// Imperative recovery state of a filesystem, e.g. full or partial
+    MgsIrState(MgsFsStat<String>),
+    /// Clients of a filesystem that don't support imperative recovery
+    MgsIrNonirClients(MgsFsStat<u64>),
+    MgsNidtblVersion(MgsFsStat<u64>),
+    MgsIrNotifyCount(MgsFsStat<u64>),
+    /// Number of OST pools configured for a filesystem
+    MgsPoolCount(MgsFsStat<u64>),
+    /// The OSTs in an OST pool
+    Pool(PoolStat),
     LockCount(TargetStat<u64>),
     LockTimeouts(TargetStat<u64>),
     LockUnusedCount(TargetStat<u64>),
@@ -522,39 +763,419 @@ pub enum TargetStats {
     RecoveryCompletedClients(TargetStat<u64>),
     RecoveryEvictedClients(TargetStat<u64>),
     Llite(LliteStat),
+    LliteCache(LliteCacheStat),
     ExportStats(TargetStat<Vec<ExportStats>>),
+    /// Grant bytes of an OST export
+    ExportGrant(TargetStat<ExportGrant>),
+    /// The `snapshot_time` of a stats param, in milliseconds since the epoch
+    SnapshotTime(TargetStat<u64>),
+    /// Files currently open on an MDT
+    OpenFiles(TargetStat<u64>),
+    /// Clients evicted by an MDT since it was mounted
+    Evictions(TargetStat<u64>),
+    /// A `pool.*` stat of the lock namespace of a target, named by its param
+    LdlmPool(TargetStat<u64>),
     Mds(MdsStat),
     Changelog(TargetStat<ChangelogStat>),
     QuotaStats(TargetQuotaStat<QuotaStats>),
     QuotaStatsOsd(TargetStat<QuotaStatsOsd>),
+    QuotaEnforcement(TargetStat<QuotaEnforcement>),
+    /// Security flavor of a client import
+    SrpcInfo(ImportStat<SrpcInfo>),
+    /// Connection state of a client import
+    Import(ImportStat<ImportState>),
+    /// RPCs in flight and histograms of the RPCs sent by an `osc` import
+    OscRpcStats(ImportStat<OscRpcStats>),
+    /// A stat of the lock namespace of a client import, like `lru_size`, named by its param
+    ImportLdlm(ImportStat<u64>),
+    /// Object precreation state of an OST as seen by an MDT, like `prealloc_status`, named by its param
+    OspPrealloc(OspStat<i64>),
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum LNetStats {
     SendCount(LNetStat<i64>),
     RecvCount(LNetStat<i64>),
     DropCount(LNetStat<i64>),
+    NiHealthValue(LNetStat<i64>),
+    /// Whether the local NI has hit an unrecoverable error
+    NiFatalError(LNetStat<i64>),
+    NiInterrupts(LNetStat<i64>),
+    NiDropped(LNetStat<i64>),
+    NiTimeouts(LNetStat<i64>),
+    /// Send credits of the local NI, shared out over its CPTs
+    NiCredits(LNetStat<i64>),
+    NiPeerCredits(LNetStat<i64>),
+    NiPeerBufferCredits(LNetStat<i64>),
     SendLength(LNetStatGlobal<i64>),
     RecvLength(LNetStatGlobal<i64>),
     DropLength(LNetStatGlobal<i64>),
+    PeerSendCount(LNetStat<i64>),
+    PeerRecvCount(LNetStat<i64>),
+    PeerDropCount(LNetStat<i64>),
+    PeerHealthValue(LNetStat<i64>),
+    PeerMaxTxCredits(LNetStat<i64>),
+    PeerAvailableTxCredits(LNetStat<i64>),
+    PeerMinTxCredits(LNetStat<i64>),
+    PeerAvailableRtrCredits(LNetStat<i64>),
+    PeerMinRtrCredits(LNetStat<i64>),
+    Route(LNetRoute),
+    /// Whether the node routes between its nets
+    RoutingEnabled(LNetStatGlobal<bool>),
+    RouterBuffers(LNetRouterBuffers),
+    /// Whether peers are discovered, from `lnetctl global show`
+    DiscoveryEnabled(LNetStatGlobal<bool>),
+    /// Discovery state of a peer: `discovered`, `discovering`, `failed`, `disabled` or `undiscovered`
+    PeerDiscoveryState(LNetStat<String>),
+    UdspPolicies(LNetUdspPolicies),
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum LustreServiceStats {
     LdlmCanceld(Vec<Stat>),
     LdlmCbd(Vec<Stat>),
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum NodemapStats {
+    /// Whether nodemap enforcement is enabled
+    Active(HostStat<bool>),
+    Id(NodemapStat<u64>),
+    AdminNodemap(NodemapStat<bool>),
+    TrustedNodemap(NodemapStat<bool>),
+    SquashUid(NodemapStat<u64>),
+    SquashGid(NodemapStat<u64>),
+}
+
+/// A Stat specific to a ZFS pool.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ZpoolStat<T> {
+    pub pool: String,
+    pub param: Param,
+    pub value: T,
+}
+
+/// ZFS stats shared by every target on the node
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum ZfsStats {
+    ArcHits(NodeStat<u64>),
+    ArcMisses(NodeStat<u64>),
+    /// Current size of the ARC in bytes
+    ArcSize(NodeStat<u64>),
+    /// Maximum size of the ARC in bytes
+    ArcMaxSize(NodeStat<u64>),
+    /// Free space fragmentation of the pool in percent
+    PoolFragmentation(ZpoolStat<u64>),
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum Record {
     Host(HostStats),
     LNetStat(LNetStats),
     LustreService(LustreServiceStats),
     Node(NodeStats),
+    Nodemap(NodemapStats),
     Target(TargetStats),
+    Zfs(ZfsStats),
+}
+
+/// Tells the series records are exported as apart by everything but their
+/// value, so the same stat read twice, e.g. under an old and a new proc path,
+/// can be exported once.
+pub trait Series {
+    /// The name of the stat, which tells apart the variants of an enum of stats.
+    fn stat(&self) -> &'static str {
+        ""
+    }
+
+    /// The labels of the series, in a fixed order.
+    fn labels(&self) -> Vec<&str> {
+        vec![]
+    }
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+/// Implements [`Series`] for stats labelled by `fields`, and for those
+/// wrapping a `value` by the labels of the value after them.
+macro_rules! labelled {
+    ($($t:ident<T> { $($field:ident),* }),* $(,)?) => {
+        $(impl<T: Series> Series for $t<T> {
+            fn labels(&self) -> Vec<&str> {
+                let mut xs: Vec<&str> = vec![$(&*self.$field),*];
+
+                xs.extend(self.value.labels());
+
+                xs
+            }
+        })*
+    };
+    ($($t:ident { $($field:ident),* }),* $(,)?) => {
+        $(impl Series for $t {
+            fn labels(&self) -> Vec<&str> {
+                vec![$(&*self.$field),*]
+            }
+        })*
+    };
+}
+
+/// Implements [`Series`] for enums of stats, naming each stat by its variant.
+///
+/// Every variant has to be listed, so a new one does not build until it is.
+macro_rules! variants {
+    ($($t:ident { $($variant:ident),* $(,)? })*) => {
+        $(impl Series for $t {
+            fn stat(&self) -> &'static str {
+                match self {
+                    $(Self::$variant(_) => stringify!($variant)),*
+                }
+            }
+
+            fn labels(&self) -> Vec<&str> {
+                match self {
+                    $(Self::$variant(x) => x.labels()),*
+                }
+            }
+        })*
+    };
+}
+
+/// Values without labels of their own.
+macro_rules! unlabelled {
+    ($($t:ty),* $(,)?) => {
+        $(impl Series for $t {})*
+    };
+}
+
+unlabelled!(
+    u64,
+    i64,
+    bool,
+    String,
+    Stat,
+    BrwStats,
+    FsName,
+    Device,
+    HealthCheckStat,
+    RecoveryStatus,
+    ChangelogStat,
+    QuotaEnforcement,
+    SrpcInfo,
+    ImportState,
+    OscRpcStats,
+);
+
+impl<T: Series> Series for Vec<T> {
+    fn labels(&self) -> Vec<&str> {
+        self.iter().flat_map(Series::labels).collect()
+    }
+}
+
+labelled!(
+    HostStat<T> { param },
+    NodeStat<T> { param },
+    TargetStat<T> { kind, target, param },
+    ImportStat<T> { kind, target, param },
+    TargetQuotaStat<T> { pool, manager, target, param },
+    MgsFsStat<T> { target, fs, param },
+    OspStat<T> { target, mdt, param },
+    LNetStat<T> { nid, param },
+    LNetStatGlobal<T> { param },
+    NodemapStat<T> { nodemap, param },
+    ZpoolStat<T> { pool, param },
+);
+
+labelled!(
+    ExportStats { nid },
+    ExportGrant { nid, kind },
+    PoolStat { target, fs, param, pool },
+    OssStat { param },
+    MdsStat { param },
+    LliteStat { target, param },
+    LliteCacheStat { target, param },
+    LNetRoute { net, gateway },
+    LNetRouterBuffers { size },
+    LNetUdspPolicies { action },
+);
+
+fn quota_kind(kind: &QuotaKind) -> &'static str {
+    match kind {
+        QuotaKind::Usr => "usr",
+        QuotaKind::Grp => "grp",
+        QuotaKind::Prj => "prj",
+    }
+}
+
+impl Series for QuotaStats {
+    fn labels(&self) -> Vec<&str> {
+        vec![quota_kind(&self.kind)]
+    }
+}
+
+impl Series for QuotaStatsOsd {
+    fn labels(&self) -> Vec<&str> {
+        vec![quota_kind(&self.kind)]
+    }
+}
+
+variants! {
+    HostStats {
+        MemusedMax,
+        Memused,
+        LNetMemUsed,
+        HealthCheck,
+        Devices,
+        Version,
+    }
+    NodeStats {
+        CpuUser,
+        CpuSystem,
+        CpuIowait,
+        CpuTotal,
+        MemTotal,
+        MemFree,
+        SwapTotal,
+        SwapFree,
+    }
+    TargetStats {
+        Stats,
+        BrwStats,
+        FilesFree,
+        FilesTotal,
+        FsType,
+        KBytesAvail,
+        KBytesFree,
+        KBytesTotal,
+        NumExports,
+        TotDirty,
+        TotGranted,
+        TotPending,
+        BrwSize,
+        Tunable,
+        ZfsNonrotational,
+        ContendedLocks,
+        ContentionSeconds,
+        ConnectedClients,
+        CtimeAgeLimit,
+        EarlyLockCancel,
+        FsNames,
+        MgsIrState,
+        MgsIrNonirClients,
+        MgsNidtblVersion,
+        MgsIrNotifyCount,
+        MgsPoolCount,
+        Pool,
+        LockCount,
+        LockTimeouts,
+        LockUnusedCount,
+        LruMaxAge,
+        LruSize,
+        MaxNolockBytes,
+        MaxParallelAst,
+        ResourceCount,
+        ThreadsMin,
+        ThreadsMax,
+        ThreadsStarted,
+        RecoveryStatus,
+        Oss,
+        RecoveryConnectedClients,
+        RecoveryCompletedClients,
+        RecoveryEvictedClients,
+        Llite,
+        LliteCache,
+        ExportStats,
+        ExportGrant,
+        SnapshotTime,
+        OpenFiles,
+        Evictions,
+        LdlmPool,
+        Mds,
+        Changelog,
+        QuotaStats,
+        QuotaStatsOsd,
+        QuotaEnforcement,
+        SrpcInfo,
+        Import,
+        OscRpcStats,
+        ImportLdlm,
+        OspPrealloc,
+    }
+    LNetStats {
+        SendCount,
+        RecvCount,
+        DropCount,
+        NiHealthValue,
+        NiFatalError,
+        NiInterrupts,
+        NiDropped,
+        NiTimeouts,
+        NiCredits,
+        NiPeerCredits,
+        NiPeerBufferCredits,
+        SendLength,
+        RecvLength,
+        DropLength,
+        PeerSendCount,
+        PeerRecvCount,
+        PeerDropCount,
+        PeerHealthValue,
+        PeerMaxTxCredits,
+        PeerAvailableTxCredits,
+        PeerMinTxCredits,
+        PeerAvailableRtrCredits,
+        PeerMinRtrCredits,
+        Route,
+        RoutingEnabled,
+        RouterBuffers,
+        DiscoveryEnabled,
+        PeerDiscoveryState,
+        UdspPolicies,
+    }
+    LustreServiceStats {
+        LdlmCanceld,
+        LdlmCbd,
+    }
+    NodemapStats {
+        Active,
+        Id,
+        AdminNodemap,
+        TrustedNodemap,
+        SquashUid,
+        SquashGid,
+    }
+    ZfsStats {
+        ArcHits,
+        ArcMisses,
+        ArcSize,
+        ArcMaxSize,
+        PoolFragmentation,
+    }
+}
+
+impl Record {
+    fn stats(&self) -> &dyn Series {
+        match self {
+            Self::Host(x) => x,
+            Self::LNetStat(x) => x,
+            Self::LustreService(x) => x,
+            Self::Node(x) => x,
+            Self::Nodemap(x) => x,
+            Self::Target(x) => x,
+            Self::Zfs(x) => x,
+        }
+    }
+}
+
+/// A record is named and labelled by the stat it holds.
+impl Series for Record {
+    fn stat(&self) -> &'static str {
+        self.stats().stat()
+    }
+
+    fn labels(&self) -> Vec<&str> {
+        self.stats().labels()
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct QuotaStatLimits {
     pub hard: u64,
     pub soft: u64,
@@ -562,37 +1183,45 @@ pub struct QuotaStatLimits {
     pub time: u64,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct QuotaStatUsage {
     pub inodes: u64,
     pub kbytes: u64,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct QuotaStat {
     pub id: u64,
     pub limits: QuotaStatLimits,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct QuotaStatOsd {
     pub id: u64,
     pub usage: QuotaStatUsage,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct QuotaStats {
     pub kind: QuotaKind,
     pub stats: Vec<QuotaStat>,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct QuotaStatsOsd {
     pub kind: QuotaKind,
     pub stats: Vec<QuotaStatOsd>,
 }
 
-#[derive(PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+/// Which quota kinds a target enforces, from `quota_slave.info`
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct QuotaEnforcement {
+    pub usr: bool,
+    pub grp: bool,
+    pub prj: bool,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum QuotaKind {
     Usr,
     Grp,
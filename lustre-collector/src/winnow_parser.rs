@@ -0,0 +1,410 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! A winnow implementation of the `lctl get_param` pipeline in [`crate::parser`].
+//!
+//! The params that make up the bulk of large outputs, such as the stats of every export,
+//! are parsed here. Any other param is handed to the combine parser, so the two produce
+//! the same records, and the same leftover input or error for output that doesn't parse.
+//!
+//! Each param here must only be accepted where combine would accept it the same way,
+//! so these parsers give up on anything combine could treat differently.
+
+use crate::{
+    ldlm::LDLM,
+    llite::LLITE,
+    mdd_parser::MDD,
+    mds::{
+        client_count_parser,
+        mds_parser::MDS,
+        mdt_parser::{
+            self, MdtStat, EVICTION_COUNT, IDENTITY_ACQUIRE_EXPIRE, IDENTITY_EXPIRE, NUM_OPEN_FILES,
+        },
+    },
+    oss::{
+        obdfilter_parser::{
+            self, ObdfilterStat, BRW_SIZE, EXPORTS, MAX_DIRTY_MB, NUM_EXPORTS,
+            READCACHE_MAX_FILESIZE, STATS, SYNC_JOURNAL, TOT_DIRTY, TOT_GRANTED, TOT_PENDING,
+        },
+        oss_parser::OST,
+    },
+    parser,
+    quota::QMT,
+    top_level_parser::{
+        self, TopLevelStat, HEALTH_CHECK, LNET_MEMUSED, MEMUSED, MEMUSED_MAX, VERSION,
+    },
+    types::{Param, Record, Stat, Target},
+    ExportGrant, ExportStats, HealthCheckStat,
+};
+use combine::{easy, EasyParser as _};
+use winnow::{
+    combinator::{alt, cut_err, delimited, opt, peek, preceded, repeat, separated, terminated},
+    error::{ContextError, ErrMode},
+    token::{one_of, take_till, take_while},
+    ModalResult, Parser,
+};
+
+/// Words a stat can't be named, as they start the next param.
+const NOT_STATS: [&str; 9] = ["obdfilter", "mgs", "mdt", LDLM, OST, LLITE, MDS, MDD, QMT];
+
+/// Parses `lctl get_param` output like [`parser::parse_output`].
+pub(crate) fn parse(input: &str) -> Result<(Vec<Record>, &str), easy::Errors<char, &str, usize>> {
+    let mut records = vec![];
+    let mut rest = input;
+
+    loop {
+        let mut i = rest;
+
+        if let Ok(xs) = record(&mut i) {
+            records.extend(xs);
+            rest = i;
+
+            continue;
+        }
+
+        match combine::optional(parser::record()).easy_parse(rest) {
+            Ok((Some(xs), i)) => {
+                records.extend(xs);
+                rest = i;
+            }
+            Ok((None, _)) => return Ok((records, rest)),
+            Err(err) => return Err(err.map_position(|p| p.translate_position(input))),
+        }
+    }
+}
+
+fn record(input: &mut &str) -> ModalResult<Vec<Record>> {
+    alt((
+        top_level.map(|x| vec![x]),
+        connected_clients,
+        obdfilter,
+        mdt,
+    ))
+    .parse_next(input)
+}
+
+fn param<'a>(name: &'static str) -> impl Parser<&'a str, Param, ErrMode<ContextError>> {
+    terminated(name, '=').map(|x: &str| Param(x.to_string()))
+}
+
+fn digits(input: &mut &str) -> ModalResult<u64> {
+    take_while(1.., |c: char| c.is_ascii_digit())
+        .try_map(str::parse)
+        .parse_next(input)
+}
+
+fn spaces(input: &mut &str) -> ModalResult<()> {
+    take_while(0.., char::is_whitespace)
+        .void()
+        .parse_next(input)
+}
+
+fn word<'a>(input: &mut &'a str) -> ModalResult<&'a str> {
+    take_while(1.., |c: char| c.is_alphanumeric() || c == '_').parse_next(input)
+}
+
+fn target(input: &mut &str) -> ModalResult<Target> {
+    take_while(1.., |c: char| c.is_alphanumeric() || c == '_' || c == '-')
+        .map(|x: &str| Target(x.to_string()))
+        .parse_next(input)
+}
+
+fn nid(input: &mut &str) -> ModalResult<String> {
+    (
+        take_while(1.., |c: char| c.is_alphanumeric() || c == '.'),
+        '@',
+        take_while(1.., char::is_alphanumeric),
+    )
+        .map(|(ip, _, lnet)| format!("{ip}@{lnet}"))
+        .parse_next(input)
+}
+
+fn top_level(input: &mut &str) -> ModalResult<Record> {
+    terminated(
+        alt((
+            (param(MEMUSED), digits.map(TopLevelStat::Memused)),
+            (param(MEMUSED_MAX), digits.map(TopLevelStat::MemusedMax)),
+            (
+                param(LNET_MEMUSED),
+                // Counter can overflow and go negative.
+                (opt('-'), digits)
+                    .map(|(negative, x)| TopLevelStat::LnetMemused(negative.map_or(x, |_| 0))),
+            ),
+            (
+                param(HEALTH_CHECK),
+                health_stats.map(TopLevelStat::HealthCheck),
+            ),
+            (
+                param(VERSION),
+                take_till(0.., '\n').map(|x: &str| TopLevelStat::Version(x.trim().to_string())),
+            ),
+        )),
+        '\n',
+    )
+    .map(|(param, x)| top_level_parser::record(param, x))
+    .parse_next(input)
+}
+
+fn health_stats(input: &mut &str) -> ModalResult<HealthCheckStat> {
+    let target_health = delimited(
+        ("device", one_of(char::is_whitespace)),
+        target,
+        (one_of(char::is_whitespace), "reported unhealthy"),
+    );
+
+    alt((
+        "healthy".map(|_| (true, vec![])),
+        "LBUG".map(|_| (false, vec![])),
+        "NOT HEALTHY".map(|_| (false, vec![])),
+        terminated(repeat(1.., terminated(target_health, '\n')), "NOT HEALTHY")
+            .map(|xs| (false, xs)),
+    ))
+    .map(|(healthy, targets)| HealthCheckStat { healthy, targets })
+    .parse_next(input)
+}
+
+fn time<'a>(name: &'static str) -> impl Parser<&'a str, String, ErrMode<ContextError>> {
+    (
+        name,
+        opt(':'),
+        spaces,
+        terminated(digits, '.'),
+        terminated(digits, take_till(0.., '\n')),
+        '\n',
+    )
+        .map(|(_, _, _, secs, nsecs, _)| format!("{secs}.{nsecs}"))
+}
+
+/// A `name 10 samples [reqs] 1 1 10 10` line.
+fn stat(input: &mut &str) -> ModalResult<Stat> {
+    let (name, _, samples, _, _, _, units) = (
+        word,
+        spaces,
+        digits,
+        spaces,
+        "samples",
+        spaces,
+        delimited('[', word, ']'),
+    )
+        .parse_next(input)?;
+
+    let min_max_sum = (
+        preceded(spaces, digits),
+        preceded(spaces, digits),
+        preceded(spaces, digits),
+    );
+
+    let sum_sq = alt((
+        '\n'.map(|_| None),
+        terminated(preceded(spaces, digits), '\n').map(Some),
+    ));
+
+    let (min_max_sum, sumsquare) = alt((
+        '\n'.map(|_| (None, None)),
+        (min_max_sum, sum_sq).map(|(x, y)| (Some(x), y)),
+    ))
+    .parse_next(input)?;
+
+    Ok(Stat {
+        name: name.to_string(),
+        units: units.to_string(),
+        samples,
+        min: min_max_sum.map(|x| x.0),
+        max: min_max_sum.map(|x| x.1),
+        sum: min_max_sum.map(|x| x.2),
+        sumsquare,
+    })
+}
+
+/// A stats param, along with its `snapshot_time` in milliseconds since the epoch.
+fn timed_stats(input: &mut &str) -> ModalResult<(Option<u64>, Vec<Stat>)> {
+    opt('\n').parse_next(input)?;
+
+    let snapshot_time = time("snapshot_time").parse_next(input)?;
+
+    if opt(time("start_time")).parse_next(input)?.is_some() {
+        time("elapsed_time").parse_next(input)?;
+    }
+
+    let mut xs = vec![];
+
+    // Stats run up to a line that can't start one, anything else must be a stat.
+    loop {
+        match word.parse_peek(*input) {
+            Ok((_, x)) if !NOT_STATS.contains(&x) => xs.push(stat(input)?),
+            _ => break,
+        }
+    }
+
+    let millis = snapshot_time
+        .parse::<f64>()
+        .ok()
+        .map(|x| (x * 1_000.0) as u64);
+
+    Ok((millis, xs))
+}
+
+fn export_stats(input: &mut &str) -> ModalResult<ExportStats> {
+    (terminated(nid, ".stats="), timed_stats)
+        .map(|(nid, (_, stats))| ExportStats { nid, stats })
+        .parse_next(input)
+}
+
+fn export_grant(input: &mut &str) -> ModalResult<ExportGrant> {
+    (
+        terminated(nid, '.'),
+        terminated(alt(("dirty", "granted", "pending")), '='),
+        terminated(digits, '\n'),
+    )
+        .map(|(nid, kind, bytes)| ExportGrant {
+            nid,
+            kind: kind.to_string(),
+            bytes,
+        })
+        .parse_next(input)
+}
+
+fn u64_param<'a>(name: &'static str) -> impl Parser<&'a str, (Param, u64), ErrMode<ContextError>> {
+    (param(name), terminated(digits, '\n'))
+}
+
+fn obdfilter(input: &mut &str) -> ModalResult<Vec<Record>> {
+    let target = delimited("obdfilter.", target, '.').parse_next(input)?;
+
+    let (param, x) = alt((
+        (param(STATS), timed_stats)
+            .map(|(param, (time, xs))| (param, ObdfilterStat::Stats(time, xs))),
+        u64_param(NUM_EXPORTS).map(|(param, x)| (param, ObdfilterStat::NumExports(x))),
+        u64_param(TOT_DIRTY).map(|(param, x)| (param, ObdfilterStat::TotDirty(x))),
+        u64_param(TOT_GRANTED).map(|(param, x)| (param, ObdfilterStat::TotGranted(x))),
+        u64_param(TOT_PENDING).map(|(param, x)| (param, ObdfilterStat::TotPending(x))),
+        u64_param(BRW_SIZE).map(|(param, x)| (param, ObdfilterStat::BrwSize(x))),
+        alt((
+            u64_param(MAX_DIRTY_MB),
+            u64_param(READCACHE_MAX_FILESIZE),
+            u64_param(SYNC_JOURNAL),
+        ))
+        .map(|(param, x)| (param, ObdfilterStat::Tunable(x))),
+        (
+            terminated(EXPORTS, '.').map(|x: &str| Param(x.to_string())),
+            alt((
+                export_grant.map(ObdfilterStat::ExportGrant),
+                repeat(1.., export_stats).map(ObdfilterStat::ExportStats),
+            )),
+        ),
+    ))
+    .parse_next(input)?;
+
+    Ok(obdfilter_parser::records(target, param, x))
+}
+
+fn mdt(input: &mut &str) -> ModalResult<Vec<Record>> {
+    let target = delimited("mdt.", target, '.').parse_next(input)?;
+
+    let (param, x) = alt((
+        u64_param(mdt_parser::NUM_EXPORTS).map(|(param, x)| (param, MdtStat::NumExports(x))),
+        u64_param(NUM_OPEN_FILES).map(|(param, x)| (param, MdtStat::OpenFiles(x))),
+        u64_param(EVICTION_COUNT).map(|(param, x)| (param, MdtStat::Evictions(x))),
+        alt((
+            u64_param(IDENTITY_EXPIRE),
+            u64_param(IDENTITY_ACQUIRE_EXPIRE),
+        ))
+        .map(|(param, x)| (param, MdtStat::Tunable(x))),
+        (param(mdt_parser::STATS), timed_stats)
+            .map(|(param, (time, xs))| (param, MdtStat::Stats(time, xs))),
+        (
+            terminated(EXPORTS, '.').map(|x: &str| Param(x.to_string())),
+            repeat(1.., export_stats).map(MdtStat::ExportStats),
+        ),
+    ))
+    .parse_next(input)?;
+
+    Ok(mdt_parser::records(target, param, x))
+}
+
+/// A run of `mdt.*.exports.*.uuid` params, counted into the connected clients of each MDT.
+fn connected_clients(input: &mut &str) -> ModalResult<Vec<Record>> {
+    repeat(1.., interface_clients)
+        .map(client_count_parser::records)
+        .parse_next(input)
+}
+
+fn interface_clients(input: &mut &str) -> ModalResult<(String, u64)> {
+    let target = delimited(
+        "mdt.",
+        take_while(1.., |c: char| c.is_alphanumeric() || c == '_' || c == '-'),
+        ('.', EXPORTS, '.', nid, ".uuid="),
+    )
+    .parse_next(input)?;
+
+    let clients =
+        cut_err(alt((preceded('\n', clients), terminated(client, '\n')))).parse_next(input)?;
+
+    Ok((target.to_string(), clients))
+}
+
+/// Newline separated client uuids, which may end with a newline.
+fn clients(input: &mut &str) -> ModalResult<u64> {
+    let mut n = 0;
+
+    while let Some(x) = opt(client).parse_next(input)? {
+        n += x;
+
+        if opt('\n').parse_next(input)?.is_none() {
+            break;
+        }
+    }
+
+    Ok(n)
+}
+
+/// A client uuid, or the uuid of another target which isn't counted.
+fn client(input: &mut &str) -> ModalResult<u64> {
+    preceded(
+        separated::<_, _, (), _, _, _, _>(1.., take_while(1.., char::is_alphanumeric), '-'),
+        alt(("_UUID".map(|_| 0), peek('\n').map(|_| 1))),
+    )
+    .parse_next(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::parser;
+    use combine::EasyParser as _;
+    use include_dir::{include_dir, Dir};
+
+    static VALID_FIXTURES: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/src/fixtures/valid/");
+
+    #[test]
+    fn matches_combine_on_valid_fixtures() {
+        for file in VALID_FIXTURES.find("**/*.txt").unwrap() {
+            let Some(file) = file.as_file() else {
+                continue;
+            };
+
+            let contents = file.contents_utf8().unwrap();
+
+            let expected = parser::parse().easy_parse(contents).unwrap();
+
+            assert_eq!(
+                parse(contents).unwrap(),
+                expected,
+                "{}",
+                file.path().display()
+            );
+        }
+    }
+
+    #[test]
+    fn matches_combine_on_leftover_input() {
+        let xs = include_str!("./fixtures/vanished_params.txt");
+
+        let expected = parser::parse()
+            .easy_parse(xs)
+            .map_err(|err| err.map_position(|p| p.translate_position(xs)));
+
+        assert_eq!(parse(xs), expected);
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{
+    base_parsers::{digits, param, period, target},
+    types::{
+        NodeStat, Param, Record, Target, TargetStat, TargetStats, TargetVariant, ZfsStats,
+        ZpoolStat,
+    },
+    LustreCollectorError,
+};
+use combine::{
+    attempt,
+    error::{ParseError, StreamError},
+    parser::char::{newline, string},
+    stream::{Stream, StreamErrorFor},
+    Parser,
+};
+use std::str;
+
+pub(crate) const NONROTATIONAL: &str = "nonrotational";
+
+/// Path of the ZFS ARC kstats on Linux
+pub const ARCSTATS: &str = "/proc/spl/kstat/zfs/arcstats";
+
+/// Arguments to `zpool` that print the fragmentation of every pool
+pub const ZPOOL_FRAGMENTATION_ARGS: [&str; 4] = ["list", "-Hp", "-o", "name,fragmentation"];
+
+pub(crate) fn params() -> Vec<String> {
+    vec![format!("osd-zfs.*.{NONROTATIONAL}")]
+}
+
+fn target_and_variant<I>() -> impl Parser<I, Output = (Target, TargetVariant)>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        attempt(string("osd-zfs").skip(period())),
+        target().skip(period()),
+    )
+        .and_then(move |(_, x)| -> Result<_, _> {
+            let variant = match (&x).try_into() {
+                Ok(x) => x,
+                Err(e) => return Err(StreamErrorFor::<I>::other(e)),
+            };
+
+            Ok((x, variant))
+        })
+        .message("while parsing osd-zfs target_and_variant")
+}
+
+pub(crate) fn parse<I>() -> impl Parser<I, Output = Record>
+where
+    I: Stream<Token = char>,
+    I::Error: ParseError<I::Token, I::Range, I::Position>,
+{
+    (
+        // Other osd-zfs params are shared with ldiskfs and handled by the osd parser
+        attempt((target_and_variant(), param(NONROTATIONAL))),
+        digits().skip(newline()),
+    )
+        .map(|(((target, kind), param), value)| {
+            TargetStats::ZfsNonrotational(TargetStat {
+                kind,
+                target,
+                param,
+                value: value != 0,
+            })
+        })
+        .map(Record::Target)
+        .message("while parsing osd-zfs")
+}
+
+/// Parses the contents of `/proc/spl/kstat/zfs/arcstats`.
+///
+/// The first two lines are the kstat header and column names, the rest are `name type data`.
+pub fn parse_arcstats_output(output: &[u8]) -> Result<Vec<Record>, LustreCollectorError> {
+    let output = str::from_utf8(output)?;
+
+    let mut records = vec![];
+
+    for line in output.lines().skip(2) {
+        let mut xs = line.split_whitespace();
+
+        let (Some(name), Some(_), Some(value)) = (xs.next(), xs.next(), xs.next()) else {
+            continue;
+        };
+
+        let f = match name {
+            "hits" => ZfsStats::ArcHits,
+            "misses" => ZfsStats::ArcMisses,
+            "size" => ZfsStats::ArcSize,
+            "c_max" => ZfsStats::ArcMaxSize,
+            _ => continue,
+        };
+
+        let value = value.parse().map_err(|_| {
+            LustreCollectorError::ConversionError(format!("Could not parse arcstats {name}"))
+        })?;
+
+        records.push(Record::Zfs(f(NodeStat {
+            param: Param(name.to_string()),
+            value,
+        })));
+    }
+
+    Ok(records)
+}
+
+/// Parses the output of `zpool list -Hp -o name,fragmentation`.
+///
+/// Pools that don't report fragmentation show `-` and are skipped.
+pub fn parse_zpool_fragmentation_output(
+    output: &[u8],
+) -> Result<Vec<Record>, LustreCollectorError> {
+    let output = str::from_utf8(output)?;
+
+    let mut records = vec![];
+
+    for line in output.lines() {
+        let Some((pool, fragmentation)) = line.split_once('\t') else {
+            continue;
+        };
+
+        // Older zpool versions still print a trailing `%` with -p.
+        let fragmentation = fragmentation.trim().trim_end_matches('%');
+
+        if fragmentation == "-" {
+            continue;
+        }
+
+        let value = fragmentation.parse().map_err(|_| {
+            LustreCollectorError::ConversionError(format!(
+                "Could not parse fragmentation of pool {pool}"
+            ))
+        })?;
+
+        records.push(Record::Zfs(ZfsStats::PoolFragmentation(ZpoolStat {
+            pool: pool.to_string(),
+            param: Param("fragmentation".to_string()),
+            value,
+        })));
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use combine::{many, EasyParser};
+    use insta::assert_debug_snapshot;
+
+    #[test]
+    fn test_zfs_nonrotational() {
+        static FIXTURE: &str = r#"osd-zfs.fs-MDT0000.nonrotational=1
+osd-zfs.fs-OST0000.nonrotational=0
+"#;
+
+        let result = many::<Vec<_>, _, _>(parse())
+            .easy_parse(FIXTURE)
+            .map_err(|err| err.map_position(|p| p.translate_position(FIXTURE)))
+            .unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+
+    #[test]
+    fn test_arcstats() {
+        let result = parse_arcstats_output(include_bytes!("fixtures/arcstats.txt")).unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+
+    #[test]
+    fn test_zpool_fragmentation() {
+        let result =
+            parse_zpool_fragmentation_output(b"mdt0pool\t3\nost0pool\t27\nbootpool\t-\n").unwrap();
+
+        assert_debug_snapshot!(result);
+    }
+}
@@ -1,8 +1,16 @@
-use std::io::BufReader;
+use std::{fs::File, io::BufReader};
 
 use const_format::{formatcp, str_repeat};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use lustrefs_exporter::jobstats::jobstats_stream;
+use lustrefs_exporter::{
+    bench::{allocations, fixture_available, peak_rss_kb, CountingAlloc},
+    jobstats::{jobstats_stream, JobstatsOptions},
+};
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const DS86: &str = "fixtures/jobstats_only/ds86.txt";
 
 const JOBSTAT_JOB: &str = r#"
 - job_id:          "FAKE_JOB"
@@ -40,13 +48,51 @@ job_stats:{}"#,
 async fn parse_synthetic_yaml(input: &'static str) {
     let f = BufReader::with_capacity(128 * 1_024, input.as_bytes());
 
-    let (fut, mut rx) = jobstats_stream(f);
+    let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
+
+    while rx.recv().await.is_some() {}
+
+    fut.await.unwrap();
+}
+
+async fn parse_file(path: &str) {
+    let f = BufReader::with_capacity(128 * 1_024, File::open(path).unwrap());
+
+    let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
 
     while rx.recv().await.is_some() {}
 
     fut.await.unwrap();
 }
 
+fn criterion_benchmark_ds86(c: &mut Criterion) {
+    if !fixture_available(DS86) {
+        eprintln!("Skipping jobstats ds86, {DS86} is missing or has not been fetched from git-lfs");
+
+        return;
+    }
+
+    let rt = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+
+    let before = allocations();
+
+    rt.block_on(parse_file(DS86));
+
+    eprintln!(
+        "jobstats ds86: {} allocations, peak RSS {} kB",
+        allocations() - before,
+        peak_rss_kb().unwrap_or_default()
+    );
+
+    let mut group = c.benchmark_group("jobstats ds86");
+
+    group.sample_size(10);
+    group.bench_function("parse", |b| {
+        b.to_async(&rt).iter(|| black_box(parse_file(DS86)))
+    });
+    group.finish();
+}
+
 fn criterion_benchmark_fast(c: &mut Criterion) {
     c.bench_function("jobstats 100", |b| {
         b.to_async(tokio::runtime::Builder::new_multi_thread().build().unwrap())
@@ -60,6 +106,6 @@ fn criterion_benchmark_fast(c: &mut Criterion) {
 criterion_group! {
     name = benches;
     config = Criterion::default();
-    targets = criterion_benchmark_fast
+    targets = criterion_benchmark_fast, criterion_benchmark_ds86
 }
 criterion_main!(benches);
@@ -0,0 +1,43 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Captures what the exporter is built from for `lustre_exporter_build_info`.
+
+use std::{env, path::Path, process::Command};
+
+/// The trimmed stdout of `cmd`, if it ran successfully and printed anything.
+fn output(cmd: &mut Command) -> Option<String> {
+    let x = cmd.output().ok().filter(|x| x.status.success())?;
+    let x = String::from_utf8(x.stdout).ok()?;
+
+    Some(x.trim().to_string()).filter(|x| !x.is_empty())
+}
+
+fn main() {
+    // Release tarballs have no .git, so packaging can pass the commit in instead
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+
+    for x in ["../.git/HEAD", "../.git/logs/HEAD"] {
+        if Path::new(x).exists() {
+            println!("cargo:rerun-if-changed={x}");
+        }
+    }
+
+    let git_sha = env::var("GIT_SHA")
+        .ok()
+        .filter(|x| !x.is_empty())
+        .or_else(|| output(Command::new("git").args(["rev-parse", "--short=12", "HEAD"])))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let rustc = output(
+        Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string())).arg("--version"),
+    )
+    .unwrap_or_else(|| "unknown".to_string());
+
+    let profile = env::var("PROFILE").unwrap_or_else(|_| "unknown".to_string());
+
+    println!("cargo:rustc-env=LUSTREFS_EXPORTER_GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=LUSTREFS_EXPORTER_RUSTC={rustc}");
+    println!("cargo:rustc-env=LUSTREFS_EXPORTER_PROFILE={profile}");
+}
@@ -20,6 +20,7 @@ cargo build --release
 install -v -d %{buildroot}%{_bindir}
 install -v -d %{buildroot}%{_unitdir}
 install -v -m 0644 lustrefs_exporter.service %{buildroot}%{_unitdir}
+install -v -m 0644 lustrefs_exporter.socket %{buildroot}%{_unitdir}
 install -v ../target/release/lustrefs-exporter %{buildroot}%{_bindir}
 %{__ln_s} lustrefs-exporter %{buildroot}%{_bindir}/lustrefs_exporter
 
@@ -27,12 +28,13 @@ install -v ../target/release/lustrefs-exporter %{buildroot}%{_bindir}
 %{_bindir}/lustrefs-exporter
 %{_bindir}/lustrefs_exporter
 %{_unitdir}/lustrefs_exporter.service
+%{_unitdir}/lustrefs_exporter.socket
 
 %post
-%systemd_post %{name}.service
+%systemd_post %{name}.service %{name}.socket
 
 %preun
-%systemd_preun %{name}.service
+%systemd_preun %{name}.service %{name}.socket
 
 %postun
-%systemd_postun %{name}.service
+%systemd_postun %{name}.service %{name}.socket
@@ -0,0 +1,259 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Replays captured `lctl get_param` and jobstats output through the parse and
+//! encode path a scrape takes, for `lustrefs-exporter bench`.
+
+use crate::{
+    build_lustre_stats,
+    jobstats::{jobstats_stream, JobstatsOptions},
+    Error,
+};
+use lustre_collector::parse_lctl_output_lossy;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt, fs,
+    hint::black_box,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Counts allocations, so they can be reported next to the timings.
+/// Only counts once installed as the `#[global_allocator]`, which the
+/// exporter binary does with the `bench` feature.
+pub struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// The allocations made so far by [`CountingAlloc`].
+pub fn allocations() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// The peak resident set size of this process in kB, from `/proc/self/status`.
+pub fn peak_rss_kb() -> Option<u64> {
+    fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|x| x.strip_prefix("VmHWM:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Whether `path` holds the fixture itself rather than its git-lfs pointer.
+pub fn fixture_available(path: impl AsRef<Path>) -> bool {
+    let mut head = [0; 7];
+
+    fs::File::open(path)
+        .and_then(|mut x| x.read_exact(&mut head))
+        .is_ok()
+        && &head != b"version"
+}
+
+/// How one file fared over every replay.
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub bytes: usize,
+    pub elapsed: Duration,
+    /// `None` unless [`CountingAlloc`] is installed
+    pub allocations: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct BenchReport {
+    pub iterations: usize,
+    pub files: Vec<FileReport>,
+    /// Files that are git-lfs pointers which have not been fetched
+    pub skipped: Vec<PathBuf>,
+    pub peak_rss_kb: Option<u64>,
+}
+
+fn mib_per_sec(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON) / (1024.0 * 1024.0)
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let runs = self.iterations.max(1);
+
+        for x in &self.files {
+            write!(
+                f,
+                "{}: {:.1} MiB/s, {:?}",
+                x.path.display(),
+                mib_per_sec(x.bytes * runs, x.elapsed),
+                x.elapsed / runs as u32,
+            )?;
+
+            match x.allocations {
+                Some(allocations) => {
+                    writeln!(f, " and {} allocations per run", allocations / runs)?
+                }
+                None => writeln!(f, " per run")?,
+            }
+        }
+
+        for x in &self.skipped {
+            writeln!(f, "{}: skipped, not fetched from git-lfs", x.display())?;
+        }
+
+        let bytes = self.files.iter().map(|x| x.bytes).sum::<usize>();
+        let elapsed = self.files.iter().map(|x| x.elapsed).sum();
+
+        writeln!(
+            f,
+            "{} files of {bytes} bytes replayed {} times: {:.1} MiB/s, peak RSS {} kB",
+            self.files.len(),
+            self.iterations,
+            mib_per_sec(bytes * runs, elapsed),
+            self.peak_rss_kb.unwrap_or_default(),
+        )
+    }
+}
+
+/// Appends `path` to `files`, or every file under it if it is a directory.
+fn find_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+
+        return Ok(());
+    }
+
+    for x in fs::read_dir(path)? {
+        find_files(&x?.path(), files)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `contents` through the parse and encode path, returning the bytes encoded.
+///
+/// `job_stats` output is streamed as on `/metrics`, anything else is parsed as `lctl get_param` output.
+async fn replay(contents: &Arc<[u8]>) -> Result<usize, Error> {
+    let first_line = contents.split(|x| *x == b'\n').next().unwrap_or_default();
+
+    if !first_line.ends_with(b".job_stats=") {
+        let (records, _) = parse_lctl_output_lossy(contents)?;
+
+        return Ok(build_lustre_stats(records).len());
+    }
+
+    let (fut, mut rx) = jobstats_stream(
+        Cursor::new(Arc::clone(contents)),
+        JobstatsOptions::default(),
+    );
+
+    let mut bytes = 0;
+
+    while let Some(x) = rx.recv().await {
+        bytes += x.len();
+    }
+
+    fut.await?;
+
+    Ok(bytes)
+}
+
+/// Replays every file under `paths` `iterations` times.
+pub async fn run(paths: &[PathBuf], iterations: usize) -> Result<BenchReport, Error> {
+    let mut files = vec![];
+
+    for x in paths {
+        find_files(x, &mut files)?;
+    }
+
+    files.sort();
+
+    let mut report = BenchReport {
+        iterations,
+        files: vec![],
+        skipped: vec![],
+        peak_rss_kb: None,
+    };
+
+    for path in files {
+        if !fixture_available(&path) {
+            report.skipped.push(path);
+
+            continue;
+        }
+
+        let contents: Arc<[u8]> = fs::read(&path)?.into();
+
+        let before = allocations();
+        let start = Instant::now();
+
+        for _ in 0..iterations {
+            black_box(replay(&contents).await?);
+        }
+
+        report.files.push(FileReport {
+            path,
+            bytes: contents.len(),
+            elapsed: start.elapsed(),
+            allocations: cfg!(feature = "bench").then(|| allocations() - before),
+        });
+    }
+
+    report.peak_rss_kb = peak_rss_kb();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn replays_fixtures() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+        let report = run(
+            &[
+                dir.join("../lustre-collector/src/fixtures/valid/valid.txt"),
+                dir.join("fixtures/jobstats_only"),
+            ],
+            2,
+        )
+        .await
+        .unwrap();
+
+        assert!(!report.files.is_empty());
+        assert!(report.files.iter().all(|x| x.bytes > 0));
+        assert_eq!(
+            report.files.len() + report.skipped.len(),
+            1 + std::fs::read_dir(dir.join("fixtures/jobstats_only"))
+                .unwrap()
+                .count()
+        );
+    }
+}
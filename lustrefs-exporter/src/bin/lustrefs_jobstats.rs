@@ -0,0 +1,152 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Converts Lustre job_stats to the Prometheus text format once, without running the HTTP server.
+//!
+//! Meant to be run from cron, writing to stdout, a file, or a pushgateway.
+
+use clap::Parser;
+use lustrefs_exporter::{
+    jobstats::{jobstats_stream, lctl_jobstats, JobFilter, JobLimit, JobstatsOptions},
+    routes::Timeouts,
+    sink::parse_push_url,
+    Error,
+};
+use regex::Regex;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+#[derive(Debug, Parser)]
+pub struct CommandOpts {
+    /// Read job_stats from this file instead of running `lctl get_param`
+    #[clap(short, long)]
+    pub input: Option<PathBuf>,
+
+    /// Write the metrics to this file instead of stdout
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Push the metrics to this pushgateway, e.g. http://pushgateway:9091 or
+    /// https://pushgateway:9091
+    #[clap(long, env = "LUSTREFS_JOBSTATS_PUSHGATEWAY", value_parser = parse_push_url)]
+    pub pushgateway: Option<String>,
+
+    /// Job name to push the metrics under
+    #[clap(long, default_value = "lustrefs_jobstats")]
+    pub job: String,
+
+    /// Only export jobids matching this regex
+    #[clap(long, value_parser = Regex::new)]
+    pub filter: Option<Regex>,
+
+    /// Don't export jobids matching this regex
+    #[clap(long, value_parser = Regex::new)]
+    pub exclude: Option<Regex>,
+
+    /// Collapse the jobs past this many into a single _overflow job per target
+    #[clap(long)]
+    pub max_jobs: Option<usize>,
+
+    /// Seconds `lctl get_param` may run for before it is killed and the dump fails
+    #[clap(long, env = "LUSTREFS_JOBSTATS_DUMP_TIMEOUT", default_value_t = Timeouts::default().jobstats.as_secs())]
+    pub dump_timeout: u64,
+}
+
+async fn encode<R: BufRead + Send + 'static>(
+    reader: R,
+    filter: JobFilter,
+    max_jobs: Option<usize>,
+) -> Result<String, Error> {
+    let (handle, mut rx) = jobstats_stream(
+        reader,
+        JobstatsOptions {
+            filter,
+            limit: JobLimit::from(max_jobs),
+            ..Default::default()
+        },
+    );
+
+    let mut metrics = String::new();
+
+    while let Some(x) = rx.recv().await {
+        metrics.push_str(&x);
+    }
+
+    handle.await?;
+
+    Ok(metrics)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt().with_writer(io::stderr).init();
+
+    let opts = CommandOpts::parse();
+
+    let filter = JobFilter {
+        include: opts.filter.clone(),
+        exclude: opts.exclude.clone(),
+    };
+
+    let metrics = match &opts.input {
+        Some(path) => {
+            let reader = BufReader::with_capacity(128 * 1_024, File::open(path)?);
+
+            encode(reader, filter, opts.max_jobs).await?
+        }
+        None => {
+            let mut child = lctl_jobstats().spawn()?;
+
+            let reader = BufReader::with_capacity(
+                128 * 1_024,
+                child.stdout.take().ok_or(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "stdout missing for lctl jobstats call.",
+                ))?,
+            );
+
+            let timeout = Duration::from_secs(opts.dump_timeout);
+
+            match tokio::time::timeout(timeout, encode(reader, filter, opts.max_jobs)).await {
+                Ok(metrics) => {
+                    child.wait()?;
+
+                    metrics?
+                }
+                Err(_) => {
+                    child.kill()?;
+                    child.wait()?;
+
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("lctl jobstats did not finish within {timeout:?}"),
+                    )
+                    .into());
+                }
+            }
+        }
+    };
+
+    if let Some(url) = &opts.pushgateway {
+        let url = format!("{}/metrics/job/{}", url.trim_end_matches('/'), opts.job);
+
+        tokio::task::spawn_blocking(move || {
+            ureq::put(&url)
+                .set("Content-Type", "text/plain; version=0.0.4")
+                .send_string(&metrics)
+                .map_err(Box::new)
+        })
+        .await??;
+    } else if let Some(path) = &opts.output {
+        File::create(path)?.write_all(metrics.as_bytes())?;
+    } else {
+        io::stdout().lock().write_all(metrics.as_bytes())?;
+    }
+
+    Ok(())
+}
@@ -5,15 +5,27 @@
 use std::{collections::BTreeMap, ops::Deref};
 
 use lustre_collector::{
-    BrwStats, BrwStatsBucket, ChangeLogUser, ChangelogStat, OssStat, Stat, TargetStat, TargetStats,
+    BrwStats, ChangeLogUser, ChangelogStat, ImportStat, OssStat, Stat, TargetStat, TargetStats,
 };
-use prometheus_exporter_base::{prelude::*, Yes};
+use prometheus_exporter_base::prelude::*;
 
 use crate::{
-    llite::build_llite_stats,
-    quota::{build_ost_quota_stats, build_quota_stats},
-    stats::{build_export_stats, build_mds_stats, build_stats},
-    LabelProm, Metric, StatsMapExt, ToMetricInst,
+    grant::build_export_grant_stats,
+    import::build_import_stats,
+    llite::{build_llite_cache_stats, build_llite_stats},
+    mgs::{
+        build_mgs_fs_stats, build_mgs_ir_nonir_clients_stats, build_mgs_ir_notify_stats,
+        build_mgs_ir_state_stats, build_mgs_nidtbl_version_stats, build_mgs_pool_count_stats,
+    },
+    osp::build_osp_stats,
+    pool::build_pool_stats,
+    quota::{build_ost_quota_stats, build_quota_enforcement_stats, build_quota_stats},
+    quota_names::QuotaNames,
+    rpc_stats::build_osc_rpc_stats,
+    srpc::build_srpc_info_stats,
+    stats::{build_export_stats, build_mds_stats, build_snapshot_time_stats, build_stats},
+    tunable::build_tunable_stats,
+    Metric, StatsMapExt, TargetLabels, ToMetricInst,
 };
 
 static DISK_IO_TOTAL: Metric = Metric {
@@ -94,6 +106,18 @@ static EXPORTS_TOTAL: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+static MDT_OPEN_FILES: Metric = Metric {
+    name: "lustre_mdt_open_files",
+    help: "Number of files currently open on the MDT",
+    r#type: MetricType::Gauge,
+};
+
+static MDT_EVICTIONS_TOTAL: Metric = Metric {
+    name: "lustre_mdt_evictions_total",
+    help: "Total number of clients the MDT has evicted since it was mounted",
+    r#type: MetricType::Counter,
+};
+
 static EXPORTS_DIRTY_TOTAL: Metric = Metric {
     name: "lustre_exports_dirty_total",
     help: "Total number of exports that have been marked dirty",
@@ -112,6 +136,18 @@ static EXPORTS_PENDING_TOTAL: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+static ZFS_NONROTATIONAL: Metric = Metric {
+    name: "lustre_osd_zfs_nonrotational",
+    help: "Whether the ZFS backed target is on non-rotational storage. 1 is non-rotational, 0 is rotational.",
+    r#type: MetricType::Gauge,
+};
+
+static BRW_SIZE_MB: Metric = Metric {
+    name: "lustre_target_brw_size_mb",
+    help: "Maximum bulk RPC size in megabytes the target accepts",
+    r#type: MetricType::Gauge,
+};
+
 static LOCK_CONTENDED_TOTAL: Metric = Metric {
     name: "lustre_lock_contended_total",
     help: "Number of contended locks",
@@ -136,12 +172,121 @@ static LOCK_COUNT_TOTAL: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+static LOCK_UNUSED_COUNT: Metric = Metric {
+    name: "lustre_ldlm_lock_unused_count",
+    help: "Number of granted locks of the namespace that are unused and held in its LRU",
+    r#type: MetricType::Gauge,
+};
+
+static LRU_SIZE: Metric = Metric {
+    name: "lustre_ldlm_lru_size",
+    help: "Size of the lock LRU of the namespace. The number of unused locks when it is sized dynamically, the most it may hold otherwise",
+    r#type: MetricType::Gauge,
+};
+
+static LRU_MAX_AGE_SECONDS: Metric = Metric {
+    name: "lustre_ldlm_lru_max_age_seconds",
+    help:
+        "How long an unused lock may stay in the lock LRU of the namespace before it is cancelled",
+    r#type: MetricType::Gauge,
+};
+
 static LOCK_TIMEOUT_TOTAL: Metric = Metric {
     name: "lustre_lock_timeout_total",
     help: "Number of lock timeouts",
     r#type: MetricType::Counter,
 };
 
+static LDLM_POOL_GRANTED: Metric = Metric {
+    name: "lustre_ldlm_pool_granted",
+    help: "Number of locks granted from the lock pool of the target",
+    r#type: MetricType::Gauge,
+};
+
+static LDLM_POOL_GRANT_RATE: Metric = Metric {
+    name: "lustre_ldlm_pool_grant_rate",
+    help: "Number of locks granted per second from the lock pool of the target",
+    r#type: MetricType::Gauge,
+};
+
+static LDLM_POOL_CANCEL_RATE: Metric = Metric {
+    name: "lustre_ldlm_pool_cancel_rate",
+    help: "Number of locks cancelled per second in the lock pool of the target",
+    r#type: MetricType::Gauge,
+};
+
+static LDLM_POOL_LIMIT: Metric = Metric {
+    name: "lustre_ldlm_pool_limit",
+    help:
+        "Number of locks the lock pool of the target can grant before clients are asked to cancel",
+    r#type: MetricType::Gauge,
+};
+
+static LDLM_POOL_SLV: Metric = Metric {
+    name: "lustre_ldlm_pool_slv",
+    help: "Server lock volume of the lock pool of the target, which shrinks as the pool comes under pressure",
+    r#type: MetricType::Gauge,
+};
+
+/// Exports a `pool.*` stat of the lock namespace of a target, picking the family by its param.
+fn build_ldlm_pool_stats(
+    x: &TargetStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let metric = match x.param.0.as_str() {
+        "pool.granted" => LDLM_POOL_GRANTED,
+        "pool.grant_rate" => LDLM_POOL_GRANT_RATE,
+        "pool.cancel_rate" => LDLM_POOL_CANCEL_RATE,
+        "pool.limit" => LDLM_POOL_LIMIT,
+        "pool.slv" => LDLM_POOL_SLV,
+        x => {
+            tracing::debug!("Unhandled ldlm pool stat: {x}");
+
+            return;
+        }
+    };
+
+    stats_map
+        .get_mut_metric(metric)
+        .render_and_append_instance(&x.to_metric_inst());
+}
+
+/// Exports a stat of the lock namespace of a client import, picking the family by its param.
+fn build_import_ldlm_stats(
+    x: &ImportStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let metric = match x.param.0.as_str() {
+        "lock_count" => LOCK_COUNT_TOTAL,
+        "lock_unused_count" => LOCK_UNUSED_COUNT,
+        "lru_size" => LRU_SIZE,
+        "lru_max_age" => {
+            stats_map
+                .get_mut_metric(LRU_MAX_AGE_SECONDS)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("component", x.kind.deref())
+                        .with_label("target", x.target.deref())
+                        .with_value(x.value as f64 / 1000.0),
+                );
+
+            return;
+        }
+        x => {
+            tracing::debug!("Unhandled import ldlm stat: {x}");
+
+            return;
+        }
+    };
+
+    stats_map.get_mut_metric(metric).render_and_append_instance(
+        &PrometheusInstance::new()
+            .with_label("component", x.kind.deref())
+            .with_label("target", x.target.deref())
+            .with_value(x.value),
+    );
+}
+
 static BLOCK_MAPS_MSEC_TOTAL: Metric = Metric {
     name: "lustre_block_maps_milliseconds_total",
     help: "Number of block maps in milliseconds",
@@ -184,6 +329,8 @@ fn build_brw_stats(
         ..
     } = x;
 
+    let labels = TargetLabels::new(kind, &target);
+
     for x in value {
         let BrwStats { name, buckets, .. } = x;
 
@@ -202,11 +349,17 @@ fn build_brw_stats(
         for b in buckets {
             let size = b.name.to_string();
 
-            let (r, w) = rw_inst(b, kind.to_prom_label(), target.deref());
-
             metric
-                .render_and_append_instance(&r.with_label("size", size.as_str()))
-                .render_and_append_instance(&w.with_label("size", size.as_str()));
+                .render_and_append_instance(
+                    &labels
+                        .operation_inst("read", b.read)
+                        .with_label("size", size.as_str()),
+                )
+                .render_and_append_instance(
+                    &labels
+                        .operation_inst("write", b.write)
+                        .with_label("size", size.as_str()),
+                );
         }
     }
 }
@@ -247,6 +400,57 @@ static CHANGELOG_USER_IDLE_SEC: Metric = Metric {
     r#type: MetricType::Gauge,
 };
 
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&DISK_IO_TOTAL, "osd-*.*.brw_stats"),
+    (&DISK_IO_FRAGS, "osd-*.*.brw_stats"),
+    (&DISK_IO, "osd-*.*.brw_stats"),
+    (&DISCONTIGUOUS_PAGES_TOTAL, "osd-*.*.brw_stats"),
+    (&DISCONTIGUOUS_BLOCKS_TOTAL, "osd-*.*.brw_stats"),
+    (&IO_TIME_MILLISECONDS_TOTAL, "osd-*.*.brw_stats"),
+    (&PAGES_PER_BULK_RW_TOTAL, "osd-*.*.brw_stats"),
+    (&INODES_FREE, "osd-*.*.filesfree"),
+    (&INODES_MAXIMUM, "osd-*.*.filestotal"),
+    (&AVAILABLE_KBYTES, "osd-*.*.kbytesavail"),
+    (&FREE_KBYTES, "osd-*.*.kbytesfree"),
+    (&CAPACITY_KBYTES, "osd-*.*.kbytestotal"),
+    (&EXPORTS_TOTAL, "*.*.num_exports"),
+    (&MDT_OPEN_FILES, "mdt.*MDT*.num_open_files"),
+    (&MDT_EVICTIONS_TOTAL, "mdt.*MDT*.eviction_count"),
+    (&EXPORTS_DIRTY_TOTAL, "obdfilter.*OST*.tot_dirty"),
+    (&EXPORTS_GRANTED_TOTAL, "obdfilter.*OST*.tot_granted"),
+    (&EXPORTS_PENDING_TOTAL, "obdfilter.*OST*.tot_pending"),
+    (&ZFS_NONROTATIONAL, "osd-zfs.*.nonrotational"),
+    (&BRW_SIZE_MB, "obdfilter.*OST*.brw_size"),
+    (&LOCK_CONTENDED_TOTAL, "ldlm.namespaces.*.contended_locks"),
+    (
+        &LOCK_CONTENTION_SECONDS_TOTAL,
+        "ldlm.namespaces.*.contention_seconds",
+    ),
+    (&CONNECTED_CLIENTS, "mdt.*.exports.*.uuid"),
+    (&LOCK_COUNT_TOTAL, "ldlm.namespaces.*.lock_count"),
+    (&LOCK_UNUSED_COUNT, "ldlm.namespaces.*.lock_unused_count"),
+    (&LRU_SIZE, "ldlm.namespaces.*.lru_size"),
+    (&LRU_MAX_AGE_SECONDS, "ldlm.namespaces.*.lru_max_age"),
+    (&LOCK_TIMEOUT_TOTAL, "ldlm.namespaces.*.lock_timeouts"),
+    (&LDLM_POOL_GRANTED, "ldlm.namespaces.*.pool.granted"),
+    (&LDLM_POOL_GRANT_RATE, "ldlm.namespaces.*.pool.grant_rate"),
+    (&LDLM_POOL_CANCEL_RATE, "ldlm.namespaces.*.pool.cancel_rate"),
+    (&LDLM_POOL_LIMIT, "ldlm.namespaces.*.pool.limit"),
+    (&LDLM_POOL_SLV, "ldlm.namespaces.*.pool.slv"),
+    (&BLOCK_MAPS_MSEC_TOTAL, "osd-*.*.brw_stats"),
+    (&RECOVERY_STATUS, "*.*.recovery_status"),
+    (&RECOVERY_STATUS_COMPLETED_CLIENTS, "*.*.recovery_status"),
+    (&RECOVERY_STATUS_CONNECTED_CLIENTS, "*.*.recovery_status"),
+    (&RECOVERY_STATUS_EVICTED_CLIENTS, "*.*.recovery_status"),
+    (&OST_STATS, "ost.OSS.ost.stats"),
+    (&OST_IO_STATS, "ost.OSS.ost_io.stats"),
+    (&OST_CREATE_STATS, "ost.OSS.ost_create.stats"),
+    (&CHANGELOG_CURRENT_INDEX, "mdd.*.changelog_users"),
+    (&CHANGELOG_USER_INDEX, "mdd.*.changelog_users"),
+    (&CHANGELOG_USER_IDLE_SEC, "mdd.*.changelog_users"),
+];
+
 fn build_oss_stats(x: OssStat, stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>) {
     let OssStat { param, stats } = x;
 
@@ -321,31 +525,9 @@ fn build_changelog_stats(
         .render_and_append_instance(&current_index);
 }
 
-fn rw_inst<'a>(
-    x: BrwStatsBucket,
-    kind: &'a str,
-    target: &'a str,
-) -> (
-    PrometheusInstance<'a, u64, Yes>,
-    PrometheusInstance<'a, u64, Yes>,
-) {
-    let read = PrometheusInstance::new()
-        .with_label("component", kind)
-        .with_label("operation", "read")
-        .with_label("target", target)
-        .with_value(x.read);
-
-    let write = PrometheusInstance::new()
-        .with_label("component", kind)
-        .with_label("operation", "write")
-        .with_label("target", target)
-        .with_value(x.write);
-
-    (read, write)
-}
-
 pub fn build_target_stats(
     x: TargetStats,
+    quota_names: &QuotaNames,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
 ) {
     match x {
@@ -386,6 +568,16 @@ pub fn build_target_stats(
                 .get_mut_metric(EXPORTS_TOTAL)
                 .render_and_append_instance(&x.to_metric_inst());
         }
+        TargetStats::OpenFiles(x) => {
+            stats_map
+                .get_mut_metric(MDT_OPEN_FILES)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        TargetStats::Evictions(x) => {
+            stats_map
+                .get_mut_metric(MDT_EVICTIONS_TOTAL)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
         TargetStats::TotDirty(x) => {
             stats_map
                 .get_mut_metric(EXPORTS_DIRTY_TOTAL)
@@ -401,6 +593,21 @@ pub fn build_target_stats(
                 .get_mut_metric(EXPORTS_PENDING_TOTAL)
                 .render_and_append_instance(&x.to_metric_inst());
         }
+        TargetStats::BrwSize(x) => {
+            stats_map
+                .get_mut_metric(BRW_SIZE_MB)
+                .render_and_append_instance(&x.to_metric_inst());
+
+            build_tunable_stats(&x, stats_map);
+        }
+        TargetStats::Tunable(x) => build_tunable_stats(&x, stats_map),
+        TargetStats::ZfsNonrotational(x) => {
+            stats_map
+                .get_mut_metric(ZFS_NONROTATIONAL)
+                .render_and_append_instance(
+                    &TargetLabels::new(x.kind, &x.target).inst(u8::from(x.value)),
+                );
+        }
         TargetStats::ContendedLocks(x) => {
             stats_map
                 .get_mut_metric(LOCK_CONTENDED_TOTAL)
@@ -419,7 +626,13 @@ pub fn build_target_stats(
 
         TargetStats::CtimeAgeLimit(_x) => {}
         TargetStats::EarlyLockCancel(_x) => {}
-        TargetStats::FsNames(_x) => {}
+        TargetStats::FsNames(x) => build_mgs_fs_stats(x, stats_map),
+        TargetStats::MgsIrState(x) => build_mgs_ir_state_stats(x, stats_map),
+        TargetStats::MgsIrNonirClients(x) => build_mgs_ir_nonir_clients_stats(x, stats_map),
+        TargetStats::MgsNidtblVersion(x) => build_mgs_nidtbl_version_stats(x, stats_map),
+        TargetStats::MgsIrNotifyCount(x) => build_mgs_ir_notify_stats(x, stats_map),
+        TargetStats::MgsPoolCount(x) => build_mgs_pool_count_stats(x, stats_map),
+        TargetStats::Pool(x) => build_pool_stats(&x, stats_map),
         TargetStats::LockCount(x) => {
             stats_map
                 .get_mut_metric(LOCK_COUNT_TOTAL)
@@ -430,10 +643,27 @@ pub fn build_target_stats(
                 .get_mut_metric(LOCK_TIMEOUT_TOTAL)
                 .render_and_append_instance(&x.to_metric_inst());
         }
-        TargetStats::LockUnusedCount(_x) => {}
-        TargetStats::LruMaxAge(_x) => {}
-        TargetStats::LruSize(_x) => {}
+        TargetStats::LdlmPool(x) => build_ldlm_pool_stats(&x, stats_map),
+        TargetStats::LockUnusedCount(x) => {
+            stats_map
+                .get_mut_metric(LOCK_UNUSED_COUNT)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        TargetStats::LruMaxAge(x) => {
+            // lru_max_age is in milliseconds
+            stats_map
+                .get_mut_metric(LRU_MAX_AGE_SECONDS)
+                .render_and_append_instance(
+                    &TargetLabels::new(x.kind, &x.target).inst(x.value as f64 / 1000.0),
+                );
+        }
+        TargetStats::LruSize(x) => {
+            stats_map
+                .get_mut_metric(LRU_SIZE)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
         TargetStats::Llite(x) => build_llite_stats(x, stats_map),
+        TargetStats::LliteCache(x) => build_llite_cache_stats(x, stats_map),
         TargetStats::MaxNolockBytes(_x) => {}
         TargetStats::MaxParallelAst(_x) => {}
         TargetStats::ResourceCount(_x) => {}
@@ -483,14 +713,26 @@ pub fn build_target_stats(
         TargetStats::ExportStats(x) => {
             build_export_stats(x, stats_map);
         }
+        TargetStats::SnapshotTime(x) => build_snapshot_time_stats(x, stats_map),
+        TargetStats::ExportGrant(x) => {
+            build_export_grant_stats(x, stats_map);
+        }
         TargetStats::QuotaStats(x) => {
-            build_quota_stats(x, stats_map);
+            build_quota_stats(x, quota_names, stats_map);
         }
         TargetStats::QuotaStatsOsd(x) => {
-            build_ost_quota_stats(x, stats_map);
+            build_ost_quota_stats(x, quota_names, stats_map);
+        }
+        TargetStats::QuotaEnforcement(x) => {
+            build_quota_enforcement_stats(x, stats_map);
         }
         TargetStats::Oss(x) => build_oss_stats(x, stats_map),
         TargetStats::Changelog(x) => build_changelog_stats(x, stats_map),
         TargetStats::Mds(x) => build_mds_stats(x, stats_map),
+        TargetStats::SrpcInfo(x) => build_srpc_info_stats(x, stats_map),
+        TargetStats::Import(x) => build_import_stats(x, stats_map),
+        TargetStats::OscRpcStats(x) => build_osc_rpc_stats(&x, stats_map),
+        TargetStats::OspPrealloc(x) => build_osp_stats(&x, stats_map),
+        TargetStats::ImportLdlm(x) => build_import_ldlm_stats(&x, stats_map),
     };
 }
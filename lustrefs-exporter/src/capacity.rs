@@ -0,0 +1,130 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt};
+use lustre_collector::{TargetStats, TargetVariant};
+use prometheus_exporter_base::prelude::*;
+use std::collections::BTreeMap;
+
+static FS_CAPACITY_KBYTES: Metric = Metric {
+    name: "lustre_fs_capacity_kilobytes",
+    help: "Capacity of the OSTs of each filesystem on this host, in kilobytes.",
+    r#type: MetricType::Gauge,
+};
+
+static FS_FREE_KBYTES: Metric = Metric {
+    name: "lustre_fs_free_kilobytes",
+    help: "Free space of the OSTs of each filesystem on this host, in kilobytes.",
+    r#type: MetricType::Gauge,
+};
+
+static FS_AVAILABLE_KBYTES: Metric = Metric {
+    name: "lustre_fs_available_kilobytes",
+    help: "Space of the OSTs of each filesystem on this host available to users, in kilobytes.",
+    r#type: MetricType::Gauge,
+};
+
+static FS_INODES_MAXIMUM: Metric = Metric {
+    name: "lustre_fs_inodes_maximum",
+    help: "Maximum number of inodes of the MDTs of each filesystem on this host.",
+    r#type: MetricType::Gauge,
+};
+
+static FS_INODES_FREE: Metric = Metric {
+    name: "lustre_fs_inodes_free",
+    help: "Free inodes of the MDTs of each filesystem on this host.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&FS_CAPACITY_KBYTES, "osd-*.*OST*.kbytestotal"),
+    (&FS_FREE_KBYTES, "osd-*.*OST*.kbytesfree"),
+    (&FS_AVAILABLE_KBYTES, "osd-*.*OST*.kbytesavail"),
+    (&FS_INODES_MAXIMUM, "osd-*.*MDT*.filestotal"),
+    (&FS_INODES_FREE, "osd-*.*MDT*.filesfree"),
+];
+
+/// The space and inodes of the local targets, summed by filesystem.
+///
+/// Space is summed over OSTs and inodes over MDTs, as `lfs df` reports them,
+/// so a filesystem with its targets on several hosts is summed across them
+/// with `sum by (fsname)`. A sum is left out if there are no local targets
+/// to take it over, rather than exported as zero.
+#[derive(Debug, Default)]
+pub struct FsCapacity {
+    /// By target, as a param can be read more than once in a scrape
+    targets: BTreeMap<String, [Option<u64>; 5]>,
+}
+
+impl FsCapacity {
+    pub fn observe(&mut self, x: &TargetStats) {
+        let (i, x) = match x {
+            TargetStats::KBytesTotal(x) => (0, x),
+            TargetStats::KBytesFree(x) => (1, x),
+            TargetStats::KBytesAvail(x) => (2, x),
+            TargetStats::FilesTotal(x) => (3, x),
+            TargetStats::FilesFree(x) => (4, x),
+            _ => return,
+        };
+
+        let kind = if i < 3 {
+            TargetVariant::Ost
+        } else {
+            TargetVariant::Mdt
+        };
+
+        if x.kind != kind {
+            return;
+        }
+
+        self.targets.entry(x.target.0.clone()).or_default()[i] = Some(x.value);
+    }
+}
+
+pub fn build_fs_capacity_stats(
+    x: FsCapacity,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let metrics = [
+        FS_CAPACITY_KBYTES,
+        FS_FREE_KBYTES,
+        FS_AVAILABLE_KBYTES,
+        FS_INODES_MAXIMUM,
+        FS_INODES_FREE,
+    ];
+
+    let mut filesystems: BTreeMap<&str, [Option<u64>; 5]> = BTreeMap::new();
+
+    for (target, values) in &x.targets {
+        // Targets are named <fsname>-<OST|MDT><index>
+        let Some((fsname, _)) = target.rsplit_once('-') else {
+            continue;
+        };
+
+        let sums = filesystems.entry(fsname).or_default();
+
+        for (sum, value) in sums.iter_mut().zip(values) {
+            if let Some(x) = value {
+                *sum = Some(sum.unwrap_or_default().saturating_add(*x));
+            }
+        }
+    }
+
+    for (fsname, sums) in &filesystems {
+        for (metric, sum) in metrics.iter().zip(sums) {
+            let Some(sum) = sum else {
+                continue;
+            };
+
+            stats_map
+                .get_mut_metric(*metric)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("fsname", *fsname)
+                        .with_value(*sum),
+                );
+        }
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Collection without the HTTP server, for daemons that embed the exporter.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use lustrefs_exporter::{collector::Collector, procfs::Backend};
+//!
+//! let collector = Collector::builder()
+//!     .jobstats(false)
+//!     .backend(Backend::Procfs)
+//!     .build();
+//!
+//! let registry = collector.collect().await;
+//!
+//! print!("{}", registry.text);
+//! # }
+//! ```
+
+use crate::{
+    build_lustre_stats_with_quota_names,
+    collectors::{self, Collectors},
+    jobstats::{JobFilter, JobLimit},
+    procfs::{Backend, ParamSource},
+    quota_names::QuotaNames,
+    routes::{collect_sources, spawn_jobstats, AppState, Budget, Timeouts},
+};
+use std::{sync::Arc, time::Instant};
+
+/// The metrics of a single collection.
+#[derive(Debug, Default)]
+pub struct Registry {
+    /// The Lustre metrics in the Prometheus text format, jobstats included
+    pub text: String,
+    /// Each source and whether it could be read
+    pub sources: Vec<(&'static str, bool)>,
+    /// The sources skipped as the collection ran out of time
+    pub skipped: Vec<&'static str>,
+}
+
+/// Collects and renders the Lustre metrics of this node.
+#[derive(Debug)]
+pub struct Collector {
+    state: Arc<AppState>,
+    jobstats: bool,
+}
+
+impl Collector {
+    pub fn builder() -> CollectorBuilder {
+        CollectorBuilder::default()
+    }
+
+    /// Reads every enabled source once and renders it.
+    ///
+    /// Sources that fail or time out are left out and reported in [`Registry::sources`].
+    pub async fn collect(&self) -> Registry {
+        let state = &self.state;
+        let start = Instant::now();
+
+        let jobstats = if self.jobstats && state.collectors.enabled(collectors::Collector::Jobstats)
+        {
+            let limit = JobLimit::from(state.jobstats_max_jobs);
+
+            match spawn_jobstats(state, state.jobstats_filter.clone(), limit, false).await {
+                Ok(x) => Some(x),
+                Err(e) => {
+                    tracing::warn!("Could not collect jobstats: {e}");
+
+                    Some(None)
+                }
+            }
+        } else {
+            None
+        };
+
+        let budget = Budget::new(start, state.timeouts.scrape);
+
+        let (sources, skipped) = collect_sources(state, &budget, false).await;
+
+        let mut registry = Registry {
+            sources: sources.iter().map(|(x, y)| (*x, y.is_some())).collect(),
+            skipped,
+            ..Default::default()
+        };
+
+        registry.text = build_lustre_stats_with_quota_names(
+            sources
+                .into_iter()
+                .filter_map(|(_, x)| x)
+                .flatten()
+                .collect(),
+            &state.quota_names,
+        );
+
+        if let Some(jobstats) = jobstats {
+            registry.sources.push(("jobstats", jobstats.is_some()));
+
+            if let Some(mut rx) = jobstats {
+                while let Some(x) = rx.recv().await {
+                    registry.text.push_str(&x);
+                }
+            }
+        }
+
+        registry
+    }
+}
+
+/// Options for a [`Collector`], which match the exporter's defaults unless set.
+#[derive(Debug)]
+pub struct CollectorBuilder {
+    jobstats: bool,
+    backend: Backend,
+    timeouts: Timeouts,
+    collectors: Collectors,
+    jobstats_filter: JobFilter,
+    jobstats_max_jobs: Option<usize>,
+    quota_names: QuotaNames,
+}
+
+impl Default for CollectorBuilder {
+    fn default() -> Self {
+        Self {
+            jobstats: true,
+            backend: Backend::default(),
+            timeouts: Timeouts::default(),
+            collectors: Collectors::default(),
+            jobstats_filter: JobFilter::default(),
+            jobstats_max_jobs: None,
+            quota_names: QuotaNames::default(),
+        }
+    }
+}
+
+impl CollectorBuilder {
+    /// Whether to collect jobstats, which can be slow on busy servers.
+    pub fn jobstats(mut self, x: bool) -> Self {
+        self.jobstats = x;
+        self
+    }
+
+    /// Whether params are read with lctl or from procfs.
+    pub fn backend(mut self, x: Backend) -> Self {
+        self.backend = x;
+        self
+    }
+
+    /// How long each command, and the whole collection, may take.
+    pub fn timeouts(mut self, x: Timeouts) -> Self {
+        self.timeouts = x;
+        self
+    }
+
+    /// Which groups of params and metrics to collect.
+    pub fn collectors(mut self, x: Collectors) -> Self {
+        self.collectors = x;
+        self
+    }
+
+    /// Which jobids to collect jobstats for.
+    pub fn jobstats_filter(mut self, x: JobFilter) -> Self {
+        self.jobstats_filter = x;
+        self
+    }
+
+    /// Collapses jobs past this many into an `_overflow` job per target.
+    pub fn jobstats_max_jobs(mut self, x: usize) -> Self {
+        self.jobstats_max_jobs = Some(x);
+        self
+    }
+
+    /// Names to label quota metrics with.
+    pub fn quota_names(mut self, x: QuotaNames) -> Self {
+        self.quota_names = x;
+        self
+    }
+
+    pub fn build(self) -> Collector {
+        Collector {
+            state: Arc::new(AppState {
+                timeouts: self.timeouts,
+                collectors: self.collectors,
+                jobstats_filter: self.jobstats_filter,
+                jobstats_max_jobs: self.jobstats_max_jobs,
+                quota_names: self.quota_names,
+                params: ParamSource::new(self.backend),
+                ..Default::default()
+            }),
+            jobstats: self.jobstats,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Collector;
+    use crate::{
+        collectors::{self, Collectors},
+        procfs::Backend,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn collect_without_jobstats() {
+        let registry = Collector::builder()
+            .jobstats(false)
+            .backend(Backend::Procfs)
+            .collectors(Collectors::new(&[], &[collectors::Collector::Lnet]))
+            .build()
+            .collect()
+            .await;
+
+        assert!(registry.sources.iter().any(|(x, _)| *x == "lctl"));
+        assert!(registry.sources.iter().all(|(x, _)| *x != "jobstats"));
+        assert!(registry.skipped.is_empty());
+    }
+}
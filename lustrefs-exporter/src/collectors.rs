@@ -0,0 +1,273 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use clap::ValueEnum;
+use lustre_collector::{parser, Record, TargetStat, TargetStats, TargetVariant};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// A group of related params and metric families that can be turned off as a whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Collector {
+    Ost,
+    Mdt,
+    Lnet,
+    Quota,
+    Jobstats,
+    Llite,
+    Mgs,
+    /// `lnetctl peer show`, which grows with the number of peers
+    LnetPeer,
+    /// `lnetctl route show` and `lnetctl routing show`
+    LnetRoute,
+    /// `lnetctl udsp show`
+    LnetUdsp,
+}
+
+impl Collector {
+    /// Whether the group is only collected when asked for with `--collectors`.
+    pub fn opt_in(self) -> bool {
+        matches!(self, Self::LnetPeer | Self::LnetRoute | Self::LnetUdsp)
+    }
+
+    /// The group an `lctl get_param` param belongs to, or `None` if it is always collected.
+    fn for_param(param: &str) -> Option<Self> {
+        if param.starts_with("qmt.") || param.contains(".quota_slave.") {
+            Some(Self::Quota)
+        } else if param.starts_with("obdfilter.") || param.starts_with("ost.") {
+            Some(Self::Ost)
+        } else if ["mdt.", "mds.", "mdd.", "lod.", "osp."]
+            .iter()
+            .any(|x| param.starts_with(x))
+        {
+            Some(Self::Mdt)
+        } else if ["llite.", "lov.", "osc.*.rpc_stats"]
+            .iter()
+            .any(|x| param.starts_with(x))
+        {
+            Some(Self::Llite)
+        } else if param.starts_with("mgs.") {
+            Some(Self::Mgs)
+        } else {
+            None
+        }
+    }
+
+    /// The group a record belongs to, or `None` if it is always exported.
+    pub(crate) fn for_record(record: &Record) -> Option<Self> {
+        let x = match record {
+            Record::LNetStat(_) => return Some(Self::Lnet),
+            Record::Target(x) => x,
+            _ => return None,
+        };
+
+        let kind = match x {
+            TargetStats::QuotaStats(_)
+            | TargetStats::QuotaStatsOsd(_)
+            | TargetStats::QuotaEnforcement(_) => return Some(Self::Quota),
+            TargetStats::Llite(_) | TargetStats::LliteCache(_) => return Some(Self::Llite),
+            TargetStats::FsNames(_)
+            | TargetStats::MgsIrState(_)
+            | TargetStats::MgsIrNonirClients(_)
+            | TargetStats::MgsNidtblVersion(_)
+            | TargetStats::MgsIrNotifyCount(_)
+            | TargetStats::MgsPoolCount(_) => return Some(Self::Mgs),
+            // Pools are listed by both MDTs and clients
+            TargetStats::Pool(_) => return None,
+            TargetStats::Oss(_) => return Some(Self::Ost),
+            TargetStats::Mds(_) | TargetStats::OspPrealloc(_) => return Some(Self::Mdt),
+            // Imports are on clients and servers alike
+            TargetStats::SrpcInfo(_) | TargetStats::Import(_) | TargetStats::ImportLdlm(_) => {
+                return None
+            }
+            TargetStats::OscRpcStats(_) => return Some(Self::Llite),
+            TargetStats::Stats(TargetStat { kind, .. })
+            | TargetStats::BrwStats(TargetStat { kind, .. })
+            | TargetStats::FilesFree(TargetStat { kind, .. })
+            | TargetStats::FilesTotal(TargetStat { kind, .. })
+            | TargetStats::FsType(TargetStat { kind, .. })
+            | TargetStats::KBytesAvail(TargetStat { kind, .. })
+            | TargetStats::KBytesFree(TargetStat { kind, .. })
+            | TargetStats::KBytesTotal(TargetStat { kind, .. })
+            | TargetStats::NumExports(TargetStat { kind, .. })
+            | TargetStats::TotDirty(TargetStat { kind, .. })
+            | TargetStats::TotGranted(TargetStat { kind, .. })
+            | TargetStats::TotPending(TargetStat { kind, .. })
+            | TargetStats::BrwSize(TargetStat { kind, .. })
+            | TargetStats::Tunable(TargetStat { kind, .. })
+            | TargetStats::ZfsNonrotational(TargetStat { kind, .. })
+            | TargetStats::ContendedLocks(TargetStat { kind, .. })
+            | TargetStats::ContentionSeconds(TargetStat { kind, .. })
+            | TargetStats::ConnectedClients(TargetStat { kind, .. })
+            | TargetStats::CtimeAgeLimit(TargetStat { kind, .. })
+            | TargetStats::EarlyLockCancel(TargetStat { kind, .. })
+            | TargetStats::LockCount(TargetStat { kind, .. })
+            | TargetStats::LockTimeouts(TargetStat { kind, .. })
+            | TargetStats::LockUnusedCount(TargetStat { kind, .. })
+            | TargetStats::LruMaxAge(TargetStat { kind, .. })
+            | TargetStats::LruSize(TargetStat { kind, .. })
+            | TargetStats::MaxNolockBytes(TargetStat { kind, .. })
+            | TargetStats::MaxParallelAst(TargetStat { kind, .. })
+            | TargetStats::ResourceCount(TargetStat { kind, .. })
+            | TargetStats::ThreadsMin(TargetStat { kind, .. })
+            | TargetStats::ThreadsMax(TargetStat { kind, .. })
+            | TargetStats::ThreadsStarted(TargetStat { kind, .. })
+            | TargetStats::RecoveryStatus(TargetStat { kind, .. })
+            | TargetStats::RecoveryConnectedClients(TargetStat { kind, .. })
+            | TargetStats::RecoveryCompletedClients(TargetStat { kind, .. })
+            | TargetStats::RecoveryEvictedClients(TargetStat { kind, .. })
+            | TargetStats::ExportStats(TargetStat { kind, .. })
+            | TargetStats::ExportGrant(TargetStat { kind, .. })
+            | TargetStats::SnapshotTime(TargetStat { kind, .. })
+            | TargetStats::OpenFiles(TargetStat { kind, .. })
+            | TargetStats::Evictions(TargetStat { kind, .. })
+            | TargetStats::LdlmPool(TargetStat { kind, .. })
+            | TargetStats::Changelog(TargetStat { kind, .. }) => kind,
+        };
+
+        Some(match kind {
+            TargetVariant::Ost => Self::Ost,
+            TargetVariant::Mdt => Self::Mdt,
+            TargetVariant::Mgt => Self::Mgs,
+        })
+    }
+}
+
+/// The collector groups enabled for this exporter. All but the opt-in ones by default.
+#[derive(Debug, Clone)]
+pub struct Collectors(BTreeSet<Collector>);
+
+impl Default for Collectors {
+    fn default() -> Self {
+        Self(
+            Collector::value_variants()
+                .iter()
+                .copied()
+                .filter(|x| !x.opt_in())
+                .collect(),
+        )
+    }
+}
+
+impl Collectors {
+    /// Enables `enabled`, minus `disabled`.
+    ///
+    /// If `enabled` only lists opt-in groups, they are added to the default ones.
+    pub fn new(enabled: &[Collector], disabled: &[Collector]) -> Self {
+        let Self(mut xs) = if enabled.iter().all(|x| x.opt_in()) {
+            Self::default()
+        } else {
+            Self(BTreeSet::new())
+        };
+
+        xs.extend(enabled);
+
+        for x in disabled {
+            xs.remove(x);
+        }
+
+        Self(xs)
+    }
+
+    pub fn enabled(&self, x: Collector) -> bool {
+        self.0.contains(&x)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Collector> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Whether something in group `x` should be collected, where `None` is always collected.
+    fn wants(&self, x: Option<Collector>) -> bool {
+        match x {
+            Some(x) => self.enabled(x),
+            None => true,
+        }
+    }
+
+    /// The `lctl get_param` params to request.
+    pub fn params(&self) -> Vec<String> {
+        parser::params()
+            .into_iter()
+            .filter(|x| self.wants(Collector::for_param(x)))
+            .collect()
+    }
+
+    /// Whether metrics should be built from `record`.
+    pub fn wants_record(&self, record: &Record) -> bool {
+        self.wants(Collector::for_record(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Collector, Collectors};
+    use lustre_collector::{parse_lctl_output, parser};
+
+    #[test]
+    fn test_default_collects_everything() {
+        assert_eq!(Collectors::default().params(), parser::params());
+    }
+
+    #[test]
+    fn test_disabled_collectors() {
+        let collectors = Collectors::new(&[], &[Collector::Quota, Collector::Ost]);
+
+        let params = collectors.params();
+
+        assert!(params.iter().any(|x| x == "memused"));
+        assert!(params.iter().any(|x| x == "osd-*.*.kbytesfree"));
+        assert!(params.iter().any(|x| x == "mdt.*.md_stats"));
+        assert!(!params.iter().any(|x| x.starts_with("qmt.")));
+        assert!(!params.iter().any(|x| x.contains("quota_slave")));
+        assert!(!params.iter().any(|x| x.starts_with("obdfilter.")));
+
+        let records = parse_lctl_output(
+            br#"memused=1000
+osd-ldiskfs.fs-MDT0000.kbytesfree=100
+osd-ldiskfs.fs-OST0000.kbytesfree=200
+"#,
+        )
+        .unwrap();
+
+        let kept: Vec<_> = records
+            .iter()
+            .filter(|x| collectors.wants_record(x))
+            .collect();
+
+        assert_eq!(kept, [&records[0], &records[1]]);
+    }
+
+    #[test]
+    fn test_enabled_collectors() {
+        let collectors = Collectors::new(&[Collector::Mdt, Collector::Lnet], &[Collector::Lnet]);
+
+        assert!(collectors.enabled(Collector::Mdt));
+        assert!(!collectors.enabled(Collector::Lnet));
+        assert!(!collectors.enabled(Collector::Jobstats));
+    }
+
+    #[test]
+    fn test_opt_in_collectors() {
+        let collectors = Collectors::default();
+
+        assert!(collectors.enabled(Collector::Lnet));
+        assert!(!collectors.enabled(Collector::LnetPeer));
+        assert!(!collectors.enabled(Collector::LnetRoute));
+        assert!(!collectors.enabled(Collector::LnetUdsp));
+
+        let collectors = Collectors::new(&[Collector::LnetPeer], &[]);
+
+        assert!(collectors.enabled(Collector::Ost));
+        assert!(collectors.enabled(Collector::Lnet));
+        assert!(collectors.enabled(Collector::LnetPeer));
+        assert!(!collectors.enabled(Collector::LnetUdsp));
+
+        let collectors = Collectors::new(&[Collector::Lnet, Collector::LnetUdsp], &[]);
+
+        assert!(!collectors.enabled(Collector::Ost));
+        assert!(collectors.enabled(Collector::LnetUdsp));
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::Error;
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fmt, fs, path::Path};
+
+/// Expands `${VAR}` references in a config string value with the value of the environment variable.
+///
+/// `$$` is an escaped `$`. Referencing a variable that is not set is an error, so a missing
+/// secret fails loudly instead of silently becoming an empty string.
+pub fn expand_env(value: &str) -> Result<String, Error> {
+    expand_with(value, |name| env::var(name).ok())
+}
+
+fn expand_with(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, Error> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(idx) = rest.find('$') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+
+        if let Some(x) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = x;
+        } else if let Some(x) = rest.strip_prefix('{') {
+            let end = x
+                .find('}')
+                .ok_or_else(|| Error::EnvExpansion(format!("Unterminated ${{ in {value}")))?;
+
+            let name = &x[..end];
+
+            if name.is_empty() {
+                return Err(Error::EnvExpansion(format!("Empty ${{}} in {value}")));
+            }
+
+            let var = lookup(name).ok_or_else(|| {
+                Error::EnvExpansion(format!("Environment variable {name} is not set"))
+            })?;
+
+            out.push_str(&var);
+            rest = &x[end + 1..];
+        } else {
+            out.push('$');
+        }
+    }
+
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bool(x) => write!(f, "{x}"),
+            Self::Int(x) => write!(f, "{x}"),
+            Self::Float(x) => write!(f, "{x}"),
+            Self::String(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// A setting of a config file, a list for flags that take several values.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Setting {
+    One(Scalar),
+    Many(Vec<Scalar>),
+}
+
+/// Reads the settings of a YAML, or with a `.toml` extension TOML, config file.
+///
+/// Returns each setting as the id of the flag it sets, its long name with `_`
+/// for `-`, and its values as they would be given on the command line.
+pub fn load_file(path: &Path) -> Result<Vec<(String, Vec<String>)>, Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::Config(format!("Could not read {}: {e}", path.display())))?;
+
+    let toml = path.extension().is_some_and(|x| x == "toml");
+
+    parse_settings(&contents, toml, |name| env::var(name).ok())
+        .map_err(|e| Error::Config(format!("Invalid config file {}: {e}", path.display())))
+}
+
+fn parse_settings(
+    contents: &str,
+    toml: bool,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, Vec<String>)>, Error> {
+    let settings: BTreeMap<String, Setting> = if toml {
+        toml::from_str(contents).map_err(|e| Error::Config(e.to_string()))?
+    } else {
+        serde_yaml::from_str(contents).map_err(|e| Error::Config(e.to_string()))?
+    };
+
+    settings
+        .into_iter()
+        .map(|(key, setting)| {
+            let values = match setting {
+                Setting::One(x) => vec![x],
+                Setting::Many(xs) => xs,
+            };
+
+            let values = values
+                .iter()
+                .map(|x| expand_with(&x.to_string(), &lookup))
+                .collect::<Result<_, _>>()?;
+
+            Ok((key.replace('-', "_"), values))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_with, parse_settings};
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "TOKEN" => Some("s3cr3t".to_string()),
+            "HOST" => Some("pushgateway".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_variables() {
+        assert_eq!(
+            expand_with("http://${HOST}:9091/?token=${TOKEN}", lookup).unwrap(),
+            "http://pushgateway:9091/?token=s3cr3t"
+        );
+    }
+
+    #[test]
+    fn leaves_plain_values_alone() {
+        assert_eq!(
+            expand_with("/var/lib/lustrefs-exporter", lookup).unwrap(),
+            "/var/lib/lustrefs-exporter"
+        );
+        assert_eq!(expand_with("cost $5", lookup).unwrap(), "cost $5");
+    }
+
+    #[test]
+    fn escapes_dollar() {
+        assert_eq!(expand_with("$${TOKEN}", lookup).unwrap(), "${TOKEN}");
+    }
+
+    #[test]
+    fn parses_yaml_and_toml_settings() {
+        let expected = vec![
+            (
+                "collectors".to_string(),
+                vec!["ost".to_string(), "lnet".to_string()],
+            ),
+            ("enable_exemplars".to_string(), vec!["true".to_string()]),
+            ("jobstats_filter".to_string(), vec!["pipeline".to_string()]),
+            ("lctl_timeout".to_string(), vec!["10".to_string()]),
+        ];
+
+        let yaml = r#"
+lctl-timeout: 10
+collectors: [ost, lnet]
+enable_exemplars: true
+jobstats_filter: "${HOST}"
+"#;
+
+        let lookup = |name: &str| (name == "HOST").then(|| "pipeline".to_string());
+
+        assert_eq!(parse_settings(yaml, false, lookup).unwrap(), expected);
+
+        let toml = r#"
+lctl_timeout = 10
+collectors = ["ost", "lnet"]
+enable-exemplars = true
+jobstats_filter = "${HOST}"
+"#;
+
+        assert_eq!(parse_settings(toml, true, lookup).unwrap(), expected);
+
+        assert!(parse_settings("lctl_timeout: {a: 1}", false, lookup).is_err());
+    }
+
+    #[test]
+    fn fails_on_unset_or_malformed() {
+        assert!(expand_with("${MISSING}", lookup).is_err());
+        assert!(expand_with("${TOKEN", lookup).is_err());
+        assert!(expand_with("${}", lookup).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use lustre_collector::{HostStats, Record, Target, TargetStat, TargetStats, TargetVariant};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// An OST reports its size but has no brw_stats.
+pub const OST_MISSING_BRW_STATS: &str = "ost_missing_brw_stats";
+/// health_check names a target that none of the other params report on.
+pub const UNKNOWN_HEALTH_CHECK_TARGET: &str = "unknown_health_check_target";
+/// A target reports more free space than it has in total.
+pub const KBYTES_FREE_EXCEEDS_TOTAL: &str = "kbytes_free_exceeds_total";
+
+/// Cross-checks the records of a scrape against each other.
+///
+/// Partial kernel state, like a target that is half set up or half torn down,
+/// usually shows up as records missing for some params but not others. Returns
+/// the number of inconsistencies found, by reason.
+pub fn check(records: &[Record]) -> BTreeMap<&'static str, u64> {
+    let mut warnings = BTreeMap::new();
+
+    let mut targets = HashSet::new();
+    let mut ost_sizes = HashSet::new();
+    let mut brw_stats = HashSet::new();
+    let mut kbytes_total = HashMap::new();
+    let mut kbytes_free = vec![];
+    let mut health_check = vec![];
+
+    for record in records {
+        match record {
+            Record::Target(x) => match x {
+                TargetStats::Stats(TargetStat { target, .. })
+                | TargetStats::FilesTotal(TargetStat { target, .. }) => {
+                    targets.insert(target);
+                }
+                TargetStats::BrwStats(TargetStat { target, .. }) => {
+                    targets.insert(target);
+                    brw_stats.insert(target);
+                }
+                TargetStats::KBytesTotal(TargetStat {
+                    kind,
+                    target,
+                    value,
+                    ..
+                }) => {
+                    targets.insert(target);
+                    kbytes_total.insert(target, *value);
+
+                    if *kind == TargetVariant::Ost {
+                        ost_sizes.insert(target);
+                    }
+                }
+                TargetStats::KBytesFree(TargetStat { target, value, .. }) => {
+                    kbytes_free.push((target, *value));
+                }
+                _ => {}
+            },
+            Record::Host(HostStats::HealthCheck(x)) => {
+                health_check.extend(x.value.targets.iter());
+            }
+            _ => {}
+        }
+    }
+
+    let mut warn = |reason: &'static str, target: &Target| {
+        tracing::debug!("Inconsistent records for {}: {reason}", target.0);
+
+        *warnings.entry(reason).or_default() += 1;
+    };
+
+    for target in ost_sizes.difference(&brw_stats) {
+        warn(OST_MISSING_BRW_STATS, target);
+    }
+
+    for target in health_check {
+        if !targets.contains(target) {
+            warn(UNKNOWN_HEALTH_CHECK_TARGET, target);
+        }
+    }
+
+    for (target, free) in kbytes_free {
+        if kbytes_total.get(target).is_some_and(|total| free > *total) {
+            warn(KBYTES_FREE_EXCEEDS_TOTAL, target);
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check, KBYTES_FREE_EXCEEDS_TOTAL, OST_MISSING_BRW_STATS, UNKNOWN_HEALTH_CHECK_TARGET,
+    };
+    use lustre_collector::parse_lctl_output;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_consistent_records() {
+        let x = parse_lctl_output(
+            include_str!("../../lustre-collector/src/fixtures/valid/valid.txt").as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(check(&x), BTreeMap::new());
+    }
+
+    #[test]
+    fn test_inconsistent_records() {
+        let x = parse_lctl_output(
+            br#"health_check=device fs-OST0002 reported unhealthy
+NOT HEALTHY
+osd-ldiskfs.fs-OST0000.kbytestotal=1000
+osd-ldiskfs.fs-OST0000.kbytesfree=2000
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            check(&x),
+            BTreeMap::from([
+                (KBYTES_FREE_EXCEEDS_TOTAL, 1),
+                (OST_MISSING_BRW_STATS, 1),
+                (UNKNOWN_HEALTH_CHECK_TARGET, 1),
+            ])
+        );
+    }
+}
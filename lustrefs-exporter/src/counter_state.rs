@@ -0,0 +1,277 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Error, Metric, StatsMapExt};
+use lustre_collector::{Record, TargetStat, TargetStats};
+use prometheus_exporter_base::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const STATE_FILE: &str = "counters.json";
+
+/// How often the snapshot is written to the state dir at most.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+static COUNTER_RESET_TOTAL: Metric = Metric {
+    name: "lustre_exporter_counter_reset_total",
+    help: "Total number of stats counters seen going backwards between scrapes, by param.",
+    r#type: MetricType::Counter,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[(&COUNTER_RESET_TOTAL, "exporter")];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Snapshot {
+    /// Last samples and sum of each stat, keyed by target, param and stat name
+    counters: BTreeMap<String, (u64, Option<u64>)>,
+    /// Resets detected so far, keyed by param
+    resets: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    snapshot: Snapshot,
+    /// When the snapshot was last written, if it was
+    saved: Option<Instant>,
+}
+
+impl Inner {
+    fn due(&self) -> bool {
+        match self.saved {
+            Some(x) => x.elapsed() >= SAVE_INTERVAL,
+            None => true,
+        }
+    }
+}
+
+/// Tracks the `stats` counters of each target across scrapes to detect resets.
+///
+/// With a state dir, the snapshot is persisted so resets that happen while the
+/// exporter is restarting are detected too. Jobstats are streamed and not tracked.
+#[derive(Debug, Default)]
+pub struct CounterState {
+    path: Option<PathBuf>,
+    // Observing and saving take turns, so a save never writes a half-updated
+    // snapshot or overwrites a newer one
+    inner: Mutex<Inner>,
+}
+
+impl CounterState {
+    /// Loads the snapshot persisted in `dir`, if there is one.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(STATE_FILE);
+
+        let snapshot = match fs::read(&path) {
+            Ok(x) => serde_json::from_slice(&x)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Snapshot::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            inner: Mutex::new(Inner {
+                snapshot,
+                saved: None,
+            }),
+        })
+    }
+
+    /// Compares the stats in `records` with the previous scrape and counts the
+    /// ones that went backwards.
+    pub fn observe(&self, records: &[Record]) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let Snapshot { counters, resets } = &mut inner.snapshot;
+
+        let mut current = BTreeMap::new();
+
+        for x in records {
+            let Record::Target(TargetStats::Stats(TargetStat {
+                kind,
+                param,
+                target,
+                value,
+            })) = x
+            else {
+                continue;
+            };
+
+            for stat in value {
+                let key = format!("{kind}/{}/{}/{}", target.0, param.0, stat.name);
+                let value = (stat.samples, stat.sum);
+
+                let reset = counters
+                    .get(&key)
+                    .is_some_and(|(samples, sum)| value.0 < *samples || value.1 < *sum);
+
+                if reset {
+                    *resets.entry(param.0.clone()).or_default() += 1;
+                }
+
+                current.insert(key, value);
+            }
+        }
+
+        *counters = current;
+    }
+
+    /// Whether [`CounterState::save`] would write the snapshot now.
+    pub fn save_due(&self) -> bool {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        self.path.is_some() && inner.due()
+    }
+
+    /// Writes the snapshot to the state dir, if one was given and it was not
+    /// written within the last [`SAVE_INTERVAL`].
+    pub fn save(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !inner.due() {
+            return Ok(());
+        }
+
+        let tmp = path.with_extension("json.tmp");
+
+        fs::write(&tmp, serde_json::to_vec(&inner.snapshot)?)?;
+        fs::rename(tmp, path)?;
+
+        inner.saved = Some(Instant::now());
+
+        Ok(())
+    }
+
+    pub fn render(&self) -> String {
+        let mut stats_map = BTreeMap::new();
+
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        for (param, count) in &inner.snapshot.resets {
+            stats_map
+                .get_mut_metric(COUNTER_RESET_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("param", param.as_str())
+                        .with_value(*count),
+                );
+        }
+
+        stats_map
+            .values()
+            .map(|x| x.render())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CounterState;
+    use lustre_collector::{parse_lctl_output, Record};
+    use std::{env, fs};
+
+    fn records(read_bytes: u64, write_bytes: u64) -> Vec<Record> {
+        parse_lctl_output(
+            format!(
+                "obdfilter.fs-OST0000.stats=
+snapshot_time             1688603042.554414847 secs.nsecs
+start_time                1688526475.271489350 secs.nsecs
+elapsed_time              76567.282925497 secs.nsecs
+read_bytes                {read_bytes} samples [bytes] 4096 4096 {} 0
+write_bytes               {write_bytes} samples [bytes] 4096 4096 {} 0
+",
+                read_bytes * 4096,
+                write_bytes * 4096
+            )
+            .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_counter_resets() {
+        let state = CounterState::default();
+
+        state.observe(&records(10, 10));
+
+        assert_eq!(state.render(), "");
+
+        state.observe(&records(2, 20));
+
+        insta::assert_snapshot!(state.render());
+    }
+
+    #[test]
+    fn test_save_needs_state_dir() {
+        let state = CounterState::default();
+
+        state.observe(&records(10, 10));
+
+        assert!(!state.save_due());
+        state.save().unwrap();
+    }
+
+    #[test]
+    fn test_save_is_throttled() {
+        let dir = env::temp_dir().join(format!(
+            "lustrefs-exporter-state-throttle-{}",
+            std::process::id()
+        ));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let state = CounterState::load(&dir).unwrap();
+        state.observe(&records(10, 10));
+        assert!(state.save_due());
+        state.save().unwrap();
+
+        assert!(!state.save_due());
+        state.observe(&records(20, 20));
+        state.save().unwrap();
+
+        let state = CounterState::load(&dir).unwrap();
+        state.observe(&records(15, 15));
+        state.save().unwrap();
+
+        let state = CounterState::load(&dir).unwrap();
+        let rendered = state.render();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Only the first observation was written, so 15 is not a reset
+        assert_eq!(rendered, "");
+    }
+
+    #[test]
+    fn test_counter_resets_across_restarts() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-state-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let state = CounterState::load(&dir).unwrap();
+        state.observe(&records(10, 10));
+        state.save().unwrap();
+
+        let state = CounterState::load(&dir).unwrap();
+        state.observe(&records(1, 10));
+        state.save().unwrap();
+
+        let state = CounterState::load(&dir).unwrap();
+        let rendered = state.render();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(rendered.contains(r#"lustre_exporter_counter_reset_total{param="stats"} 1"#));
+    }
+}
@@ -0,0 +1,964 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt};
+use prometheus_exporter_base::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::runtime::RuntimeMetrics;
+
+static REQUESTS_TOTAL: Metric = Metric {
+    name: "lustre_exporter_requests_total",
+    help: "Total number of scrape requests received, by client address.",
+    r#type: MetricType::Counter,
+};
+
+static COLLECT_TIMEOUT_TOTAL: Metric = Metric {
+    name: "lustre_exporter_collect_timeout_total",
+    help: "Total number of times a collector command was abandoned after exceeding its timeout.",
+    r#type: MetricType::Counter,
+};
+
+static SKIPPED_PARAMS_TOTAL: Metric = Metric {
+    name: "lustre_exporter_skipped_params_total",
+    help: "Total number of lctl params that could not be parsed and were left out of a scrape.",
+    r#type: MetricType::Counter,
+};
+
+static PARSE_ERRORS_TOTAL: Metric = Metric {
+    name: "lustre_exporter_parse_errors_total",
+    help: "Total number of times an lctl param could not be parsed, by param.",
+    r#type: MetricType::Counter,
+};
+
+static LCTL_STDERR_LINES_TOTAL: Metric = Metric {
+    name: "lustre_exporter_lctl_stderr_lines_total",
+    help: "Total number of lines lctl printed to stderr, by class of error.",
+    r#type: MetricType::Counter,
+};
+
+static SOURCE_UP: Metric = Metric {
+    name: "lustre_exporter_source_up",
+    help: "Whether the collection source was read and parsed successfully during this scrape.",
+    r#type: MetricType::Gauge,
+};
+
+static SCRAPE_DURATION: Metric = Metric {
+    name: "lustre_exporter_scrape_duration_seconds",
+    help: "Time taken to collect the most recent scrape, excluding streamed jobstats.",
+    r#type: MetricType::Gauge,
+};
+
+static COMMAND_DURATION: Metric = Metric {
+    name: "lustre_exporter_command_duration_seconds",
+    help: "Time taken by the most recent run of the collection source's command.",
+    r#type: MetricType::Gauge,
+};
+
+static STAGE_DURATION: Metric = Metric {
+    name: "lustre_exporter_stage_duration_seconds",
+    help: "Time taken by each phase of the most recent run of a scrape stage: the wall time of its command, parsing the output in-process, or encoding metrics.",
+    r#type: MetricType::Gauge,
+};
+
+static PARSED_BYTES_TOTAL: Metric = Metric {
+    name: "lustre_exporter_parsed_bytes_total",
+    help: "Total number of bytes of command output parsed, by collection source.",
+    r#type: MetricType::Counter,
+};
+
+static PARSED_RECORDS_TOTAL: Metric = Metric {
+    name: "lustre_exporter_parsed_records_total",
+    help: "Total number of records parsed, by collection source.",
+    r#type: MetricType::Counter,
+};
+
+static HTTP_RESPONSES_TOTAL: Metric = Metric {
+    name: "lustre_exporter_http_responses_total",
+    help: "Total number of HTTP responses sent, by route and status code.",
+    r#type: MetricType::Counter,
+};
+
+static RESPONSE_BYTES: Metric = Metric {
+    name: "lustre_exporter_response_bytes",
+    help: "Size of the body of the last /metrics response as sent, in bytes, by content encoding.",
+    r#type: MetricType::Gauge,
+};
+
+static CONSISTENCY_WARNINGS_TOTAL: Metric = Metric {
+    name: "lustre_consistency_warnings_total",
+    help: "Total number of inconsistencies found between the records of a scrape, by reason.",
+    r#type: MetricType::Counter,
+};
+
+static SINK_DROPPED_TOTAL: Metric = Metric {
+    name: "lustre_exporter_sink_dropped_total",
+    help: "Total number of scrapes dropped because the sink could not write them before its retry queue filled up.",
+    r#type: MetricType::Counter,
+};
+
+static SINK_QUEUED: Metric = Metric {
+    name: "lustre_exporter_sink_queued",
+    help: "Number of scrapes waiting to be retried after the sink failed to write them.",
+    r#type: MetricType::Gauge,
+};
+
+static SCRAPE_WORKER_RESTARTS_TOTAL: Metric = Metric {
+    name: "lustre_exporter_scrape_worker_restarts_total",
+    help:
+        "Total number of times the scrape worker process was restarted after it failed, by reason.",
+    r#type: MetricType::Counter,
+};
+
+static PREFLIGHT_OK: Metric = Metric {
+    name: "lustre_exporter_preflight_ok",
+    help: "Whether the last preflight found lctl, lnetctl and the params of the enabled collectors usable with the privileges of the exporter.",
+    r#type: MetricType::Gauge,
+};
+
+static KAFKA_PUBLISH_ERRORS_TOTAL: Metric = Metric {
+    name: "lustre_exporter_kafka_publish_errors_total",
+    help: "Total number of records that could not be published to Kafka, by reason: too_large for records over message.max.bytes, failed otherwise.",
+    r#type: MetricType::Counter,
+};
+
+static BUILD_INFO: Metric = Metric {
+    name: "lustre_exporter_build_info",
+    help: "Always 1, labelled with the version and commit the exporter was built from, the compiler and the build profile.",
+    r#type: MetricType::Gauge,
+};
+
+static START_TIME: Metric = Metric {
+    name: "lustre_exporter_start_time_seconds",
+    help: "Time the exporter started at, in seconds since the epoch.",
+    r#type: MetricType::Gauge,
+};
+
+fn lock<T>(x: &Mutex<T>) -> MutexGuard<'_, T> {
+    x.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+static SCRAPE_PARTIAL: Metric = Metric {
+    name: "lustre_exporter_scrape_partial",
+    help: "Whether sources were skipped during this scrape because the scrape budget ran out.",
+    r#type: MetricType::Gauge,
+};
+
+static SOURCE_SKIPPED: Metric = Metric {
+    name: "lustre_exporter_source_skipped",
+    help: "Set for each collection source skipped during this scrape because the scrape budget ran out.",
+    r#type: MetricType::Gauge,
+};
+
+/// Renders whether each collection source succeeded during the current scrape,
+/// and which ones were skipped.
+pub fn render_scrape_status(sources: &[(&'static str, bool)], skipped: &[&'static str]) -> String {
+    let mut stats_map = BTreeMap::new();
+
+    stats_map
+        .get_mut_metric(SCRAPE_PARTIAL)
+        .render_and_append_instance(
+            &PrometheusInstance::new().with_value(u8::from(!skipped.is_empty())),
+        );
+
+    for source in skipped {
+        stats_map
+            .get_mut_metric(SOURCE_SKIPPED)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("source", *source)
+                    .with_value(1),
+            );
+    }
+
+    for (source, up) in sources {
+        stats_map
+            .get_mut_metric(SOURCE_UP)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("source", *source)
+                    .with_value(u8::from(*up)),
+            );
+    }
+
+    stats_map
+        .values()
+        .map(|x| x.render())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+static RUNTIME_WORKERS: Metric = Metric {
+    name: "lustre_exporter_runtime_workers",
+    help: "Number of worker threads of the async runtime.",
+    r#type: MetricType::Gauge,
+};
+
+static RUNTIME_ALIVE_TASKS: Metric = Metric {
+    name: "lustre_exporter_runtime_alive_tasks",
+    help: "Number of tasks alive in the async runtime, not counting blocking ones.",
+    r#type: MetricType::Gauge,
+};
+
+#[cfg(tokio_unstable)]
+static RUNTIME_BLOCKING_THREADS: Metric = Metric {
+    name: "lustre_exporter_runtime_blocking_threads",
+    help: "Number of threads in the blocking pool of the async runtime, busy or idle.",
+    r#type: MetricType::Gauge,
+};
+
+#[cfg(tokio_unstable)]
+static RUNTIME_IDLE_BLOCKING_THREADS: Metric = Metric {
+    name: "lustre_exporter_runtime_idle_blocking_threads",
+    help: "Number of idle threads in the blocking pool of the async runtime.",
+    r#type: MetricType::Gauge,
+};
+
+#[cfg(tokio_unstable)]
+static RUNTIME_BLOCKING_QUEUE_DEPTH: Metric = Metric {
+    name: "lustre_exporter_runtime_blocking_queue_depth",
+    help: "Number of blocking tasks waiting for a thread of the blocking pool.",
+    r#type: MetricType::Gauge,
+};
+
+#[cfg(tokio_unstable)]
+static RUNTIME_GLOBAL_QUEUE_DEPTH: Metric = Metric {
+    name: "lustre_exporter_runtime_global_queue_depth",
+    help: "Number of tasks waiting in the global queue of the async runtime.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&REQUESTS_TOTAL, "exporter"),
+    (&COLLECT_TIMEOUT_TOTAL, "exporter"),
+    (&SKIPPED_PARAMS_TOTAL, "exporter"),
+    (&PARSE_ERRORS_TOTAL, "exporter"),
+    (&LCTL_STDERR_LINES_TOTAL, "exporter"),
+    (&SOURCE_UP, "exporter"),
+    (&SCRAPE_DURATION, "exporter"),
+    (&COMMAND_DURATION, "exporter"),
+    (&STAGE_DURATION, "exporter"),
+    (&PARSED_BYTES_TOTAL, "exporter"),
+    (&PARSED_RECORDS_TOTAL, "exporter"),
+    (&HTTP_RESPONSES_TOTAL, "exporter"),
+    (&RESPONSE_BYTES, "exporter"),
+    (&CONSISTENCY_WARNINGS_TOTAL, "exporter"),
+    (&SINK_DROPPED_TOTAL, "exporter"),
+    (&SINK_QUEUED, "exporter"),
+    (&SCRAPE_WORKER_RESTARTS_TOTAL, "exporter"),
+    (&PREFLIGHT_OK, "exporter"),
+    (&KAFKA_PUBLISH_ERRORS_TOTAL, "exporter"),
+    (&BUILD_INFO, "exporter"),
+    (&START_TIME, "exporter"),
+    (&SCRAPE_PARTIAL, "exporter"),
+    (&SOURCE_SKIPPED, "exporter"),
+    (&RUNTIME_WORKERS, "exporter"),
+    (&RUNTIME_ALIVE_TASKS, "exporter"),
+    #[cfg(tokio_unstable)]
+    (&RUNTIME_BLOCKING_THREADS, "exporter"),
+    #[cfg(tokio_unstable)]
+    (&RUNTIME_IDLE_BLOCKING_THREADS, "exporter"),
+    #[cfg(tokio_unstable)]
+    (&RUNTIME_BLOCKING_QUEUE_DEPTH, "exporter"),
+    #[cfg(tokio_unstable)]
+    (&RUNTIME_GLOBAL_QUEUE_DEPTH, "exporter"),
+];
+
+/// Renders the state of the async runtime, to tell a saturated blocking pool
+/// apart from slow Lustre commands when scrapes stall.
+///
+/// The blocking pool and queue gauges are only built in with
+/// `RUSTFLAGS="--cfg tokio_unstable"`, e.g. by `make build-tokio-unstable`.
+pub fn render_runtime(metrics: &RuntimeMetrics) -> String {
+    let mut stats_map = BTreeMap::new();
+
+    stats_map
+        .get_mut_metric(RUNTIME_WORKERS)
+        .render_and_append_instance(&PrometheusInstance::new().with_value(metrics.num_workers()));
+
+    stats_map
+        .get_mut_metric(RUNTIME_ALIVE_TASKS)
+        .render_and_append_instance(
+            &PrometheusInstance::new().with_value(metrics.num_alive_tasks()),
+        );
+
+    #[cfg(tokio_unstable)]
+    for (metric, value) in [
+        (RUNTIME_BLOCKING_THREADS, metrics.num_blocking_threads()),
+        (
+            RUNTIME_IDLE_BLOCKING_THREADS,
+            metrics.num_idle_blocking_threads(),
+        ),
+        (RUNTIME_BLOCKING_QUEUE_DEPTH, metrics.blocking_queue_depth()),
+        (RUNTIME_GLOBAL_QUEUE_DEPTH, metrics.injection_queue_depth()),
+    ] {
+        stats_map
+            .get_mut_metric(metric)
+            .render_and_append_instance(&PrometheusInstance::new().with_value(value));
+    }
+
+    stats_map
+        .values()
+        .map(|x| x.render())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Where the time of a scrape stage goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Phase {
+    /// Waiting on a child process
+    Command,
+    /// Parsing its output into records
+    Parse,
+    /// Building Prometheus metrics out of the records
+    Encode,
+}
+
+impl Phase {
+    fn as_label(self) -> &'static str {
+        match self {
+            Self::Command => "command",
+            Self::Parse => "parse",
+            Self::Encode => "encode",
+        }
+    }
+}
+
+/// Interns a name read back from a scrape worker. There is only a handful of
+/// sources, stages and stderr classes, so each name is leaked at most once.
+pub(crate) fn intern(x: String) -> &'static str {
+    static NAMES: OnceLock<Mutex<BTreeSet<&'static str>>> = OnceLock::new();
+
+    let mut names = lock(NAMES.get_or_init(Default::default));
+
+    if let Some(x) = names.get(x.as_str()) {
+        return x;
+    }
+
+    let x = Box::leak(x.into_boxed_str());
+
+    names.insert(x);
+
+    x
+}
+
+/// The metrics recorded while collecting scrapes, taken out of the
+/// [`ExporterMetrics`] of a scrape worker to be added to those of the server.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScrapeMetrics {
+    collect_timeouts: BTreeMap<String, u64>,
+    skipped_params: u64,
+    parse_errors: BTreeMap<String, u64>,
+    lctl_stderr_lines: BTreeMap<String, u64>,
+    command_durations: BTreeMap<String, Duration>,
+    stage_durations: Vec<(String, Phase, Duration)>,
+    parsed_bytes: BTreeMap<String, u64>,
+    parsed_records: BTreeMap<String, u64>,
+}
+
+fn to_owned_keys<V>(x: BTreeMap<&'static str, V>) -> BTreeMap<String, V> {
+    x.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+/// When the exporter started, taken when its [`ExporterMetrics`] are created.
+#[derive(Debug)]
+struct StartTime(SystemTime);
+
+impl Default for StartTime {
+    fn default() -> Self {
+        Self(SystemTime::now())
+    }
+}
+
+/// Metrics about the exporter itself, kept across scrapes.
+#[derive(Debug, Default)]
+pub struct ExporterMetrics {
+    start_time: StartTime,
+    requests: Mutex<BTreeMap<String, u64>>,
+    collect_timeouts: Mutex<BTreeMap<&'static str, u64>>,
+    skipped_params: AtomicU64,
+    parse_errors: Mutex<BTreeMap<String, u64>>,
+    lctl_stderr_lines: Mutex<BTreeMap<&'static str, u64>>,
+    scrape_duration: Mutex<Option<Duration>>,
+    command_durations: Mutex<BTreeMap<&'static str, Duration>>,
+    stage_durations: Mutex<BTreeMap<(&'static str, Phase), Duration>>,
+    parsed_bytes: Mutex<BTreeMap<&'static str, u64>>,
+    parsed_records: Mutex<BTreeMap<&'static str, u64>>,
+    responses: Mutex<BTreeMap<(String, u16), u64>>,
+    response_bytes: Mutex<BTreeMap<String, u64>>,
+    consistency_warnings: Mutex<BTreeMap<&'static str, u64>>,
+    sink_dropped: Mutex<BTreeMap<&'static str, u64>>,
+    sink_queued: Mutex<BTreeMap<&'static str, usize>>,
+    scrape_worker_restarts: Mutex<BTreeMap<&'static str, u64>>,
+    preflight_ok: Mutex<Option<bool>>,
+    kafka_publish_errors: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl ExporterMetrics {
+    pub fn record_request(&self, client: &str) {
+        let mut requests = lock(&self.requests);
+
+        *requests.entry(client.to_string()).or_default() += 1;
+    }
+
+    pub fn record_collect_timeout(&self, source: &'static str) {
+        *lock(&self.collect_timeouts).entry(source).or_default() += 1;
+    }
+
+    pub fn record_skipped_params(&self, count: usize) {
+        self.skipped_params
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_parse_error(&self, param: &str) {
+        let mut parse_errors = lock(&self.parse_errors);
+
+        match parse_errors.get_mut(param) {
+            Some(x) => *x += 1,
+            None => {
+                parse_errors.insert(param.to_string(), 1);
+            }
+        }
+    }
+
+    pub fn record_lctl_stderr_line(&self, class: &'static str) {
+        *lock(&self.lctl_stderr_lines).entry(class).or_default() += 1;
+    }
+
+    pub fn record_scrape_duration(&self, duration: Duration) {
+        *lock(&self.scrape_duration) = Some(duration);
+    }
+
+    pub fn record_command_duration(&self, source: &'static str, duration: Duration) {
+        lock(&self.command_durations).insert(source, duration);
+
+        self.record_stage_duration(source, Phase::Command, duration);
+    }
+
+    pub fn record_stage_duration(&self, stage: &'static str, phase: Phase, duration: Duration) {
+        lock(&self.stage_durations).insert((stage, phase), duration);
+    }
+
+    pub fn record_parsed(&self, source: &'static str, bytes: usize, records: usize) {
+        *lock(&self.parsed_bytes).entry(source).or_default() += bytes as u64;
+        *lock(&self.parsed_records).entry(source).or_default() += records as u64;
+    }
+
+    pub fn record_response(&self, route: &str, status: u16) {
+        *lock(&self.responses)
+            .entry((route.to_string(), status))
+            .or_default() += 1;
+    }
+
+    pub fn record_response_bytes(&self, encoding: &str, bytes: u64) {
+        let mut response_bytes = lock(&self.response_bytes);
+
+        match response_bytes.get_mut(encoding) {
+            Some(x) => *x = bytes,
+            None => {
+                response_bytes.insert(encoding.to_string(), bytes);
+            }
+        }
+    }
+
+    pub fn record_consistency_warnings(&self, warnings: BTreeMap<&'static str, u64>) {
+        let mut consistency_warnings = lock(&self.consistency_warnings);
+
+        for (reason, count) in warnings {
+            *consistency_warnings.entry(reason).or_default() += count;
+        }
+    }
+
+    pub fn record_sink_dropped(&self, sink: &'static str) {
+        *lock(&self.sink_dropped).entry(sink).or_default() += 1;
+    }
+
+    pub fn record_sink_queued(&self, sink: &'static str, queued: usize) {
+        lock(&self.sink_queued).insert(sink, queued);
+    }
+
+    pub fn record_scrape_worker_restart(&self, reason: &'static str) {
+        *lock(&self.scrape_worker_restarts)
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn record_preflight(&self, ok: bool) {
+        *lock(&self.preflight_ok) = Some(ok);
+    }
+
+    pub fn record_kafka_publish_error(&self, reason: &'static str) {
+        *lock(&self.kafka_publish_errors).entry(reason).or_default() += 1;
+    }
+
+    /// Takes the metrics recorded while collecting scrapes since the last call,
+    /// leaving them empty.
+    pub fn take_scrape_metrics(&self) -> ScrapeMetrics {
+        ScrapeMetrics {
+            collect_timeouts: to_owned_keys(mem::take(&mut *lock(&self.collect_timeouts))),
+            skipped_params: self.skipped_params.swap(0, Ordering::Relaxed),
+            parse_errors: mem::take(&mut *lock(&self.parse_errors)),
+            lctl_stderr_lines: to_owned_keys(mem::take(&mut *lock(&self.lctl_stderr_lines))),
+            command_durations: to_owned_keys(mem::take(&mut *lock(&self.command_durations))),
+            stage_durations: mem::take(&mut *lock(&self.stage_durations))
+                .into_iter()
+                .map(|((stage, phase), duration)| (stage.to_string(), phase, duration))
+                .collect(),
+            parsed_bytes: to_owned_keys(mem::take(&mut *lock(&self.parsed_bytes))),
+            parsed_records: to_owned_keys(mem::take(&mut *lock(&self.parsed_records))),
+        }
+    }
+
+    /// Adds the counters of `metrics` to these, and replaces the durations.
+    pub fn record_scrape_metrics(&self, metrics: ScrapeMetrics) {
+        for (source, count) in metrics.collect_timeouts {
+            *lock(&self.collect_timeouts)
+                .entry(intern(source))
+                .or_default() += count;
+        }
+
+        self.skipped_params
+            .fetch_add(metrics.skipped_params, Ordering::Relaxed);
+
+        for (param, count) in metrics.parse_errors {
+            *lock(&self.parse_errors).entry(param).or_default() += count;
+        }
+
+        for (class, count) in metrics.lctl_stderr_lines {
+            *lock(&self.lctl_stderr_lines)
+                .entry(intern(class))
+                .or_default() += count;
+        }
+
+        for (source, duration) in metrics.command_durations {
+            lock(&self.command_durations).insert(intern(source), duration);
+        }
+
+        for (stage, phase, duration) in metrics.stage_durations {
+            self.record_stage_duration(intern(stage), phase, duration);
+        }
+
+        for (source, bytes) in metrics.parsed_bytes {
+            *lock(&self.parsed_bytes).entry(intern(source)).or_default() += bytes;
+        }
+
+        for (source, records) in metrics.parsed_records {
+            *lock(&self.parsed_records)
+                .entry(intern(source))
+                .or_default() += records;
+        }
+    }
+
+    pub fn build_stats(&self, stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>) {
+        for (client, count) in lock(&self.requests).iter() {
+            stats_map
+                .get_mut_metric(REQUESTS_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("client", client.as_str())
+                        .with_value(*count),
+                );
+        }
+
+        for (source, count) in lock(&self.collect_timeouts).iter() {
+            stats_map
+                .get_mut_metric(COLLECT_TIMEOUT_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("source", *source)
+                        .with_value(*count),
+                );
+        }
+
+        let skipped_params = self.skipped_params.load(Ordering::Relaxed);
+
+        if skipped_params > 0 {
+            stats_map
+                .get_mut_metric(SKIPPED_PARAMS_TOTAL)
+                .render_and_append_instance(&PrometheusInstance::new().with_value(skipped_params));
+        }
+
+        for (param, count) in lock(&self.parse_errors).iter() {
+            stats_map
+                .get_mut_metric(PARSE_ERRORS_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("param", param.as_str())
+                        .with_value(*count),
+                );
+        }
+
+        for (class, count) in lock(&self.lctl_stderr_lines).iter() {
+            stats_map
+                .get_mut_metric(LCTL_STDERR_LINES_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("class", *class)
+                        .with_value(*count),
+                );
+        }
+
+        if let Some(duration) = *lock(&self.scrape_duration) {
+            stats_map
+                .get_mut_metric(SCRAPE_DURATION)
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(duration.as_secs_f64()),
+                );
+        }
+
+        for (source, duration) in lock(&self.command_durations).iter() {
+            stats_map
+                .get_mut_metric(COMMAND_DURATION)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("source", *source)
+                        .with_value(duration.as_secs_f64()),
+                );
+        }
+
+        for ((stage, phase), duration) in lock(&self.stage_durations).iter() {
+            stats_map
+                .get_mut_metric(STAGE_DURATION)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("stage", *stage)
+                        .with_label("phase", phase.as_label())
+                        .with_value(duration.as_secs_f64()),
+                );
+        }
+
+        for (source, bytes) in lock(&self.parsed_bytes).iter() {
+            stats_map
+                .get_mut_metric(PARSED_BYTES_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("source", *source)
+                        .with_value(*bytes),
+                );
+        }
+
+        for (source, records) in lock(&self.parsed_records).iter() {
+            stats_map
+                .get_mut_metric(PARSED_RECORDS_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("source", *source)
+                        .with_value(*records),
+                );
+        }
+
+        for ((route, status), count) in lock(&self.responses).iter() {
+            stats_map
+                .get_mut_metric(HTTP_RESPONSES_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("route", route.as_str())
+                        .with_label("status", status.to_string().as_str())
+                        .with_value(*count),
+                );
+        }
+
+        for (encoding, bytes) in lock(&self.response_bytes).iter() {
+            stats_map
+                .get_mut_metric(RESPONSE_BYTES)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("encoding", encoding.as_str())
+                        .with_value(*bytes),
+                );
+        }
+
+        for (reason, count) in lock(&self.consistency_warnings).iter() {
+            stats_map
+                .get_mut_metric(CONSISTENCY_WARNINGS_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("reason", *reason)
+                        .with_value(*count),
+                );
+        }
+
+        for (sink, count) in lock(&self.sink_dropped).iter() {
+            stats_map
+                .get_mut_metric(SINK_DROPPED_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("sink", *sink)
+                        .with_value(*count),
+                );
+        }
+
+        for (sink, queued) in lock(&self.sink_queued).iter() {
+            stats_map
+                .get_mut_metric(SINK_QUEUED)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("sink", *sink)
+                        .with_value(*queued),
+                );
+        }
+
+        for (reason, count) in lock(&self.scrape_worker_restarts).iter() {
+            stats_map
+                .get_mut_metric(SCRAPE_WORKER_RESTARTS_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("reason", *reason)
+                        .with_value(*count),
+                );
+        }
+
+        if let Some(ok) = *lock(&self.preflight_ok) {
+            stats_map
+                .get_mut_metric(PREFLIGHT_OK)
+                .render_and_append_instance(&PrometheusInstance::new().with_value(u8::from(ok)));
+        }
+
+        for (reason, count) in lock(&self.kafka_publish_errors).iter() {
+            stats_map
+                .get_mut_metric(KAFKA_PUBLISH_ERRORS_TOTAL)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("reason", *reason)
+                        .with_value(*count),
+                );
+        }
+    }
+
+    /// Renders what the exporter was built from and when it started, to follow
+    /// version rollouts and restarts across hosts.
+    pub fn render_build_info(&self) -> String {
+        let mut stats_map = BTreeMap::new();
+
+        stats_map
+            .get_mut_metric(BUILD_INFO)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("version", env!("CARGO_PKG_VERSION"))
+                    .with_label("git_sha", env!("LUSTREFS_EXPORTER_GIT_SHA"))
+                    .with_label("rustc", env!("LUSTREFS_EXPORTER_RUSTC"))
+                    .with_label("profile", env!("LUSTREFS_EXPORTER_PROFILE"))
+                    .with_value(1),
+            );
+
+        let start_time = self
+            .start_time
+            .0
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        stats_map
+            .get_mut_metric(START_TIME)
+            .render_and_append_instance(
+                &PrometheusInstance::new().with_value(start_time.as_secs_f64()),
+            );
+
+        stats_map
+            .values()
+            .map(|x| x.render())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn render(&self) -> String {
+        let mut stats_map = BTreeMap::new();
+
+        self.build_stats(&mut stats_map);
+
+        stats_map
+            .values()
+            .map(|x| x.render())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_runtime, render_scrape_status, ExporterMetrics, Phase};
+    use std::{
+        collections::BTreeMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[test]
+    fn test_requests_by_client() {
+        let metrics = ExporterMetrics::default();
+
+        assert_eq!(metrics.render(), "");
+
+        metrics.record_request("10.0.0.1");
+        metrics.record_request("10.0.0.2");
+        metrics.record_request("10.0.0.1");
+
+        insta::assert_snapshot!(metrics.render());
+    }
+
+    #[test]
+    fn test_collect_timeouts_by_source() {
+        let metrics = ExporterMetrics::default();
+
+        metrics.record_collect_timeout("lctl");
+        metrics.record_collect_timeout("jobstats");
+        metrics.record_collect_timeout("lctl");
+
+        insta::assert_snapshot!(metrics.render());
+    }
+
+    #[test]
+    fn test_skipped_params() {
+        let metrics = ExporterMetrics::default();
+
+        metrics.record_skipped_params(3);
+        metrics.record_skipped_params(1);
+        metrics.record_parse_error("obdfilter.fs-OST0000.stats");
+        metrics.record_parse_error("mdt.fs-MDT0000.md_stats");
+        metrics.record_parse_error("obdfilter.fs-OST0000.stats");
+
+        insta::assert_snapshot!(metrics.render());
+    }
+
+    #[test]
+    fn test_collection_metrics() {
+        let metrics = ExporterMetrics::default();
+
+        metrics.record_scrape_duration(Duration::from_millis(1_250));
+        metrics.record_command_duration("lctl", Duration::from_millis(800));
+        metrics.record_command_duration("lnetctl_net", Duration::from_millis(20));
+        metrics.record_command_duration("lctl", Duration::from_millis(900));
+        metrics.record_parsed("lctl", 4_096, 30);
+        metrics.record_parsed("lctl", 2_048, 12);
+        metrics.record_parsed("lnetctl_net", 512, 4);
+        metrics.record_stage_duration("lctl", Phase::Parse, Duration::from_millis(150));
+        metrics.record_stage_duration("lustre_stats", Phase::Encode, Duration::from_millis(40));
+
+        insta::assert_snapshot!(metrics.render());
+    }
+
+    #[test]
+    fn test_scrape_metrics_move_between_processes() {
+        let worker = ExporterMetrics::default();
+
+        worker.record_collect_timeout("lctl");
+        worker.record_skipped_params(2);
+        worker.record_parse_error("obdfilter.fs-OST0000.stats");
+        worker.record_lctl_stderr_line("permission");
+        worker.record_command_duration("lctl", Duration::from_millis(800));
+        worker.record_stage_duration("lctl", Phase::Parse, Duration::from_millis(150));
+        worker.record_parsed("lctl", 4_096, 30);
+
+        let expected = worker.render();
+
+        let metrics = worker.take_scrape_metrics();
+
+        assert_eq!(worker.render(), "");
+        assert_eq!(worker.take_scrape_metrics(), Default::default());
+
+        let metrics = serde_json::from_slice(&serde_json::to_vec(&metrics).unwrap()).unwrap();
+
+        let server = ExporterMetrics::default();
+
+        server.record_scrape_metrics(metrics);
+
+        assert_eq!(server.render(), expected);
+    }
+
+    #[test]
+    fn test_responses_by_status() {
+        let metrics = ExporterMetrics::default();
+
+        metrics.record_response("/metrics", 200);
+        metrics.record_response("/metrics", 200);
+        metrics.record_response("/metrics", 503);
+        metrics.record_response("/debug/jobstats/:target", 404);
+        metrics.record_response_bytes("identity", 2_097_152);
+        metrics.record_response_bytes("identity", 1_048_576);
+
+        insta::assert_snapshot!(metrics.render());
+    }
+
+    #[test]
+    fn test_consistency_warnings() {
+        let metrics = ExporterMetrics::default();
+
+        metrics.record_consistency_warnings(BTreeMap::from([("ost_missing_brw_stats", 2)]));
+        metrics.record_consistency_warnings(BTreeMap::from([
+            ("ost_missing_brw_stats", 1),
+            ("unknown_health_check_target", 1),
+        ]));
+
+        insta::assert_snapshot!(metrics.render());
+    }
+
+    #[test]
+    fn test_build_info() {
+        let metrics = ExporterMetrics::default();
+
+        let x = metrics.render_build_info();
+
+        assert!(x.contains(&format!(
+            "lustre_exporter_build_info{{version=\"{}\",git_sha=\"",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(x.contains(&format!(
+            "lustre_exporter_start_time_seconds {}\n",
+            metrics
+                .start_time
+                .0
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs_f64()
+        )));
+    }
+
+    #[test]
+    fn test_source_up() {
+        insta::assert_snapshot!(render_scrape_status(
+            &[("lctl", true), ("lnetctl_net", false), ("jobstats", true)],
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_skipped_sources() {
+        insta::assert_snapshot!(render_scrape_status(
+            &[("lctl", true)],
+            &["lnetctl_net", "jobstats"]
+        ));
+    }
+
+    #[test]
+    fn test_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .build()
+            .unwrap();
+
+        let x = render_runtime(&runtime.metrics());
+
+        assert!(x.contains("lustre_exporter_runtime_workers 2\n"));
+        assert!(x.contains("lustre_exporter_runtime_alive_tasks 0\n"));
+
+        // The workers themselves start on the blocking pool, so its numbers vary here
+        #[cfg(tokio_unstable)]
+        for name in [
+            "blocking_threads",
+            "idle_blocking_threads",
+            "blocking_queue_depth",
+            "global_queue_depth",
+        ] {
+            assert!(x.contains(&format!("\nlustre_exporter_runtime_{name} ")));
+        }
+    }
+}
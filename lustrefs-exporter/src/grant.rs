@@ -0,0 +1,100 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt, TargetLabels};
+use lustre_collector::{ExportGrant, Target, TargetStat, TargetStats, TargetVariant};
+use prometheus_exporter_base::prelude::*;
+use std::collections::BTreeMap;
+
+static GRANT_FREE_BYTES: Metric = Metric {
+    name: "lustre_grant_free_bytes",
+    help: "Free space of the OST that has not been granted to clients, in bytes. Never negative.",
+    r#type: MetricType::Gauge,
+};
+
+static EXPORT_GRANT_BYTES: Metric = Metric {
+    name: "lustre_export_grant_bytes",
+    help: "Bytes of grant held by each export of the OST, by kind: dirty, granted or pending.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&GRANT_FREE_BYTES, "obdfilter.*OST*.tot_granted"),
+    (
+        &EXPORT_GRANT_BYTES,
+        "obdfilter.*OST*.exports.*.{dirty,granted,pending}",
+    ),
+];
+
+/// The free space and granted bytes of each OST, which come from separate params.
+#[derive(Debug, Default)]
+pub struct Grants {
+    targets: BTreeMap<String, (Option<u64>, Option<u64>)>,
+}
+
+impl Grants {
+    pub fn observe(&mut self, x: &TargetStats) {
+        match x {
+            TargetStats::KBytesFree(TargetStat {
+                kind: TargetVariant::Ost,
+                target,
+                value,
+                ..
+            }) => {
+                self.targets.entry(target.0.clone()).or_default().0 = Some(*value);
+            }
+            TargetStats::TotGranted(TargetStat {
+                kind: TargetVariant::Ost,
+                target,
+                value,
+                ..
+            }) => {
+                self.targets.entry(target.0.clone()).or_default().1 = Some(*value);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn build_export_grant_stats(
+    x: TargetStat<ExportGrant>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let TargetStat {
+        target,
+        value: ExportGrant { nid, kind, bytes },
+        ..
+    } = x;
+
+    stats_map
+        .get_mut_metric(EXPORT_GRANT_BYTES)
+        .render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("target", target.0.as_str())
+                .with_label("nid", nid.as_str())
+                .with_label("kind", kind.as_str())
+                .with_value(bytes),
+        );
+}
+
+pub fn build_grant_stats(
+    x: Grants,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    for (target, values) in x.targets {
+        let (Some(kbytes_free), Some(tot_granted)) = values else {
+            continue;
+        };
+
+        let target = Target(target);
+
+        stats_map
+            .get_mut_metric(GRANT_FREE_BYTES)
+            .render_and_append_instance(
+                &TargetLabels::new(TargetVariant::Ost, &target)
+                    .inst((kbytes_free * 1024).saturating_sub(tot_granted)),
+            );
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Keeps the last background collections in memory, so `/history` can show
+//! what a metric did between two scrapes of Prometheus.
+//!
+//! Each collection is parsed once when it is kept, into the values of its
+//! series, so requests only look up the metric families they select.
+
+use crate::sink::parse_sample;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+};
+
+/// The values of a series over the kept collections.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Series {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    /// Milliseconds since the epoch and the value at that time, oldest first
+    pub values: Vec<(u64, f64)>,
+}
+
+/// The values of each series by sample name, then labels.
+type Samples = BTreeMap<String, BTreeMap<BTreeMap<String, String>, VecDeque<(u64, f64)>>>;
+
+#[derive(Debug, Default)]
+struct Kept {
+    /// When each kept collection finished, oldest first
+    timestamps: VecDeque<u64>,
+    samples: Samples,
+}
+
+/// The last background collections, with the time each finished at.
+#[derive(Debug, Default)]
+pub struct History {
+    capacity: usize,
+    kept: Mutex<Kept>,
+}
+
+/// Whether `name` is a sample of `family`, including the series of histograms and summaries.
+fn in_family(name: &str, family: &str) -> bool {
+    matches!(
+        name.strip_prefix(family),
+        Some("" | "_bucket" | "_sum" | "_count" | "_total")
+    )
+}
+
+impl History {
+    /// Keeps up to `capacity` collections, none if it is zero.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            kept: Default::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Keeps the samples of a collection finished at `timestamp`, in
+    /// milliseconds since the epoch, dropping the oldest one if there are too many.
+    pub fn push(&self, timestamp: u64, body: &[u8]) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut kept = self.kept.lock().unwrap_or_else(|e| e.into_inner());
+        let Kept {
+            timestamps,
+            samples,
+        } = &mut *kept;
+
+        if timestamps.len() >= self.capacity {
+            if let Some(oldest) = timestamps.pop_front() {
+                for series in samples.values_mut() {
+                    for values in series.values_mut() {
+                        while values.front().is_some_and(|(x, _)| *x <= oldest) {
+                            values.pop_front();
+                        }
+                    }
+
+                    series.retain(|_, values| !values.is_empty());
+                }
+
+                samples.retain(|_, series| !series.is_empty());
+            }
+        }
+
+        timestamps.push_back(timestamp);
+
+        let lines = body
+            .split(|x| *x == b'\n')
+            .filter_map(|x| std::str::from_utf8(x).ok());
+
+        for sample in lines.filter_map(|x| parse_sample(x, timestamp)) {
+            let labels = sample
+                .labels
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect();
+
+            let series = match samples.get_mut(sample.name) {
+                Some(x) => x,
+                None => samples.entry(sample.name.to_string()).or_default(),
+            };
+
+            series
+                .entry(labels)
+                .or_default()
+                .push_back((sample.timestamp, sample.value));
+        }
+    }
+
+    /// The series of `families` in the collections finished at or after `since`.
+    pub fn series(&self, since: u64, families: &[String]) -> Vec<Series> {
+        let kept = self.kept.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut out = vec![];
+
+        for (name, series) in &kept.samples {
+            if !families.iter().any(|x| in_family(name, x)) {
+                continue;
+            }
+
+            for (labels, values) in series {
+                let values: Vec<_> = values
+                    .iter()
+                    .filter(|(x, _)| *x >= since)
+                    .copied()
+                    .collect();
+
+                if values.is_empty() {
+                    continue;
+                }
+
+                out.push(Series {
+                    name: name.clone(),
+                    labels: labels.clone(),
+                    values,
+                });
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_collections() {
+        let history = History::new(2);
+
+        for x in 1..=3 {
+            history.push(
+                x * 1000,
+                format!(
+                    "# TYPE lustre_mem_used gauge\nlustre_mem_used {x}\nlustre_mem_used_max 9\nlustre_stats_total{{target=\"fs-OST0000\"}} {x}\n"
+                )
+                .as_bytes(),
+            );
+        }
+
+        let series = history.series(0, &["lustre_mem_used".to_string()]);
+
+        assert_eq!(
+            series,
+            [Series {
+                name: "lustre_mem_used".to_string(),
+                labels: BTreeMap::new(),
+                values: vec![(2000, 2.0), (3000, 3.0)],
+            }]
+        );
+
+        let series = history.series(3000, &["lustre_stats".to_string()]);
+
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].labels["target"], "fs-OST0000");
+        assert_eq!(series[0].values, [(3000, 3.0)]);
+    }
+
+    #[test]
+    fn forgets_series_of_dropped_collections() {
+        let history = History::new(1);
+
+        history.push(1000, b"lustre_target_up{target=\"fs-OST0000\"} 1\n");
+        history.push(2000, b"lustre_target_up{target=\"fs-OST0001\"} 1\n");
+
+        let series = history.series(0, &["lustre_target_up".to_string()]);
+
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].labels["target"], "fs-OST0001");
+        assert_eq!(
+            history.kept.lock().unwrap().samples["lustre_target_up"].len(),
+            1
+        );
+    }
+
+    #[test]
+    fn disabled_keeps_nothing() {
+        let history = History::default();
+
+        history.push(1000, b"lustre_mem_used 1\n");
+
+        assert!(!history.enabled());
+        assert!(history
+            .series(0, &["lustre_mem_used".to_string()])
+            .is_empty());
+    }
+}
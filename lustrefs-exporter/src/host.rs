@@ -3,6 +3,7 @@ use lustre_collector::HostStats;
 use prometheus_exporter_base::prelude::*;
 use std::collections::BTreeMap;
 use std::ops::Deref;
+use std::{fs, path::Path, sync::OnceLock};
 
 static LUSTRE_TARGETS_HEALTHY: Metric = Metric {
     name: "lustre_health_healthy",
@@ -28,6 +29,112 @@ static MEM_USED_MAX_SAMPLES: Metric = Metric {
     r#type: MetricType::Gauge,
 };
 
+static DEVICE_INFO: Metric = Metric {
+    name: "lustre_device_info",
+    help: "Lustre devices configured on the node, as listed by lctl dl. Always 1.",
+    r#type: MetricType::Gauge,
+};
+
+static DEVICE_REFCOUNT: Metric = Metric {
+    name: "lustre_device_refcount",
+    help: "Number of references held on the Lustre device.",
+    r#type: MetricType::Gauge,
+};
+
+static VERSION_INFO: Metric = Metric {
+    name: "lustre_version_info",
+    help: "Lustre release loaded on the node, with the release of the kernel it runs on. Always 1.",
+    r#type: MetricType::Gauge,
+};
+
+static MODULE_INFO: Metric = Metric {
+    name: "lustre_module_info",
+    help: "Lustre kernel modules loaded on the node, with the version each reports. Always 1.",
+    r#type: MetricType::Gauge,
+};
+
+/// Where the loaded kernel modules are listed.
+pub const MODULES_ROOT: &str = "/sys/module";
+
+/// The Lustre and LNet modules reported by `lustre_module_info`, when loaded.
+const LUSTRE_MODULES: [&str; 25] = [
+    "fid",
+    "fld",
+    "kkfilnd",
+    "ko2iblnd",
+    "ksocklnd",
+    "lfsck",
+    "libcfs",
+    "lmv",
+    "lnet",
+    "lod",
+    "lov",
+    "lquota",
+    "lustre",
+    "mdc",
+    "mdd",
+    "mdt",
+    "mgc",
+    "mgs",
+    "obdclass",
+    "ofd",
+    "osc",
+    "osd_ldiskfs",
+    "osd_zfs",
+    "osp",
+    "ptlrpc",
+];
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&LUSTRE_TARGETS_HEALTHY, "health_check"),
+    (&LNET_MEM_USED_SAMPLES, "lnet_memused"),
+    (&MEM_USED_SAMPLES, "memused"),
+    (&MEM_USED_MAX_SAMPLES, "memused_max"),
+    (&DEVICE_INFO, "devices"),
+    (&DEVICE_REFCOUNT, "devices"),
+    (&VERSION_INFO, "version"),
+    (&MODULE_INFO, "/sys/module/*/version"),
+];
+
+/// The release of the running kernel, as `uname -r` prints it.
+fn kernel_release() -> &'static str {
+    static RELEASE: OnceLock<String> = OnceLock::new();
+
+    RELEASE.get_or_init(|| {
+        fs::read_to_string("/proc/sys/kernel/osrelease")
+            .map(|x| x.trim().to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Renders the version of each Lustre module loaded under `root`, like
+/// [`MODULES_ROOT`], so nodes left on an old release after an upgrade stand out.
+pub fn render_module_versions(root: &Path) -> String {
+    let mut stats_map = BTreeMap::new();
+
+    for module in LUSTRE_MODULES {
+        let Ok(version) = fs::read_to_string(root.join(module).join("version")) else {
+            continue;
+        };
+
+        stats_map
+            .get_mut_metric(MODULE_INFO)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("module", module)
+                    .with_label("version", version.trim())
+                    .with_value(1),
+            );
+    }
+
+    stats_map
+        .values()
+        .map(|x| x.render())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub fn build_host_stats(
     x: HostStats,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
@@ -68,5 +175,87 @@ pub fn build_host_stats(
                 .get_mut_metric(MEM_USED_MAX_SAMPLES)
                 .render_and_append_instance(&x.to_metric_inst());
         }
+        HostStats::Devices(x) => {
+            for device in x.value {
+                stats_map
+                    .get_mut_metric(DEVICE_INFO)
+                    .render_and_append_instance(
+                        &PrometheusInstance::new()
+                            .with_label("type", device.kind.as_str())
+                            .with_label("name", device.name.as_str())
+                            .with_label("uuid", device.uuid.as_str())
+                            .with_label("status", device.status.as_str())
+                            .with_value(1),
+                    );
+
+                stats_map
+                    .get_mut_metric(DEVICE_REFCOUNT)
+                    .render_and_append_instance(
+                        &PrometheusInstance::new()
+                            .with_label("type", device.kind.as_str())
+                            .with_label("name", device.name.as_str())
+                            .with_value(device.refcount),
+                    );
+            }
+        }
+        HostStats::Version(x) => {
+            stats_map
+                .get_mut_metric(VERSION_INFO)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("version", x.value.as_str())
+                        .with_label("kernel", kernel_release())
+                        .with_value(1),
+                );
+        }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lustre_collector::{parse_lctl_output, Record};
+    use std::env;
+
+    #[test]
+    fn test_version_info() {
+        let records = parse_lctl_output(b"version=2.15.4\n").unwrap();
+
+        let mut stats_map = BTreeMap::new();
+
+        for x in records {
+            if let Record::Host(x) = x {
+                build_host_stats(x, &mut stats_map);
+            }
+        }
+
+        assert_eq!(
+            stats_map[VERSION_INFO.name].render(),
+            format!(
+                "# HELP lustre_version_info {}\n# TYPE lustre_version_info gauge\nlustre_version_info{{version=\"2.15.4\",kernel=\"{}\"}} 1\n",
+                VERSION_INFO.help,
+                kernel_release()
+            )
+        );
+    }
+
+    #[test]
+    fn test_module_versions() {
+        let root =
+            env::temp_dir().join(format!("lustrefs-exporter-modules-{}", std::process::id()));
+
+        for (module, version) in [("lnet", "2.15.4"), ("lustre", "2.15.4"), ("ext4", "1.0")] {
+            fs::create_dir_all(root.join(module)).unwrap();
+            fs::write(root.join(module).join("version"), format!("{version}\n")).unwrap();
+        }
+
+        fs::create_dir_all(root.join("ptlrpc")).unwrap();
+
+        let output = render_module_versions(&root);
+
+        fs::remove_dir_all(&root).unwrap();
+
+        insta::assert_snapshot!(output);
+        assert_eq!(render_module_versions(&root), "");
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use std::collections::BTreeMap;
+
+use lustre_collector::{ImportStat, ImportState};
+use prometheus_exporter_base::prelude::*;
+
+use crate::{Metric, StatsMapExt};
+
+static IMPORT_STATE: Metric = Metric {
+    name: "lustre_import_state",
+    help: "Connection state of a client import. 1 for the state the import is in, 0 for the others. FULL once connected.",
+    r#type: MetricType::Gauge,
+};
+
+static IMPORT_RECONNECTS_TOTAL: Metric = Metric {
+    name: "lustre_import_reconnects_total",
+    help: "Number of times a client import has tried to connect to its target.",
+    r#type: MetricType::Counter,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&IMPORT_STATE, "{mdc,osc}.*.import"),
+    (&IMPORT_RECONNECTS_TOTAL, "{mdc,osc}.*.import"),
+];
+
+/// The states an import moves through, so alerts can match on a 0 as well as a 1.
+const STATES: [&str; 11] = [
+    "CLOSED",
+    "NEW",
+    "DISCONN",
+    "CONNECTING",
+    "REPLAY",
+    "REPLAY_LOCKS",
+    "REPLAY_WAIT",
+    "RECOVER",
+    "FULL",
+    "EVICTED",
+    "IDLE",
+];
+
+pub fn build_import_stats(
+    x: ImportStat<ImportState>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let ImportStat { target, value, .. } = x;
+
+    let states = STATES
+        .iter()
+        .copied()
+        .chain((!STATES.contains(&value.state.as_str())).then_some(value.state.as_str()));
+
+    for state in states {
+        stats_map
+            .get_mut_metric(IMPORT_STATE)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("local", target.0.as_str())
+                    .with_label("target", value.remote.0.as_str())
+                    .with_label("state", state)
+                    .with_value(u8::from(state == value.state)),
+            );
+    }
+
+    stats_map
+        .get_mut_metric(IMPORT_RECONNECTS_TOTAL)
+        .render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("local", target.0.as_str())
+                .with_label("target", value.remote.0.as_str())
+                .with_value(value.connection_attempts),
+        );
+}
@@ -1,9 +1,23 @@
-use crate::{Error, LabelProm, Metric};
+use crate::{escape_label_value, quota_names::QuotaNames, Error, LabelProm, Metric};
+use clap::ValueEnum;
 use compact_str::{format_compact, CompactString, ToCompactString};
-use lustre_collector::TargetVariant;
+use lustre_collector::{QuotaKind, TargetVariant};
+use memchr::memchr;
 use prometheus_exporter_base::MetricType;
 use regex::Regex;
-use std::{io::BufRead, sync::LazyLock};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::{self, Reverse},
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    fmt::Write,
+    io::BufRead,
+    mem,
+    ops::ControlFlow,
+    process::{Command, Stdio},
+    str,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use tokio::{
     sync::mpsc::{self, Receiver, Sender},
     task::JoinHandle,
@@ -51,276 +65,1480 @@ static WRITE_BYTES: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+static READ_BYTES_SIZE: Metric = Metric {
+    name: "lustre_job_read_bytes_size",
+    help: "Sizes of the reads that have been recorded. Lustre counts each in the power of two at or below its size, exported as a bucket up to the next power of two.",
+    r#type: MetricType::Histogram,
+};
+static WRITE_BYTES_SIZE: Metric = Metric {
+    name: "lustre_job_write_bytes_size",
+    help: "Sizes of the writes that have been recorded. Lustre counts each in the power of two at or below its size, exported as a bucket up to the next power of two.",
+    r#type: MetricType::Histogram,
+};
+
 static MDT_JOBSTATS_SAMPLES: Metric = Metric {
     name: "lustre_job_stats_total",
     help: "Number of operations the filesystem has performed, recorded by jobstats.",
     r#type: MetricType::Counter,
 };
 
+static JOB_IDS_INFO: Metric = Metric {
+    name: "lustre_job_ids_info",
+    help: "The parts of a jobid, split into labels. Always 1.",
+    r#type: MetricType::Gauge,
+};
+
+static TARGET_JOB_OPS: Metric = Metric {
+    name: "lustre_target_job_ops_total",
+    help: "Number of create, destroy, punch and setattr operations on a target, summed over all of its jobs.",
+    r#type: MetricType::Counter,
+};
+
+/// Matches the `procname.uid` jobids of the default `jobid_var=procname_uid`.
+pub const DEFAULT_JOBID_PATTERN: &str = r"^(?<procname>.+)\.(?<uid>[0-9]+)$";
+
+/// Splits jobids into the labels of a `lustre_job_ids_info` series per job,
+/// so they can be joined on in PromQL instead of being taken apart with regexes.
+#[derive(Debug, Clone)]
+pub struct JobIdInfo {
+    /// Each named capture group becomes a label
+    pub pattern: Regex,
+    /// Resolves a `uid` capture to a `user` label
+    pub names: Arc<QuotaNames>,
+}
+
+impl JobIdInfo {
+    /// Writes the labels split from `jobid`, each preceded by a comma.
+    ///
+    /// Returns `false` if `jobid` does not match the pattern.
+    fn write_labels(&self, jobid: &str, out: &mut String) -> bool {
+        let Some(caps) = self.pattern.captures(jobid) else {
+            return false;
+        };
+
+        for name in self.pattern.capture_names().flatten() {
+            if let Some(x) = caps.name(name) {
+                _ = write!(out, ",{name}=\"{}\"", escape_label_value(x.as_str()));
+            }
+        }
+
+        let user = caps
+            .name("uid")
+            .and_then(|x| x.as_str().parse().ok())
+            .and_then(|x| self.names.get(&QuotaKind::Usr, x));
+
+        if let Some(x) = user {
+            _ = write!(out, ",user=\"{x}\"");
+        }
+
+        true
+    }
+}
+
+/// Which jobids to export, to bound the cardinality of per-process jobids.
+#[derive(Debug, Clone, Default)]
+pub struct JobFilter {
+    /// Only export jobids matching this
+    pub include: Option<Regex>,
+    /// Drop jobids matching this, even if they match `include`
+    pub exclude: Option<Regex>,
+}
+
+impl JobFilter {
+    pub fn matches(&self, jobid: &str) -> bool {
+        if self.exclude.as_ref().is_some_and(|x| x.is_match(jobid)) {
+            return false;
+        }
+
+        match &self.include {
+            Some(x) => x.is_match(jobid),
+            None => true,
+        }
+    }
+}
+
+/// What jobstats are rolled up by, instead of exporting the stats of every job.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobAggregate {
+    /// Export every job
+    #[default]
+    None,
+    /// By the first numeric part of the jobid, the uid of `procname.uid` or `uid.gid` jobids
+    User,
+    /// By the number of the jobid up to its first `:` or `.`, as in `SLURM_JOB123:0:client` or `123.0`
+    SlurmJob,
+}
+
+impl JobAggregate {
+    /// The label jobs are rolled up under.
+    fn label(self) -> &'static str {
+        match self {
+            Self::None => "jobid",
+            Self::User => "user",
+            Self::SlurmJob => "slurm_job",
+        }
+    }
+
+    /// The group `jobid` is rolled up into, `None` if jobs are not aggregated.
+    ///
+    /// Jobids that do not have the expected format are rolled up into `_other`.
+    fn group(self, jobid: &str) -> Option<&str> {
+        let x = match self {
+            Self::None => return None,
+            Self::User => jobid
+                .split('.')
+                .find(|x| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit())),
+            Self::SlurmJob => jobid
+                .split([':', '.'])
+                .next()
+                .map(|x| x.trim_start_matches(|x: char| !x.is_ascii_digit()))
+                .filter(|x| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit())),
+        };
+
+        Some(x.unwrap_or(OTHER_JOBID))
+    }
+}
+
+/// How many jobs are exported, the rest being collapsed into an `_overflow` job per target.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum JobLimit {
+    /// Export every job
+    #[default]
+    None,
+    /// The first this many jobs, over all targets
+    Max(usize),
+    /// This many jobs per target, those with the most bytes read and written
+    /// on OSTs and with the most operations on MDTs
+    Top(usize),
+}
+
+impl From<Option<usize>> for JobLimit {
+    fn from(x: Option<usize>) -> Self {
+        x.map_or(Self::None, Self::Max)
+    }
+}
+
+/// How [`jobstats_stream`] exports jobs. The default exports every job as is.
+#[derive(Debug, Clone, Default)]
+pub struct JobstatsOptions {
+    /// Only jobs matching this are exported
+    pub filter: JobFilter,
+    /// How many jobs are exported, the rest being collapsed into `_overflow`
+    pub limit: JobLimit,
+    /// The output is meant for an OpenMetrics response, and the write bytes of
+    /// each job carry its jobid and snapshot time as an exemplar
+    pub exemplars: bool,
+    /// Exports a `lustre_job_ids_info` series per job with the labels split from its jobid
+    pub ids: Option<JobIdInfo>,
+    /// Keeps job counters increasing across the resets of purged jobs
+    pub deltas: Option<Arc<JobDeltas>>,
+    /// Rolls the stats of jobs up into their group, which `limit`, `ids` and
+    /// `deltas` do not apply to
+    pub aggregate: JobAggregate,
+}
+
+/// Seconds a job may go unseen before [`JobDeltas`] forgets its counters, an hour.
+pub const DEFAULT_JOBSTATS_DELTA_RETENTION: u64 = 60 * 60;
+
+/// A jobstats counter as last read, and what it has been read up to before its resets.
 #[derive(Debug)]
+struct DeltaCounter {
+    last: u64,
+    offset: u64,
+    seen: Instant,
+}
+
+/// Keeps the counters of jobs increasing across the resets Lustre causes when
+/// it purges a job that has been idle for `job_cleanup_interval`.
+///
+/// The last value read is kept per target, jobid, metric and operation, and
+/// a value lower than it is taken as a reset. Jobs not seen for `retention`
+/// are forgotten. Histograms and the `_overflow` job are exported as read.
+#[derive(Debug)]
+pub struct JobDeltas {
+    retention: Duration,
+    counters: Mutex<HashMap<String, DeltaCounter>>,
+}
+
+impl JobDeltas {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            counters: Mutex::default(),
+        }
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    /// The value of the counter under `key` as if it had never been reset, given it now reads `value`.
+    fn observe(&self, key: &str, value: u64, now: Instant) -> u64 {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+
+        let Some(x) = counters.get_mut(key) else {
+            counters.insert(
+                key.to_string(),
+                DeltaCounter {
+                    last: value,
+                    offset: 0,
+                    seen: now,
+                },
+            );
+
+            return value;
+        };
+
+        if value < x.last {
+            x.offset += x.last;
+        }
+
+        x.last = value;
+        x.seen = now;
+
+        x.offset + value
+    }
+
+    /// Forgets the counters not seen within the retention.
+    fn expire(&self, now: Instant) {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+
+        counters.retain(|_, x| now.duration_since(x.seen) <= self.retention);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 enum State {
     Empty,
-    Target(String),
-    TargetJob(String, String),
-    TargetJobStats(String, String, Vec<String>),
+    Target,
+    TargetJob,
+    TargetJobStats,
+}
+
+/// What to do with the stats of the current job.
+#[derive(Debug, Clone, Copy)]
+enum JobMode {
+    /// Filtered out
+    Skip,
+    Export,
+    /// Past the job limit, aggregated into the target's `_overflow` job
+    Overflow,
+    /// Rolled up into the group of the job, per [`JobAggregate`]
+    Aggregate,
+    /// Held back until the end of its target, to be exported if it is one of its [`TopJobs`]
+    Top,
+}
+
+/// A job held back by [`TopJobs`].
+#[derive(Debug, Default)]
+struct TopJob {
+    /// Bytes read and written on OSTs, operations on MDTs
+    score: u64,
+    /// The job as exported
+    out: String,
+    /// The stats of the job, to add to the `_overflow` job if it is not kept
+    stats: Vec<(&'static Metric, Field, CompactString, CompactString)>,
+}
+
+impl PartialEq for TopJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.score == other.score
+    }
+}
+
+impl Eq for TopJob {}
+
+impl PartialOrd for TopJob {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TopJob {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.score.cmp(&other.score)
+    }
+}
+
+/// Keeps the `n` jobs of the current target with the most I/O, collapsing
+/// the rest into its `_overflow` job, per [`JobLimit::Top`].
+#[derive(Debug, Default)]
+struct TopJobs {
+    n: usize,
+    /// The jobs kept so far, the one with the least I/O on top
+    kept: BinaryHeap<Reverse<TopJob>>,
+    current: TopJob,
+}
+
+/// Streams jobstats lines into Prometheus text, keeping its buffers across
+/// jobs so that steady state parsing does not allocate beyond the output.
+struct Parser<'a> {
+    tx: &'a Sender<CompactString>,
+    filter: &'a JobFilter,
+    exemplars: bool,
+    ids: Option<&'a JobIdInfo>,
+    deltas: Option<&'a JobDeltas>,
+    /// When this stream started, for the retention of `deltas`
+    now: Instant,
+    /// Scratch buffer for the keys of `deltas`
+    delta_key: String,
+    overflow: Overflow,
+    top: Option<TopJobs>,
+    aggregate: JobAggregate,
+    /// The stats rolled up per target, then per group
+    groups: BTreeMap<String, BTreeMap<String, OverflowTarget>>,
+    state: State,
+    kind: TargetVariant,
+    target: String,
+    jobid: String,
+    /// The group the current job is rolled up into
+    group: String,
+    /// The `component`, `target` and `jobid` labels of the current job, closing brace included
+    labels: String,
+    /// The labels split from the jobid of the current job
+    id_labels: String,
+    snapshot_time: String,
+    mode: JobMode,
+    target_ops: TargetOps,
+}
+
+impl Parser<'_> {
+    fn handle_line(&mut self, line: &str) -> Result<ControlFlow<()>, Error> {
+        // Nobody is listening anymore, stop reading so lctl can be reaped.
+        if self.tx.is_closed() {
+            return Ok(ControlFlow::Break(()));
+        }
+
+        if line == "job_stats:"
+            || line.starts_with("  start_time:")
+            || line.starts_with("  elapsed_time:")
+        {
+            return Ok(ControlFlow::Continue(()));
+        }
+
+        match self.state {
+            State::Empty | State::Target | State::TargetJobStats
+                if line.starts_with("obdfilter") || line.starts_with("mdt.") =>
+            {
+                let (kind, target) =
+                    parse_target(line).ok_or_else(|| Error::NoCap("target", line.to_owned()))?;
+
+                self.finish_target()?;
+
+                self.kind = kind;
+                self.target.clear();
+                self.target.push_str(target);
+
+                self.state = State::Target;
+            }
+            State::Target | State::TargetJobStats if line.starts_with("- job_id:") => {
+                self.start_job(line)?;
+
+                self.state = State::TargetJob;
+            }
+            State::TargetJob | State::TargetJobStats if line.starts_with("  ") => {
+                self.handle_stat(line)?;
+
+                self.state = State::TargetJobStats;
+            }
+            x => {
+                tracing::debug!("Unexpected line: {line}, state: {x:?}");
+
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn start_job(&mut self, line: &str) -> Result<(), Error> {
+        self.finish_job()?;
+
+        self.jobid.clear();
+        self.jobid.extend(
+            line.trim_start_matches("- job_id:")
+                .chars()
+                .filter(|&x| x != '"'),
+        );
+
+        let jobid = self.jobid.trim();
+
+        self.snapshot_time.clear();
+
+        self.mode = if !self.filter.matches(jobid) {
+            JobMode::Skip
+        } else if let Some(group) = self.aggregate.group(jobid) {
+            let groups = match self.groups.get_mut(self.target.as_str()) {
+                Some(x) => x,
+                None => self.groups.entry(self.target.clone()).or_default(),
+            };
+
+            let x = match groups.get_mut(group) {
+                Some(x) => x,
+                None => groups
+                    .entry(group.to_string())
+                    .or_insert(OverflowTarget::new(self.kind)),
+            };
+
+            x.jobs += 1;
+
+            self.group.clear();
+            self.group.push_str(group);
+
+            JobMode::Aggregate
+        } else if self.top.is_some() {
+            JobMode::Top
+        } else if self.overflow.job(self.kind, &self.target) {
+            JobMode::Overflow
+        } else {
+            JobMode::Export
+        };
+
+        if let JobMode::Export | JobMode::Top = self.mode {
+            self.labels.clear();
+
+            _ = write!(
+                self.labels,
+                "component=\"{}\",target=\"{}\",jobid=\"{}\"}}",
+                self.kind.to_prom_label(),
+                self.target,
+                escape_label_value(jobid)
+            );
+
+            self.send_ids_info();
+        }
+
+        Ok(())
+    }
+
+    /// Sends `x`, or holds it back with the current job if it is [`JobMode::Top`].
+    fn send(&mut self, x: &str) {
+        match (self.mode, &mut self.top) {
+            (JobMode::Top, Some(top)) => top.current.out.push_str(x),
+            _ => {
+                _ = self.tx.blocking_send(x.into());
+            }
+        }
+    }
+
+    /// Keeps the job just read if it is one of the top jobs of its target so
+    /// far, adding the job it takes the place of to the `_overflow` job.
+    fn finish_job(&mut self) -> Result<(), Error> {
+        let (JobMode::Top, Some(top)) = (self.mode, &mut self.top) else {
+            return Ok(());
+        };
+
+        self.mode = JobMode::Skip;
+
+        top.kept.push(Reverse(mem::take(&mut top.current)));
+
+        if top.kept.len() <= top.n {
+            return Ok(());
+        }
+
+        let Some(Reverse(dropped)) = top.kept.pop() else {
+            return Ok(());
+        };
+
+        let x = match self.overflow.targets.get_mut(self.target.as_str()) {
+            Some(x) => x,
+            None => self
+                .overflow
+                .targets
+                .entry(self.target.clone())
+                .or_insert(OverflowTarget::new(self.kind)),
+        };
+
+        x.jobs += 1;
+
+        for (metric, field, stat_name, value) in &dropped.stats {
+            x.add(metric, *field, stat_name, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the jobs kept for the target just read, the one with the most I/O first.
+    fn finish_target(&mut self) -> Result<(), Error> {
+        self.finish_job()?;
+
+        let Some(top) = &mut self.top else {
+            return Ok(());
+        };
+
+        for Reverse(x) in mem::take(&mut top.kept).into_sorted_vec() {
+            _ = self.tx.blocking_send(x.out.into());
+        }
+
+        Ok(())
+    }
+
+    fn send_ids_info(&mut self) {
+        let Some(ids) = self.ids else {
+            return;
+        };
+
+        self.id_labels.clear();
+
+        if !ids.write_labels(self.jobid.trim(), &mut self.id_labels) {
+            return;
+        }
+
+        let labels = self.labels.strip_suffix('}').unwrap_or(&self.labels);
+
+        let x = format_compact!("{}{{{labels}{}}} 1\n", JOB_IDS_INFO.name, self.id_labels);
+
+        self.send(&x);
+    }
+
+    fn handle_stat(&mut self, line: &str) -> Result<(), Error> {
+        let stat = parse_stat_line(line);
+
+        // Jobs that are not exported still count towards the totals of their target
+        if let Some(x) = &stat {
+            self.target_ops.add(self.kind, &self.target, x)?;
+        }
+
+        if let JobMode::Skip = self.mode {
+            return Ok(());
+        }
+
+        if let Some(x) = line.strip_prefix("  snapshot_time:") {
+            let x = x.trim();
+
+            if x.parse::<f64>().is_ok() {
+                self.snapshot_time.clear();
+                self.snapshot_time.push_str(x);
+            }
+
+            return Ok(());
+        }
+
+        let stat = stat.ok_or_else(|| Error::NoCap("job_stat", line.to_owned()))?;
+
+        // The overflow job only keeps plain sums, so histograms are exported for exported jobs alone
+        if let (JobMode::Export | JobMode::Top, Some(hist)) = (self.mode, stat.hist) {
+            if let Some(metric) = job_hist_metric(stat.name) {
+                self.send_hist(metric, hist, &stat)?;
+            }
+        }
+
+        for (metric, field) in job_metrics(&self.kind, stat.name) {
+            let value = stat.field(*field);
+
+            if let JobMode::Overflow = self.mode {
+                if let Some(x) = self.overflow.targets.get_mut(self.target.as_str()) {
+                    x.add(metric, *field, stat.name, value)?;
+                }
+
+                continue;
+            }
+
+            if let JobMode::Aggregate = self.mode {
+                if let Some(x) = self
+                    .groups
+                    .get_mut(self.target.as_str())
+                    .and_then(|x| x.get_mut(self.group.as_str()))
+                {
+                    x.add(metric, *field, stat.name, value)?;
+                }
+
+                continue;
+            }
+
+            if let (JobMode::Top, Some(top)) = (self.mode, &mut self.top) {
+                let io = match self.kind {
+                    TargetVariant::Ost => {
+                        [READ_BYTES.name, WRITE_BYTES.name].contains(&metric.name)
+                    }
+                    _ => metric.name == MDT_JOBSTATS_SAMPLES.name,
+                };
+
+                if io {
+                    top.current.score = top.current.score.saturating_add(value.parse()?);
+                }
+
+                top.current
+                    .stats
+                    .push((metric, *field, stat.name.into(), value.into()));
+            }
+
+            let jobid = self.jobid.trim();
+
+            let delta;
+
+            let value = match self.deltas {
+                Some(deltas) if matches!(metric.r#type, MetricType::Counter) => {
+                    self.delta_key.clear();
+
+                    _ = write!(
+                        self.delta_key,
+                        "{}\0{jobid}\0{}\0{}",
+                        self.target, metric.name, stat.name
+                    );
+
+                    delta = deltas
+                        .observe(&self.delta_key, value.parse()?, self.now)
+                        .to_compact_string();
+
+                    delta.as_str()
+                }
+                _ => value,
+            };
+
+            let exemplar =
+                (self.exemplars && metric.name == WRITE_BYTES.name).then_some(Exemplar {
+                    jobid,
+                    timestamp: Some(self.snapshot_time.as_str()).filter(|x| !x.is_empty()),
+                });
+
+            let x = format_stat(metric.name, stat.name, &self.labels, value, exemplar);
+
+            self.send(&x);
+        }
+
+        Ok(())
+    }
+
+    /// Sends the buckets, sum and count of the `hist` field of `stat` as a single chunk.
+    ///
+    /// Lustre keys each bucket by its lower bound, so `4K: 35` counts sizes from
+    /// 4K up to 8K. Prometheus wants the upper bound as `le`, so each bucket is
+    /// exported as twice its key.
+    fn send_hist(&mut self, metric: &Metric, hist: &str, stat: &StatLine<'_>) -> Result<(), Error> {
+        let Some(mut buckets) = hist_buckets(hist) else {
+            tracing::debug!("Unexpected jobstats hist: {hist}");
+
+            return Ok(());
+        };
+
+        buckets.sort_unstable();
+
+        let samples = stat.samples.parse::<u64>()?;
+        let labels = self.labels.strip_suffix('}').unwrap_or(&self.labels);
+        let name = metric.name;
+
+        let mut out = String::new();
+        let mut cumulative = 0;
+
+        for (size, count) in buckets {
+            cumulative += count;
+
+            let le = size.saturating_mul(2);
+
+            _ = writeln!(out, "{name}_bucket{{{labels},le=\"{le}\"}} {cumulative}");
+        }
+
+        _ = writeln!(
+            out,
+            "{name}_bucket{{{labels},le=\"+Inf\"}} {}",
+            cumulative.max(samples)
+        );
+        _ = writeln!(out, "{name}_sum{{{labels}}} {}", stat.sum);
+        _ = writeln!(out, "{name}_count{{{labels}}} {}", cumulative.max(samples));
+
+        self.send(&out);
+
+        Ok(())
+    }
 }
 
+/// Calls `f` with each line of `r`, without its line ending.
+///
+/// Lines are borrowed straight out of the reader's buffer, only lines
+/// straddling two reads are copied into a scratch buffer first.
+fn for_each_line<R: BufRead>(
+    mut r: R,
+    mut f: impl FnMut(&str) -> Result<ControlFlow<()>, Error>,
+) -> Result<(), Error> {
+    let mut partial = Vec::new();
+
+    fn trim_cr(x: &[u8]) -> &[u8] {
+        x.strip_suffix(b"\r").unwrap_or(x)
+    }
+
+    loop {
+        let buf = r.fill_buf()?;
+
+        if buf.is_empty() {
+            break;
+        }
+
+        let (used, flow) = match memchr(b'\n', buf) {
+            Some(i) if partial.is_empty() => (i + 1, f(str::from_utf8(trim_cr(&buf[..i]))?)?),
+            Some(i) => {
+                partial.extend_from_slice(&buf[..i]);
+
+                let flow = f(str::from_utf8(trim_cr(&partial))?)?;
+
+                partial.clear();
+
+                (i + 1, flow)
+            }
+            None => {
+                partial.extend_from_slice(buf);
+
+                (buf.len(), ControlFlow::Continue(()))
+            }
+        };
+
+        r.consume(used);
+
+        if flow.is_break() {
+            return Ok(());
+        }
+    }
+
+    if !partial.is_empty() {
+        _ = f(str::from_utf8(trim_cr(&partial))?)?;
+    }
+
+    Ok(())
+}
+
+/// The params holding the job_stats of every OST and MDT.
+pub const JOBSTATS_PARAMS: [&str; 2] = ["obdfilter.*OST*.job_stats", "mdt.*.job_stats"];
+
+/// The `lctl get_param` call listing the job_stats of every OST and MDT, with its stdout piped.
+///
+/// It is not bounded in time, callers are expected to kill it once their timeout has elapsed.
+pub fn lctl_jobstats() -> Command {
+    let mut cmd = Command::new("lctl");
+
+    cmd.arg("get_param")
+        .args(JOBSTATS_PARAMS)
+        .stdout(Stdio::piped());
+
+    cmd
+}
+
+/// Streams `job_stats` output from `f` as Prometheus text, as `options` say.
+///
+/// Past the jobs the limit keeps, the rest are collapsed into a single
+/// `_overflow` job per target.
 pub fn jobstats_stream<R: BufRead + std::marker::Send + 'static>(
     f: R,
+    options: JobstatsOptions,
 ) -> (JoinHandle<()>, Receiver<CompactString>) {
+    let JobstatsOptions {
+        filter,
+        limit,
+        exemplars,
+        ids,
+        deltas,
+        aggregate,
+    } = options;
+
     let (tx, rx) = mpsc::channel(200);
 
-    enum LoopInstruction {
-        Noop,
-        Return,
+    let x = tokio::task::spawn_blocking(move || {
+        let mut parser = Parser {
+            tx: &tx,
+            filter: &filter,
+            exemplars,
+            ids: ids.as_ref(),
+            deltas: deltas.as_deref(),
+            now: Instant::now(),
+            delta_key: String::new(),
+            overflow: Overflow {
+                max_jobs: match limit {
+                    JobLimit::Max(x) => Some(x),
+                    _ => None,
+                },
+                ..Default::default()
+            },
+            top: match limit {
+                JobLimit::Top(n) => Some(TopJobs {
+                    n,
+                    ..Default::default()
+                }),
+                _ => None,
+            },
+            aggregate,
+            groups: BTreeMap::new(),
+            state: State::Empty,
+            kind: TargetVariant::Ost,
+            target: String::new(),
+            jobid: String::new(),
+            group: String::new(),
+            labels: String::new(),
+            id_labels: String::new(),
+            snapshot_time: String::new(),
+            mode: JobMode::Skip,
+            target_ops: TargetOps::default(),
+        };
+
+        // Send a new line to make sure we are printing stats with a separating empty line.
+        // OpenMetrics does not allow empty lines.
+        if !exemplars {
+            _ = tx.blocking_send("\n".to_compact_string());
+        }
+
+        let mut stopped = false;
+
+        let r = for_each_line(f, |line| {
+            let flow = parser.handle_line(line)?;
+
+            stopped = flow.is_break();
+
+            Ok(flow)
+        });
+
+        if let Err(e) = r {
+            tracing::debug!("Unexpected error processing jobstats lines: {e}");
+
+            return;
+        }
+
+        if stopped {
+            return;
+        }
+
+        if let Err(e) = parser.finish_target() {
+            tracing::debug!("Unexpected error processing jobstats lines: {e}");
+
+            return;
+        }
+
+        if let Some(x) = parser.deltas {
+            x.expire(parser.now);
+        }
+
+        parser.overflow.render(&tx);
+        render_groups(parser.groups, parser.aggregate, &tx);
+        parser.target_ops.render(&tx);
+    });
+
+    (x, rx)
+}
+
+/// Parses a `obdfilter.<target>.job_stats=` or `mdt.<target>.job_stats=` line.
+fn parse_target(line: &str) -> Option<(TargetVariant, &str)> {
+    let x = line.strip_suffix(".job_stats=")?;
+
+    let (kind, target) = if let Some(x) = x.strip_prefix("obdfilter.") {
+        (TargetVariant::Ost, x)
+    } else {
+        (TargetVariant::Mdt, x.strip_prefix("mdt.")?)
+    };
+
+    let valid = !target.is_empty()
+        && target
+            .bytes()
+            .all(|x| x.is_ascii_alphanumeric() || x == b'_' || x == b'-');
+
+    valid.then_some((kind, target))
+}
+
+/// A `  <name>: { samples: .., unit: .., min: .., max: .., sum: .., sumsq: .. }` line.
+#[derive(Debug, PartialEq)]
+struct StatLine<'a> {
+    name: &'a str,
+    samples: &'a str,
+    unit: &'a str,
+    min: &'a str,
+    max: &'a str,
+    sum: &'a str,
+    sumsq: &'a str,
+    /// The buckets of the `hist: { 4K: 35, 8K: 49 }` field newer releases add, without the braces
+    hist: Option<&'a str>,
+}
+
+impl<'a> StatLine<'a> {
+    fn field(&self, x: Field) -> &'a str {
+        match x {
+            Field::Samples => self.samples,
+            Field::Min => self.min,
+            Field::Max => self.max,
+            Field::Sum => self.sum,
+        }
+    }
+}
+
+/// Splits the `<key>: <value>` field at the start of `x`, returning the value and what follows it.
+fn stat_field<'a>(x: &'a str, key: &str, numeric: bool) -> Option<(&'a str, &'a str)> {
+    let x = x
+        .trim_start()
+        .strip_prefix(key)?
+        .strip_prefix(':')?
+        .trim_start();
+
+    let end = x.find([',', ' ', '}']).unwrap_or(x.len());
+    let (value, rest) = x.split_at(end);
+
+    let valid = if numeric {
+        value.bytes().all(|x| x.is_ascii_digit())
+    } else {
+        value.bytes().all(|x| x.is_ascii_lowercase())
+    };
+
+    if value.is_empty() || !valid {
+        return None;
+    }
+
+    Some((value, rest.strip_prefix(',').unwrap_or(rest)))
+}
+
+fn parse_stat_line(line: &str) -> Option<StatLine<'_>> {
+    let (name, rest) = line.strip_prefix("  ")?.split_once(':')?;
+
+    if name.is_empty() || !name.bytes().all(|x| x.is_ascii_lowercase() || x == b'_') {
+        return None;
+    }
+
+    let rest = rest.trim_start().strip_prefix('{')?;
+
+    let (samples, rest) = stat_field(rest, "samples", true)?;
+    let (unit, rest) = stat_field(rest, "unit", false)?;
+    let (min, rest) = stat_field(rest, "min", true)?;
+    let (max, rest) = stat_field(rest, "max", true)?;
+    let (sum, rest) = stat_field(rest, "sum", true)?;
+    let (sumsq, rest) = stat_field(rest, "sumsq", true)?;
+
+    let hist = rest
+        .trim_start()
+        .strip_prefix("hist:")
+        .and_then(|x| x.trim_start().strip_prefix('{'))
+        .and_then(|x| x.split_once('}'))
+        .map(|(x, _)| x);
+
+    Some(StatLine {
+        name,
+        samples,
+        unit,
+        min,
+        max,
+        sum,
+        sumsq,
+        hist,
+    })
+}
+
+/// Parses a histogram bucket size such as `512`, `4K` or `1M` into bytes.
+fn parse_size(x: &str) -> Option<u64> {
+    let (n, shift) = match x.as_bytes().last()? {
+        b'K' => (&x[..x.len() - 1], 10),
+        b'M' => (&x[..x.len() - 1], 20),
+        b'G' => (&x[..x.len() - 1], 30),
+        _ => (x, 0),
+    };
+
+    n.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// The size in bytes and count of each bucket of a `hist` field, or `None` if one can't be parsed.
+fn hist_buckets(x: &str) -> Option<Vec<(u64, u64)>> {
+    x.split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            let (size, count) = x.split_once(':')?;
+
+            Some((parse_size(size.trim())?, count.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// OpenMetrics limits the labels of an exemplar to this many characters.
+const MAX_EXEMPLAR_LABELS_LEN: usize = 128;
+
+/// An exemplar labelled with the jobid of the sample it is attached to.
+struct Exemplar<'a> {
+    jobid: &'a str,
+    /// The `snapshot_time` of the job, if it has one
+    timestamp: Option<&'a str>,
+}
+
+/// Formats a sample line, where `labels` are the formatted labels of its job.
+fn format_stat(
+    name: &str,
+    stat_name: &str,
+    labels: &str,
+    value: &str,
+    exemplar: Option<Exemplar<'_>>,
+) -> CompactString {
+    match exemplar {
+        Some(Exemplar { jobid, timestamp })
+            if "jobid".len() + jobid.len() <= MAX_EXEMPLAR_LABELS_LEN =>
+        {
+            let timestamp = timestamp
+                .map(|x| format_compact!(" {x}"))
+                .unwrap_or_default();
+            let jobid = escape_label_value(jobid);
+
+            format_compact!(
+                "{name}{{operation=\"{stat_name}\",{labels} {value} # {{jobid=\"{jobid}\"}} {value}{timestamp}\n"
+            )
+        }
+        _ => format_compact!("{name}{{operation=\"{stat_name}\",{labels} {value}\n"),
+    }
+}
+
+/// Which field of a job stat a metric is rendered from.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Samples,
+    Min,
+    Max,
+    Sum,
+}
+
+static OST_READ_BYTES: [(Metric, Field); 4] = [
+    (READ_SAMPLES, Field::Samples),
+    (READ_MIN_SIZE_BYTES, Field::Min),
+    (READ_MAX_SIZE_BYTES, Field::Max),
+    (READ_BYTES, Field::Sum),
+];
+static OST_WRITE_BYTES: [(Metric, Field); 4] = [
+    (WRITE_SAMPLES, Field::Samples),
+    (WRITE_MIN_SIZE_BYTES, Field::Min),
+    (WRITE_MAX_SIZE_BYTES, Field::Max),
+    (WRITE_BYTES, Field::Sum),
+];
+static JOBSTATS_SAMPLES: [(Metric, Field); 1] = [(MDT_JOBSTATS_SAMPLES, Field::Samples)];
+
+/// The histogram the `hist` field of a job stat is exported as.
+fn job_hist_metric(stat_name: &str) -> Option<&'static Metric> {
+    match stat_name {
+        "read_bytes" => Some(&READ_BYTES_SIZE),
+        "write_bytes" => Some(&WRITE_BYTES_SIZE),
+        _ => None,
+    }
+}
+
+/// The metrics a job stat is exported as.
+fn job_metrics(kind: &TargetVariant, stat_name: &str) -> &'static [(Metric, Field)] {
+    match (kind, stat_name) {
+        (TargetVariant::Ost, "read_bytes") => &OST_READ_BYTES,
+        (TargetVariant::Ost, "write_bytes") => &OST_WRITE_BYTES,
+        (
+            TargetVariant::Ost,
+            "getattr" | "setattr" | "punch" | "sync" | "destroy" | "create" | "statfs" | "get_info"
+            | "set_info" | "quotactl",
+        ) => &JOBSTATS_SAMPLES,
+        (
+            TargetVariant::Mdt,
+            "open"
+            | "close"
+            | "mknod"
+            | "link"
+            | "unlink"
+            | "mkdir"
+            | "rmdir"
+            | "rename"
+            | "getattr"
+            | "setattr"
+            | "getxattr"
+            | "setxattr"
+            | "statfs"
+            | "sync"
+            | "samedir_rename"
+            | "parallel_rename_file"
+            | "parallel_rename_dir"
+            | "crossdir_rename"
+            | "read"
+            | "write"
+            | "read_bytes"
+            | "write_bytes"
+            | "punch"
+            | "migrate",
+        ) => &JOBSTATS_SAMPLES,
+        (TargetVariant::Ost, x) => {
+            tracing::debug!("Unhandled OST jobstats stats: {x}");
+            &[]
+        }
+        (TargetVariant::Mdt, x) => {
+            tracing::debug!("Unhandled MDT jobstats stats: {x}");
+            &[]
+        }
+        (TargetVariant::Mgt, _) => &[],
+    }
+}
+
+static DROPPED_JOBS: Metric = Metric {
+    name: "lustre_job_stats_dropped_jobs",
+    help: "Number of jobs collapsed into the _overflow job because the jobstats job limit was reached.",
+    r#type: MetricType::Gauge,
+};
+static AGGREGATED_JOBS: Metric = Metric {
+    name: "lustre_job_stats_aggregated_jobs",
+    help: "Number of jobs rolled up into the group by --jobstats-aggregate.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&READ_SAMPLES, "obdfilter.*OST*.job_stats, mdt.*.job_stats"),
+    (
+        &READ_MIN_SIZE_BYTES,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (
+        &READ_MAX_SIZE_BYTES,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (&READ_BYTES, "obdfilter.*OST*.job_stats, mdt.*.job_stats"),
+    (&WRITE_SAMPLES, "obdfilter.*OST*.job_stats, mdt.*.job_stats"),
+    (
+        &WRITE_MIN_SIZE_BYTES,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (
+        &WRITE_MAX_SIZE_BYTES,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (&WRITE_BYTES, "obdfilter.*OST*.job_stats, mdt.*.job_stats"),
+    (
+        &READ_BYTES_SIZE,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (
+        &WRITE_BYTES_SIZE,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (
+        &MDT_JOBSTATS_SAMPLES,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (&JOB_IDS_INFO, "obdfilter.*OST*.job_stats, mdt.*.job_stats"),
+    (
+        &TARGET_JOB_OPS,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+    (&DROPPED_JOBS, "obdfilter.*OST*.job_stats, mdt.*.job_stats"),
+    (
+        &AGGREGATED_JOBS,
+        "obdfilter.*OST*.job_stats, mdt.*.job_stats",
+    ),
+];
+
+const OVERFLOW_JOBID: &str = "_overflow";
+
+/// The group of jobids that do not have the format expected by their [`JobAggregate`].
+const OTHER_JOBID: &str = "_other";
+
+/// Jobs of a target collapsed together, into the `_overflow` job or the group of a [`JobAggregate`].
+#[derive(Debug)]
+struct OverflowTarget {
+    kind: TargetVariant,
+    jobs: u64,
+    /// Values by metric name, then by stat name
+    values: BTreeMap<&'static str, BTreeMap<String, u64>>,
+}
+
+impl OverflowTarget {
+    fn new(kind: TargetVariant) -> Self {
+        Self {
+            kind,
+            jobs: 0,
+            values: BTreeMap::new(),
+        }
+    }
+
+    fn render(&self, tx: &Sender<CompactString>, labels: &str) {
+        for (name, values) in &self.values {
+            for (stat_name, value) in values {
+                _ = tx.blocking_send(format_stat(
+                    name,
+                    stat_name,
+                    labels,
+                    &value.to_string(),
+                    None,
+                ));
+            }
+        }
+    }
+
+    fn add(
+        &mut self,
+        metric: &Metric,
+        field: Field,
+        stat_name: &str,
+        value: &str,
+    ) -> Result<(), Error> {
+        let value = value.parse::<u64>()?;
+
+        let values = self.values.entry(metric.name).or_default();
+
+        let Some(x) = values.get_mut(stat_name) else {
+            values.insert(stat_name.to_string(), value);
+
+            return Ok(());
+        };
+
+        *x = match field {
+            Field::Min => (*x).min(value),
+            Field::Max => (*x).max(value),
+            Field::Samples | Field::Sum => *x + value,
+        };
+
+        Ok(())
+    }
+}
+
+/// Caps the number of jobs exported, collapsing the rest into an `_overflow` job per target.
+#[derive(Debug, Default)]
+struct Overflow {
+    max_jobs: Option<usize>,
+    jobs: usize,
+    targets: BTreeMap<String, OverflowTarget>,
+}
+
+impl Overflow {
+    /// Counts another job, returning whether the limit is reached and it
+    /// should be aggregated into the `_overflow` job of `target`.
+    fn job(&mut self, kind: TargetVariant, target: &str) -> bool {
+        self.jobs += 1;
+
+        match self.max_jobs {
+            Some(x) if self.jobs > x => {}
+            _ => return false,
+        }
+
+        let x = match self.targets.get_mut(target) {
+            Some(x) => x,
+            None => self
+                .targets
+                .entry(target.to_string())
+                .or_insert(OverflowTarget::new(kind)),
+        };
+
+        x.jobs += 1;
+
+        true
+    }
+
+    fn render(self, tx: &Sender<CompactString>) {
+        if self.targets.is_empty() {
+            return;
+        }
+
+        for (target, x) in &self.targets {
+            let labels = format!(
+                "component=\"{}\",target=\"{target}\",jobid=\"{OVERFLOW_JOBID}\"}}",
+                x.kind.to_prom_label()
+            );
+
+            x.render(tx, &labels);
+        }
+
+        _ = tx.blocking_send(format_compact!(
+            "# HELP {} {}\n# TYPE {} gauge\n",
+            DROPPED_JOBS.name,
+            DROPPED_JOBS.help,
+            DROPPED_JOBS.name
+        ));
+
+        for (target, x) in self.targets {
+            _ = tx.blocking_send(format_compact!(
+                "{}{{component=\"{}\",target=\"{target}\"}} {}\n",
+                DROPPED_JOBS.name,
+                x.kind.to_prom_label(),
+                x.jobs
+            ));
+        }
+    }
+}
+
+/// Sends the stats rolled up per target and group by `aggregate`, then the number of jobs in each group.
+fn render_groups(
+    groups: BTreeMap<String, BTreeMap<String, OverflowTarget>>,
+    aggregate: JobAggregate,
+    tx: &Sender<CompactString>,
+) {
+    if groups.is_empty() {
+        return;
     }
 
-    fn handle_line(
-        tx: &Sender<CompactString>,
-        maybe_line: Result<String, Error>,
-        mut state: State,
-    ) -> Result<(State, LoopInstruction), Error> {
-        let line = maybe_line?;
+    let label = aggregate.label();
 
-        match state {
-            _ if line == "job_stats:"
-                || line.starts_with("  start_time:")
-                || line.starts_with("  elapsed_time:")
-                || line.starts_with("  snapshot_time:") =>
-            {
-                return Ok((state, LoopInstruction::Noop))
-            }
-            State::Empty | State::Target(_)
-                if line.starts_with("obdfilter") || line.starts_with("mdt.") =>
-            {
-                state = State::Target(line);
-            }
-            State::Target(x) if line.starts_with("- job_id:") => {
-                state = State::TargetJob(x, line);
-            }
-            State::TargetJob(target, job) if line.starts_with("  ") => {
-                let mut xs = Vec::with_capacity(10);
+    for (target, xs) in &groups {
+        for (group, x) in xs {
+            let labels = format!(
+                "component=\"{}\",target=\"{target}\",{label}=\"{}\"}}",
+                x.kind.to_prom_label(),
+                escape_label_value(group)
+            );
 
-                xs.push(line);
+            x.render(tx, &labels);
+        }
+    }
 
-                state = State::TargetJobStats(target, job, xs);
-            }
-            State::TargetJobStats(target, job, mut stats) if line.starts_with("  ") => {
-                stats.push(line);
+    let mut out = format!(
+        "# HELP {} {}\n# TYPE {} gauge\n",
+        AGGREGATED_JOBS.name, AGGREGATED_JOBS.help, AGGREGATED_JOBS.name
+    );
 
-                state = State::TargetJobStats(target, job, stats);
-            }
-            State::TargetJobStats(target, job, stats) if line.starts_with("- job_id:") => {
-                render_stat(tx, &target, job, stats)?;
+    for (target, xs) in groups {
+        for (group, x) in xs {
+            _ = writeln!(
+                out,
+                "{}{{component=\"{}\",target=\"{target}\",{label}=\"{}\"}} {}",
+                AGGREGATED_JOBS.name,
+                x.kind.to_prom_label(),
+                escape_label_value(&group),
+                x.jobs
+            );
+        }
+    }
 
-                state = State::TargetJob(target, line);
-            }
-            State::TargetJobStats(target, job, stats)
-                if line.starts_with("obdfilter") || line.starts_with("mdt.") =>
-            {
-                render_stat(tx, &target, job, stats)?;
+    _ = tx.blocking_send(out.into());
+}
 
-                state = State::Target(line);
-            }
-            x => {
-                tracing::debug!("Unexpected line: {line}, state: {x:?}");
-                return Ok((x, LoopInstruction::Return));
-            }
-        }
+/// The operations summed per target into `lustre_target_job_ops_total`.
+const TARGET_OPS: [&str; 4] = ["create", "destroy", "punch", "setattr"];
 
-        Ok((state, LoopInstruction::Noop))
-    }
+/// Totals of [`TARGET_OPS`] per target over every job, whether it is exported or not.
+#[derive(Debug, Default)]
+struct TargetOps(BTreeMap<String, (TargetVariant, [u64; TARGET_OPS.len()])>);
 
-    let x = tokio::task::spawn_blocking(move || {
-        let mut state = State::Empty;
+impl TargetOps {
+    fn add(&mut self, kind: TargetVariant, target: &str, stat: &StatLine<'_>) -> Result<(), Error> {
+        let Some(i) = TARGET_OPS.iter().position(|&x| x == stat.name) else {
+            return Ok(());
+        };
 
-        // Send a new line to make sure we are printing stats with a separating empty line
-        _ = tx.blocking_send("\n".to_compact_string());
+        let samples = stat.samples.parse::<u64>()?;
 
-        for line in f.lines() {
-            let r = handle_line(&tx, line.map_err(Error::Io), state);
+        let (_, ops) = match self.0.get_mut(target) {
+            Some(x) => x,
+            None => self
+                .0
+                .entry(target.to_string())
+                .or_insert((kind, [0; TARGET_OPS.len()])),
+        };
 
-            match r {
-                Ok((new_state, LoopInstruction::Noop)) => state = new_state,
-                Ok((_, LoopInstruction::Return)) => return,
-                Err(e) => {
-                    tracing::debug!("Unexpected error processing jobstats lines: {e}");
+        ops[i] += samples;
 
-                    return;
-                }
-            }
-        }
+        Ok(())
+    }
 
-        if let State::TargetJobStats(target, job, stats) = state {
-            if let Err(e) = render_stat(&tx, &target, job, stats) {
-                tracing::debug!("Unexpected error processing jobstats lines: {e}");
-            };
+    /// Sends the whole metric family as a single chunk.
+    fn render(self, tx: &Sender<CompactString>) {
+        if self.0.is_empty() {
+            return;
         }
-    });
 
-    (x, rx)
-}
+        let mut out = format!(
+            "# HELP {} {}\n# TYPE {} counter\n",
+            TARGET_JOB_OPS.name, TARGET_JOB_OPS.help, TARGET_JOB_OPS.name
+        );
 
-static TARGET: LazyLock<regex::Regex> = LazyLock::new(|| {
-    Regex::new(r#"^(obdfilter|mdt)\.([a-zA-Z0-9_-]+)\.job_stats=$"#).expect("A Well-formed regex")
-});
-
-static JOB_STAT: LazyLock<regex::Regex> = LazyLock::new(|| {
-    Regex::new(
-        r"(?x)
-        ^\ \ (?<stat>[a-z_]+):\ +\{         # 1. stat name
-        \ samples:\ +(?<sample>[0-9]+),     # 2. sample value
-        \ unit:\ +([a-z]+),                 # 3. unit value
-        \ min:\ +(?<min>[0-9]+),            # 4. min value
-        \ max:\ +(?<max>[0-9]+),            # 5. max value
-        \ sum:\ +(?<sum>[0-9]+),            # 6. sum value
-        \ sumsq:\ +(?<sumsq>[0-9]+)         # 7. sumsq value
-",
-    )
-    .expect("A Well-formed regex")
-});
-
-fn send_stat(
-    tx: &Sender<CompactString>,
-    name: &str,
-    stat_name: &str,
-    target: &str,
-    job: &str,
-    kind: &TargetVariant,
-    value: &str,
-) {
-    _ = tx.blocking_send(name.to_compact_string());
+        for (target, (kind, ops)) in self.0 {
+            for (operation, value) in TARGET_OPS.iter().zip(ops) {
+                _ = writeln!(
+                    out,
+                    "{}{{component=\"{}\",target=\"{target}\",operation=\"{operation}\"}} {value}",
+                    TARGET_JOB_OPS.name,
+                    kind.to_prom_label()
+                );
+            }
+        }
 
-    _ = tx.blocking_send("{operation=".to_compact_string());
+        _ = tx.blocking_send(out.into());
+    }
+}
 
-    _ = tx.blocking_send(format_compact!("\"{stat_name}\","));
+/// The stats of a single job on a single target.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct JobStats {
+    pub target: String,
+    pub job_id: String,
+    pub snapshot_time: Option<String>,
+    pub stats: Vec<JobStat>,
+}
 
-    _ = tx.blocking_send(format_compact!("component=\"{}\",", kind.to_prom_label()));
+#[derive(Debug, PartialEq, Serialize)]
+pub struct JobStat {
+    pub name: String,
+    pub samples: u64,
+    pub unit: String,
+    pub min: u64,
+    pub max: u64,
+    pub sum: u64,
+    pub sumsq: u64,
+    /// The count of each bucket of the size histogram, by bucket size in bytes
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub hist: BTreeMap<u64, u64>,
+}
 
-    _ = tx.blocking_send(format_compact!("target=\"{target}\","));
+/// Scans `job_stats` output for `jobid` and returns the first match.
+pub fn find_job<R: BufRead>(f: R, jobid: &str) -> Result<Option<JobStats>, Error> {
+    let mut target = String::new();
+    let mut found: Option<JobStats> = None;
 
-    _ = tx.blocking_send(format_compact!("jobid=\"{job}\"}} {value}\n"));
-}
+    for_each_line(f, |line| {
+        if line.starts_with("obdfilter") || line.starts_with("mdt.") {
+            if found.is_some() {
+                return Ok(ControlFlow::Break(()));
+            }
 
-fn render_stat(
-    tx: &Sender<CompactString>,
-    target: &str,
-    job: String,
-    stats: Vec<String>,
-) -> Result<(), Error> {
-    let (_, [kind, target]) = TARGET
-        .captures(target)
-        .ok_or_else(|| Error::NoCap("target", target.to_owned()))?
-        .extract();
+            target.clear();
+            target.push_str(line);
+        } else if let Some(job) = line.strip_prefix("- job_id:") {
+            if found.is_some() {
+                return Ok(ControlFlow::Break(()));
+            }
 
-    let kind = if kind == "obdfilter" {
-        TargetVariant::Ost
-    } else {
-        TargetVariant::Mdt
-    };
+            if job.replace('"', "").trim() != jobid {
+                return Ok(ControlFlow::Continue(()));
+            }
 
-    let job = job.replace("- job_id:", "").replace('"', "");
-    let jobid = job.trim();
-
-    for stat in stats {
-        let cap = JOB_STAT
-            .captures(&stat)
-            .ok_or_else(|| Error::NoCap("job_stat", stat.to_owned()))?;
-
-        let (_, [stat_name, samples, _unit, min, max, sum, _sumsq]) = cap.extract();
-
-        if kind == TargetVariant::Ost {
-            match stat_name {
-                "read_bytes" => {
-                    for (value, metric) in [
-                        (samples, READ_SAMPLES),
-                        (min, READ_MIN_SIZE_BYTES),
-                        (max, READ_MAX_SIZE_BYTES),
-                        (sum, READ_BYTES),
-                    ] {
-                        send_stat(tx, metric.name, stat_name, target, jobid, &kind, value);
-                    }
-                }
-                "write_bytes" => {
-                    for (value, metric) in [
-                        (samples, WRITE_SAMPLES),
-                        (min, WRITE_MIN_SIZE_BYTES),
-                        (max, WRITE_MAX_SIZE_BYTES),
-                        (sum, WRITE_BYTES),
-                    ] {
-                        send_stat(tx, metric.name, stat_name, target, jobid, &kind, value);
-                    }
-                }
-                "getattr" | "setattr" | "punch" | "sync" | "destroy" | "create" | "statfs"
-                | "get_info" | "set_info" | "quotactl" => {
-                    send_stat(
-                        tx,
-                        MDT_JOBSTATS_SAMPLES.name,
-                        stat_name,
-                        target,
-                        jobid,
-                        &kind,
-                        samples,
-                    );
-                }
-                x => {
-                    tracing::debug!("Unhandled OST jobstats stats: {x}");
-                    continue;
-                }
-            };
-        } else if kind == TargetVariant::Mdt {
-            match stat_name {
-                "open"
-                | "close"
-                | "mknod"
-                | "link"
-                | "unlink"
-                | "mkdir"
-                | "rmdir"
-                | "rename"
-                | "getattr"
-                | "setattr"
-                | "getxattr"
-                | "setxattr"
-                | "statfs"
-                | "sync"
-                | "samedir_rename"
-                | "parallel_rename_file"
-                | "parallel_rename_dir"
-                | "crossdir_rename"
-                | "read"
-                | "write"
-                | "read_bytes"
-                | "write_bytes"
-                | "punch"
-                | "migrate" => {
-                    send_stat(
-                        tx,
-                        MDT_JOBSTATS_SAMPLES.name,
-                        stat_name,
-                        target,
-                        jobid,
-                        &kind,
-                        samples,
-                    );
-                }
-                x => {
-                    tracing::debug!("Unhandled MDT jobstats stats: {x}");
-                    continue;
-                }
-            };
+            let (_, target) =
+                parse_target(&target).ok_or_else(|| Error::NoCap("target", line.to_owned()))?;
+
+            found = Some(JobStats {
+                target: target.to_string(),
+                job_id: jobid.to_string(),
+                snapshot_time: None,
+                stats: vec![],
+            });
+        } else if let Some(job) = found.as_mut() {
+            if let Some(x) = line.strip_prefix("  snapshot_time:") {
+                job.snapshot_time = Some(x.trim().to_string());
+            } else if let Some(x) = parse_stat_line(line) {
+                job.stats.push(JobStat {
+                    name: x.name.to_string(),
+                    samples: x.samples.parse()?,
+                    unit: x.unit.to_string(),
+                    min: x.min.parse()?,
+                    max: x.max.parse()?,
+                    sum: x.sum.parse()?,
+                    sumsq: x.sumsq.parse()?,
+                    hist: x
+                        .hist
+                        .and_then(hist_buckets)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .collect(),
+                });
+            }
         }
-    }
 
-    Ok(())
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    Ok(found)
 }
 
 #[cfg(test)]
 pub mod tests {
     use const_format::{formatcp, str_repeat};
 
-    use crate::jobstats::jobstats_stream;
-    use std::{fs::File, io::BufReader};
+    use crate::jobstats::{
+        find_job, jobstats_stream, JobAggregate, JobDeltas, JobFilter, JobIdInfo, JobLimit,
+        JobStat, JobstatsOptions, DEFAULT_JOBID_PATTERN,
+    };
+    use crate::validate::validate;
+    use regex::Regex;
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        fs::File,
+        io::{BufReader, Cursor},
+        sync::Arc,
+        time::{Duration, Instant},
+    };
 
     #[tokio::test(flavor = "multi_thread")]
     async fn parse_larger_yaml() {
@@ -328,7 +1546,7 @@ pub mod tests {
 
         let f = BufReader::with_capacity(128 * 1_024, f);
 
-        let (fut, mut rx) = jobstats_stream(f);
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
 
         let mut cnt = 0;
 
@@ -338,7 +1556,7 @@ pub mod tests {
 
         fut.await.unwrap();
 
-        assert_eq!(cnt, 21_147_876 + 1);
+        assert_eq!(cnt, 3_524_646 + 2);
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -347,7 +1565,7 @@ pub mod tests {
 
         let f = BufReader::with_capacity(128 * 1_024, f);
 
-        let (fut, mut rx) = jobstats_stream(f);
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
 
         let mut cnt = 0;
 
@@ -357,7 +1575,7 @@ pub mod tests {
 
         fut.await.unwrap();
 
-        assert_eq!(cnt, 5_310_036 + 1);
+        assert_eq!(cnt, 885_006 + 2);
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -366,7 +1584,7 @@ pub mod tests {
 
         let f = BufReader::with_capacity(128 * 1_024, f);
 
-        let (fut, mut rx) = jobstats_stream(f);
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
 
         let mut cnt = 0;
 
@@ -376,7 +1594,7 @@ pub mod tests {
 
         fut.await.unwrap();
 
-        assert_eq!(cnt, 1_728 + 1);
+        assert_eq!(cnt, 288 + 2);
     }
 
     const JOBSTAT_JOB: &str = r#"
@@ -408,7 +1626,7 @@ job_stats:{}"#,
     async fn parse_synthetic_yaml() {
         let f = BufReader::with_capacity(128 * 1_024, INPUT_10_JOBS.as_bytes());
 
-        let (fut, mut rx) = jobstats_stream(f);
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
 
         let mut output = String::with_capacity(10 * 2 * JOBSTAT_JOB.len());
 
@@ -425,7 +1643,493 @@ job_stats:{}"#,
              10) // 10 metrics for "getattr" | "setattr" | "punch" | "sync" | "destroy" | "create" | "statfs" | "get_info" | "set_info" | "quotactl"
              * 10
                 + 1
+                + 2 // HELP and TYPE of lustre_target_job_ops_total
+                + 4 // Per target totals of create, destroy, punch and setattr
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lines_straddling_reads() {
+        async fn render(f: BufReader<&'static [u8]>) -> String {
+            let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
+
+            let mut output = String::new();
+
+            while let Some(x) = rx.recv().await {
+                output.push_str(x.as_str());
+            }
+
+            fut.await.unwrap();
+
+            output
+        }
+
+        let expected = render(BufReader::new(INPUT_10_JOBS.as_bytes())).await;
+
+        // A buffer smaller than a line means every line is split across reads
+        let output = render(BufReader::with_capacity(7, INPUT_10_JOBS.as_bytes())).await;
+
+        assert_eq!(output, expected);
+        assert!(expected.contains(
+            r#"lustre_job_stats_total{operation="quotactl",component="ost",target="ds002-OST0000",jobid="FAKE_JOB"} 0"#
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn target_job_ops() {
+        let input = format!(
+            "{INPUT_10_JOBS}{}",
+            JOBSTAT_JOB.replace("FAKE_JOB", "dd.1000").replace(
+                "create:          { samples:           0",
+                "create:          { samples:           7"
+            )
+        );
+
+        // Totals cover filtered out jobs too
+        let filter = JobFilter {
+            include: None,
+            exclude: Some(Regex::new("^dd").unwrap()),
+        };
+
+        let (fut, mut rx) = jobstats_stream(
+            BufReader::new(Cursor::new(input)),
+            JobstatsOptions {
+                filter,
+                limit: JobLimit::Max(2),
+                ..Default::default()
+            },
+        );
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        let totals: Vec<_> = output
+            .lines()
+            .filter(|x| x.starts_with("lustre_target_job_ops_total"))
+            .collect();
+
+        assert_eq!(
+            totals,
+            [
+                r#"lustre_target_job_ops_total{component="ost",target="ds002-OST0000",operation="create"} 7"#,
+                r#"lustre_target_job_ops_total{component="ost",target="ds002-OST0000",operation="destroy"} 0"#,
+                r#"lustre_target_job_ops_total{component="ost",target="ds002-OST0000",operation="punch"} 0"#,
+                r#"lustre_target_job_ops_total{component="ost",target="ds002-OST0000",operation="setattr"} 0"#,
+            ]
+        );
+        assert!(!output.contains("dd.1000"));
+
+        let family = output
+            .find("# HELP lustre_target_job_ops_total")
+            .map(|x| &output[x..])
+            .unwrap();
+
+        assert_eq!(validate(family, false), BTreeSet::new());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn job_ids_info() {
+        let input = format!(
+            "{INPUT_10_JOBS}{}",
+            JOBSTAT_JOB.replace("FAKE_JOB", "dd.1000")
+        );
+
+        let ids = JobIdInfo {
+            pattern: Regex::new(DEFAULT_JOBID_PATTERN).unwrap(),
+            names: Default::default(),
+        };
+
+        let (fut, mut rx) = jobstats_stream(
+            BufReader::new(Cursor::new(input)),
+            JobstatsOptions {
+                ids: Some(ids),
+                ..Default::default()
+            },
+        );
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        let info: Vec<_> = output
+            .lines()
+            .filter(|x| x.starts_with("lustre_job_ids_info"))
+            .collect();
+
+        assert_eq!(
+            info,
+            [
+                r#"lustre_job_ids_info{component="ost",target="ds002-OST0000",jobid="dd.1000",procname="dd",uid="1000"} 1"#
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn escape_jobids() {
+        let input = format!(
+            "obdfilter.ds002-OST0000.job_stats=\njob_stats:{}",
+            JOBSTAT_JOB.replace("FAKE_JOB", r"C:\tmp\dd")
+        );
+
+        let (fut, mut rx) = jobstats_stream(
+            BufReader::new(Cursor::new(input)),
+            JobstatsOptions::default(),
+        );
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        assert!(output.contains(r#"jobid="C:\\tmp\\dd""#));
+        assert_eq!(validate(&output, false), BTreeSet::new());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn filter_jobids() {
+        let input = format!(
+            "{INPUT_10_JOBS}{}{}",
+            JOBSTAT_JOB.replace("FAKE_JOB", "dd.1234"),
+            JOBSTAT_JOB.replace("FAKE_JOB", "cp.5678")
+        );
+
+        let filter = JobFilter {
+            include: Some(Regex::new(r"^[a-z]+\.[0-9]+$").unwrap()),
+            exclude: Some(Regex::new(r"^cp\.").unwrap()),
+        };
+
+        let (fut, mut rx) = jobstats_stream(
+            BufReader::new(Cursor::new(input)),
+            JobstatsOptions {
+                filter,
+                ..Default::default()
+            },
+        );
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        assert_eq!(output.lines().count(), 4 + 4 + 10 + 1 + 2 + 4);
+        assert!(output.contains(r#"jobid="dd.1234""#));
+        assert!(!output.contains("FAKE_JOB"));
+        assert!(!output.contains("cp.5678"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn aggregate_jobs() {
+        async fn render(input: &str, aggregate: JobAggregate) -> String {
+            let (fut, mut rx) = jobstats_stream(
+                BufReader::new(Cursor::new(input.to_string())),
+                JobstatsOptions {
+                    limit: JobLimit::Max(1),
+                    aggregate,
+                    ..Default::default()
+                },
+            );
+
+            let mut output = String::new();
+
+            while let Some(x) = rx.recv().await {
+                output.push_str(x.as_str());
+            }
+
+            fut.await.unwrap();
+
+            output
+        }
+
+        let input = [
+            "dd.1234",
+            "cp.1234",
+            "1234.100",
+            "SLURM_JOB123:0:client",
+            "123.1",
+            "cp",
+        ]
+        .iter()
+        .fold(
+            "obdfilter.ds002-OST0000.job_stats=\njob_stats:".to_string(),
+            |acc, x| acc + &JOBSTAT_JOB.replace("FAKE_JOB", x),
+        );
+
+        let output = render(&input, JobAggregate::User).await;
+
+        assert!(!output.contains("jobid="));
+        assert!(output.contains(
+            r#"lustre_job_write_bytes_total{operation="write_bytes",component="ost",target="ds002-OST0000",user="1234"} 16404480"#
+        ));
+        assert!(output.contains(
+            r#"lustre_job_write_maximum_size_bytes{operation="write_bytes",component="ost",target="ds002-OST0000",user="1234"} 475136"#
+        ));
+
+        insta::assert_snapshot!(output
+            .lines()
+            .filter(|x| x.starts_with("lustre_job_stats_aggregated_jobs"))
+            .collect::<Vec<_>>()
+            .join("\n"));
+
+        let output = render(&input, JobAggregate::SlurmJob).await;
+
+        assert!(output.contains(
+            r#"lustre_job_stats_aggregated_jobs{component="ost",target="ds002-OST0000",slurm_job="123"} 2"#
+        ));
+        assert!(output.contains(
+            r#"lustre_job_stats_aggregated_jobs{component="ost",target="ds002-OST0000",slurm_job="_other"} 3"#
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn top_jobs() {
+        let job = |jobid: &str, sum: &str| {
+            JOBSTAT_JOB
+                .replace("FAKE_JOB", jobid)
+                .replace("sum:          5468160", sum)
+        };
+
+        let input = format!(
+            "obdfilter.ds002-OST0000.job_stats=\njob_stats:{}{}{}\nobdfilter.ds002-OST0001.job_stats=\njob_stats:{}",
+            job("small", "sum: 1000"),
+            job("large", "sum: 5468160"),
+            job("medium", "sum: 2000"),
+            job("only", "sum: 10"),
+        );
+
+        let (fut, mut rx) = jobstats_stream(
+            BufReader::new(Cursor::new(input)),
+            JobstatsOptions {
+                limit: JobLimit::Top(1),
+                ..Default::default()
+            },
+        );
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        assert!(output.contains(
+            r#"lustre_job_write_bytes_total{operation="write_bytes",component="ost",target="ds002-OST0000",jobid="large"} 5468160"#
+        ));
+        assert!(output.contains(
+            r#"lustre_job_write_bytes_total{operation="write_bytes",component="ost",target="ds002-OST0001",jobid="only"} 10"#
+        ));
+        assert!(!output.contains(r#"jobid="small""#));
+        assert!(!output.contains(r#"jobid="medium""#));
+        assert!(output.contains(
+            r#"lustre_job_write_bytes_total{operation="write_bytes",component="ost",target="ds002-OST0000",jobid="_overflow"} 3000"#
+        ));
+        assert!(output.contains(
+            r#"lustre_job_stats_dropped_jobs{component="ost",target="ds002-OST0000"} 2"#
+        ));
+        assert!(!output.contains(r#"target="ds002-OST0001",jobid="_overflow""#));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn cap_jobs() {
+        let f = BufReader::with_capacity(128 * 1_024, INPUT_10_JOBS.as_bytes());
+
+        let (fut, mut rx) = jobstats_stream(
+            f,
+            JobstatsOptions {
+                limit: JobLimit::Max(8),
+                ..Default::default()
+            },
+        );
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        insta::assert_snapshot!(output
+            .lines()
+            .filter(|x| !x.contains("FAKE_JOB"))
+            .collect::<Vec<_>>()
+            .join("\n"));
+        assert_eq!(output.matches(r#"jobid="FAKE_JOB""#).count(), 18 * 8);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_bytes_exemplars() {
+        let f = BufReader::with_capacity(128 * 1_024, INPUT_10_JOBS.as_bytes());
+
+        let (fut, mut rx) = jobstats_stream(
+            f,
+            JobstatsOptions {
+                exemplars: true,
+                ..Default::default()
+            },
         );
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        assert!(!output.lines().any(str::is_empty));
+        assert_eq!(output.matches(" # {").count(), 10);
+        assert!(output.contains(
+            r#"lustre_job_write_bytes_total{operation="write_bytes",component="ost",target="ds002-OST0000",jobid="FAKE_JOB"} 5468160 # {jobid="FAKE_JOB"} 5468160 1720516680"#
+        ));
+    }
+
+    const HIST_JOB: &str = r#"obdfilter.ds002-OST0000.job_stats=
+job_stats:
+- job_id:          "dd.0"
+  snapshot_time:   1720516680
+  read_bytes:      { samples:           0, unit: bytes, min:        0, max:        0, sum:                0, sumsq:                  0, hist: {  } }
+  write_bytes:     { samples:          52, unit: bytes, min:     4096, max:  1048576, sum:          5468160, sumsq:      1071040692224, hist: { 4K: 35, 8K: 9, 1M: 8 } }
+"#;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_bytes_hist() {
+        let f = BufReader::with_capacity(128 * 1_024, HIST_JOB.as_bytes());
+
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        let hist: Vec<_> = output
+            .lines()
+            .filter(|x| x.starts_with("lustre_job_write_bytes_size"))
+            .collect();
+
+        assert_eq!(
+            hist,
+            vec![
+                r#"lustre_job_write_bytes_size_bucket{component="ost",target="ds002-OST0000",jobid="dd.0",le="8192"} 35"#,
+                r#"lustre_job_write_bytes_size_bucket{component="ost",target="ds002-OST0000",jobid="dd.0",le="16384"} 44"#,
+                r#"lustre_job_write_bytes_size_bucket{component="ost",target="ds002-OST0000",jobid="dd.0",le="2097152"} 52"#,
+                r#"lustre_job_write_bytes_size_bucket{component="ost",target="ds002-OST0000",jobid="dd.0",le="+Inf"} 52"#,
+                r#"lustre_job_write_bytes_size_sum{component="ost",target="ds002-OST0000",jobid="dd.0"} 5468160"#,
+                r#"lustre_job_write_bytes_size_count{component="ost",target="ds002-OST0000",jobid="dd.0"} 52"#,
+            ]
+        );
+        assert!(output.contains(r#"lustre_job_read_bytes_size_bucket{component="ost",target="ds002-OST0000",jobid="dd.0",le="+Inf"} 0"#));
+
+        let job = find_job(HIST_JOB.as_bytes(), "dd.0").unwrap().unwrap();
+
+        assert_eq!(
+            job.stats[1].hist,
+            BTreeMap::from([(4096, 35), (8192, 9), (1048576, 8)])
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hist_buckets_are_upper_bounds() {
+        // A single write of 6000 bytes, which Lustre counts in its 4K bucket
+        let input = HIST_JOB
+            .replace("samples:          52", "samples:           1")
+            .replace("sum:          5468160", "sum:             6000")
+            .replace("hist: { 4K: 35, 8K: 9, 1M: 8 }", "hist: { 4K: 1 }");
+
+        let f = BufReader::new(Cursor::new(input));
+
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
+
+        let mut output = String::new();
+
+        while let Some(x) = rx.recv().await {
+            output.push_str(x.as_str());
+        }
+
+        fut.await.unwrap();
+
+        let le: Vec<_> = output
+            .lines()
+            .filter_map(|x| x.strip_prefix("lustre_job_write_bytes_size_bucket{"))
+            .filter_map(|x| x.split_once(",le=\"")?.1.split_once("\"}"))
+            .filter(|(le, _)| *le != "+Inf")
+            .map(|(le, count)| {
+                (
+                    le.parse::<u64>().unwrap(),
+                    count.trim().parse::<u64>().unwrap(),
+                )
+            })
+            .collect();
+
+        // The only bucket is at or above the size of the write
+        assert_eq!(le, vec![(8192, 1)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn deltas_across_purges() {
+        async fn render(input: String, deltas: &Arc<JobDeltas>) -> String {
+            let (fut, mut rx) = jobstats_stream(
+                BufReader::new(Cursor::new(input)),
+                JobstatsOptions {
+                    deltas: Some(Arc::clone(deltas)),
+                    ..Default::default()
+                },
+            );
+
+            let mut output = String::new();
+
+            while let Some(x) = rx.recv().await {
+                output.push_str(x.as_str());
+            }
+
+            fut.await.unwrap();
+
+            output
+        }
+
+        let deltas = Arc::new(JobDeltas::new(Duration::from_secs(60)));
+
+        let purged = HIST_JOB
+            .replace("samples:          52", "samples:          10")
+            .replace("sum:          5468160", "sum:          1000");
+
+        render(HIST_JOB.to_string(), &deltas).await;
+        render(purged.clone(), &deltas).await;
+
+        let output = render(purged, &deltas).await;
+
+        assert!(output.contains(
+            r#"lustre_job_write_samples_total{operation="write_bytes",component="ost",target="ds002-OST0000",jobid="dd.0"} 62"#
+        ));
+        assert!(output.contains(
+            r#"lustre_job_write_bytes_total{operation="write_bytes",component="ost",target="ds002-OST0000",jobid="dd.0"} 5469160"#
+        ));
+        assert!(output.contains(
+            r#"lustre_job_write_maximum_size_bytes{operation="write_bytes",component="ost",target="ds002-OST0000",jobid="dd.0"} 1048576"#
+        ));
+
+        deltas.expire(Instant::now() + Duration::from_secs(120));
+
+        assert!(deltas.counters.lock().unwrap().is_empty());
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -434,7 +2138,7 @@ job_stats:{}"#,
 
         let f = BufReader::with_capacity(128 * 1_024, f);
 
-        let (fut, mut rx) = jobstats_stream(f);
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
 
         let mut cnt = 0;
 
@@ -444,7 +2148,7 @@ job_stats:{}"#,
 
         fut.await.unwrap();
 
-        assert_eq!(cnt, 108 + 1);
+        assert_eq!(cnt, 18 + 2);
     }
 
     #[tokio::test(flavor = "multi_thread")]
@@ -453,7 +2157,7 @@ job_stats:{}"#,
 
         let f = BufReader::with_capacity(128 * 1_024, f);
 
-        let (fut, mut rx) = jobstats_stream(f);
+        let (fut, mut rx) = jobstats_stream(f, JobstatsOptions::default());
 
         let mut output = r#"previous_stat{foo="bar"} 0"#.to_string();
 
@@ -465,4 +2169,42 @@ job_stats:{}"#,
 
         insta::assert_snapshot!(output);
     }
+
+    #[test]
+    fn find_single_job() {
+        let input = r#"obdfilter.ds002-OST0000.job_stats=
+job_stats:
+- job_id:          "dd.0"
+  snapshot_time:   1720516680
+  read_bytes:      { samples:           0, unit: bytes, min:        0, max:        0, sum:                0, sumsq:                  0 }
+  write_bytes:     { samples:          52, unit: bytes, min:     4096, max:   475136, sum:          5468160, sumsq:      1071040692224 }
+- job_id:          "cp.0"
+  snapshot_time:   1720516690
+  write_bytes:     { samples:           3, unit: bytes, min:     4096, max:     8192, sum:            16384, sumsq:          100663296 }
+obdfilter.ds002-OST0001.job_stats=
+job_stats:
+- job_id:          "cp.0"
+  snapshot_time:   1720516695
+  write_bytes:     { samples:           1, unit: bytes, min:     4096, max:     4096, sum:             4096, sumsq:           16777216 }"#;
+
+        let job = find_job(input.as_bytes(), "cp.0").unwrap().unwrap();
+
+        assert_eq!(job.target, "ds002-OST0000");
+        assert_eq!(job.snapshot_time.as_deref(), Some("1720516690"));
+        assert_eq!(
+            job.stats,
+            vec![JobStat {
+                name: "write_bytes".to_string(),
+                samples: 3,
+                unit: "bytes".to_string(),
+                min: 4096,
+                max: 8192,
+                sum: 16384,
+                sumsq: 100663296,
+                hist: BTreeMap::new(),
+            }]
+        );
+
+        assert_eq!(find_job(input.as_bytes(), "missing").unwrap(), None);
+    }
 }
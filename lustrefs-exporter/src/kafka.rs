@@ -0,0 +1,285 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Publishes the records of each background collection to a Kafka topic as
+//! JSON, one message per record, so stream processing downstream, like per job
+//! accounting, can reuse the parsed records rather than parsing lctl output a
+//! second time.
+//!
+//! Publishing goes through librdkafka, so TLS, SASL, compression and the like
+//! are set with its own properties, e.g. `security.protocol=sasl_ssl` or
+//! `compression.type=zstd`. Messages are keyed by hostname, so the records of
+//! a host stay in order on one partition.
+//!
+//! Only built with the `kafka` feature.
+
+use crate::{routes::AppState, Error};
+use lustre_collector::Record;
+use rdkafka::{
+    error::{KafkaError, RDKafkaErrorCode},
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig,
+};
+use serde::Serialize;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::JoinHandle;
+
+const CLIENT_ID: &str = "lustrefs-exporter";
+
+/// How long to wait for room in the queue of librdkafka before trying again.
+const QUEUE_FULL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The value published for each record.
+#[derive(Debug, Serialize)]
+struct Message<'a> {
+    host: &'a str,
+    /// Milliseconds since the epoch
+    timestamp: i64,
+    record: &'a Record,
+}
+
+/// Why a record could not be published, for `lustre_exporter_kafka_publish_errors_total`.
+fn reason(e: &KafkaError) -> &'static str {
+    match e.rdkafka_error_code() {
+        Some(RDKafkaErrorCode::MessageSizeTooLarge) => "too_large",
+        _ => "failed",
+    }
+}
+
+/// Publishes records keyed by host to a topic.
+pub struct KafkaProducer {
+    pub topic: String,
+    producer: FutureProducer,
+}
+
+impl std::fmt::Debug for KafkaProducer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaProducer")
+            .field("topic", &self.topic)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KafkaProducer {
+    /// A producer for `topic` on the `brokers`, as `host:port`, with the librdkafka
+    /// properties in `config` set on top.
+    pub fn new(
+        brokers: &[String],
+        topic: String,
+        config: &[(String, String)],
+    ) -> Result<Self, Error> {
+        let mut client = ClientConfig::new();
+
+        client
+            .set("bootstrap.servers", brokers.join(","))
+            .set("client.id", CLIENT_ID);
+
+        for (key, value) in config {
+            client.set(key, value);
+        }
+
+        Ok(Self {
+            topic,
+            producer: client.create()?,
+        })
+    }
+
+    /// Publishes each of `records` as a message keyed by `host`, with a timestamp
+    /// in milliseconds since the epoch, and waits for them to be delivered.
+    ///
+    /// Records that could not be published are counted by reason in the
+    /// metrics of `state`. Returns how many were delivered.
+    pub async fn publish(
+        &self,
+        state: &AppState,
+        host: &str,
+        records: &[Record],
+        timestamp: i64,
+    ) -> usize {
+        let mut deliveries = Vec::with_capacity(records.len());
+        let mut error = None;
+
+        let mut fail = |e: KafkaError| {
+            state
+                .exporter_metrics
+                .record_kafka_publish_error(reason(&e));
+
+            error = Some(e);
+        };
+
+        for record in records {
+            let value = match serde_json::to_vec(&Message {
+                host,
+                timestamp,
+                record,
+            }) {
+                Ok(x) => x,
+                Err(e) => {
+                    fail(KafkaError::MessageProduction(
+                        RDKafkaErrorCode::InvalidMessage,
+                    ));
+
+                    tracing::debug!("Could not serialize a record for Kafka: {e}");
+
+                    continue;
+                }
+            };
+
+            // librdkafka copies the message, so `value` can go once it is queued
+            let mut message = FutureRecord::to(&self.topic)
+                .key(host)
+                .payload(&value)
+                .timestamp(timestamp);
+
+            loop {
+                match self.producer.send_result(message) {
+                    Ok(x) => deliveries.push(x),
+                    Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), x)) => {
+                        message = x;
+
+                        tokio::time::sleep(QUEUE_FULL_BACKOFF).await;
+
+                        continue;
+                    }
+                    Err((e, _)) => fail(e),
+                }
+
+                break;
+            }
+        }
+
+        let mut delivered = 0;
+
+        for x in deliveries {
+            match x.await {
+                Ok(Ok(_)) => delivered += 1,
+                Ok(Err((e, _))) => fail(e),
+                Err(_) => fail(KafkaError::Canceled),
+            }
+        }
+
+        if let Some(e) = error {
+            tracing::warn!(
+                "Could not publish {} of {} records to Kafka topic {}: {e}",
+                records.len() - delivered,
+                records.len(),
+                self.topic
+            );
+        }
+
+        delivered
+    }
+}
+
+/// Publishes the records of each background collection with `producer`, as
+/// soon as it is done.
+pub fn spawn(state: Arc<AppState>, producer: KafkaProducer) -> JoinHandle<()> {
+    let mut collections = state.snapshot.subscribe();
+
+    tokio::spawn(async move {
+        while collections.changed().await.is_ok() {
+            let Some(collection) = collections.borrow_and_update().clone() else {
+                continue;
+            };
+
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as i64;
+
+            producer
+                .publish(&state, &state.hostname, &collection.records, timestamp)
+                .await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::mocking::MockCluster;
+
+    fn fixture() -> Vec<Record> {
+        lustre_collector::parse_lctl_output(include_bytes!(
+            "../../lustre-collector/src/fixtures/valid/valid.txt"
+        ))
+        .unwrap()
+    }
+
+    fn config(x: &[(&str, &str)]) -> Vec<(String, String)> {
+        x.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn publishes_a_message_per_record() {
+        let cluster = MockCluster::new(1).unwrap();
+
+        cluster.create_topic("lustre", 3, 1).unwrap();
+
+        // Small enough that some records of the fixture do not fit
+        let producer = KafkaProducer::new(
+            &[cluster.bootstrap_servers()],
+            "lustre".to_string(),
+            &config(&[("message.max.bytes", "1000"), ("batch.size", "1000")]),
+        )
+        .unwrap();
+
+        let state = AppState::default();
+        let records = fixture();
+
+        let sizes: Vec<_> = records
+            .iter()
+            .map(|record| {
+                serde_json::to_vec(&Message {
+                    host: "node1",
+                    timestamp: 1000,
+                    record,
+                })
+                .unwrap()
+                .len()
+            })
+            .collect();
+
+        // librdkafka counts the key and some overhead of its own too
+        let too_large = sizes.iter().filter(|x| **x > 1000).count();
+        let maybe_too_large = sizes.iter().filter(|x| **x > 900).count();
+
+        assert!(too_large > 0);
+
+        let delivered = producer.publish(&state, "node1", &records, 1000).await;
+
+        assert!(delivered <= records.len() - too_large);
+        assert!(delivered >= records.len() - maybe_too_large);
+
+        assert!(state.exporter_metrics.render().contains(&format!(
+            "lustre_exporter_kafka_publish_errors_total{{reason=\"too_large\"}} {}\n",
+            records.len() - delivered
+        )));
+    }
+
+    #[tokio::test]
+    async fn counts_undelivered_records() {
+        let producer = KafkaProducer::new(
+            &["127.0.0.1:1".to_string()],
+            "lustre".to_string(),
+            &config(&[("message.timeout.ms", "100")]),
+        )
+        .unwrap();
+
+        let state = AppState::default();
+        let records = &fixture()[..2];
+
+        assert_eq!(producer.publish(&state, "node1", records, 1000).await, 0);
+
+        assert!(state
+            .exporter_metrics
+            .render()
+            .contains("lustre_exporter_kafka_publish_errors_total{reason=\"failed\"} 2\n"));
+    }
+}
@@ -2,32 +2,86 @@
 // Use of this source code is governed by a MIT-style
 // license that can be found in the LICENSE file.
 
+pub mod bench;
 pub mod brw_stats;
+pub mod capacity;
+pub mod collector;
+pub mod collectors;
+pub mod config;
+pub mod consistency;
+pub mod counter_state;
+pub mod exporter_metrics;
+pub mod grant;
+pub mod history;
 pub mod host;
+pub mod import;
 pub mod jobstats;
+#[cfg(feature = "kafka")]
+pub mod kafka;
 pub mod llite;
 pub mod lnet;
+pub mod logging;
+pub mod mgs;
+pub mod nodemap;
+pub mod osp;
+pub mod pool;
+pub mod preflight;
+pub mod procfs;
 pub mod quota;
+pub mod quota_names;
+pub mod recovery;
+pub mod routes;
+pub mod rpc_stats;
+#[cfg(feature = "sandbox")]
+pub mod sandbox;
+pub mod sd;
 pub mod service;
+pub mod sink;
+pub mod srpc;
 pub mod stats;
+pub mod systemd;
+pub mod target_registry;
+pub mod threads;
+pub mod tunable;
+pub mod validate;
+pub mod worker;
+pub mod zfs;
 
 use axum::{
     http::{self, StatusCode},
     response::{IntoResponse, Response},
 };
 use brw_stats::build_target_stats;
+use capacity::{build_fs_capacity_stats, FsCapacity};
+use grant::{build_grant_stats, Grants};
 use host::build_host_stats;
 use lnet::build_lnet_stats;
 use lustre_collector::{
-    HostStat, LNetStat, LNetStatGlobal, LustreCollectorError, Record, TargetStat, TargetVariant,
+    HostStat, LNetStat, LNetStatGlobal, LustreCollectorError, MgsFsStat, Record, Series, Target,
+    TargetStat, TargetVariant,
 };
+use nodemap::build_nodemap_stats;
 use num_traits::Num;
+use osp::{build_prealloc_stats, Prealloc};
 use prometheus_exporter_base::{prelude::*, Yes};
+use quota_names::QuotaNames;
+use recovery::{build_recovery_progress_stats, Recovery};
+use rpc_stats::ClientRpcHistograms;
 use service::build_service_stats;
-use std::{collections::BTreeMap, fmt, ops::Deref};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fmt,
+    mem::{self, Discriminant},
+    ops::Deref,
+};
+use threads::{build_threads_stats, ServiceThreads};
+use zfs::build_zfs_stats;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error(transparent)]
+    Axum(#[from] axum::Error),
     #[error(transparent)]
     Http(#[from] http::Error),
     #[error(transparent)]
@@ -38,6 +92,19 @@ pub enum Error {
     LustreCollector(#[from] LustreCollectorError),
     #[error(transparent)]
     Utf8(#[from] std::str::Utf8Error),
+    #[error(transparent)]
+    ParseInt(#[from] std::num::ParseIntError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Push(#[from] Box<ureq::Error>),
+    #[cfg(feature = "kafka")]
+    #[error(transparent)]
+    Kafka(#[from] rdkafka::error::KafkaError),
+    #[error("{0}")]
+    EnvExpansion(String),
+    #[error("{0}")]
+    Config(String),
     #[error("Could not find match for {0} in {1}")]
     NoCap(&'static str, String),
 }
@@ -57,6 +124,84 @@ struct Metric {
     r#type: MetricType,
 }
 
+/// A metric family the exporter can produce, as listed by `/metadata`.
+#[derive(Debug, serde::Serialize)]
+pub struct MetricMetadata {
+    pub name: &'static str,
+    pub r#type: &'static str,
+    pub help: &'static str,
+    /// The lctl param or command the family is read from, `exporter` for the exporter's own metrics
+    pub source: &'static str,
+}
+
+/// Every metric family the exporter can produce, sorted by name.
+pub fn metadata() -> Vec<MetricMetadata> {
+    let mut xs: Vec<_> = [
+        brw_stats::METADATA,
+        capacity::METADATA,
+        counter_state::METADATA,
+        exporter_metrics::METADATA,
+        grant::METADATA,
+        host::METADATA,
+        import::METADATA,
+        jobstats::METADATA,
+        llite::METADATA,
+        lnet::METADATA,
+        mgs::METADATA,
+        nodemap::METADATA,
+        osp::METADATA,
+        pool::METADATA,
+        quota::METADATA,
+        recovery::METADATA,
+        rpc_stats::METADATA,
+        service::METADATA,
+        srpc::METADATA,
+        stats::METADATA,
+        target_registry::METADATA,
+        threads::METADATA,
+        tunable::METADATA,
+        zfs::METADATA,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|(x, source)| MetricMetadata {
+        name: x.name,
+        r#type: match x.r#type {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Histogram => "histogram",
+            MetricType::Summary => "summary",
+        },
+        help: x.help,
+        source,
+    })
+    .collect();
+
+    xs.sort_by_key(|x| x.name);
+
+    xs
+}
+
+/// Escapes backslashes, double quotes and newlines in a label value for the text format.
+fn escape_label_value(x: &str) -> Cow<'_, str> {
+    if !x.contains(['\\', '"', '\n']) {
+        return Cow::Borrowed(x);
+    }
+
+    let mut out = String::with_capacity(x.len() + 2);
+
+    for c in x.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+
+    Cow::Owned(out)
+}
+
 trait LabelProm {
     fn to_prom_label(&self) -> &'static str;
 }
@@ -71,6 +216,41 @@ impl LabelProm for TargetVariant {
     }
 }
 
+/// The `component` and `target` labels shared by every series rendered from a `TargetStat`.
+///
+/// Resolve these once per stat and reuse them for each family the stat is rendered into.
+#[derive(Debug, Clone, Copy)]
+struct TargetLabels<'a> {
+    component: &'static str,
+    target: &'a str,
+}
+
+impl<'a> TargetLabels<'a> {
+    fn new(kind: TargetVariant, target: &'a Target) -> Self {
+        Self {
+            component: kind.to_prom_label(),
+            target: target.deref(),
+        }
+    }
+
+    /// An instance labeled with `component` and `target`.
+    fn inst<T>(self, value: T) -> PrometheusInstance<'a, T, Yes> {
+        PrometheusInstance::new()
+            .with_label("component", self.component)
+            .with_label("target", self.target)
+            .with_value(value)
+    }
+
+    /// An instance labeled with `component`, `operation` and `target`.
+    fn operation_inst<T>(self, operation: &'a str, value: T) -> PrometheusInstance<'a, T, Yes> {
+        PrometheusInstance::new()
+            .with_label("component", self.component)
+            .with_label("operation", operation)
+            .with_label("target", self.target)
+            .with_value(value)
+    }
+}
+
 impl From<Metric> for PrometheusMetric<'_> {
     fn from(x: Metric) -> Self {
         PrometheusMetric::build()
@@ -93,10 +273,7 @@ where
     T: Num + fmt::Display + fmt::Debug + Copy,
 {
     fn to_metric_inst(&self) -> PrometheusInstance<'_, T, Yes> {
-        PrometheusInstance::new()
-            .with_label("component", self.kind.to_prom_label())
-            .with_label("target", self.target.deref())
-            .with_value(self.value)
+        TargetLabels::new(self.kind, &self.target).inst(self.value)
     }
 }
 
@@ -120,6 +297,18 @@ where
     }
 }
 
+impl<T> ToMetricInst<T> for MgsFsStat<T>
+where
+    T: Num + fmt::Display + fmt::Debug + Copy,
+{
+    fn to_metric_inst(&self) -> PrometheusInstance<'_, T, Yes> {
+        PrometheusInstance::new()
+            .with_label("target", self.target.deref())
+            .with_label("fs", self.fs.0.as_str())
+            .with_value(self.value)
+    }
+}
+
 impl<T> ToMetricInst<T> for HostStat<T>
 where
     T: Num + fmt::Display + fmt::Debug + Copy,
@@ -156,29 +345,91 @@ impl StatsMapExt for BTreeMap<&'static str, PrometheusMetric<'static>> {
 }
 
 pub fn build_lustre_stats(output: Vec<Record>) -> String {
+    build_lustre_stats_with_quota_names(output, &QuotaNames::default())
+}
+
+/// Like [`build_lustre_stats`], labelling quota metrics with the names `quota_names` resolves.
+pub fn build_lustre_stats_with_quota_names(
+    output: Vec<Record>,
+    quota_names: &QuotaNames,
+) -> String {
     let mut stats_map = BTreeMap::new();
+    let mut nodemaps = vec![];
+    let mut grants = Grants::default();
+    let mut capacity = FsCapacity::default();
+    let mut prealloc = Prealloc::default();
+    let mut client_rpc = ClientRpcHistograms::default();
+    let mut recovery = Recovery::default();
+    let mut threads = ServiceThreads::default();
+
+    // Some kernels report a param under both its old and new proc path, so
+    // the same records come out of lctl twice and would be exported twice
+    let mut seen = HashSet::new();
+    let mut dropped = 0;
 
     for x in output {
+        if !seen.insert(series_key(&x)) {
+            dropped += 1;
+
+            continue;
+        }
+
         match x {
             lustre_collector::Record::Host(x) => {
                 build_host_stats(x, &mut stats_map);
             }
             lustre_collector::Record::Node(_) => {}
+            lustre_collector::Record::Nodemap(x) => {
+                nodemaps.push(x);
+            }
             lustre_collector::Record::LNetStat(x) => {
                 build_lnet_stats(x, &mut stats_map);
             }
             lustre_collector::Record::Target(x) => {
-                build_target_stats(x, &mut stats_map);
+                grants.observe(&x);
+                capacity.observe(&x);
+                prealloc.observe(&x);
+                client_rpc.observe(&x);
+                recovery.observe(&x);
+                threads.observe(&x);
+                build_target_stats(x, quota_names, &mut stats_map);
             }
             lustre_collector::Record::LustreService(x) => {
                 build_service_stats(x, &mut stats_map);
             }
+            lustre_collector::Record::Zfs(x) => {
+                build_zfs_stats(x, &mut stats_map);
+            }
         }
     }
 
+    build_nodemap_stats(nodemaps, &mut stats_map);
+    build_grant_stats(grants, &mut stats_map);
+    build_fs_capacity_stats(capacity, &mut stats_map);
+    build_prealloc_stats(prealloc, &mut stats_map);
+    build_recovery_progress_stats(recovery, &mut stats_map);
+    build_threads_stats(threads, &mut stats_map);
+
+    if dropped > 0 {
+        tracing::debug!("Dropped {dropped} repeated records");
+    }
+
     stats_map
         .values()
         .map(|x| x.render())
+        .chain(client_rpc.into_families())
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+/// The metric family and labels of the series a record is built into, which
+/// tell the records of a scrape apart.
+type SeriesKey = (Discriminant<Record>, &'static str, String);
+
+fn series_key(record: &Record) -> SeriesKey {
+    (
+        mem::discriminant(record),
+        record.stat(),
+        record.labels().join("\n"),
+    )
+}
@@ -4,7 +4,7 @@
 
 use std::{collections::BTreeMap, ops::Deref};
 
-use lustre_collector::LliteStat;
+use lustre_collector::{LliteCacheStat, LliteStat};
 use prometheus_exporter_base::prelude::*;
 
 use crate::{Metric, StatsMapExt};
@@ -15,24 +15,149 @@ static LLITE_STATS_SAMPLES: Metric = Metric {
     r#type: MetricType::Gauge,
 };
 
+static READAHEAD_HITS: Metric = Metric {
+    name: "lustre_client_readahead_hits_total",
+    help: "Total number of pages read that were already in the client read-ahead cache.",
+    r#type: MetricType::Counter,
+};
+
+static READAHEAD_MISSES: Metric = Metric {
+    name: "lustre_client_readahead_misses_total",
+    help: "Total number of pages read that missed the client read-ahead cache.",
+    r#type: MetricType::Counter,
+};
+
+static READAHEAD_EVENTS: Metric = Metric {
+    name: "lustre_client_readahead_events_total",
+    help: "Total number of pages that went through each client read-ahead event, such as hits, misses and window resets.",
+    r#type: MetricType::Counter,
+};
+
+static CACHED_MB: Metric = Metric {
+    name: "lustre_client_cached_mb",
+    help: "Megabytes of file data currently cached by the client.",
+    r#type: MetricType::Gauge,
+};
+
+static MAX_CACHED_MB: Metric = Metric {
+    name: "lustre_client_max_cached_mb",
+    help: "Maximum megabytes of file data the client may cache.",
+    r#type: MetricType::Gauge,
+};
+
+static UNUSED_CACHED_MB: Metric = Metric {
+    name: "lustre_client_unused_cached_mb",
+    help: "Megabytes of the client cache that are currently unused.",
+    r#type: MetricType::Gauge,
+};
+
+static CACHE_RECLAIM: Metric = Metric {
+    name: "lustre_client_cache_reclaim_total",
+    help: "Total number of times the client had to reclaim cached pages.",
+    r#type: MetricType::Counter,
+};
+
+static UNSTABLE_PAGES: Metric = Metric {
+    name: "lustre_client_unstable_pages",
+    help: "Number of pages written by the client but not yet committed by the servers.",
+    r#type: MetricType::Gauge,
+};
+
+static UNSTABLE_MB: Metric = Metric {
+    name: "lustre_client_unstable_mb",
+    help: "Megabytes written by the client but not yet committed by the servers.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&LLITE_STATS_SAMPLES, "llite.*.stats"),
+    (&READAHEAD_HITS, "llite.*.read_ahead_stats"),
+    (&READAHEAD_MISSES, "llite.*.read_ahead_stats"),
+    (&READAHEAD_EVENTS, "llite.*.read_ahead_stats"),
+    (&CACHED_MB, "llite.*.max_cached_mb"),
+    (&MAX_CACHED_MB, "llite.*.max_cached_mb"),
+    (&UNUSED_CACHED_MB, "llite.*.max_cached_mb"),
+    (&CACHE_RECLAIM, "llite.*.max_cached_mb"),
+    (&UNSTABLE_PAGES, "llite.*.unstable_stats"),
+    (&UNSTABLE_MB, "llite.*.unstable_stats"),
+];
+
 pub fn build_llite_stats(
     x: LliteStat,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
 ) {
     let LliteStat {
         target,
-        param: _,
+        param,
         stats,
     } = x;
 
     for stat in stats {
-        stats_map
-            .get_mut_metric(LLITE_STATS_SAMPLES)
-            .render_and_append_instance(
-                &PrometheusInstance::new()
-                    .with_label("operation", stat.name.deref())
-                    .with_label("target", target.deref())
-                    .with_value(stat.samples),
-            );
+        let metric = match (param.0.as_str(), stat.name.as_str()) {
+            ("read_ahead_stats", event) => {
+                stats_map
+                    .get_mut_metric(READAHEAD_EVENTS)
+                    .render_and_append_instance(
+                        &PrometheusInstance::new()
+                            .with_label("event", event)
+                            .with_label("target", target.deref())
+                            .with_value(stat.samples),
+                    );
+
+                match event {
+                    "hits" => READAHEAD_HITS,
+                    "misses" => READAHEAD_MISSES,
+                    _ => continue,
+                }
+            }
+            _ => {
+                stats_map
+                    .get_mut_metric(LLITE_STATS_SAMPLES)
+                    .render_and_append_instance(
+                        &PrometheusInstance::new()
+                            .with_label("operation", stat.name.deref())
+                            .with_label("target", target.deref())
+                            .with_value(stat.samples),
+                    );
+
+                continue;
+            }
+        };
+
+        stats_map.get_mut_metric(metric).render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("target", target.deref())
+                .with_value(stat.samples),
+        );
+    }
+}
+
+pub fn build_llite_cache_stats(
+    x: LliteCacheStat,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let LliteCacheStat {
+        target,
+        param,
+        values,
+    } = x;
+
+    for (name, value) in values {
+        let metric = match (param.0.as_str(), name.as_str()) {
+            ("max_cached_mb", "used_mb") => CACHED_MB,
+            ("max_cached_mb", "max_cached_mb") => MAX_CACHED_MB,
+            ("max_cached_mb", "unused_mb") => UNUSED_CACHED_MB,
+            ("max_cached_mb", "reclaim_count") => CACHE_RECLAIM,
+            ("unstable_stats", "unstable_pages") => UNSTABLE_PAGES,
+            ("unstable_stats", "unstable_mb") => UNSTABLE_MB,
+            _ => continue,
+        };
+
+        stats_map.get_mut_metric(metric).render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("target", target.deref())
+                .with_value(value),
+        );
     }
 }
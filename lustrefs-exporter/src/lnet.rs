@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use lustre_collector::LNetStats;
+use lustre_collector::{LNetRoute, LNetRouterBuffers, LNetStats, LNetUdspPolicies};
 use prometheus_exporter_base::prelude::*;
 
 use crate::{Metric, StatsMapExt, ToMetricInst};
@@ -21,6 +21,47 @@ static DROP_COUNT: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+static NI_HEALTH_VALUE: Metric = Metric {
+    name: "lustre_lnet_ni_health_value",
+    help: "Health value of the local NI, 1000 is fully healthy",
+    r#type: MetricType::Gauge,
+};
+static NI_FATAL_ERROR: Metric = Metric {
+    name: "lustre_lnet_ni_fatal_error",
+    help: "Whether the local NI has hit an unrecoverable error",
+    r#type: MetricType::Gauge,
+};
+static NI_INTERRUPTS: Metric = Metric {
+    name: "lustre_lnet_ni_interrupts_total",
+    help: "Total number of sends on the local NI that were interrupted",
+    r#type: MetricType::Counter,
+};
+static NI_DROPPED: Metric = Metric {
+    name: "lustre_lnet_ni_dropped_total",
+    help: "Total number of sends on the local NI that were dropped",
+    r#type: MetricType::Counter,
+};
+static NI_TIMEOUTS: Metric = Metric {
+    name: "lustre_lnet_ni_timeouts_total",
+    help: "Total number of sends on the local NI that timed out",
+    r#type: MetricType::Counter,
+};
+static NI_CREDITS: Metric = Metric {
+    name: "lustre_lnet_ni_credits",
+    help: "Number of send credits of the local NI, shared out over its CPTs",
+    r#type: MetricType::Gauge,
+};
+static NI_PEER_CREDITS: Metric = Metric {
+    name: "lustre_lnet_ni_peer_credits",
+    help: "Number of send credits a peer of the local NI can use at once",
+    r#type: MetricType::Gauge,
+};
+static NI_PEER_BUFFER_CREDITS: Metric = Metric {
+    name: "lustre_lnet_ni_peer_buffer_credits",
+    help: "Number of router buffer credits a peer of the local NI can use at once",
+    r#type: MetricType::Gauge,
+};
+
 static SEND_BYTES: Metric = Metric {
     name: "lustre_send_bytes_total",
     help: "Total number of bytes that have been sent",
@@ -37,6 +78,151 @@ static DROP_BYTES: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+static PEER_SEND_COUNT: Metric = Metric {
+    name: "lustre_lnet_peer_send_count_total",
+    help: "Total number of messages that have been sent to the peer NID",
+    r#type: MetricType::Counter,
+};
+static PEER_RECEIVE_COUNT: Metric = Metric {
+    name: "lustre_lnet_peer_receive_count_total",
+    help: "Total number of messages that have been received from the peer NID",
+    r#type: MetricType::Counter,
+};
+static PEER_DROP_COUNT: Metric = Metric {
+    name: "lustre_lnet_peer_drop_count_total",
+    help: "Total number of messages to or from the peer NID that have been dropped",
+    r#type: MetricType::Counter,
+};
+static PEER_HEALTH_VALUE: Metric = Metric {
+    name: "lustre_lnet_peer_health_value",
+    help: "Health value of the peer NID, 1000 is fully healthy",
+    r#type: MetricType::Gauge,
+};
+static PEER_MAX_TX_CREDITS: Metric = Metric {
+    name: "lustre_lnet_peer_max_tx_credits",
+    help: "Maximum number of send credits for the peer NID",
+    r#type: MetricType::Gauge,
+};
+static PEER_AVAILABLE_TX_CREDITS: Metric = Metric {
+    name: "lustre_lnet_peer_available_tx_credits",
+    help: "Number of send credits currently available for the peer NID",
+    r#type: MetricType::Gauge,
+};
+static PEER_MIN_TX_CREDITS: Metric = Metric {
+    name: "lustre_lnet_peer_min_tx_credits",
+    help: "Lowest number of send credits that have been available for the peer NID",
+    r#type: MetricType::Gauge,
+};
+static PEER_AVAILABLE_RTR_CREDITS: Metric = Metric {
+    name: "lustre_lnet_peer_available_rtr_credits",
+    help: "Number of router credits currently available for the peer NID",
+    r#type: MetricType::Gauge,
+};
+static PEER_MIN_RTR_CREDITS: Metric = Metric {
+    name: "lustre_lnet_peer_min_rtr_credits",
+    help: "Lowest number of router credits that have been available for the peer NID",
+    r#type: MetricType::Gauge,
+};
+
+static ROUTE_STATE: Metric = Metric {
+    name: "lustre_lnet_route_state",
+    help: "Whether the gateway of the route to the remote net is up",
+    r#type: MetricType::Gauge,
+};
+static ROUTE_HOPS: Metric = Metric {
+    name: "lustre_lnet_route_hops",
+    help: "Number of hops to the remote net through the gateway, -1 if not set",
+    r#type: MetricType::Gauge,
+};
+static ROUTE_PRIORITY: Metric = Metric {
+    name: "lustre_lnet_route_priority",
+    help: "Priority of the route to the remote net through the gateway, lower is preferred",
+    r#type: MetricType::Gauge,
+};
+static ROUTING_ENABLED: Metric = Metric {
+    name: "lustre_lnet_routing_enabled",
+    help: "Whether this node routes messages between its nets",
+    r#type: MetricType::Gauge,
+};
+static ROUTER_BUFFERS: Metric = Metric {
+    name: "lustre_lnet_router_buffers",
+    help: "Number of router buffers of the size, over all CPTs",
+    r#type: MetricType::Gauge,
+};
+static ROUTER_BUFFER_CREDITS: Metric = Metric {
+    name: "lustre_lnet_router_buffer_credits",
+    help: "Number of router buffer credits of the size currently available, over all CPTs",
+    r#type: MetricType::Gauge,
+};
+static ROUTER_BUFFER_MIN_CREDITS: Metric = Metric {
+    name: "lustre_lnet_router_buffer_min_credits",
+    help:
+        "Lowest number of router buffer credits of the size that have been available, over all CPTs",
+    r#type: MetricType::Gauge,
+};
+
+static DISCOVERY_ENABLED: Metric = Metric {
+    name: "lustre_lnet_discovery_enabled",
+    help: "Whether LNet discovers the interfaces of its peers",
+    r#type: MetricType::Gauge,
+};
+static PEER_DISCOVERY_STATE: Metric = Metric {
+    name: "lustre_lnet_peer_discovery_state",
+    help: "Discovery state of the peer. 1 for the current state, 0 otherwise",
+    r#type: MetricType::Gauge,
+};
+static UDSP_POLICIES: Metric = Metric {
+    name: "lustre_lnet_udsp_policies",
+    help: "Number of User Defined Selection Policies with the action",
+    r#type: MetricType::Gauge,
+};
+
+/// The discovery states a peer can be in, as derived from its `peer state` flags.
+const DISCOVERY_STATES: [&str; 5] = [
+    "disabled",
+    "discovered",
+    "discovering",
+    "failed",
+    "undiscovered",
+];
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&SEND_COUNT, "lnetctl net show"),
+    (&RECEIVE_COUNT, "lnetctl net show"),
+    (&DROP_COUNT, "lnetctl net show"),
+    (&NI_HEALTH_VALUE, "lnetctl net show"),
+    (&NI_FATAL_ERROR, "lnetctl net show"),
+    (&NI_INTERRUPTS, "lnetctl net show"),
+    (&NI_DROPPED, "lnetctl net show"),
+    (&NI_TIMEOUTS, "lnetctl net show"),
+    (&NI_CREDITS, "lnetctl net show"),
+    (&NI_PEER_CREDITS, "lnetctl net show"),
+    (&NI_PEER_BUFFER_CREDITS, "lnetctl net show"),
+    (&SEND_BYTES, "lnetctl net show"),
+    (&RECEIVE_BYTES, "lnetctl net show"),
+    (&DROP_BYTES, "lnetctl net show"),
+    (&PEER_SEND_COUNT, "lnetctl peer show"),
+    (&PEER_RECEIVE_COUNT, "lnetctl peer show"),
+    (&PEER_DROP_COUNT, "lnetctl peer show"),
+    (&PEER_HEALTH_VALUE, "lnetctl peer show"),
+    (&PEER_MAX_TX_CREDITS, "lnetctl peer show"),
+    (&PEER_AVAILABLE_TX_CREDITS, "lnetctl peer show"),
+    (&PEER_MIN_TX_CREDITS, "lnetctl peer show"),
+    (&PEER_AVAILABLE_RTR_CREDITS, "lnetctl peer show"),
+    (&PEER_MIN_RTR_CREDITS, "lnetctl peer show"),
+    (&ROUTE_STATE, "lnetctl route show"),
+    (&ROUTE_HOPS, "lnetctl route show"),
+    (&ROUTE_PRIORITY, "lnetctl route show"),
+    (&ROUTING_ENABLED, "lnetctl routing show"),
+    (&ROUTER_BUFFERS, "lnetctl routing show"),
+    (&ROUTER_BUFFER_CREDITS, "lnetctl routing show"),
+    (&ROUTER_BUFFER_MIN_CREDITS, "lnetctl routing show"),
+    (&DISCOVERY_ENABLED, "lnetctl global show"),
+    (&PEER_DISCOVERY_STATE, "lnetctl peer show"),
+    (&UDSP_POLICIES, "lnetctl udsp show"),
+];
+
 pub fn build_lnet_stats(
     x: LNetStats,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
@@ -57,6 +243,46 @@ pub fn build_lnet_stats(
                 .get_mut_metric(DROP_COUNT)
                 .render_and_append_instance(&x.to_metric_inst());
         }
+        LNetStats::NiHealthValue(x) => {
+            stats_map
+                .get_mut_metric(NI_HEALTH_VALUE)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::NiFatalError(x) => {
+            stats_map
+                .get_mut_metric(NI_FATAL_ERROR)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::NiInterrupts(x) => {
+            stats_map
+                .get_mut_metric(NI_INTERRUPTS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::NiDropped(x) => {
+            stats_map
+                .get_mut_metric(NI_DROPPED)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::NiTimeouts(x) => {
+            stats_map
+                .get_mut_metric(NI_TIMEOUTS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::NiCredits(x) => {
+            stats_map
+                .get_mut_metric(NI_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::NiPeerCredits(x) => {
+            stats_map
+                .get_mut_metric(NI_PEER_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::NiPeerBufferCredits(x) => {
+            stats_map
+                .get_mut_metric(NI_PEER_BUFFER_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
         LNetStats::SendLength(x) => {
             stats_map
                 .get_mut_metric(SEND_BYTES)
@@ -72,5 +298,123 @@ pub fn build_lnet_stats(
                 .get_mut_metric(DROP_BYTES)
                 .render_and_append_instance(&x.to_metric_inst());
         }
+        LNetStats::PeerSendCount(x) => {
+            stats_map
+                .get_mut_metric(PEER_SEND_COUNT)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerRecvCount(x) => {
+            stats_map
+                .get_mut_metric(PEER_RECEIVE_COUNT)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerDropCount(x) => {
+            stats_map
+                .get_mut_metric(PEER_DROP_COUNT)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerHealthValue(x) => {
+            stats_map
+                .get_mut_metric(PEER_HEALTH_VALUE)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerMaxTxCredits(x) => {
+            stats_map
+                .get_mut_metric(PEER_MAX_TX_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerAvailableTxCredits(x) => {
+            stats_map
+                .get_mut_metric(PEER_AVAILABLE_TX_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerMinTxCredits(x) => {
+            stats_map
+                .get_mut_metric(PEER_MIN_TX_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerAvailableRtrCredits(x) => {
+            stats_map
+                .get_mut_metric(PEER_AVAILABLE_RTR_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::PeerMinRtrCredits(x) => {
+            stats_map
+                .get_mut_metric(PEER_MIN_RTR_CREDITS)
+                .render_and_append_instance(&x.to_metric_inst());
+        }
+        LNetStats::Route(LNetRoute {
+            net,
+            gateway,
+            hop,
+            priority,
+            up,
+        }) => {
+            for (metric, value) in [
+                (ROUTE_STATE, i64::from(up)),
+                (ROUTE_HOPS, hop),
+                (ROUTE_PRIORITY, priority),
+            ] {
+                stats_map.get_mut_metric(metric).render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("net", net.as_str())
+                        .with_label("gateway", gateway.as_str())
+                        .with_value(value),
+                );
+            }
+        }
+        LNetStats::RoutingEnabled(x) => {
+            stats_map
+                .get_mut_metric(ROUTING_ENABLED)
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(u8::from(x.value)),
+                );
+        }
+        LNetStats::RouterBuffers(LNetRouterBuffers {
+            size,
+            buffers,
+            credits,
+            min_credits,
+        }) => {
+            for (metric, value) in [
+                (ROUTER_BUFFERS, buffers),
+                (ROUTER_BUFFER_CREDITS, credits),
+                (ROUTER_BUFFER_MIN_CREDITS, min_credits),
+            ] {
+                stats_map.get_mut_metric(metric).render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("size", size.as_str())
+                        .with_value(value),
+                );
+            }
+        }
+        LNetStats::DiscoveryEnabled(x) => {
+            stats_map
+                .get_mut_metric(DISCOVERY_ENABLED)
+                .render_and_append_instance(
+                    &PrometheusInstance::new().with_value(u8::from(x.value)),
+                );
+        }
+        LNetStats::PeerDiscoveryState(x) => {
+            for state in DISCOVERY_STATES {
+                stats_map
+                    .get_mut_metric(PEER_DISCOVERY_STATE)
+                    .render_and_append_instance(
+                        &PrometheusInstance::new()
+                            .with_label("nid", x.nid.as_str())
+                            .with_label("state", state)
+                            .with_value(u8::from(state == x.value)),
+                    );
+            }
+        }
+        LNetStats::UdspPolicies(LNetUdspPolicies { action, count }) => {
+            stats_map
+                .get_mut_metric(UDSP_POLICIES)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("action", action.as_str())
+                        .with_value(count),
+                );
+        }
     };
 }
@@ -0,0 +1,223 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Log output, as plain text or as one JSON object per line for log pipelines.
+
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use std::fmt::{self, Debug};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{
+        format::Writer,
+        time::{FormatTime, SystemTime},
+        FmtContext, FormatEvent, FormatFields, MakeWriter,
+    },
+    layer::Context,
+    registry::LookupSpan,
+    Layer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// A JSON object per line, with the fields of the event and its spans
+    Json,
+}
+
+/// Collects the fields of an event or span into a JSON object.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}").into());
+    }
+}
+
+/// The fields of a span, kept in its extensions for [`Json`] to print.
+struct Fields(Map<String, Value>);
+
+/// Records the fields of every span, so [`Json`] can print them with each event.
+struct SpanFields;
+
+impl<S> Layer<S> for SpanFields
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut fields = Map::new();
+
+        attrs.record(&mut JsonVisitor(&mut fields));
+
+        span.extensions_mut().insert(Fields(fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut extensions = span.extensions_mut();
+
+        if let Some(Fields(fields)) = extensions.get_mut::<Fields>() {
+            values.record(&mut JsonVisitor(fields));
+        }
+    }
+}
+
+/// Formats each event as a single line JSON object.
+struct Json;
+
+impl<S, N> FormatEvent<S, N> for Json
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let mut fields = Map::new();
+
+        event.record(&mut JsonVisitor(&mut fields));
+
+        let spans: Vec<_> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let mut x = span
+                    .extensions()
+                    .get::<Fields>()
+                    .map(|Fields(x)| x.clone())
+                    .unwrap_or_default();
+
+                x.insert("name".to_string(), span.name().into());
+
+                Value::Object(x)
+            })
+            .collect();
+
+        let metadata = event.metadata();
+
+        let line = json!({
+            "timestamp": timestamp,
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "fields": fields,
+            "spans": spans,
+        });
+
+        writeln!(writer, "{line}")
+    }
+}
+
+/// The layer writing logs to `writer` in `format`.
+pub fn fmt_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => SpanFields.and_then(layer.event_format(Json)).boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fmt_layer, LogFormat};
+    use serde_json::Value;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+    use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let buffer = Buffer::default();
+
+        let subscriber =
+            tracing_subscriber::registry().with(fmt_layer(LogFormat::Json, buffer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("scrape", id = 7);
+            let _guard = span.enter();
+
+            tracing::info!(source = "lctl", elapsed_seconds = 0.5, "Collected lctl");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "Collected lctl");
+        assert_eq!(line["fields"]["source"], "lctl");
+        assert_eq!(line["fields"]["elapsed_seconds"], 0.5);
+        assert_eq!(line["spans"][0]["name"], "scrape");
+        assert_eq!(line["spans"][0]["id"], 7);
+    }
+}
@@ -2,214 +2,729 @@
 // Use of this source code is governed by a MIT-style
 // license that can be found in the LICENSE file.
 
-use axum::{
-    body::{Body, Bytes},
-    error_handling::HandleErrorLayer,
-    extract::Query,
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    routing::get,
-    BoxError, Router,
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "kafka")]
+use lustrefs_exporter::kafka::{self, KafkaProducer};
+#[cfg(feature = "sandbox")]
+use lustrefs_exporter::sandbox::Sandbox;
+use lustrefs_exporter::{
+    bench,
+    collectors::{Collector, Collectors},
+    config,
+    counter_state::CounterState,
+    history::History,
+    jobstats::{
+        JobAggregate, JobDeltas, JobFilter, JobIdInfo, DEFAULT_JOBID_PATTERN,
+        DEFAULT_JOBSTATS_DELTA_RETENTION,
+    },
+    logging::{fmt_layer, LogFormat},
+    preflight,
+    procfs::{Backend, ParamSource},
+    quota_names::QuotaNames,
+    routes::{
+        serve, serve_listener, serve_unix, spawn_collection, AppState, Compression, Timeouts,
+    },
+    sd,
+    sink::{self, NdjsonSink, Target, VictoriaMetricsSink},
+    systemd,
+    target_registry::{TargetRegistry, DEFAULT_TARGET_RETENTION},
+    worker::{self, ScrapeWorker},
+    Error,
 };
-use clap::Parser;
-use lustre_collector::{parse_lctl_output, parse_lnetctl_output, parse_lnetctl_stats, parser};
-use lustrefs_exporter::{build_lustre_stats, Error};
-use serde::Deserialize;
+use regex::Regex;
 use std::{
-    borrow::Cow,
-    convert::Infallible,
-    io::{self, BufRead, BufReader},
-    net::SocketAddr,
+    env,
+    ffi::OsString,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
 };
-use tokio::process::Command;
-use tokio_stream::{wrappers::ReceiverStream, StreamExt};
-use tower::ServiceBuilder;
 
 const LUSTREFS_EXPORTER_PORT: &str = "32221";
 
+#[cfg(feature = "bench")]
+#[global_allocator]
+static GLOBAL: bench::CountingAlloc = bench::CountingAlloc;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LogTarget {
+    /// Plain text on stdout
+    Stdout,
+    /// The systemd journal, with tracing levels mapped to syslog priorities
+    Journald,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Sink {
+    /// Serve the metrics over HTTP for Prometheus to scrape
+    Prometheus,
+    /// Scrape periodically and append each sample as a line of JSON to a file
+    Ndjson,
+    /// Scrape periodically and post the samples to the /api/v1/import endpoint of VictoriaMetrics
+    VictoriaMetrics,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Replay captured lctl and jobstats output through the parse and encode path
+    /// of a scrape, reporting throughput, allocations and peak RSS.
+    /// Allocations are only counted when built with the `bench` feature
+    Bench {
+        /// Files or directories of them, e.g. lustre-collector/src/fixtures/valid
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Times to replay each file
+        #[clap(short = 'n', long, default_value_t = 10)]
+        iterations: usize,
+    },
+    /// Collect scrapes requested over stdin, started by --isolate-scrape
+    #[clap(hide = true)]
+    ScrapeWorker,
+}
+
 #[derive(Debug, Parser)]
 pub struct CommandOpts {
-    /// Port that exporter will listen to
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// YAML, or with a .toml extension TOML, file of settings named after the long flags,
+    /// e.g. `lctl_timeout: 10`. Flags and environment variables take precedence over it
+    #[clap(long, env = "LUSTREFS_EXPORTER_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Port that exporter will listen to, unless systemd passes a socket to it
     #[clap(short, long, env = "LUSTREFS_EXPORTER_PORT", default_value = LUSTREFS_EXPORTER_PORT)]
     pub port: u16,
+
+    /// Addresses that exporter will listen on, IPv4 or IPv6. `::` accepts both unless 0.0.0.0 is also given
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_LISTEN_ADDRESS",
+        value_delimiter = ',',
+        default_value = "0.0.0.0"
+    )]
+    pub listen_address: Vec<IpAddr>,
+
+    /// Don't log or export the address and user-agent of scrape clients
+    #[clap(long, env = "LUSTREFS_EXPORTER_DISABLE_CLIENT_TRACKING")]
+    pub disable_client_tracking: bool,
+
+    /// Seconds an lctl command may run before it is dropped from the scrape
+    #[clap(long, env = "LUSTREFS_EXPORTER_LCTL_TIMEOUT", default_value_t = Timeouts::default().lctl.as_secs())]
+    pub lctl_timeout: u64,
+
+    /// Seconds an lnetctl command may run before it is dropped from the scrape
+    #[clap(long, env = "LUSTREFS_EXPORTER_LNETCTL_TIMEOUT", default_value_t = Timeouts::default().lnetctl.as_secs())]
+    pub lnetctl_timeout: u64,
+
+    /// Seconds jobstats may stream for before the rest are dropped from the scrape
+    #[clap(long, env = "LUSTREFS_EXPORTER_JOBSTATS_TIMEOUT", default_value_t = Timeouts::default().jobstats.as_secs())]
+    pub jobstats_timeout: u64,
+
+    /// Seconds a whole scrape may take before the remaining optional sources are skipped
+    #[clap(long, env = "LUSTREFS_EXPORTER_SCRAPE_BUDGET", default_value_t = Timeouts::default().scrape.as_secs())]
+    pub scrape_budget: u64,
+
+    /// Directory to persist the stats counters in, so resets across restarts are
+    /// detected. Written at most once a minute
+    #[clap(long, env = "LUSTREFS_EXPORTER_STATE_DIR")]
+    pub state_dir: Option<PathBuf>,
+
+    /// Keep reporting a target that went missing as down for this many scrapes
+    #[clap(long, env = "LUSTREFS_EXPORTER_TARGET_RETENTION", default_value_t = DEFAULT_TARGET_RETENTION)]
+    pub target_retention: usize,
+
+    /// Only export jobstats for jobids matching this regex
+    #[clap(long, env = "LUSTREFS_EXPORTER_JOBSTATS_FILTER", value_parser = Regex::new)]
+    pub jobstats_filter: Option<Regex>,
+
+    /// Don't export jobstats for jobids matching this regex
+    #[clap(long, env = "LUSTREFS_EXPORTER_JOBSTATS_EXCLUDE", value_parser = Regex::new)]
+    pub jobstats_exclude: Option<Regex>,
+
+    /// Collapse the jobstats of jobs past this many into a single _overflow job per target
+    #[clap(long, env = "LUSTREFS_EXPORTER_JOBSTATS_MAX_JOBS")]
+    pub jobstats_max_jobs: Option<usize>,
+
+    /// Keep jobstats counters increasing when Lustre purges idle jobs and they start over
+    #[clap(long, env = "LUSTREFS_EXPORTER_JOBSTATS_DELTA")]
+    pub jobstats_delta: bool,
+
+    /// Seconds a job may go unseen before --jobstats-delta forgets its counters.
+    /// Should be longer than the job_cleanup_interval of the targets
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_JOBSTATS_DELTA_RETENTION",
+        default_value_t = DEFAULT_JOBSTATS_DELTA_RETENTION
+    )]
+    pub jobstats_delta_retention: u64,
+
+    /// Roll jobstats up by user or SLURM job instead of exporting every job
+    #[clap(long, env = "LUSTREFS_EXPORTER_JOBSTATS_AGGREGATE", value_enum, default_value_t = JobAggregate::None)]
+    pub jobstats_aggregate: JobAggregate,
+
+    /// Export a lustre_job_ids_info series per job, with the parts of its jobid as labels
+    #[clap(long, env = "LUSTREFS_EXPORTER_JOBID_INFO")]
+    pub jobid_info: bool,
+
+    /// Regex splitting jobids for lustre_job_ids_info, each named group becomes a label.
+    /// A `uid` group is also resolved to a `user` label from /etc/passwd
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_JOBID_PATTERN",
+        value_parser = Regex::new,
+        default_value = DEFAULT_JOBID_PATTERN,
+        requires = "jobid_info"
+    )]
+    pub jobid_pattern: Regex,
+
+    /// Label quota metrics with the user and group names from /etc/passwd and /etc/group
+    #[clap(long, env = "LUSTREFS_EXPORTER_QUOTA_NAMES")]
+    pub quota_names: bool,
+
+    /// File mapping project ids to names, one `name:id` per line as in /etc/projid
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_QUOTA_PROJECT_MAP",
+        requires = "quota_names"
+    )]
+    pub quota_project_map: Option<PathBuf>,
+
+    /// Only collect these groups of params and metrics, all but lnet-peer,
+    /// lnet-route and lnet-udsp if not given. Listing only those adds them to
+    /// the defaults
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_COLLECTORS",
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub collectors: Vec<Collector>,
+
+    /// Don't collect these groups of params and metrics
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_NO_COLLECTOR",
+        value_enum,
+        value_delimiter = ','
+    )]
+    pub no_collector: Vec<Collector>,
+
+    /// Attach the jobid and snapshot time of each job as an exemplar on
+    /// lustre_job_write_bytes_total, when the scraper accepts OpenMetrics
+    #[clap(long, env = "LUSTREFS_EXPORTER_ENABLE_EXEMPLARS")]
+    pub enable_exemplars: bool,
+
+    /// Where to send the metrics
+    #[clap(long, env = "LUSTREFS_EXPORTER_SINK", value_enum, default_value_t = Sink::Prometheus)]
+    pub sink: Sink,
+
+    /// File the ndjson sink appends to
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_SINK_PATH",
+        required_if_eq("sink", "ndjson")
+    )]
+    pub sink_path: Option<PathBuf>,
+
+    /// Base url the victoria-metrics sink posts to, with /api/v1/import appended, e.g.
+    /// http://vm:8428, or https://vminsert:8480/insert/0/prometheus for a cluster
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_SINK_URL",
+        required_if_eq("sink", "victoria-metrics"),
+        value_parser = sink::parse_push_url
+    )]
+    pub sink_url: Option<String>,
+
+    /// Seconds between scrapes of the ndjson and victoria-metrics sinks
+    #[clap(long, env = "LUSTREFS_EXPORTER_SINK_INTERVAL", default_value_t = 60)]
+    pub sink_interval: u64,
+
+    /// Rotate the ndjson sink file once it grows past this many bytes
+    #[clap(long, env = "LUSTREFS_EXPORTER_SINK_MAX_BYTES", default_value_t = 100 * 1024 * 1024)]
+    pub sink_max_bytes: u64,
+
+    /// Number of rotated ndjson sink files to keep
+    #[clap(long, env = "LUSTREFS_EXPORTER_SINK_KEEP", default_value_t = 5)]
+    pub sink_keep: usize,
+
+    /// Scrapes the sink keeps retrying after failed writes before dropping the oldest
+    #[clap(long, env = "LUSTREFS_EXPORTER_SINK_QUEUE", default_value_t = 10)]
+    pub sink_queue: usize,
+
+    /// Kafka brokers to publish the parsed records of each background collection to as JSON,
+    /// one message per record, as host:port
+    #[cfg(feature = "kafka")]
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_KAFKA_BROKERS",
+        value_delimiter = ',',
+        requires = "kafka_topic"
+    )]
+    pub kafka_brokers: Vec<String>,
+
+    /// Kafka topic the records are published to, keyed by hostname
+    #[cfg(feature = "kafka")]
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_KAFKA_TOPIC",
+        requires = "kafka_brokers",
+        requires = "collect_interval"
+    )]
+    pub kafka_topic: Option<String>,
+
+    /// librdkafka property to publish to Kafka with, as key=value, e.g.
+    /// security.protocol=sasl_ssl, sasl.mechanism=SCRAM-SHA-512 or compression.type=zstd.
+    /// Can be given more than once
+    #[cfg(feature = "kafka")]
+    #[clap(long, value_parser = parse_property, requires = "kafka_topic")]
+    pub kafka_config: Vec<(String, String)>,
+
+    /// Listen on this Unix domain socket instead of a TCP port, for agents on the same node
+    #[clap(long, env = "LUSTREFS_EXPORTER_LISTEN_UNIX")]
+    pub listen_unix: Option<PathBuf>,
+
+    /// Octal permissions of the Unix domain socket
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_LISTEN_UNIX_MODE",
+        default_value = "660",
+        value_parser = |x: &str| u32::from_str_radix(x, 8),
+        requires = "listen_unix"
+    )]
+    pub listen_unix_mode: u32,
+
+    /// How to read Lustre params
+    #[clap(long, env = "LUSTREFS_EXPORTER_BACKEND", value_enum, default_value_t = Backend::Lctl)]
+    pub backend: Backend,
+
+    /// Serve metrics from output saved in this directory instead of running lctl or lnetctl.
+    /// Each file is named after its source, e.g. `lctl.txt`, `recovery_status.txt`,
+    /// `jobstats.txt`, `lnetctl_net.txt` or `zfs_arcstats.txt`
+    #[clap(long, env = "LUSTREFS_EXPORTER_FROM_DIR")]
+    pub from_dir: Option<PathBuf>,
+
+    /// Collect in the background at this interval, e.g. `30s` or `2m`, and serve the
+    /// latest collection on /metrics instead of collecting on every scrape. Scrapers sending
+    /// the `ETag` of the collection back in `If-None-Match` get a 304 until the next one
+    #[clap(long, env = "LUSTREFS_EXPORTER_COLLECT_INTERVAL", value_parser = parse_interval)]
+    pub collect_interval: Option<Duration>,
+
+    /// Keep this many background collections in memory and serve the series of selected
+    /// metric families over them on /history?metrics=<family>,...&minutes=<minutes>
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_HISTORY",
+        default_value_t = 0,
+        requires = "collect_interval"
+    )]
+    pub history: usize,
+
+    /// Collect and parse scrapes of /metrics in a child process, so a hung lctl or a parser
+    /// running out of memory cannot take the server down. A scrape the worker fails is
+    /// answered with a 503, which Prometheus records as an empty scrape with `up` at 0,
+    /// and the worker is restarted
+    #[clap(long, env = "LUSTREFS_EXPORTER_ISOLATE_SCRAPE")]
+    pub isolate_scrape: bool,
+
+    /// Deny a fixed list of syscalls to the exporter and the commands it runs with seccomp,
+    /// and stop them writing outside /dev, and the state, sink and socket dirs for the
+    /// exporter itself, with Landlock
+    #[cfg(feature = "sandbox")]
+    #[clap(long, env = "LUSTREFS_EXPORTER_SANDBOX", value_enum, default_value_t = Sandbox::Off)]
+    pub sandbox: Sandbox,
+
+    /// How to compress responses for scrapers that accept it: `none`, `gzip:<level>` from 0,
+    /// fastest, to 9, smallest, or `zstd:<level>` from 1 to 22, with gzip at the same level for
+    /// scrapers that only accept gzip. The level can be left out for the default one
+    #[clap(long, env = "LUSTREFS_EXPORTER_COMPRESSION", default_value_t = Compression::default())]
+    pub compression: Compression,
+
+    /// Send responses smaller than this many bytes uncompressed, as compressing them saves
+    /// little bandwidth for the CPU it takes. Anything above 64 KiB counts as 64 KiB
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_COMPRESSION_MIN_BYTES",
+        default_value_t = 1024
+    )]
+    pub compression_min_bytes: usize,
+
+    /// Bearer token enabling /debug/dump, which returns the raw lctl and lnetctl output of a scrape
+    #[clap(long, env = "LUSTREFS_EXPORTER_DEBUG_TOKEN", hide_env_values = true)]
+    pub debug_token: Option<String>,
+
+    /// Where to send logs
+    #[clap(long, env = "LUSTREFS_EXPORTER_LOG_TARGET", value_enum, default_value_t = LogTarget::Stdout)]
+    pub log_target: LogTarget,
+
+    /// Format of the logs written to stdout
+    #[clap(long, env = "LUSTREFS_EXPORTER_LOG_FORMAT", value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Log level and per-module directives, e.g. `info,lustrefs_exporter::routes=debug`.
+    /// Falls back to RUST_LOG if not given
+    #[clap(
+        long,
+        env = "LUSTREFS_EXPORTER_LOG_LEVEL",
+        value_parser = |x: &str| EnvFilter::try_new(x).map(|_| x.to_string())
+    )]
+    pub log_level: Option<String>,
+
+    /// Print the params, stats and Lustre releases this build supports as JSON and exit
+    #[clap(long)]
+    pub print_support_matrix: bool,
 }
 
-async fn handle_error(error: BoxError) -> impl IntoResponse {
-    if error.is::<tower::timeout::error::Elapsed>() {
-        return (StatusCode::REQUEST_TIMEOUT, Cow::from("request timed out"));
+/// Parses a `key=value` property.
+#[cfg(feature = "kafka")]
+fn parse_property(x: &str) -> Result<(String, String), String> {
+    match x.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("{x}: expected key=value")),
     }
+}
 
-    if error.is::<tower::load_shed::error::Overloaded>() {
-        return (
-            StatusCode::SERVICE_UNAVAILABLE,
-            Cow::from("service is overloaded, try again later"),
-        );
+/// Parses an interval given in seconds, optionally with an `s`, `m` or `h` suffix.
+fn parse_interval(x: &str) -> Result<Duration, String> {
+    let (n, unit) = match x.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => x.split_at(i),
+        None => (x, "s"),
+    };
+
+    let n: u64 = n.parse().map_err(|e| format!("{x}: {e}"))?;
+
+    let secs = match unit {
+        "s" => n,
+        "m" => n * 60,
+        "h" => n * 60 * 60,
+        _ => return Err(format!("{x}: unknown unit {unit}, expected s, m or h")),
+    };
+
+    if secs == 0 {
+        return Err("The interval must be at least a second".to_string());
     }
 
-    (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Cow::from(format!("Unhandled internal error: {error}")),
-    )
+    Ok(Duration::from_secs(secs))
 }
 
-fn default_as_true() -> bool {
-    true
+/// Finds the config file in `args` before they are parsed, as its settings
+/// become the defaults the args are parsed with.
+fn config_arg(args: &[OsString]) -> Option<PathBuf> {
+    let mut xs = args.iter().skip(1);
+
+    while let Some(x) = xs.next() {
+        if x == "--config" {
+            return xs.next().map(PathBuf::from);
+        }
+
+        if let Some(x) = x.to_str().and_then(|x| x.strip_prefix("--config=")) {
+            return Some(x.into());
+        }
+    }
+
+    env::var_os("LUSTREFS_EXPORTER_CONFIG").map(PathBuf::from)
 }
 
-#[derive(Debug, Deserialize)]
-struct Params {
-    // Only disable jobstats if "jobstats=false"
-    #[serde(default = "default_as_true")]
-    jobstats: bool,
+/// Parses `args`, taking the defaults of the flags from the config file if one is given.
+fn parse_opts(args: Vec<OsString>) -> Result<CommandOpts, Error> {
+    let mut cmd = CommandOpts::command();
+
+    if let Some(path) = config_arg(&args) {
+        for (key, values) in config::load_file(&path)? {
+            if key == "config" || cmd.get_arguments().all(|x| x.get_id() != key.as_str()) {
+                return Err(Error::Config(format!(
+                    "Unknown setting {key} in {}",
+                    path.display()
+                )));
+            }
+
+            cmd = cmd.mut_arg(key, |x| x.default_values(values));
+        }
+    }
+
+    let matches = cmd.try_get_matches_from(args).unwrap_or_else(|e| e.exit());
+
+    Ok(CommandOpts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()))
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Error> {
-    tracing_subscriber::fmt::init();
+/// Sets up logging, to stderr instead of stdout if `stderr`.
+fn init_logging(target: LogTarget, format: LogFormat, level: Option<&str>, stderr: bool) {
+    let writer = || match stderr {
+        true => BoxMakeWriter::new(io::stderr),
+        false => BoxMakeWriter::new(io::stdout),
+    };
 
-    let opts = CommandOpts::parse();
+    let filter = match level {
+        Some(x) => EnvFilter::new(x),
+        None => EnvFilter::from_default_env(),
+    };
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], opts.port));
+    let registry = tracing_subscriber::registry().with(filter);
 
-    tracing::info!("Listening on http://{addr}/metrics");
+    match target {
+        LogTarget::Stdout => registry.with(fmt_layer(format, writer())).init(),
+        // ERROR maps to priority 3 (err) down to TRACE at 7 (debug), so journalctl -p works.
+        LogTarget::Journald => match tracing_journald::layer() {
+            Ok(layer) => registry.with(layer).init(),
+            Err(e) => {
+                registry.with(fmt_layer(format, writer())).init();
 
-    let listener = tokio::net::TcpListener::bind(("0.0.0.0", opts.port)).await?;
+                tracing::warn!("Could not connect to the systemd journal, logging to stdout: {e}");
+            }
+        },
+    }
+}
+
+fn runtime() -> io::Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+}
 
-    let load_shedder = ServiceBuilder::new()
-        .layer(HandleErrorLayer::new(handle_error))
-        .load_shed()
-        .concurrency_limit(10); // Max 10 concurrent scrape
+fn main() -> Result<(), Error> {
+    let opts = parse_opts(env::args_os().collect())?;
 
-    let app = Router::new()
-        .route("/metrics", get(scrape))
-        .layer(load_shedder);
+    if opts.print_support_matrix {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&lustre_collector::support::support_matrix())?
+        );
+
+        return Ok(());
+    }
 
-    axum::serve(listener, app).await?;
+    if let Some(Command::Bench { paths, iterations }) = &opts.command {
+        print!("{}", runtime()?.block_on(bench::run(paths, *iterations))?);
 
-    Ok(())
+        return Ok(());
+    }
+
+    // The worker answers the server on stdout
+    let is_worker = matches!(opts.command, Some(Command::ScrapeWorker));
+
+    init_logging(
+        opts.log_target,
+        opts.log_format,
+        opts.log_level.as_deref(),
+        is_worker,
+    );
+
+    #[cfg(feature = "sandbox")]
+    {
+        use std::path::Path;
+
+        // The dirs of the files the exporter writes as it runs
+        let dir = |x: &Path| match x.parent() {
+            Some(x) if !x.as_os_str().is_empty() => x.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let writable: Vec<_> = [
+            opts.state_dir.clone(),
+            opts.sink_path.as_deref().map(dir),
+            opts.listen_unix.as_deref().map(dir),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        // Before the runtime starts, so its threads all start sandboxed
+        opts.sandbox
+            .restrict_self(&writable.iter().map(PathBuf::as_path).collect::<Vec<_>>())?;
+    }
+
+    runtime()?.block_on(run(opts, is_worker))
 }
 
-async fn scrape(Query(params): Query<Params>) -> Result<Response<Body>, Error> {
-    let jobstats = if params.jobstats {
-        let child = tokio::task::spawn_blocking(move || {
-            let child = std::process::Command::new("lctl")
-                .arg("get_param")
-                .args(["obdfilter.*OST*.job_stats", "mdt.*.job_stats"])
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::piped())
-                .spawn()?;
-
-            Ok::<_, Error>(child)
-        })
-        .await?;
-
-        match child {
-            Ok(mut child) => {
-                let reader = BufReader::with_capacity(
-                    128 * 1_024,
-                    child.stdout.take().ok_or(io::Error::new(
-                        io::ErrorKind::NotFound,
-                        "stdout missing for lctl jobstats call.",
-                    ))?,
-                );
-
-                let reader_stderr = BufReader::new(child.stderr.take().ok_or(io::Error::new(
-                    io::ErrorKind::NotFound,
-                    "stderr missing for lctl jobstats call.",
-                ))?);
-
-                tokio::task::spawn(async move {
-                    for line in reader_stderr.lines().map_while(Result::ok) {
-                        tracing::debug!("stderr: {}", line);
-                    }
-                });
-
-                let (_, rx) = lustrefs_exporter::jobstats::jobstats_stream(reader);
-
-                tokio::task::spawn_blocking(move || {
-                    if let Err(e) = child.wait() {
-                        tracing::debug!("Unexpected error when waiting for child: {e}");
-                    }
-                });
-
-                let stream = ReceiverStream::new(rx)
-                    .map(|x| Bytes::from_iter(x.into_bytes()))
-                    .map(Ok::<_, Infallible>);
-
-                Some(stream)
-            }
-            Err(e) => {
-                tracing::debug!("Error while spawning lctl jobstats: {e}");
+/// Serves metrics, or scrapes for the server if `is_worker`.
+async fn run(opts: CommandOpts, is_worker: bool) -> Result<(), Error> {
+    let scrape_worker = if opts.isolate_scrape && !is_worker {
+        let args = env::args_os().skip(1).chain([worker::SUBCOMMAND.into()]);
 
-                None
-            }
-        }
+        Some(ScrapeWorker::new(env::current_exe()?, args))
     } else {
         None
     };
 
-    let mut output = vec![];
+    let counters = match &opts.state_dir {
+        Some(dir) => CounterState::load(dir)?,
+        None => CounterState::default(),
+    };
 
-    let lctl = Command::new("lctl")
-        .arg("get_param")
-        .args(parser::params())
-        .kill_on_drop(true)
-        .output()
-        .await?;
+    let quota_names = if opts.quota_names {
+        QuotaNames::load(opts.quota_project_map.as_deref())?
+    } else {
+        QuotaNames::default()
+    };
 
-    let mut lctl_output = parse_lctl_output(&lctl.stdout)?;
+    let job_ids = if opts.jobid_info {
+        Some(JobIdInfo {
+            pattern: opts.jobid_pattern,
+            names: Arc::new(QuotaNames::load(None)?),
+        })
+    } else {
+        None
+    };
 
-    output.append(&mut lctl_output);
+    let state = Arc::new(AppState {
+        track_clients: !opts.disable_client_tracking,
+        timeouts: Timeouts {
+            lctl: Duration::from_secs(opts.lctl_timeout),
+            lnetctl: Duration::from_secs(opts.lnetctl_timeout),
+            jobstats: Duration::from_secs(opts.jobstats_timeout),
+            scrape: Duration::from_secs(opts.scrape_budget),
+        },
+        counters,
+        targets: TargetRegistry::new(opts.target_retention),
+        jobstats_filter: JobFilter {
+            include: opts.jobstats_filter,
+            exclude: opts.jobstats_exclude,
+        },
+        jobstats_max_jobs: opts.jobstats_max_jobs,
+        job_deltas: opts.jobstats_delta.then(|| {
+            Arc::new(JobDeltas::new(Duration::from_secs(
+                opts.jobstats_delta_retention,
+            )))
+        }),
+        jobstats_aggregate: opts.jobstats_aggregate,
+        quota_names,
+        job_ids,
+        collectors: Collectors::new(&opts.collectors, &opts.no_collector),
+        exemplars: opts.enable_exemplars,
+        params: ParamSource::new(opts.backend),
+        // The sink scrapes on its own schedule already
+        collect_interval: opts
+            .collect_interval
+            .filter(|_| opts.sink == Sink::Prometheus),
+        history: History::new(opts.history),
+        debug_token: opts.debug_token,
+        from_dir: opts.from_dir,
+        scrape_worker,
+        #[cfg(feature = "sandbox")]
+        sandbox: opts.sandbox,
+        compression: opts.compression,
+        compression_min_bytes: opts.compression_min_bytes,
+        hostname: sd::hostname(),
+        ..Default::default()
+    });
+
+    if is_worker {
+        return worker::run(state).await;
+    }
 
-    let lnetctl = Command::new("lnetctl")
-        .args(["net", "show", "-v", "4"])
-        .kill_on_drop(true)
-        .output()
-        .await?;
+    preflight::check(&state).await.log();
 
-    let lnetctl_stats = std::str::from_utf8(&lnetctl.stdout)?;
-    let mut lnetctl_output = parse_lnetctl_output(lnetctl_stats)?;
+    if let Some(interval) = state.collect_interval {
+        tracing::info!("Collecting every {interval:?} in the background");
 
-    output.append(&mut lnetctl_output);
+        spawn_collection(Arc::clone(&state), interval);
+    }
 
-    let lnetctl_stats_output = Command::new("lnetctl")
-        .args(["stats", "show"])
-        .kill_on_drop(true)
-        .output()
-        .await?;
+    #[cfg(feature = "kafka")]
+    if let Some(topic) = opts.kafka_topic {
+        if state.collect_interval.is_none() {
+            return Err(Error::Config(
+                "Kafka publishes the background collections, which only the prometheus sink runs"
+                    .to_string(),
+            ));
+        }
 
-    let mut lnetctl_stats_record =
-        parse_lnetctl_stats(std::str::from_utf8(&lnetctl_stats_output.stdout)?)?;
+        tracing::info!("Publishing records to Kafka topic {topic}");
 
-    output.append(&mut lnetctl_stats_record);
+        kafka::spawn(
+            Arc::clone(&state),
+            KafkaProducer::new(&opts.kafka_brokers, topic, &opts.kafka_config)?,
+        );
+    }
 
-    let lustre_stats = build_lustre_stats(output);
+    let target = match (opts.sink, opts.sink_path, opts.sink_url) {
+        (Sink::Ndjson, Some(path), _) => Some(Target::Ndjson(NdjsonSink {
+            path,
+            max_bytes: opts.sink_max_bytes,
+            keep: opts.sink_keep,
+        })),
+        (Sink::VictoriaMetrics, _, Some(url)) => {
+            Some(Target::VictoriaMetrics(VictoriaMetricsSink { url }))
+        }
+        _ => None,
+    };
 
-    let body = if let Some(stream) = jobstats {
-        let merged =
-            tokio_stream::StreamExt::chain(tokio_stream::once(Ok(lustre_stats.into())), stream);
+    if let Some(target) = target {
+        tracing::info!("Writing metrics to {target}");
 
-        Body::from_stream(merged)
-    } else {
-        tracing::debug!("Jobstats collection disabled");
+        systemd::notify_ready();
+        systemd::spawn_watchdog();
+
+        return sink::run(
+            state,
+            target,
+            Duration::from_secs(opts.sink_interval),
+            opts.sink_queue,
+        )
+        .await;
+    }
+
+    if let Some(path) = &opts.listen_unix {
+        let server = serve_unix(state, path, opts.listen_unix_mode).await?;
+
+        tracing::info!("Listening on unix:{}", path.display());
 
-        Body::from(lustre_stats)
+        systemd::notify_ready();
+        systemd::spawn_watchdog();
+
+        return server.await?;
+    }
+
+    let (addrs, server) = match systemd::listener()? {
+        Some(listener) => {
+            tracing::info!(
+                "Using the socket passed by systemd, ignoring --port and --listen-address"
+            );
+
+            let (addr, server) = serve_listener(state, listener).await?;
+
+            (vec![addr], server)
+        }
+        None => {
+            let addrs: Vec<_> = opts
+                .listen_address
+                .iter()
+                .map(|&x| SocketAddr::new(x, opts.port))
+                .collect();
+
+            serve(state, &addrs).await?
+        }
     };
 
-    let response_builder = Response::builder().status(StatusCode::OK);
+    for addr in addrs {
+        tracing::info!("Listening on http://{addr}/metrics");
+    }
 
-    let resp = response_builder.body(body)?;
+    systemd::notify_ready();
+    systemd::spawn_watchdog();
 
-    Ok(resp)
+    server.await?
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::build_lustre_stats;
+    use super::{parse_interval, parse_opts, Collector};
     use combine::parser::EasyParser;
     use include_dir::{include_dir, Dir};
     use insta::assert_snapshot;
-    use lustre_collector::parser::parse;
+    use lustre_collector::{parser::parse, Record, TargetStat, TargetStats};
+    use lustrefs_exporter::{
+        build_lustre_stats, metadata,
+        validate::{validate, Violation},
+    };
+    use std::{collections::BTreeSet, time::Duration};
 
     static VALID_FIXTURES: Dir<'_> =
         include_dir!("$CARGO_MANIFEST_DIR/../lustre-collector/src/fixtures/valid/");
@@ -237,6 +752,157 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_valid_fixtures_are_compliant() {
+        let mut violations = BTreeSet::new();
+
+        for file in VALID_FIXTURES
+            .find("**/*")
+            .unwrap()
+            .filter_map(|x| x.as_file())
+        {
+            let contents = file.contents_utf8().unwrap();
+
+            let result = parse()
+                .easy_parse(contents)
+                .map_err(|err| err.map_position(|p| p.translate_position(contents)))
+                .unwrap();
+
+            let x = build_lustre_stats(result.0);
+
+            violations.extend(validate(&x, false).into_iter().map(|x| match x {
+                Violation::DuplicateSeries(x) => format!(
+                    "series of {} repeated",
+                    x.split('{').next().unwrap_or_default()
+                ),
+                x => x.to_string(),
+            }));
+        }
+
+        // Counters that predate the _total convention are kept for compatibility.
+        // Anything else showing up here is a bug.
+        assert_snapshot!(violations.into_iter().collect::<Vec<_>>().join("\n"));
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_interval("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_interval("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("10d").is_err());
+        assert!(parse_interval("s").is_err());
+    }
+
+    #[test]
+    fn test_config_file_is_layered_under_flags() {
+        let path =
+            std::env::temp_dir().join(format!("lustrefs-exporter-{}.yaml", std::process::id()));
+
+        std::fs::write(
+            &path,
+            "port: 9000\nlctl_timeout: 5\ncollectors: [ost, mdt]\nquota_names: true\n",
+        )
+        .unwrap();
+
+        let args = |xs: &[&str]| {
+            ["lustrefs-exporter", "--config", path.to_str().unwrap()]
+                .iter()
+                .chain(xs)
+                .map(Into::into)
+                .collect()
+        };
+
+        let opts = parse_opts(args(&["--lctl-timeout", "7"])).unwrap();
+
+        assert_eq!(opts.port, 9000);
+        assert_eq!(opts.lctl_timeout, 7);
+        assert_eq!(opts.collectors, [Collector::Ost, Collector::Mdt]);
+        assert!(opts.quota_names);
+
+        std::fs::write(&path, "no_such_flag: 1\n").unwrap();
+
+        assert!(parse_opts(args(&[])).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_valid_fixtures_have_metadata() {
+        let names: BTreeSet<_> = metadata().into_iter().map(|x| x.name).collect();
+
+        for file in VALID_FIXTURES
+            .find("**/*")
+            .unwrap()
+            .filter_map(|x| x.as_file())
+        {
+            let contents = file.contents_utf8().unwrap();
+
+            let result = parse()
+                .easy_parse(contents)
+                .map_err(|err| err.map_position(|p| p.translate_position(contents)))
+                .unwrap();
+
+            for line in build_lustre_stats(result.0).lines() {
+                if let Some(x) = line.strip_prefix("# TYPE ") {
+                    let name = x.split(' ').next().unwrap_or_default();
+
+                    assert!(names.contains(name), "{name} missing from metadata");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicated_params() {
+        let output = include_str!("../fixtures/stats.json");
+
+        let mut x: Vec<lustre_collector::Record> = serde_json::from_str(output).unwrap();
+
+        let once = build_lustre_stats(serde_json::from_str(output).unwrap());
+
+        x.extend(serde_json::from_str::<Vec<_>>(output).unwrap());
+
+        assert_eq!(build_lustre_stats(x), once);
+    }
+
+    #[test]
+    fn test_repeated_brw_stats_are_dropped() {
+        let contents = include_str!(
+            "../../lustre-collector/src/fixtures/valid/lustre-2.14.0_ddn133/2.14.0_ddn133_exports.txt"
+        );
+
+        let (records, _) = parse().easy_parse(contents).unwrap();
+
+        // The fixture lists the brw_stats of every target twice
+        let brw_stats = records
+            .iter()
+            .filter(|x| {
+                matches!(
+                    x,
+                    Record::Target(TargetStats::BrwStats(TargetStat { target, .. }))
+                        if target.0 == "ai400x2-OST0000"
+                )
+            })
+            .count();
+
+        assert_eq!(brw_stats, 2);
+
+        let x = build_lustre_stats(records);
+
+        let series: Vec<_> = x
+            .lines()
+            .filter(|x| x.starts_with("lustre_dio_frags{") && x.contains("ai400x2-OST0000"))
+            .collect();
+
+        assert!(!series.is_empty());
+        assert_eq!(series.iter().collect::<BTreeSet<_>>().len(), series.len());
+
+        assert!(!validate(&x, false)
+            .iter()
+            .any(|x| matches!(x, Violation::DuplicateSeries(_))));
+    }
+
     #[test]
     fn test_stats() {
         let output = include_str!("../fixtures/stats.json");
@@ -291,4 +957,119 @@ mod tests {
 
         insta::assert_snapshot!(x);
     }
+
+    #[test]
+    fn test_lnetctl_net_stats() {
+        let output = include_str!("../../lustre-collector/src/fixtures/lnetctl_net_show.txt");
+
+        let x = lustre_collector::parse_lnetctl_output(output).unwrap();
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
+
+    #[test]
+    fn test_lnetctl_peer_stats() {
+        let output = include_str!("../../lustre-collector/src/fixtures/lnetctl_peer_show.txt");
+
+        let x = lustre_collector::parse_lnetctl_peer_stats(output).unwrap();
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
+
+    #[test]
+    fn test_lnetctl_route_stats() {
+        let mut x = lustre_collector::parse_lnetctl_routes(include_str!(
+            "../../lustre-collector/src/fixtures/lnetctl_route_show.txt"
+        ))
+        .unwrap();
+
+        x.append(
+            &mut lustre_collector::parse_lnetctl_routing(include_str!(
+                "../../lustre-collector/src/fixtures/lnetctl_routing_show.txt"
+            ))
+            .unwrap(),
+        );
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
+
+    #[test]
+    fn test_lnetctl_udsp_stats() {
+        let x = lustre_collector::parse_lnetctl_udsp(include_str!(
+            "../../lustre-collector/src/fixtures/lnetctl_udsp_show.txt"
+        ))
+        .unwrap();
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
+
+    #[test]
+    fn test_zfs_stats() {
+        let mut x = lustre_collector::parse_arcstats_output(include_bytes!(
+            "../../lustre-collector/src/fixtures/arcstats.txt"
+        ))
+        .unwrap();
+
+        x.append(
+            &mut lustre_collector::parse_zpool_fragmentation_output(b"mdt0pool\t3\nost0pool\t27\n")
+                .unwrap(),
+        );
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
+
+    #[test]
+    fn test_mgs_fs_stats() {
+        let output =
+            b"mgs.MGS.live.fs\nmgs.MGS.live.fs2\nmgs.MGS.live.nodemap\nmgs.MGS.live.params\n";
+
+        let x = lustre_collector::parse_mgs_fs_output(output).unwrap();
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
+
+    #[test]
+    fn test_mgs_live_stats() {
+        let mut x = lustre_collector::parse_mgs_live_output(include_bytes!(
+            "../../lustre-collector/src/fixtures/mgs_live.txt"
+        ))
+        .unwrap();
+
+        x.append(
+            &mut lustre_collector::parse_pool_list_output(
+                &lustre_collector::Target("MGS".into()),
+                &lustre_collector::FsName("fs".into()),
+                b"Pools from fs:\nfs.flash\nfs.archive\n",
+            )
+            .unwrap(),
+        );
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
+
+    #[test]
+    fn test_recovery_status_stats() {
+        let output =
+            include_bytes!("../../lustre-collector/src/fixtures/recovery-multiple-recovering.txt");
+
+        let x = lustre_collector::parse_recovery_status_output(output).unwrap();
+
+        let x = build_lustre_stats(x);
+
+        insta::assert_snapshot!(x);
+    }
 }
@@ -0,0 +1,148 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt, ToMetricInst};
+use lustre_collector::{FsName, MgsFsStat, TargetStat};
+use prometheus_exporter_base::prelude::*;
+use std::{collections::BTreeMap, ops::Deref};
+
+static MGS_FILESYSTEM: Metric = Metric {
+    name: "lustre_mgs_filesystem",
+    help: "Filesystems registered with the MGS. Always 1.",
+    r#type: MetricType::Gauge,
+};
+
+static MGS_FILESYSTEMS: Metric = Metric {
+    name: "lustre_mgs_filesystems",
+    help: "Number of filesystems registered with the MGS.",
+    r#type: MetricType::Gauge,
+};
+
+static MGS_IR_STATE: Metric = Metric {
+    name: "lustre_mgs_imperative_recovery_state",
+    help: "Imperative recovery state of the filesystem. 1 for the current state, 0 otherwise.",
+    r#type: MetricType::Gauge,
+};
+
+static MGS_IR_NONIR_CLIENTS: Metric = Metric {
+    name: "lustre_mgs_imperative_recovery_nonir_clients",
+    help: "Number of clients of the filesystem that don't support imperative recovery.",
+    r#type: MetricType::Gauge,
+};
+
+static MGS_NIDTBL_VERSION: Metric = Metric {
+    name: "lustre_mgs_nidtbl_version",
+    help: "Version of the NID table the MGS sends to clients for imperative recovery.",
+    r#type: MetricType::Gauge,
+};
+
+static MGS_IR_NOTIFY: Metric = Metric {
+    name: "lustre_mgs_imperative_recovery_notify_total",
+    help: "Total number of imperative recovery notifications sent to clients of the filesystem.",
+    r#type: MetricType::Counter,
+};
+
+static MGS_POOL_COUNT: Metric = Metric {
+    name: "lustre_mgs_pool_count",
+    help: "Number of OST pools configured for the filesystem.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&MGS_FILESYSTEM, "mgs.*.live.*"),
+    (&MGS_FILESYSTEMS, "mgs.*.live.*"),
+    (&MGS_IR_STATE, "mgs.*.live.*"),
+    (&MGS_IR_NONIR_CLIENTS, "mgs.*.live.*"),
+    (&MGS_NIDTBL_VERSION, "mgs.*.live.*"),
+    (&MGS_IR_NOTIFY, "mgs.*.live.*"),
+    (&MGS_POOL_COUNT, "lctl pool_list"),
+];
+
+/// The states imperative recovery can be in, see `ir_strings` in mgs_nids.c.
+const IR_STATES: [&str; 4] = ["disabled", "full", "partial", "startup"];
+
+pub fn build_mgs_ir_state_stats(
+    x: MgsFsStat<String>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let mut states = IR_STATES.to_vec();
+
+    if !states.contains(&x.value.as_str()) {
+        states.push(x.value.as_str());
+    }
+
+    for state in states {
+        stats_map
+            .get_mut_metric(MGS_IR_STATE)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("target", x.target.deref())
+                    .with_label("fs", x.fs.0.as_str())
+                    .with_label("state", state)
+                    .with_value(u8::from(state == x.value)),
+            );
+    }
+}
+
+pub fn build_mgs_ir_nonir_clients_stats(
+    x: MgsFsStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    stats_map
+        .get_mut_metric(MGS_IR_NONIR_CLIENTS)
+        .render_and_append_instance(&x.to_metric_inst());
+}
+
+pub fn build_mgs_nidtbl_version_stats(
+    x: MgsFsStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    stats_map
+        .get_mut_metric(MGS_NIDTBL_VERSION)
+        .render_and_append_instance(&x.to_metric_inst());
+}
+
+pub fn build_mgs_ir_notify_stats(
+    x: MgsFsStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    stats_map
+        .get_mut_metric(MGS_IR_NOTIFY)
+        .render_and_append_instance(&x.to_metric_inst());
+}
+
+pub fn build_mgs_pool_count_stats(
+    x: MgsFsStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    stats_map
+        .get_mut_metric(MGS_POOL_COUNT)
+        .render_and_append_instance(&x.to_metric_inst());
+}
+
+pub fn build_mgs_fs_stats(
+    x: TargetStat<Vec<FsName>>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let TargetStat { target, value, .. } = x;
+
+    for FsName(fs) in &value {
+        stats_map
+            .get_mut_metric(MGS_FILESYSTEM)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("fs", fs.as_str())
+                    .with_value(1),
+            );
+    }
+
+    stats_map
+        .get_mut_metric(MGS_FILESYSTEMS)
+        .render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("target", target.deref())
+                .with_value(value.len()),
+        );
+}
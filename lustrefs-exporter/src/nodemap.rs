@@ -0,0 +1,120 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt};
+use lustre_collector::{NodemapStat, NodemapStats};
+use prometheus_exporter_base::{prelude::*, Yes};
+use std::collections::BTreeMap;
+
+static NODEMAP_ACTIVE: Metric = Metric {
+    name: "lustre_nodemap_active",
+    help: "Whether nodemap enforcement is enabled. 1 is active, 0 is inactive.",
+    r#type: MetricType::Gauge,
+};
+
+static NODEMAP_COUNT: Metric = Metric {
+    name: "lustre_nodemap_count",
+    help: "Number of nodemaps configured, including the default nodemap.",
+    r#type: MetricType::Gauge,
+};
+
+static NODEMAP_ID: Metric = Metric {
+    name: "lustre_nodemap_id",
+    help: "The id assigned to the nodemap.",
+    r#type: MetricType::Gauge,
+};
+
+static NODEMAP_ADMIN: Metric = Metric {
+    name: "lustre_nodemap_admin",
+    help: "Whether root is squashed for clients in the nodemap. 1 is not squashed, 0 is squashed.",
+    r#type: MetricType::Gauge,
+};
+
+static NODEMAP_TRUSTED: Metric = Metric {
+    name: "lustre_nodemap_trusted",
+    help:
+        "Whether client ids in the nodemap are trusted without mapping. 1 is trusted, 0 is mapped.",
+    r#type: MetricType::Gauge,
+};
+
+static NODEMAP_SQUASH_UID: Metric = Metric {
+    name: "lustre_nodemap_squash_uid",
+    help: "The uid unmapped users in the nodemap are squashed to.",
+    r#type: MetricType::Gauge,
+};
+
+static NODEMAP_SQUASH_GID: Metric = Metric {
+    name: "lustre_nodemap_squash_gid",
+    help: "The gid unmapped groups in the nodemap are squashed to.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&NODEMAP_ACTIVE, "nodemap.active"),
+    (&NODEMAP_COUNT, "nodemap.*.id"),
+    (&NODEMAP_ID, "nodemap.*.id"),
+    (&NODEMAP_ADMIN, "nodemap.*.admin_nodemap"),
+    (&NODEMAP_TRUSTED, "nodemap.*.trusted_nodemap"),
+    (&NODEMAP_SQUASH_UID, "nodemap.*.squash_uid"),
+    (&NODEMAP_SQUASH_GID, "nodemap.*.squash_gid"),
+];
+
+fn nodemap_inst<T: Copy + Into<u64>>(x: &NodemapStat<T>) -> PrometheusInstance<'_, u64, Yes> {
+    PrometheusInstance::new()
+        .with_label("nodemap", x.nodemap.as_str())
+        .with_value(x.value.into())
+}
+
+pub fn build_nodemap_stats(
+    xs: Vec<NodemapStats>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let mut count = 0;
+
+    for x in xs {
+        match x {
+            NodemapStats::Active(x) => {
+                stats_map
+                    .get_mut_metric(NODEMAP_ACTIVE)
+                    .render_and_append_instance(
+                        &PrometheusInstance::new().with_value(u8::from(x.value)),
+                    );
+            }
+            NodemapStats::Id(x) => {
+                count += 1;
+
+                stats_map
+                    .get_mut_metric(NODEMAP_ID)
+                    .render_and_append_instance(&nodemap_inst(&x));
+            }
+            NodemapStats::AdminNodemap(x) => {
+                stats_map
+                    .get_mut_metric(NODEMAP_ADMIN)
+                    .render_and_append_instance(&nodemap_inst(&x));
+            }
+            NodemapStats::TrustedNodemap(x) => {
+                stats_map
+                    .get_mut_metric(NODEMAP_TRUSTED)
+                    .render_and_append_instance(&nodemap_inst(&x));
+            }
+            NodemapStats::SquashUid(x) => {
+                stats_map
+                    .get_mut_metric(NODEMAP_SQUASH_UID)
+                    .render_and_append_instance(&nodemap_inst(&x));
+            }
+            NodemapStats::SquashGid(x) => {
+                stats_map
+                    .get_mut_metric(NODEMAP_SQUASH_GID)
+                    .render_and_append_instance(&nodemap_inst(&x));
+            }
+        }
+    }
+
+    if count > 0 {
+        stats_map
+            .get_mut_metric(NODEMAP_COUNT)
+            .render_and_append_instance(&PrometheusInstance::new().with_value(count));
+    }
+}
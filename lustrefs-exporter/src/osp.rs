@@ -0,0 +1,116 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt};
+use lustre_collector::{OspStat, TargetStats};
+use prometheus_exporter_base::prelude::*;
+use std::collections::BTreeMap;
+
+static PREALLOC_NEXT_ID: Metric = Metric {
+    name: "lustre_osp_prealloc_next_id",
+    help: "Id of the next precreated OST object the MDT will hand out.",
+    r#type: MetricType::Gauge,
+};
+
+static PREALLOC_LAST_ID: Metric = Metric {
+    name: "lustre_osp_prealloc_last_id",
+    help: "Id of the last OST object precreated for the MDT.",
+    r#type: MetricType::Gauge,
+};
+
+static PREALLOC_STATUS: Metric = Metric {
+    name: "lustre_osp_prealloc_status",
+    help: "Status of object precreation on the OST for the MDT. 0 when healthy, a negative errno otherwise, like -28 when the OST is out of space.",
+    r#type: MetricType::Gauge,
+};
+
+static PREALLOC_OBJECTS: Metric = Metric {
+    name: "lustre_osp_prealloc_objects",
+    help: "Number of precreated OST objects the MDT has left to hand out. Creates on the OST stall once it reaches 0.",
+    r#type: MetricType::Gauge,
+};
+
+static CREATE_COUNT: Metric = Metric {
+    name: "lustre_osp_create_count",
+    help: "Number of objects the MDT asks the OST to precreate at a time.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&PREALLOC_NEXT_ID, "osp.*OST*.prealloc_next_id"),
+    (&PREALLOC_LAST_ID, "osp.*OST*.prealloc_last_id"),
+    (&PREALLOC_STATUS, "osp.*OST*.prealloc_status"),
+    (&PREALLOC_OBJECTS, "osp.*OST*.prealloc_last_id"),
+    (&CREATE_COUNT, "osp.*OST*.create_count"),
+];
+
+pub fn build_osp_stats(
+    x: &OspStat<i64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let metric = match x.param.0.as_str() {
+        "prealloc_next_id" => PREALLOC_NEXT_ID,
+        "prealloc_last_id" => PREALLOC_LAST_ID,
+        "prealloc_status" => PREALLOC_STATUS,
+        "create_count" => CREATE_COUNT,
+        x => {
+            tracing::debug!("Unhandled osp stat: {x}");
+
+            return;
+        }
+    };
+
+    stats_map.get_mut_metric(metric).render_and_append_instance(
+        &PrometheusInstance::new()
+            .with_label("target", x.target.0.as_str())
+            .with_label("mdt", x.mdt.0.as_str())
+            .with_value(x.value),
+    );
+}
+
+/// The next and last precreated object ids of each OST and MDT pair, which come from separate params.
+#[derive(Debug, Default)]
+pub struct Prealloc {
+    devices: BTreeMap<(String, String), (Option<i64>, Option<i64>)>,
+}
+
+impl Prealloc {
+    pub fn observe(&mut self, x: &TargetStats) {
+        let TargetStats::OspPrealloc(x) = x else {
+            return;
+        };
+
+        let entry = self
+            .devices
+            .entry((x.target.0.clone(), x.mdt.0.clone()))
+            .or_default();
+
+        match x.param.0.as_str() {
+            "prealloc_next_id" => entry.0 = Some(x.value),
+            "prealloc_last_id" => entry.1 = Some(x.value),
+            _ => {}
+        }
+    }
+}
+
+pub fn build_prealloc_stats(
+    x: Prealloc,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    for ((target, mdt), values) in x.devices {
+        let (Some(next_id), Some(last_id)) = values else {
+            continue;
+        };
+
+        stats_map
+            .get_mut_metric(PREALLOC_OBJECTS)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("target", target.as_str())
+                    .with_label("mdt", mdt.as_str())
+                    .with_value((last_id - next_id + 1).max(0)),
+            );
+    }
+}
@@ -0,0 +1,37 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use std::collections::BTreeMap;
+
+use lustre_collector::PoolStat;
+use prometheus_exporter_base::prelude::*;
+
+use crate::{Metric, StatsMapExt};
+
+static POOL_MEMBER_INFO: Metric = Metric {
+    name: "lustre_pool_member_info",
+    help: "OSTs belonging to each OST pool of the filesystem. Always 1.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] =
+    &[(&POOL_MEMBER_INFO, "lod.*.pools.*, lov.*.pools.*")];
+
+pub fn build_pool_stats(
+    x: &PoolStat,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    for ost in &x.members {
+        stats_map
+            .get_mut_metric(POOL_MEMBER_INFO)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("fsname", x.fs.0.as_str())
+                    .with_label("pool", x.pool.as_str())
+                    .with_label("ost", ost.0.as_str())
+                    .with_value(1),
+            );
+    }
+}
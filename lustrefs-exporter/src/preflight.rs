@@ -0,0 +1,260 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Checks what the exporter can collect with the privileges it runs with, so
+//! a deployment without root or `CAP_SYS_ADMIN` finds out what it is missing
+//! at startup rather than from gaps in its dashboards.
+
+use crate::{
+    collectors::Collector,
+    procfs::{self, PARAM_ROOTS},
+    routes::AppState,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, io, time::Duration};
+use tokio::process::Command;
+
+/// The capabilities collecting can need, by bit in `CapEff`.
+const CAPABILITIES: [(&str, u32); 3] = [
+    ("CAP_DAC_READ_SEARCH", 2),
+    ("CAP_NET_ADMIN", 12),
+    ("CAP_SYS_ADMIN", 21),
+];
+
+/// How many unreadable params are listed in a check.
+const MAX_LISTED_PARAMS: usize = 5;
+
+/// The outcome of one check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    /// What was checked
+    pub name: &'static str,
+    pub ok: bool,
+    /// Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What to do about it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<&'static str>,
+}
+
+impl Check {
+    fn ok(name: &'static str) -> Self {
+        Self {
+            name,
+            ok: true,
+            error: None,
+            hint: None,
+        }
+    }
+
+    fn failed(name: &'static str, error: String, hint: &'static str) -> Self {
+        Self {
+            name,
+            ok: false,
+            error: Some(error),
+            hint: Some(hint),
+        }
+    }
+}
+
+/// The checks of a preflight, as returned by `/preflight`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Preflight {
+    /// Whether every check passed
+    pub ok: bool,
+    /// The effective uid of the exporter
+    pub uid: u32,
+    /// Whether the exporter holds each of the capabilities collecting can need
+    pub capabilities: BTreeMap<&'static str, bool>,
+    pub checks: Vec<Check>,
+}
+
+impl Preflight {
+    /// Logs each failed check with what to do about it.
+    pub fn log(&self) {
+        for x in self.checks.iter().filter(|x| !x.ok) {
+            tracing::warn!(
+                "Preflight check {} failed: {}. {}",
+                x.name,
+                x.error.as_deref().unwrap_or_default(),
+                x.hint.unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Reads the capabilities out of the `CapEff` line of `/proc/self/status`.
+fn capabilities(status: &str) -> BTreeMap<&'static str, bool> {
+    let effective = status
+        .lines()
+        .find_map(|x| x.strip_prefix("CapEff:"))
+        .and_then(|x| u64::from_str_radix(x.trim(), 16).ok())
+        .unwrap_or_default();
+
+    CAPABILITIES
+        .into_iter()
+        .map(|(name, bit)| (name, effective & (1 << bit) != 0))
+        .collect()
+}
+
+/// Runs `cmd`, returning why it could not be run or did not succeed.
+async fn run(cmd: &mut Command, timeout: Duration) -> Result<(), String> {
+    let output = tokio::time::timeout(timeout, cmd.kill_on_drop(true).output()).await;
+
+    match output {
+        Ok(Ok(x)) if x.status.success() => Ok(()),
+        Ok(Ok(x)) => Err(format!(
+            "exited with {}: {}",
+            x.status,
+            String::from_utf8_lossy(&x.stderr).trim()
+        )),
+        Ok(Err(e)) if e.kind() == io::ErrorKind::NotFound => Err("not found in PATH".to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("did not finish within {timeout:?}")),
+    }
+}
+
+/// Checks that lctl and lnetctl can be run and the params of the enabled
+/// collectors read, and records the outcome in `lustre_exporter_preflight_ok`.
+pub async fn check(state: &AppState) -> Preflight {
+    let mut checks = vec![];
+
+    // Saved output needs no privileges at all
+    if state.from_dir.is_none() {
+        if !state.params.use_procfs() {
+            let mut cmd = Command::new("lctl");
+
+            cmd.args(["get_param", "-n", "version"]);
+
+            #[cfg(feature = "sandbox")]
+            state.sandbox.apply(cmd.as_std_mut());
+
+            checks.push(match run(&mut cmd, state.timeouts.lctl).await {
+                Ok(()) => Check::ok("lctl"),
+                Err(e) => Check::failed(
+                    "lctl",
+                    format!("lctl {e}"),
+                    "Install the Lustre utilities, or read params without lctl with --backend procfs",
+                ),
+            });
+        }
+
+        if state.collectors.enabled(Collector::Lnet) {
+            let mut cmd = Command::new("lnetctl");
+
+            cmd.args(["net", "show"]);
+
+            #[cfg(feature = "sandbox")]
+            state.sandbox.apply(cmd.as_std_mut());
+
+            checks.push(match run(&mut cmd, state.timeouts.lnetctl).await {
+                Ok(()) => Check::ok("lnetctl"),
+                Err(e) => Check::failed(
+                    "lnetctl",
+                    format!("lnetctl {e}"),
+                    "Install the Lustre utilities and grant CAP_SYS_ADMIN, which lnetctl needs to query LNet, or disable the lnet collector with --no-collector lnet",
+                ),
+            });
+        }
+
+        let params = state.collectors.params();
+
+        let unreadable =
+            tokio::task::spawn_blocking(move || procfs::unreadable_params(&PARAM_ROOTS, &params))
+                .await
+                .unwrap_or_default();
+
+        checks.push(match unreadable.first() {
+            None => Check::ok("params"),
+            Some((_, e)) => Check::failed(
+                "params",
+                format!(
+                    "{} params cannot be read ({e}): {}",
+                    unreadable.len(),
+                    unreadable
+                        .iter()
+                        .take(MAX_LISTED_PARAMS)
+                        .map(|(x, _)| x.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                "Grant CAP_DAC_READ_SEARCH, or disable the collectors of these params with --no-collector",
+            ),
+        });
+    }
+
+    let preflight = Preflight {
+        ok: checks.iter().all(|x| x.ok),
+        // SAFETY: geteuid has no preconditions and cannot fail
+        uid: unsafe { libc::geteuid() },
+        capabilities: capabilities(&fs::read_to_string("/proc/self/status").unwrap_or_default()),
+        checks,
+    };
+
+    state.exporter_metrics.record_preflight(preflight.ok);
+
+    preflight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effective_capabilities() {
+        let x = capabilities(
+            "Name:\tlustrefs-export\nCapInh:\t0000000000000000\nCapEff:\t0000000000201004\n",
+        );
+
+        assert_eq!(
+            x,
+            BTreeMap::from([
+                ("CAP_DAC_READ_SEARCH", true),
+                ("CAP_NET_ADMIN", true),
+                ("CAP_SYS_ADMIN", true),
+            ])
+        );
+
+        assert!(capabilities("CapEff:\t0000000000000000\n")
+            .values()
+            .all(|x| !x));
+    }
+
+    #[tokio::test]
+    async fn saved_output_needs_no_privileges() {
+        let state = AppState {
+            from_dir: Some(std::env::temp_dir()),
+            ..Default::default()
+        };
+
+        let x = check(&state).await;
+
+        assert!(x.ok);
+        assert!(x.checks.is_empty());
+        assert!(state
+            .exporter_metrics
+            .render()
+            .contains("lustre_exporter_preflight_ok 1"));
+    }
+
+    #[tokio::test]
+    async fn missing_commands_fail() {
+        let timeout = Duration::from_secs(5);
+
+        let mut cmd = Command::new("lustrefs-exporter-no-such-command");
+
+        assert_eq!(
+            run(&mut cmd, timeout).await,
+            Err("not found in PATH".to_string())
+        );
+
+        let mut cmd = Command::new("false");
+
+        assert!(run(&mut cmd, timeout)
+            .await
+            .unwrap_err()
+            .starts_with("exited with"));
+    }
+}
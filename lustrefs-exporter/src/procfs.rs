@@ -0,0 +1,406 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Reads Lustre params straight from procfs and sysfs, for nodes where lctl
+//! is missing or too slow to answer a scrape.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// The directories `lctl get_param` looks for params in, in the order it looks.
+pub const PARAM_ROOTS: [&str; 3] = [
+    "/sys/fs/lustre",
+    "/proc/fs/lustre",
+    "/sys/kernel/debug/lustre",
+];
+
+/// How long [`Backend::Auto`] reads params from procfs after lctl timed out,
+/// before trying lctl again.
+pub const LCTL_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How params are read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Run `lctl get_param`
+    #[default]
+    Lctl,
+    /// Read the param files under /proc/fs/lustre and /sys/fs/lustre
+    Procfs,
+    /// Use lctl while it is installed and answering in time, procfs otherwise.
+    /// lctl is tried again 5 minutes after it timed out
+    Auto,
+}
+
+/// Decides where params are read from, for the configured [`Backend`].
+#[derive(Debug, Default)]
+pub struct ParamSource {
+    backend: Backend,
+    /// Whether `lctl` was found in `PATH`, checked on first use
+    lctl_installed: OnceLock<bool>,
+    /// When lctl last timed out with [`Backend::Auto`]
+    lctl_timed_out: Mutex<Option<Instant>>,
+    /// The sources whose procfs read has not finished yet
+    reading: Arc<Mutex<HashSet<&'static str>>>,
+}
+
+impl ParamSource {
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            ..Default::default()
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Whether params should be read from procfs rather than lctl.
+    pub fn use_procfs(&self) -> bool {
+        match self.backend {
+            Backend::Lctl => false,
+            Backend::Procfs => true,
+            Backend::Auto => {
+                self.lctl_timing_out() || !*self.lctl_installed.get_or_init(lctl_installed)
+            }
+        }
+    }
+
+    /// Whether lctl timed out less than [`LCTL_RETRY_INTERVAL`] ago.
+    fn lctl_timing_out(&self) -> bool {
+        let mut timed_out = self
+            .lctl_timed_out
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        match *timed_out {
+            Some(x) if x.elapsed() < LCTL_RETRY_INTERVAL => true,
+            Some(_) => {
+                tracing::info!("Trying lctl again to read params");
+
+                *timed_out = None;
+
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Records that lctl did not answer in time, so [`Backend::Auto`] reads
+    /// params from procfs for the next [`LCTL_RETRY_INTERVAL`].
+    ///
+    /// Returns whether the param that timed out should be read from procfs instead.
+    pub fn record_lctl_timeout(&self) -> bool {
+        if self.backend != Backend::Auto {
+            return false;
+        }
+
+        let mut timed_out = self
+            .lctl_timed_out
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        if timed_out.replace(Instant::now()).is_none() {
+            tracing::warn!(
+                "lctl timed out, reading params from procfs for the next {LCTL_RETRY_INTERVAL:?}"
+            );
+        }
+
+        true
+    }
+
+    /// Marks the procfs read of `source` as started, until the returned guard
+    /// is dropped.
+    ///
+    /// Returns `None` while the previous read of `source` has not finished, so a
+    /// param file stuck in the kernel holds at most one blocking thread per
+    /// source rather than one more each scrape.
+    pub fn start_read(&self, source: &'static str) -> Option<Reading> {
+        let reading = Arc::clone(&self.reading);
+
+        let started = reading
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(source);
+
+        started.then_some(Reading { source, reading })
+    }
+}
+
+/// A procfs read in progress, see [`ParamSource::start_read`].
+#[derive(Debug)]
+pub struct Reading {
+    source: &'static str,
+    reading: Arc<Mutex<HashSet<&'static str>>>,
+}
+
+impl Drop for Reading {
+    fn drop(&mut self) {
+        self.reading
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(self.source);
+    }
+}
+
+fn lctl_installed() -> bool {
+    let installed = env::var_os("PATH")
+        .map(|x| env::split_paths(&x).any(|dir| dir.join("lctl").is_file()))
+        .unwrap_or(false);
+
+    if !installed {
+        tracing::info!("lctl is not installed, reading params from procfs");
+    }
+
+    installed
+}
+
+/// Expands the `{a,b}` alternatives of a param pattern, as lctl does.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some((start, end)) = pattern
+        .find('{')
+        .and_then(|start| Some((start, start + pattern[start..].find('}')?)))
+    else {
+        return vec![pattern.to_string()];
+    };
+
+    pattern[start + 1..end]
+        .split(',')
+        .flat_map(|x| expand_braces(&format!("{}{x}{}", &pattern[..start], &pattern[end + 1..])))
+        .collect()
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters and `?` any one.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| glob_match(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((x, rest)) => name.first() == Some(x) && glob_match(rest, &name[1..]),
+    }
+}
+
+/// Finds the param files under `dir` matching `components`.
+///
+/// A file or directory name may hold dots itself, such as the NID of an
+/// export, so each entry is matched against every run of leading components.
+fn walk(dir: &Path, prefix: &str, components: &[&str], out: &mut Vec<(String, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+
+    entries.sort_by_key(|x| x.file_name());
+
+    for entry in entries {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        let path = entry.path();
+
+        let name = if prefix.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{prefix}.{file_name}")
+        };
+
+        for n in 1..=components.len() {
+            if !glob_match(components[..n].join(".").as_bytes(), file_name.as_bytes()) {
+                continue;
+            }
+
+            if n == components.len() {
+                if path.is_file() {
+                    out.push((name.clone(), path.clone()));
+                }
+            } else if path.is_dir() {
+                walk(&path, &name, &components[n..], out);
+            }
+        }
+    }
+}
+
+/// Reads `params` from the files under `roots` and renders them the way
+/// `lctl get_param` prints them, or just their names like `lctl get_param -N`.
+///
+/// Params that can't be read are skipped, as they can vanish while being
+/// listed, for example while a target is unmounted.
+pub fn get_param(roots: &[impl AsRef<Path>], params: &[String], names_only: bool) -> Vec<u8> {
+    let mut seen = HashSet::new();
+    let mut output = vec![];
+
+    for pattern in params.iter().flat_map(|x| expand_braces(x)) {
+        let components: Vec<_> = pattern.split('.').collect();
+
+        for root in roots {
+            let mut found = vec![];
+
+            walk(root.as_ref(), "", &components, &mut found);
+
+            for (name, path) in found {
+                if !seen.insert(name.clone()) {
+                    continue;
+                }
+
+                if names_only {
+                    _ = writeln!(output, "{name}");
+
+                    continue;
+                }
+
+                let value = match fs::read(&path) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        tracing::debug!("Could not read {}: {e}", path.display());
+
+                        continue;
+                    }
+                };
+
+                _ = write!(output, "{name}=");
+
+                // Values spanning lines start on a line of their own.
+                if value
+                    .iter()
+                    .position(|x| *x == b'\n')
+                    .is_some_and(|i| i + 1 < value.len())
+                {
+                    output.push(b'\n');
+                }
+
+                output.extend_from_slice(&value);
+
+                if !value.ends_with(b"\n") {
+                    output.push(b'\n');
+                }
+            }
+        }
+    }
+
+    output
+}
+
+/// The param files under `roots` matching `params` that can't be opened, with why.
+pub fn unreadable_params(
+    roots: &[impl AsRef<Path>],
+    params: &[String],
+) -> Vec<(String, io::Error)> {
+    let mut unreadable = vec![];
+
+    for pattern in params.iter().flat_map(|x| expand_braces(x)) {
+        let components: Vec<_> = pattern.split('.').collect();
+
+        for root in roots {
+            let mut found = vec![];
+
+            walk(root.as_ref(), "", &components, &mut found);
+
+            for (name, path) in found {
+                if let Err(e) = fs::File::open(&path) {
+                    unreadable.push((name, e));
+                }
+            }
+        }
+    }
+
+    unreadable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_braces, get_param, glob_match, Backend, ParamSource, LCTL_RETRY_INTERVAL};
+    use std::{env, fs, time::Instant};
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(
+            expand_braces("ldlm.namespaces.{mdt-,filter-}*.lock_count"),
+            vec![
+                "ldlm.namespaces.mdt-*.lock_count",
+                "ldlm.namespaces.filter-*.lock_count"
+            ]
+        );
+        assert_eq!(expand_braces("memused"), vec!["memused"]);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*OST*", b"fs-OST0000"));
+        assert!(glob_match(b"osd-*", b"osd-ldiskfs"));
+        assert!(glob_match(b"glb-???", b"glb-usr"));
+        assert!(!glob_match(b"*MDT*", b"fs-OST0000"));
+    }
+
+    #[test]
+    fn test_get_param() {
+        let root = env::temp_dir().join(format!("lustrefs-exporter-procfs-{}", std::process::id()));
+        let exports = root.join("obdfilter/fs-OST0000/exports/10.0.0.1@tcp");
+
+        fs::create_dir_all(&exports).unwrap();
+        fs::create_dir_all(root.join("obdfilter/fs-MDT0000")).unwrap();
+        fs::write(root.join("memused"), "1024\n").unwrap();
+        fs::write(root.join("obdfilter/fs-OST0000/num_exports"), "2\n").unwrap();
+        fs::write(root.join("obdfilter/fs-MDT0000/num_exports"), "3\n").unwrap();
+        fs::write(
+            exports.join("stats"),
+            "snapshot_time 1.0 secs.nsecs\nread_bytes 1 samples [bytes] 4096 4096 4096\n",
+        )
+        .unwrap();
+
+        let params = [
+            "memused".to_string(),
+            "obdfilter.*OST*.num_exports".to_string(),
+            "obdfilter.*OST*.exports.*.stats".to_string(),
+        ];
+
+        let output = get_param(&[&root], &params, false);
+        let names = get_param(&[&root], &params, true);
+
+        fs::remove_dir_all(&root).unwrap();
+
+        insta::assert_snapshot!(String::from_utf8(output).unwrap());
+        assert_eq!(
+            String::from_utf8(names).unwrap(),
+            "memused\nobdfilter.fs-OST0000.num_exports\nobdfilter.fs-OST0000.exports.10.0.0.1@tcp.stats\n"
+        );
+    }
+
+    #[test]
+    fn lctl_is_tried_again_after_a_timeout() {
+        assert!(!ParamSource::new(Backend::Lctl).record_lctl_timeout());
+
+        let params = ParamSource::new(Backend::Auto);
+
+        assert!(params.record_lctl_timeout());
+        assert!(params.lctl_timing_out());
+
+        *params.lctl_timed_out.lock().unwrap() = Instant::now().checked_sub(LCTL_RETRY_INTERVAL);
+
+        assert!(!params.lctl_timing_out());
+        assert!(params.lctl_timed_out.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn one_read_per_source() {
+        let params = ParamSource::new(Backend::Procfs);
+
+        let reading = params.start_read("lctl").unwrap();
+
+        assert!(params.start_read("lctl").is_none());
+        assert!(params.start_read("jobstats").is_some());
+
+        drop(reading);
+
+        assert!(params.start_read("lctl").is_some());
+    }
+}
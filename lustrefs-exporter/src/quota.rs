@@ -2,9 +2,9 @@
 // Use of this source code is governed by a MIT-style
 // license that can be found in the LICENSE file.
 
-use crate::{LabelProm, Metric, StatsMapExt};
-use lustre_collector::{QuotaStats, QuotaStatsOsd, TargetQuotaStat, TargetStat};
-use prometheus_exporter_base::prelude::*;
+use crate::{quota_names::QuotaNames, LabelProm, Metric, StatsMapExt, TargetLabels};
+use lustre_collector::{QuotaEnforcement, QuotaStats, QuotaStatsOsd, TargetQuotaStat, TargetStat};
+use prometheus_exporter_base::{prelude::*, Yes};
 use std::{collections::BTreeMap, ops::Deref};
 
 static QUOTA_HARD: Metric = Metric {
@@ -25,6 +25,18 @@ static QUOTA_GRANTED: Metric = Metric {
     r#type: MetricType::Gauge,
 };
 
+static QUOTA_GRACE_TIME: Metric = Metric {
+    name: "lustre_quota_grace_time_seconds",
+    help: "The quota grace time. For id 0 this is the default grace period, otherwise the time since the epoch at which the grace period of the id expires.",
+    r#type: MetricType::Gauge,
+};
+
+static QUOTA_SOFT_EXCEEDED: Metric = Metric {
+    name: "lustre_quota_soft_exceeded",
+    help: "Whether the granted quota exceeds the soft limit. 1 is exceeded, 0 is not.",
+    r#type: MetricType::Gauge,
+};
+
 static QUOTA_USED_KBYTES: Metric = Metric {
     name: "lustre_quota_used_kbytes",
     help: "The hard quota for a given component.",
@@ -37,8 +49,38 @@ static QUOTA_USED_INODES: Metric = Metric {
     r#type: MetricType::Gauge,
 };
 
+static QUOTA_ENFORCEMENT_ENABLED: Metric = Metric {
+    name: "lustre_quota_enforcement_enabled",
+    help: "Whether the target enforces quota of the given kind. 1 is enforced, 0 is not.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&QUOTA_HARD, "qmt.*.*.glb-*"),
+    (&QUOTA_SOFT, "qmt.*.*.glb-*"),
+    (&QUOTA_GRANTED, "qmt.*.*.glb-*"),
+    (&QUOTA_GRACE_TIME, "qmt.*.*.glb-*"),
+    (&QUOTA_SOFT_EXCEEDED, "qmt.*.*.glb-*"),
+    (&QUOTA_USED_KBYTES, "osd-*.*.quota_slave.acct_*"),
+    (&QUOTA_USED_INODES, "osd-*.*.quota_slave.acct_*"),
+    (&QUOTA_ENFORCEMENT_ENABLED, "osd-*.*.quota_slave.info"),
+];
+
+/// Adds a `name` label to `inst` when the quota id resolved to one.
+fn with_name<'a, T>(
+    inst: PrometheusInstance<'a, T, Yes>,
+    name: Option<&'a str>,
+) -> PrometheusInstance<'a, T, Yes> {
+    match name {
+        Some(x) => inst.with_label("name", x),
+        None => inst,
+    }
+}
+
 pub fn build_quota_stats(
     x: TargetQuotaStat<QuotaStats>,
+    names: &QuotaNames,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
 ) {
     let TargetQuotaStat {
@@ -51,6 +93,7 @@ pub fn build_quota_stats(
     } = x;
 
     for s in value.stats {
+        let name = names.get(&value.kind, s.id);
         let pool = pool.deref();
         let pool = if pool == "0x0" { "" } else { pool };
         let accounting = match param.deref() {
@@ -61,44 +104,81 @@ pub fn build_quota_stats(
         };
         stats_map
             .get_mut_metric(QUOTA_HARD)
-            .render_and_append_instance(
-                &PrometheusInstance::new()
+            .render_and_append_instance(&with_name(
+                PrometheusInstance::new()
                     .with_label("target", target.deref())
                     .with_label("pool", pool)
                     .with_label("accounting", accounting)
                     .with_label("manager", manager.deref())
                     .with_label("id", s.id.to_string().as_str())
                     .with_value(s.limits.hard),
-            );
+                name,
+            ));
 
         stats_map
             .get_mut_metric(QUOTA_SOFT)
-            .render_and_append_instance(
-                &PrometheusInstance::new()
+            .render_and_append_instance(&with_name(
+                PrometheusInstance::new()
                     .with_label("target", target.deref())
                     .with_label("pool", pool)
                     .with_label("accounting", accounting)
                     .with_label("manager", manager.deref())
                     .with_label("id", s.id.to_string().as_str())
                     .with_value(s.limits.soft),
-            );
+                name,
+            ));
 
         stats_map
             .get_mut_metric(QUOTA_GRANTED)
-            .render_and_append_instance(
-                &PrometheusInstance::new()
+            .render_and_append_instance(&with_name(
+                PrometheusInstance::new()
                     .with_label("target", target.deref())
                     .with_label("pool", pool)
                     .with_label("accounting", accounting)
                     .with_label("manager", manager.deref())
                     .with_label("id", s.id.to_string().as_str())
                     .with_value(s.limits.granted),
-            );
+                name,
+            ));
+
+        stats_map
+            .get_mut_metric(QUOTA_GRACE_TIME)
+            .render_and_append_instance(&with_name(
+                PrometheusInstance::new()
+                    .with_label("target", target.deref())
+                    .with_label("pool", pool)
+                    .with_label("kind", accounting)
+                    .with_label("manager", manager.deref())
+                    .with_label("id", s.id.to_string().as_str())
+                    .with_value(s.limits.time),
+                name,
+            ));
+
+        // id 0 holds the defaults, not the usage of any user
+        if s.id == 0 {
+            continue;
+        }
+
+        let exceeded = s.limits.soft > 0 && s.limits.granted > s.limits.soft;
+
+        stats_map
+            .get_mut_metric(QUOTA_SOFT_EXCEEDED)
+            .render_and_append_instance(&with_name(
+                PrometheusInstance::new()
+                    .with_label("target", target.deref())
+                    .with_label("pool", pool)
+                    .with_label("kind", accounting)
+                    .with_label("manager", manager.deref())
+                    .with_label("id", s.id.to_string().as_str())
+                    .with_value(u8::from(exceeded)),
+                name,
+            ));
     }
 }
 
 pub fn build_ost_quota_stats(
     x: TargetStat<QuotaStatsOsd>,
+    names: &QuotaNames,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
 ) {
     let TargetStat {
@@ -109,6 +189,7 @@ pub fn build_ost_quota_stats(
     } = x;
 
     for s in value.stats {
+        let name = names.get(&value.kind, s.id);
         let accounting = match value.kind {
             lustre_collector::QuotaKind::Usr => "user",
             lustre_collector::QuotaKind::Grp => "group",
@@ -117,24 +198,52 @@ pub fn build_ost_quota_stats(
 
         stats_map
             .get_mut_metric(QUOTA_USED_INODES)
-            .render_and_append_instance(
-                &PrometheusInstance::new()
+            .render_and_append_instance(&with_name(
+                PrometheusInstance::new()
                     .with_label("component", kind.to_prom_label())
                     .with_label("accounting", accounting)
                     .with_label("target", target.deref())
                     .with_label("id", s.id.to_string().as_str())
                     .with_value(s.usage.inodes),
-            );
+                name,
+            ));
 
         stats_map
             .get_mut_metric(QUOTA_USED_KBYTES)
-            .render_and_append_instance(
-                &PrometheusInstance::new()
+            .render_and_append_instance(&with_name(
+                PrometheusInstance::new()
                     .with_label("component", kind.to_prom_label())
                     .with_label("accounting", accounting)
                     .with_label("target", target.deref())
                     .with_label("id", s.id.to_string().as_str())
                     .with_value(s.usage.kbytes),
+                name,
+            ));
+    }
+}
+
+pub fn build_quota_enforcement_stats(
+    x: TargetStat<QuotaEnforcement>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let TargetStat {
+        kind,
+        target,
+        value,
+        ..
+    } = x;
+
+    for (quota_kind, enabled) in [
+        ("user", value.usr),
+        ("group", value.grp),
+        ("project", value.prj),
+    ] {
+        stats_map
+            .get_mut_metric(QUOTA_ENFORCEMENT_ENABLED)
+            .render_and_append_instance(
+                &TargetLabels::new(kind, &target)
+                    .inst(u8::from(enabled))
+                    .with_label("kind", quota_kind),
             );
     }
 }
@@ -0,0 +1,91 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::Error;
+use lustre_collector::QuotaKind;
+use std::{collections::HashMap, fs, path::Path};
+
+const PASSWD: &str = "/etc/passwd";
+const GROUP: &str = "/etc/group";
+
+/// Maps quota ids to user, group and project names.
+///
+/// The default resolves nothing, so quota metrics only carry the numeric id.
+#[derive(Debug, Default)]
+pub struct QuotaNames {
+    users: HashMap<u64, String>,
+    groups: HashMap<u64, String>,
+    projects: HashMap<u64, String>,
+}
+
+/// Reads colon separated lines starting with a name, with the numeric id in
+/// field `id_field`: 2 for `/etc/passwd` and `/etc/group`, 1 for `/etc/projid`.
+fn parse_id_map(contents: &str, id_field: usize) -> HashMap<u64, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .filter_map(|x| {
+            let mut fields = x.split(':');
+
+            let name = fields.next()?;
+            let id = fields.nth(id_field - 1)?.trim().parse().ok()?;
+
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+impl QuotaNames {
+    /// Loads user and group names from the local account database, and project
+    /// names from `project_map` if given. The project map uses the `/etc/projid`
+    /// format of one `name:id` per line.
+    pub fn load(project_map: Option<&Path>) -> Result<Self, Error> {
+        let projects = match project_map {
+            Some(x) => parse_id_map(&fs::read_to_string(x)?, 1),
+            None => HashMap::new(),
+        };
+
+        Ok(Self {
+            users: parse_id_map(&fs::read_to_string(PASSWD)?, 2),
+            groups: parse_id_map(&fs::read_to_string(GROUP)?, 2),
+            projects,
+        })
+    }
+
+    pub fn get(&self, kind: &QuotaKind, id: u64) -> Option<&str> {
+        let names = match kind {
+            QuotaKind::Usr => &self.users,
+            QuotaKind::Grp => &self.groups,
+            QuotaKind::Prj => &self.projects,
+        };
+
+        names.get(&id).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_id_map, QuotaNames};
+    use lustre_collector::QuotaKind;
+
+    #[test]
+    fn test_parse_id_maps() {
+        let names = QuotaNames {
+            users: parse_id_map(
+                "root:x:0:0:root:/root:/bin/bash\n# comment\nalice:x:1000:1000::/home/alice:/bin/bash\nbroken:x\n",
+                2,
+            ),
+            groups: parse_id_map("root:x:0:\nscience:x:2000:alice,bob\n", 2),
+            projects: parse_id_map("climate:1234\n\nbad:id\n", 1),
+        };
+
+        assert_eq!(names.get(&QuotaKind::Usr, 1000), Some("alice"));
+        assert_eq!(names.get(&QuotaKind::Usr, 2000), None);
+        assert_eq!(names.get(&QuotaKind::Grp, 2000), Some("science"));
+        assert_eq!(names.get(&QuotaKind::Prj, 1234), Some("climate"));
+        assert_eq!(names.projects.len(), 1);
+        assert_eq!(names.users.len(), 2);
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt};
+use lustre_collector::{Record, RecoveryStatus, TargetStat, TargetStats};
+use prometheus_exporter_base::prelude::*;
+use std::{
+    collections::BTreeMap,
+    ops::Deref,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static RECOVERY_PROGRESS_RATIO: Metric = Metric {
+    name: "lustre_recovery_progress_ratio",
+    help: "Ratio of the clients connected to a target that have completed recovery, from 0 to 1.",
+    r#type: MetricType::Gauge,
+};
+
+static RECOVERY_STATUS_CHANGE_TIMESTAMP: Metric = Metric {
+    name: "lustre_recovery_status_change_timestamp_seconds",
+    help: "When the recovery status of a target last changed, in seconds since the epoch. Targets are assumed to change status when the exporter first sees them.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&RECOVERY_PROGRESS_RATIO, "*.*.recovery_status"),
+    (&RECOVERY_STATUS_CHANGE_TIMESTAMP, "*.*.recovery_status"),
+];
+
+/// The completed and connected clients of each recovering target, which come from separate records.
+#[derive(Debug, Default)]
+pub struct Recovery {
+    targets: BTreeMap<(String, String), (Option<u64>, Option<u64>)>,
+}
+
+impl Recovery {
+    pub fn observe(&mut self, x: &TargetStats) {
+        match x {
+            TargetStats::RecoveryCompletedClients(TargetStat {
+                kind,
+                target,
+                value,
+                ..
+            }) => {
+                self.targets
+                    .entry((target.0.clone(), kind.deref().to_string()))
+                    .or_default()
+                    .0 = Some(*value);
+            }
+            TargetStats::RecoveryConnectedClients(TargetStat {
+                kind,
+                target,
+                value,
+                ..
+            }) => {
+                self.targets
+                    .entry((target.0.clone(), kind.deref().to_string()))
+                    .or_default()
+                    .1 = Some(*value);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn build_recovery_progress_stats(
+    x: Recovery,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    for ((target, kind), values) in x.targets {
+        // No ratio can be given until a client has connected
+        let (Some(completed), Some(connected @ 1..)) = values else {
+            continue;
+        };
+
+        stats_map
+            .get_mut_metric(RECOVERY_PROGRESS_RATIO)
+            .render_and_append_instance(
+                &PrometheusInstance::new()
+                    .with_label("target", target.as_str())
+                    .with_label("kind", kind.as_str())
+                    .with_value(completed as f64 / connected as f64),
+            );
+    }
+}
+
+/// Remembers the recovery status of each target across scrapes, and since
+/// when it has held it, to tell how long a target has been stuck in a state.
+#[derive(Debug, Default)]
+pub struct RecoveryHistory {
+    /// The status of each target and the seconds since the epoch it changed to it at,
+    /// keyed by target and kind
+    targets: Mutex<BTreeMap<(String, String), (RecoveryStatus, f64)>>,
+}
+
+impl RecoveryHistory {
+    /// Records the status of each target in the `recovery_status` records of
+    /// a scrape, forgetting the targets missing from it.
+    pub fn observe(&self, records: &[Record]) {
+        self.observe_at(records, SystemTime::now());
+    }
+
+    fn observe_at(&self, records: &[Record], now: SystemTime) {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut seen = BTreeMap::new();
+
+        for x in records {
+            let Record::Target(TargetStats::RecoveryStatus(TargetStat {
+                kind,
+                target,
+                value: status,
+                ..
+            })) = x
+            else {
+                continue;
+            };
+
+            let key = (target.0.clone(), kind.deref().to_string());
+
+            let since = match targets.get(&key) {
+                Some((x, since)) if x == status => *since,
+                _ => now,
+            };
+
+            seen.insert(key, (*status, since));
+        }
+
+        *targets = seen;
+    }
+
+    pub fn render(&self) -> String {
+        let mut stats_map = BTreeMap::new();
+
+        let targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+
+        for ((target, kind), (_, since)) in targets.iter() {
+            stats_map
+                .get_mut_metric(RECOVERY_STATUS_CHANGE_TIMESTAMP)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("target", target.as_str())
+                        .with_label("kind", kind.as_str())
+                        .with_value(*since),
+                );
+        }
+
+        stats_map
+            .values()
+            .map(|x| x.render())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecoveryHistory;
+    use lustre_collector::{parse_recovery_status_output, Record};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn status(x: &str) -> Vec<Record> {
+        parse_recovery_status_output(
+            format!(
+                "obdfilter.fs-OST0000.recovery_status=
+status: {x}
+mdt.fs-MDT0000.recovery_status=
+status: COMPLETE
+"
+            )
+            .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn tracks_status_changes() {
+        let history = RecoveryHistory::default();
+
+        history.observe_at(&status("RECOVERING"), UNIX_EPOCH + Duration::from_secs(100));
+        history.observe_at(&status("RECOVERING"), UNIX_EPOCH + Duration::from_secs(160));
+
+        let x = history.render();
+
+        assert!(x.contains(r#"{target="fs-OST0000",kind="OST"} 100"#), "{x}");
+
+        history.observe_at(&status("COMPLETE"), UNIX_EPOCH + Duration::from_secs(220));
+
+        let x = history.render();
+
+        assert!(x.contains(r#"{target="fs-OST0000",kind="OST"} 220"#), "{x}");
+        assert!(x.contains(r#"{target="fs-MDT0000",kind="MDT"} 100"#), "{x}");
+
+        history.observe_at(&[], UNIX_EPOCH + Duration::from_secs(280));
+
+        assert_eq!(history.render(), "");
+    }
+}
@@ -0,0 +1,3007 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+#[cfg(feature = "sandbox")]
+use crate::sandbox::Sandbox;
+use crate::{
+    build_lustre_stats_with_quota_names,
+    collectors::{Collector, Collectors},
+    consistency,
+    counter_state::CounterState,
+    exporter_metrics::{render_runtime, render_scrape_status, ExporterMetrics, Phase},
+    history::{History, Series},
+    host,
+    jobstats::{
+        find_job, JobAggregate, JobDeltas, JobFilter, JobIdInfo, JobLimit, JobstatsOptions,
+        JOBSTATS_PARAMS,
+    },
+    preflight::Preflight,
+    procfs::{self, Backend, ParamSource, PARAM_ROOTS},
+    quota_names::QuotaNames,
+    recovery::RecoveryHistory,
+    sd::{target_group, Roles, TargetGroup},
+    target_registry::TargetRegistry,
+    worker::ScrapeWorker,
+    Error,
+};
+use axum::{
+    body::{Body, Bytes},
+    error_handling::HandleErrorLayer,
+    extract::{ConnectInfo, MatchedPath, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    BoxError, Json, Router,
+};
+use compact_str::CompactString;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use lustre_collector::{
+    mgs::{mgs_fs_parser, mgs_live_parser},
+    parse_arcstats_output, parse_lctl_output_lossy, parse_lnetctl_global, parse_lnetctl_output,
+    parse_lnetctl_peer_stats, parse_lnetctl_routes, parse_lnetctl_routing, parse_lnetctl_stats,
+    parse_lnetctl_udsp, parse_mgs_fs_output, parse_mgs_live_output, parse_pool_list_output,
+    parse_recovery_status_output, parse_zpool_fragmentation_output, recovery_status_parser,
+    support::{support_matrix, SupportMatrix},
+    zfs_parser, FsName, LustreCollectorError, Record, Target, TargetStats, TargetVariant,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt,
+    fs::Permissions,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, BufRead, BufReader},
+    net::SocketAddr,
+    ops::RangeInclusive,
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        process::ExitStatusExt,
+    },
+    path::PathBuf,
+    process::{ExitStatus, Output},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    net::{TcpListener, UnixListener},
+    process::Command,
+    sync::{
+        mpsc::{self, Receiver},
+        watch,
+    },
+    task::{JoinHandle, JoinSet},
+    time::MissedTickBehavior,
+};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+use tower_http::{
+    compression::{predicate::SizeAbove, CompressionLayer, CompressionLevel},
+    set_header::SetResponseHeaderLayer,
+};
+
+async fn handle_error(error: BoxError) -> impl IntoResponse {
+    if error.is::<tower::timeout::error::Elapsed>() {
+        return (StatusCode::REQUEST_TIMEOUT, Cow::from("request timed out"));
+    }
+
+    if error.is::<tower::load_shed::error::Overloaded>() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Cow::from("service is overloaded, try again later"),
+        );
+    }
+
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Cow::from(format!("Unhandled internal error: {error}")),
+    )
+}
+
+fn default_as_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct Params {
+    // Only disable jobstats if "jobstats=false"
+    #[serde(default = "default_as_true")]
+    jobstats: bool,
+    // Only collect the MGS filesystem inventory if "mgs=true"
+    #[serde(default)]
+    mgs: bool,
+    // Only export jobids matching this regex, instead of the --jobstats-filter one
+    jobstats_filter: Option<String>,
+    // Only export this many jobs per target, those with the most I/O, instead of the first --jobstats-max-jobs
+    jobstats_top: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonParams {
+    // Only collect the MGS filesystem inventory if "mgs=true"
+    #[serde(default)]
+    mgs: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobstatsLookupParams {
+    jobid: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryParams {
+    /// Only return the collections of the last this many minutes
+    minutes: Option<u64>,
+    /// Comma separated metric families to return
+    metrics: Option<String>,
+}
+
+/// How long each collector command may run before it is abandoned.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub lctl: Duration,
+    pub lnetctl: Duration,
+    pub jobstats: Duration,
+    /// Wall time for a whole scrape, after which optional sources are skipped
+    pub scrape: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            lctl: Duration::from_secs(30),
+            lnetctl: Duration::from_secs(30),
+            jobstats: Duration::from_secs(60),
+            scrape: Duration::from_secs(60),
+        }
+    }
+}
+
+/// How responses are compressed for scrapers that accept it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Gzip at a level from 0, fastest, to 9, smallest
+    Gzip(i32),
+    /// Zstd at a level from 1, fastest, to 22, smallest, falling back to gzip
+    /// at the same level, capped at 9, for scrapers that only accept gzip
+    Zstd(i32),
+    None,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::Gzip(6)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    /// Parses `none`, `gzip[:<level>]` or `zstd[:<level>]`.
+    fn from_str(x: &str) -> Result<Self, Self::Err> {
+        let (algorithm, level) = match x.split_once(':') {
+            Some((algorithm, level)) => (algorithm, Some(level)),
+            None => (x, None),
+        };
+
+        let level = |range: RangeInclusive<i32>, default| match level {
+            None => Ok(default),
+            Some(level) => match level.parse() {
+                Ok(level) if range.contains(&level) => Ok(level),
+                _ => Err(format!(
+                    "{x}: the {algorithm} level must be from {} to {}",
+                    range.start(),
+                    range.end()
+                )),
+            },
+        };
+
+        match algorithm {
+            "none" if level.is_none() => Ok(Self::None),
+            "gzip" => level(0..=9, 6).map(Self::Gzip),
+            "zstd" => level(1..=22, 3).map(Self::Zstd),
+            _ => Err(format!(
+                "{x}: unknown compression, expected none, gzip[:<level>] or zstd[:<level>]"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gzip(level) => write!(f, "gzip:{level}"),
+            Self::Zstd(level) => write!(f, "zstd:{level}"),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+impl Compression {
+    /// The layer compressing responses of at least `min_bytes`, up to 64 KiB, or
+    /// `None` if compression is disabled. Streamed bodies of unknown size are compressed.
+    fn layer(self, min_bytes: usize) -> Option<CompressionLayer<SizeAbove>> {
+        let level = match self {
+            Self::Gzip(x) | Self::Zstd(x) => x,
+            Self::None => return None,
+        };
+
+        let layer = CompressionLayer::new()
+            .no_br()
+            .no_deflate()
+            .gzip(true)
+            .zstd(matches!(self, Self::Zstd(_)))
+            .quality(CompressionLevel::Precise(level))
+            .compress_when(SizeAbove::new(u16::try_from(min_bytes).unwrap_or(u16::MAX)));
+
+        Some(layer)
+    }
+}
+
+/// Optional sources are skipped once less than this is left of the scrape budget.
+const MIN_SOURCE_BUDGET: Duration = Duration::from_secs(1);
+
+/// What is left of the wall time budget of a single scrape.
+pub(crate) struct Budget {
+    deadline: Instant,
+}
+
+impl Budget {
+    pub(crate) fn new(start: Instant, budget: Duration) -> Self {
+        Self {
+            deadline: start + budget,
+        }
+    }
+
+    /// The time left until the deadline.
+    pub(crate) fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// The timeout to run an optional source with, or `None` if it should be skipped.
+    fn timeout(&self, timeout: Duration) -> Option<Duration> {
+        let remaining = self.remaining();
+
+        (remaining >= MIN_SOURCE_BUDGET).then(|| timeout.min(remaining))
+    }
+}
+
+type LnetctlParser = fn(&str) -> Result<Vec<Record>, LustreCollectorError>;
+
+/// The lnetctl commands read on every scrape, by source, with the group that
+/// enables each. All of them also need `lnet`.
+static LNETCTL_SOURCES: [(&str, &[&str], LnetctlParser, Collector); 7] = [
+    (
+        "lnetctl_net",
+        &["net", "show", "-v", "4"],
+        parse_lnetctl_output,
+        Collector::Lnet,
+    ),
+    (
+        "lnetctl_stats",
+        &["stats", "show"],
+        parse_lnetctl_stats,
+        Collector::Lnet,
+    ),
+    (
+        "lnetctl_peer",
+        &["peer", "show", "-v", "4"],
+        parse_lnetctl_peer_stats,
+        Collector::LnetPeer,
+    ),
+    (
+        "lnetctl_route",
+        &["route", "show", "-v"],
+        parse_lnetctl_routes,
+        Collector::LnetRoute,
+    ),
+    (
+        "lnetctl_routing",
+        &["routing", "show"],
+        parse_lnetctl_routing,
+        Collector::LnetRoute,
+    ),
+    (
+        "lnetctl_global",
+        &["global", "show"],
+        parse_lnetctl_global,
+        Collector::Lnet,
+    ),
+    (
+        "lnetctl_udsp",
+        &["udsp", "show"],
+        parse_lnetctl_udsp,
+        Collector::LnetUdsp,
+    ),
+];
+
+/// State shared by all requests.
+#[derive(Debug, Default)]
+pub struct AppState {
+    /// Record the address and user-agent of scrape clients
+    pub track_clients: bool,
+    pub timeouts: Timeouts,
+    pub exporter_metrics: ExporterMetrics,
+    pub counters: CounterState,
+    /// The targets of recent scrapes, for `lustre_target_up`
+    pub targets: TargetRegistry,
+    /// The recovery status of each target, for `lustre_recovery_status_change_timestamp_seconds`
+    pub recovery: RecoveryHistory,
+    /// Which jobids to export jobstats for
+    pub jobstats_filter: JobFilter,
+    /// Jobs past this many are collapsed into an `_overflow` job per target
+    pub jobstats_max_jobs: Option<usize>,
+    /// Keeps job counters increasing across the resets of purged jobs, if enabled
+    pub job_deltas: Option<Arc<JobDeltas>>,
+    /// What jobstats are rolled up by, instead of exporting every job
+    pub jobstats_aggregate: JobAggregate,
+    /// Names to label quota metrics with
+    pub quota_names: QuotaNames,
+    /// Splits jobids into the labels of `lustre_job_ids_info`, if enabled
+    pub job_ids: Option<JobIdInfo>,
+    /// Which groups of params and metrics to collect
+    pub collectors: Collectors,
+    /// Attach exemplars to jobstats for scrapers that accept OpenMetrics
+    pub exemplars: bool,
+    /// The address the server is bound to, once it is
+    pub bound_addrs: OnceLock<Vec<SocketAddr>>,
+    /// Whether params are read with lctl or from procfs
+    pub params: ParamSource,
+    /// Collect in the background this often, and serve the latest collection on `/metrics`
+    pub collect_interval: Option<Duration>,
+    /// The latest background collection, once one has finished, for publishers
+    /// to wait on
+    pub snapshot: watch::Sender<Option<Collection>>,
+    /// The last background collections, for `/history`
+    pub history: History,
+    /// Bearer token `/debug/dump` requires, which is disabled without one
+    pub debug_token: Option<String>,
+    /// Serve the output saved in this directory instead of running any command
+    pub from_dir: Option<PathBuf>,
+    /// Collect and parse scrapes in this child process instead of the server, if enabled
+    pub scrape_worker: Option<ScrapeWorker>,
+    /// What the commands run for scrapes are restricted to
+    #[cfg(feature = "sandbox")]
+    pub sandbox: Sandbox,
+    /// How responses are compressed for scrapers that accept it
+    pub compression: Compression,
+    /// Bodies smaller than this are sent uncompressed
+    pub compression_min_bytes: usize,
+    /// The hostname of the node, the target of `/sd` requests without a `Host` header
+    pub hostname: String,
+    /// The roles `/sd` found and when, reused for [`SD_ROLES_TTL`] without a background collection
+    pub sd_roles: tokio::sync::Mutex<Option<(Instant, Roles)>>,
+}
+
+/// How long `/sd` reuses the roles it collected itself.
+const SD_ROLES_TTL: Duration = Duration::from_secs(300);
+
+impl AppState {
+    fn record_timeout(&self, source: &'static str, timeout: Duration) {
+        tracing::warn!("{source} did not finish within {timeout:?}, skipping it for this scrape");
+
+        self.exporter_metrics.record_collect_timeout(source);
+    }
+
+    /// The latest background collection, once one has finished.
+    fn latest_collection(&self) -> Option<Collection> {
+        self.snapshot.borrow().clone()
+    }
+
+    /// The roles of the host, from the latest background collection, or else
+    /// collected at most once every [`SD_ROLES_TTL`] so service discovery polls
+    /// do not run the commands of a scrape each time.
+    async fn roles(&self) -> Roles {
+        if let Some(x) = self.latest_collection() {
+            return Roles::of(&x.records);
+        }
+
+        // Held while collecting, so concurrent polls wait for the same collection
+        let mut cached = self.sd_roles.lock().await;
+
+        if let Some((at, roles)) = &*cached {
+            if at.elapsed() < SD_ROLES_TTL {
+                return roles.clone();
+            }
+        }
+
+        let budget = Budget::new(Instant::now(), self.timeouts.scrape);
+
+        let (sources, _) = collect_sources(self, &budget, false).await;
+
+        let records: Vec<_> = sources
+            .into_iter()
+            .filter_map(|(_, x)| x)
+            .flatten()
+            .collect();
+
+        let roles = Roles::of(&records);
+
+        *cached = Some((Instant::now(), roles.clone()));
+
+        roles
+    }
+
+    /// Reads the output saved for `source` under `dir`, empty if there is none.
+    async fn read_saved(dir: &std::path::Path, source: &'static str) -> Result<Vec<u8>, Error> {
+        match tokio::fs::read(dir.join(format!("{source}.txt"))).await {
+            Ok(x) => Ok(x),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::debug!(source, "No output saved in {}", dir.display());
+
+                Ok(vec![])
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Runs `cmd` to completion, or kills it once `timeout` has elapsed.
+    ///
+    /// Returns `None` on timeout so the remaining sources can still be reported.
+    async fn output(
+        &self,
+        source: &'static str,
+        timeout: Duration,
+        cmd: &mut Command,
+    ) -> Result<Option<Output>, Error> {
+        if let Some(dir) = &self.from_dir {
+            return Ok(Some(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: Self::read_saved(dir, source).await?,
+                stderr: vec![],
+            }));
+        }
+
+        #[cfg(feature = "sandbox")]
+        self.sandbox.apply(cmd.as_std_mut());
+
+        let start = Instant::now();
+
+        let output = tokio::time::timeout(timeout, cmd.kill_on_drop(true).output()).await;
+
+        let elapsed = start.elapsed();
+
+        tracing::debug!(
+            source,
+            elapsed_seconds = elapsed.as_secs_f64(),
+            "Source collected"
+        );
+
+        self.exporter_metrics
+            .record_command_duration(source, elapsed);
+
+        match output {
+            Ok(x) => Ok(Some(x?)),
+            Err(_) => {
+                self.record_timeout(source, timeout);
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads `params` as `lctl get_param` prints them, or only their names
+    /// with `names_only`, from lctl or procfs depending on the backend.
+    ///
+    /// Returns `None` on timeout so the remaining sources can still be reported.
+    async fn get_param(
+        &self,
+        source: &'static str,
+        timeout: Duration,
+        names_only: bool,
+        params: Vec<String>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(dir) = &self.from_dir {
+            return Ok(Some(Self::read_saved(dir, source).await?));
+        }
+
+        if !self.params.use_procfs() {
+            let mut cmd = Command::new("lctl");
+
+            cmd.arg("get_param");
+
+            if names_only {
+                cmd.arg("-N");
+            }
+
+            match self.output(source, timeout, cmd.args(&params)).await? {
+                Some(x) => {
+                    self.record_lctl_stderr(source, &x.stderr);
+
+                    return Ok(Some(x.stdout));
+                }
+                // With `auto`, the source is read from procfs rather than left out of this scrape
+                None if self.params.record_lctl_timeout() => {}
+                None => return Ok(None),
+            }
+        }
+
+        // A read stuck in the kernel can't be cancelled, so it keeps its blocking thread
+        // until it returns and the source is left out until then
+        let Some(reading) = self.params.start_read(source) else {
+            tracing::warn!("{source} is still being read from procfs, skipping it for this scrape");
+
+            return Ok(None);
+        };
+
+        let start = Instant::now();
+
+        let output = tokio::time::timeout(
+            timeout,
+            tokio::task::spawn_blocking(move || {
+                let _reading = reading;
+
+                procfs::get_param(&PARAM_ROOTS, &params, names_only)
+            }),
+        )
+        .await;
+
+        let elapsed = start.elapsed();
+
+        tracing::debug!(
+            source,
+            elapsed_seconds = elapsed.as_secs_f64(),
+            "Source collected"
+        );
+
+        self.exporter_metrics
+            .record_command_duration(source, elapsed);
+
+        match output {
+            Ok(x) => Ok(Some(x?)),
+            Err(_) => {
+                self.record_timeout(source, timeout);
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Counts the lines lctl printed to `stderr` by class and logs a summary of them.
+    ///
+    /// lctl exits successfully even when some of the params could not be read, so this is
+    /// the only trace of them.
+    fn record_lctl_stderr(&self, source: &'static str, stderr: &[u8]) {
+        let stderr = String::from_utf8_lossy(stderr);
+
+        let mut lines = stderr.lines().filter(|x| !x.trim().is_empty()).peekable();
+
+        let Some(first) = lines.peek().copied() else {
+            return;
+        };
+
+        let mut classes = BTreeMap::new();
+
+        for line in lines {
+            let class = lctl_stderr_class(line);
+
+            self.exporter_metrics.record_lctl_stderr_line(class);
+
+            *classes.entry(class).or_insert(0) += 1;
+        }
+
+        let summary = classes
+            .iter()
+            .map(|(class, count)| format!("{count} {class}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        tracing::debug!(
+            source,
+            "lctl printed errors ({summary}), the first one: {first}"
+        );
+    }
+
+    /// Runs `cmd` and parses its stdout with `parse`.
+    ///
+    /// Failures are logged and returned as `None` so the other sources can still be reported.
+    async fn collect(
+        &self,
+        source: &'static str,
+        timeout: Duration,
+        cmd: &mut Command,
+        parse: impl FnOnce(&[u8]) -> Result<Vec<Record>, Error>,
+    ) -> Option<Vec<Record>> {
+        let output = match self.output(source, timeout, cmd).await {
+            Ok(x) => x?,
+            Err(e) => {
+                tracing::warn!("Could not run {source}: {e}");
+
+                return None;
+            }
+        };
+
+        self.parse(source, &output.stdout, parse)
+    }
+
+    /// Reads `params` and parses them with `parse`, like [`AppState::collect`].
+    async fn collect_params(
+        &self,
+        source: &'static str,
+        timeout: Duration,
+        names_only: bool,
+        params: Vec<String>,
+        parse: impl FnOnce(&[u8]) -> Result<Vec<Record>, Error>,
+    ) -> Option<Vec<Record>> {
+        let stdout = match self.get_param(source, timeout, names_only, params).await {
+            Ok(x) => x?,
+            Err(e) => {
+                tracing::warn!("Could not read {source}: {e}");
+
+                return None;
+            }
+        };
+
+        self.parse(source, &stdout, parse)
+    }
+
+    fn parse(
+        &self,
+        source: &'static str,
+        stdout: &[u8],
+        parse: impl FnOnce(&[u8]) -> Result<Vec<Record>, Error>,
+    ) -> Option<Vec<Record>> {
+        let start = Instant::now();
+
+        let records = parse(stdout);
+
+        self.exporter_metrics
+            .record_stage_duration(source, Phase::Parse, start.elapsed());
+
+        match records {
+            Ok(x) => {
+                self.exporter_metrics
+                    .record_parsed(source, stdout.len(), x.len());
+
+                Some(x)
+            }
+            Err(e) => {
+                tracing::warn!("Could not parse {source} output: {e}");
+
+                None
+            }
+        }
+    }
+}
+
+/// Classifies a line lctl printed to stderr, for `lustre_exporter_lctl_stderr_lines_total`.
+fn lctl_stderr_class(line: &str) -> &'static str {
+    if line.contains("Permission denied") || line.contains("Operation not permitted") {
+        "permission"
+    } else if line.contains("No such device") || line.contains("module") {
+        "module_not_loaded"
+    } else if line.contains("No such file or directory") {
+        "not_found"
+    } else if line.contains("timed out") || line.contains("Resource temporarily unavailable") {
+        "timeout"
+    } else if line.contains("Invalid argument") {
+        "invalid"
+    } else {
+        "other"
+    }
+}
+
+/// Forwards `rx` until it closes or `timeout` has elapsed.
+fn with_deadline<T: Send + 'static>(
+    state: Arc<AppState>,
+    source: &'static str,
+    timeout: Duration,
+    mut rx: Receiver<T>,
+) -> Receiver<T> {
+    let (tx, out) = mpsc::channel(rx.max_capacity());
+
+    tokio::spawn(async move {
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                x = rx.recv() => match x {
+                    Some(x) => {
+                        if tx.send(x).await.is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                },
+                _ = &mut deadline => {
+                    state.record_timeout(source, timeout);
+
+                    break;
+                }
+            }
+        }
+    });
+
+    out
+}
+
+pub fn app(state: Arc<AppState>) -> Router {
+    let load_shedder = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(handle_error))
+        .load_shed()
+        .concurrency_limit(10); // Max 10 concurrent scrape
+
+    let metrics = if state.collect_interval.is_some() {
+        get(latest_snapshot)
+    } else {
+        get(scrape)
+    };
+
+    let router = Router::new()
+        .route("/metrics", metrics)
+        .route("/json", get(json))
+        .route("/config", get(config))
+        .route("/preflight", get(preflight))
+        .route("/sd", get(sd))
+        .route("/history", get(history))
+        .route("/metadata", get(|| async { Json(crate::metadata()) }))
+        .route("/debug/families", get(families))
+        .route("/debug/support-matrix", get(support))
+        .route("/debug/jobstats/:target", get(jobstats_lookup))
+        .route("/debug/dump", get(dump))
+        .layer(load_shedder);
+
+    // Inside `track_responses`, so the sizes it records are of the bodies as sent
+    let router = match state.compression.layer(state.compression_min_bytes) {
+        Some(x) => router.route_layer(x).route_layer(
+            // The compression layer only adds `Vary` to the bodies it compresses,
+            // but a cache must not serve any response to a scraper that sent
+            // another Accept-Encoding
+            SetResponseHeaderLayer::if_not_present(
+                header::VARY,
+                HeaderValue::from_static("accept-encoding"),
+            ),
+        ),
+        None => router,
+    };
+
+    router
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&state),
+            track_responses,
+        ))
+        .with_state(state)
+}
+
+/// Gets `/metrics` from `app`, returning `None` if the scrape failed.
+pub(crate) async fn scrape_once(app: &Router, user_agent: &str) -> Result<Option<Bytes>, Error> {
+    Ok(fetch_scrape(app, user_agent).await?.map(|(x, _)| x))
+}
+
+/// The records a scrape was built from, attached to its response when
+/// collecting in the background so `/json` can serve them with the collection.
+#[derive(Debug, Clone)]
+struct ScrapedRecords(Arc<Vec<Record>>);
+
+/// Gets `/metrics` from `app` with the records attached to the response, if any.
+async fn fetch_scrape(
+    app: &Router,
+    user_agent: &str,
+) -> Result<Option<(Bytes, Option<ScrapedRecords>)>, Error> {
+    let request = axum::http::Request::get("/metrics")
+        .header(header::USER_AGENT, user_agent)
+        .body(Body::empty())?;
+
+    let mut response = match app.clone().oneshot(request).await {
+        Ok(x) => x,
+        Err(e) => match e {},
+    };
+
+    if !response.status().is_success() {
+        tracing::warn!("Scrape failed with {}, skipping it", response.status());
+
+        return Ok(None);
+    }
+
+    let records = response.extensions_mut().remove::<ScrapedRecords>();
+
+    Ok(Some((
+        axum::body::to_bytes(response.into_body(), usize::MAX).await?,
+        records,
+    )))
+}
+
+/// Collects a scrape every `interval` for `/metrics` to serve, so Lustre is
+/// read once per interval however many Prometheus instances scrape the node.
+pub fn spawn_collection(state: Arc<AppState>, interval: Duration) -> JoinHandle<()> {
+    let collector = Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(Arc::clone(&state));
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+
+        // A collection outlasting the interval delays the next one rather than
+        // starting another straight away.
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            match fetch_scrape(&collector, "lustrefs-exporter-collector").await {
+                Ok(Some((x, records))) => {
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64;
+
+                    if state.history.enabled() {
+                        let history_state = Arc::clone(&state);
+                        let body = x.clone();
+
+                        // Parsing a large collection would hold up the runtime
+                        let pushed = tokio::task::spawn_blocking(move || {
+                            history_state.history.push(timestamp, &body)
+                        })
+                        .await;
+
+                        if let Err(e) = pushed {
+                            tracing::warn!("Could not keep the collection for /history: {e}");
+                        }
+                    }
+
+                    let mut collection = Collection::new(x);
+
+                    if let Some(ScrapedRecords(records)) = records {
+                        collection.records = records;
+                    }
+
+                    state.snapshot.send_replace(Some(collection));
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Background collection failed: {e}"),
+            }
+        }
+    })
+}
+
+/// A background collection, with the `ETag` it is served with.
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub body: Bytes,
+    pub etag: String,
+    /// The records the body was built from, for `/json`
+    pub records: Arc<Vec<Record>>,
+}
+
+impl Collection {
+    pub fn new(body: Bytes) -> Self {
+        let mut hasher = DefaultHasher::new();
+
+        body.hash(&mut hasher);
+
+        Self {
+            etag: format!("\"{:016x}\"", hasher.finish()),
+            body,
+            records: Arc::default(),
+        }
+    }
+
+    /// Whether an `If-None-Match` header lists the `ETag` of this collection.
+    fn matches(&self, if_none_match: &str) -> bool {
+        if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|x| x == "*" || x.strip_prefix("W/").unwrap_or(x) == self.etag)
+    }
+}
+
+/// The response while no background collection has finished yet.
+fn no_collection_yet() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "No background collection has finished yet",
+    )
+        .into_response()
+}
+
+/// Serves the latest background collection, or `304 Not Modified` if the
+/// scraper already has it.
+///
+/// Query params are ignored, as the collection is shared by every scraper.
+async fn latest_snapshot(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let snapshot = state.latest_collection();
+
+    let if_none_match = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|x| x.to_str().ok());
+
+    match snapshot {
+        Some(x) if if_none_match.is_some_and(|y| x.matches(y)) => {
+            (StatusCode::NOT_MODIFIED, [(header::ETAG, x.etag)]).into_response()
+        }
+        Some(x) => ([(header::ETAG, x.etag)], Body::from(x.body)).into_response(),
+        None => no_collection_yet(),
+    }
+}
+
+/// The background task serving requests, which only returns if serving fails.
+pub type Server = JoinHandle<Result<(), Error>>;
+
+/// Binds a listener on `addr`.
+///
+/// An IPv6 wildcard accepts IPv4 connections too, unless `v6_only`, which lets
+/// it share a port with an IPv4 listener.
+fn bind(addr: SocketAddr, v6_only: bool) -> Result<TcpListener, Error> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// Binds every address in `addrs` and serves the exporter on them in the background.
+///
+/// Returns the addresses actually bound, and the task serving requests, which ends
+/// as soon as any of the listeners fails. When binding port 0, every address after
+/// the first reuses the port picked for it.
+pub async fn serve(
+    state: Arc<AppState>,
+    addrs: &[SocketAddr],
+) -> Result<(Vec<SocketAddr>, Server), Error> {
+    let v6_only = addrs.iter().any(SocketAddr::is_ipv4);
+
+    let mut listeners: Vec<TcpListener> = Vec::with_capacity(addrs.len());
+
+    for &addr in addrs {
+        let addr = match listeners.first() {
+            Some(x) if addr.port() == 0 => SocketAddr::new(addr.ip(), x.local_addr()?.port()),
+            _ => addr,
+        };
+
+        listeners.push(bind(addr, v6_only)?);
+    }
+
+    serve_listeners(state, listeners)
+}
+
+/// Like [`serve`], on a listener that is already bound, such as one passed by systemd.
+pub async fn serve_listener(
+    state: Arc<AppState>,
+    listener: TcpListener,
+) -> Result<(SocketAddr, Server), Error> {
+    let (addrs, server) = serve_listeners(state, vec![listener])?;
+
+    Ok((addrs[0], server))
+}
+
+fn serve_listeners(
+    state: Arc<AppState>,
+    listeners: Vec<TcpListener>,
+) -> Result<(Vec<SocketAddr>, Server), Error> {
+    let addrs = listeners
+        .iter()
+        .map(TcpListener::local_addr)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    _ = state.bound_addrs.set(addrs.clone());
+
+    let app = app(state);
+
+    let mut servers = JoinSet::new();
+
+    for listener in listeners {
+        let app = app.clone();
+
+        servers.spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+    }
+
+    let server = tokio::spawn(async move {
+        while let Some(x) = servers.join_next().await {
+            x??;
+        }
+
+        Ok(())
+    });
+
+    Ok((addrs, server))
+}
+
+/// Serves the exporter on a Unix domain socket at `path`, for agents on the same node.
+///
+/// A socket left behind by a previous run is replaced, anything else at `path`
+/// is an error. The socket is given `mode`, so access can be limited to a group.
+pub async fn serve_unix(
+    state: Arc<AppState>,
+    path: &std::path::Path,
+    mode: u32,
+) -> Result<Server, Error> {
+    match std::fs::symlink_metadata(path) {
+        Ok(x) if x.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            )
+            .into())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    std::fs::set_permissions(path, Permissions::from_mode(mode))?;
+
+    let app = app(state);
+
+    let server = tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await?;
+
+            let service = TowerToHyperService::new(app.clone());
+
+            tokio::spawn(async move {
+                if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    tracing::debug!("Error serving unix socket connection: {e}");
+                }
+            });
+        }
+    });
+
+    Ok(server)
+}
+
+/// The effective configuration of the exporter, as returned by `/config`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Config {
+    bound_addrs: Vec<SocketAddr>,
+    track_clients: bool,
+    lctl_timeout_seconds: f64,
+    lnetctl_timeout_seconds: f64,
+    jobstats_timeout_seconds: f64,
+    scrape_budget_seconds: f64,
+    jobstats_max_jobs: Option<usize>,
+    jobstats_delta_retention_seconds: Option<f64>,
+    jobstats_aggregate: JobAggregate,
+    collectors: Vec<Collector>,
+    exemplars: bool,
+    backend: Backend,
+    collect_interval_seconds: Option<f64>,
+}
+
+/// Lists the exporter for Prometheus HTTP service discovery, labelled with the
+/// Lustre roles of the host.
+///
+/// The target is the address the request was sent to, as that is one the
+/// Prometheus server can reach.
+async fn sd(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Json<Vec<TargetGroup>> {
+    let target = match headers.get(header::HOST).and_then(|x| x.to_str().ok()) {
+        Some(x) => x.to_string(),
+        None => {
+            let port = state
+                .bound_addrs
+                .get()
+                .and_then(|x| x.first())
+                .map_or(0, |x| x.port());
+
+            format!("{}:{port}", state.hostname)
+        }
+    };
+
+    Json(vec![target_group(target, &state.roles().await)])
+}
+
+/// Returns the series of the selected metric families over the last background
+/// collections, for looking into what happened between two scrapes.
+async fn history(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HistoryParams>,
+) -> Result<Response, Error> {
+    if !state.history.enabled() {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            "Set --history and --collect-interval to enable /history",
+        )
+            .into_response());
+    }
+
+    let families: Vec<_> = params
+        .metrics
+        .iter()
+        .flat_map(|x| x.split(','))
+        .filter(|x| !x.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if families.is_empty() {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Select metric families with metrics=<family>,...",
+        )
+            .into_response());
+    }
+
+    let since = params.minutes.map_or(0, |x| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .saturating_sub(Duration::from_secs(x.saturating_mul(60)))
+            .as_millis() as u64
+    });
+
+    let series: Vec<Series> =
+        tokio::task::spawn_blocking(move || state.history.series(since, &families)).await?;
+
+    Ok(Json(series).into_response())
+}
+
+/// Checks again what the exporter can collect with its privileges.
+async fn preflight(State(state): State<Arc<AppState>>) -> Json<Preflight> {
+    Json(crate::preflight::check(&state).await)
+}
+
+async fn config(State(state): State<Arc<AppState>>) -> Json<Config> {
+    let Timeouts {
+        lctl,
+        lnetctl,
+        jobstats,
+        scrape,
+    } = state.timeouts;
+
+    Json(Config {
+        bound_addrs: state.bound_addrs.get().cloned().unwrap_or_default(),
+        track_clients: state.track_clients,
+        lctl_timeout_seconds: lctl.as_secs_f64(),
+        lnetctl_timeout_seconds: lnetctl.as_secs_f64(),
+        jobstats_timeout_seconds: jobstats.as_secs_f64(),
+        scrape_budget_seconds: scrape.as_secs_f64(),
+        jobstats_max_jobs: state.jobstats_max_jobs,
+        jobstats_delta_retention_seconds: state
+            .job_deltas
+            .as_ref()
+            .map(|x| x.retention().as_secs_f64()),
+        jobstats_aggregate: state.jobstats_aggregate,
+        collectors: state.collectors.iter().collect(),
+        exemplars: state.exemplars,
+        backend: state.params.backend(),
+        collect_interval_seconds: state.collect_interval.map(|x| x.as_secs_f64()),
+    })
+}
+
+/// Counts responses by route and status code, including ones rejected by the load shedder.
+async fn track_responses(
+    State(state): State<Arc<AppState>>,
+    matched_path: MatchedPath,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+
+    state
+        .exporter_metrics
+        .record_response(matched_path.as_str(), response.status().as_u16());
+
+    if matched_path.as_str() != "/metrics" {
+        return response;
+    }
+
+    let encoding = response
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or("identity")
+        .to_string();
+
+    let (parts, body) = response.into_parts();
+
+    let mut sent = ResponseBytes {
+        state,
+        encoding,
+        bytes: 0,
+    };
+
+    let body = tokio_stream::StreamExt::map(body.into_data_stream(), move |x| {
+        if let Ok(x) = &x {
+            sent.add(x.len());
+        }
+
+        x
+    });
+
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Records the size of a `/metrics` body once it has been sent, or dropped
+/// by a scraper that went away.
+struct ResponseBytes {
+    state: Arc<AppState>,
+    encoding: String,
+    bytes: u64,
+}
+
+impl ResponseBytes {
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for ResponseBytes {
+    fn drop(&mut self) {
+        self.state
+            .exporter_metrics
+            .record_response_bytes(&self.encoding, self.bytes);
+    }
+}
+
+/// The jobid filter for a scrape, or a bad request response if `jobstats_filter` is not a valid regex.
+fn jobstats_filter(state: &AppState, params: &Params) -> Result<JobFilter, (StatusCode, String)> {
+    let mut filter = state.jobstats_filter.clone();
+
+    if let Some(x) = &params.jobstats_filter {
+        match Regex::new(x) {
+            Ok(x) => filter.include = Some(x),
+            Err(e) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid jobstats_filter {x}: {e}"),
+                ))
+            }
+        }
+    }
+
+    Ok(filter)
+}
+
+/// The jobs exported by a scrape, the top ones per target if it asks for `jobstats_top`.
+fn job_limit(state: &AppState, params: &Params) -> JobLimit {
+    params
+        .jobstats_top
+        .map_or(JobLimit::from(state.jobstats_max_jobs), JobLimit::Top)
+}
+
+/// Starts streaming the rendered jobstats of every OST and MDT.
+///
+/// Returns `None` if lctl could not be spawned, so the other sources can still be reported.
+pub(crate) async fn spawn_jobstats(
+    state: &Arc<AppState>,
+    filter: JobFilter,
+    limit: JobLimit,
+    exemplars: bool,
+) -> Result<Option<Receiver<CompactString>>, Error> {
+    if state.params.use_procfs() || state.from_dir.is_some() {
+        return procfs_jobstats(state, filter, limit, exemplars).await;
+    }
+
+    #[cfg(feature = "sandbox")]
+    let sandbox = state.sandbox;
+
+    let child = tokio::task::spawn_blocking(move || {
+        let mut cmd = crate::jobstats::lctl_jobstats();
+
+        #[cfg(feature = "sandbox")]
+        sandbox.apply(&mut cmd);
+
+        let child = cmd.stderr(std::process::Stdio::piped()).spawn()?;
+
+        Ok::<_, Error>(child)
+    })
+    .await?;
+
+    let mut child = match child {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::warn!("Error while spawning lctl jobstats: {e}");
+
+            return Ok(None);
+        }
+    };
+
+    let reader = BufReader::with_capacity(
+        128 * 1_024,
+        child.stdout.take().ok_or(io::Error::new(
+            io::ErrorKind::NotFound,
+            "stdout missing for lctl jobstats call.",
+        ))?,
+    );
+
+    let reader_stderr = BufReader::new(child.stderr.take().ok_or(io::Error::new(
+        io::ErrorKind::NotFound,
+        "stderr missing for lctl jobstats call.",
+    ))?);
+
+    let stderr_state = Arc::clone(state);
+
+    tokio::task::spawn(async move {
+        for line in reader_stderr.lines().map_while(Result::ok) {
+            tracing::debug!("stderr: {}", line);
+
+            stderr_state
+                .exporter_metrics
+                .record_lctl_stderr_line(lctl_stderr_class(&line));
+        }
+    });
+
+    let (_, rx) = crate::jobstats::jobstats_stream(
+        reader,
+        JobstatsOptions {
+            filter,
+            limit,
+            exemplars,
+            ids: state.job_ids.clone(),
+            deltas: state.job_deltas.clone(),
+            aggregate: state.jobstats_aggregate,
+        },
+    );
+
+    let rx = with_deadline(
+        Arc::clone(state),
+        "jobstats",
+        state.timeouts.jobstats.min(state.timeouts.scrape),
+        rx,
+    );
+
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = child.wait() {
+            tracing::debug!("Unexpected error when waiting for child: {e}");
+        }
+    });
+
+    Ok(Some(rx))
+}
+
+/// Like [`spawn_jobstats`], but reads the job_stats files from procfs.
+async fn procfs_jobstats(
+    state: &Arc<AppState>,
+    filter: JobFilter,
+    limit: JobLimit,
+    exemplars: bool,
+) -> Result<Option<Receiver<CompactString>>, Error> {
+    let timeout = state.timeouts.jobstats.min(state.timeouts.scrape);
+
+    let Some(output) = state
+        .get_param(
+            "jobstats",
+            timeout,
+            false,
+            JOBSTATS_PARAMS.map(String::from).to_vec(),
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let (_, rx) = crate::jobstats::jobstats_stream(
+        io::Cursor::new(output),
+        JobstatsOptions {
+            filter,
+            limit,
+            exemplars,
+            ids: state.job_ids.clone(),
+            deltas: state.job_deltas.clone(),
+            aggregate: state.jobstats_aggregate,
+        },
+    );
+
+    Ok(Some(rx))
+}
+
+/// Number of scrapes served so far, to tell the logs of concurrent scrapes apart.
+static SCRAPES: AtomicU64 = AtomicU64::new(0);
+
+/// Jobstats lines written to the client at once.
+const JOBSTATS_FLUSH_LINES: usize = 512;
+/// How long jobstats lines are held back to fill a write before they are flushed anyway.
+const JOBSTATS_FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Batches the rendered jobstats into body frames.
+///
+/// A frame is flushed once it holds [`JOBSTATS_FLUSH_LINES`] lines or
+/// [`JOBSTATS_FLUSH_INTERVAL`] has passed since its first line, so the client
+/// keeps reading while lctl is still producing output, without paying for a
+/// write per line.
+fn jobstats_frames(
+    rx: Receiver<CompactString>,
+) -> impl tokio_stream::Stream<Item = Result<Bytes, Infallible>> {
+    ReceiverStream::new(rx)
+        .chunks_timeout(JOBSTATS_FLUSH_LINES, JOBSTATS_FLUSH_INTERVAL)
+        .map(|xs| {
+            let mut frame = Vec::with_capacity(xs.iter().map(|x| x.len()).sum());
+
+            for x in xs {
+                frame.extend_from_slice(x.as_bytes());
+            }
+
+            Ok(Bytes::from(frame))
+        })
+}
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const OPENMETRICS_EOF: &str = "# EOF\n";
+
+/// Whether the scraper asked for OpenMetrics, which is needed to carry exemplars.
+fn accepts_openmetrics(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|x| x.to_str().ok())
+        .any(|x| x.contains("application/openmetrics-text"))
+}
+
+#[tracing::instrument(skip_all, fields(id = SCRAPES.fetch_add(1, Ordering::Relaxed)))]
+async fn scrape(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Query(params): Query<Params>,
+) -> Result<Response<Body>, Error> {
+    let start = Instant::now();
+
+    if state.track_clients {
+        let client = connect_info
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|x| x.to_str().ok())
+            .unwrap_or("unknown");
+
+        tracing::info!("Scrape from {client}, user-agent: {user_agent}");
+
+        state.exporter_metrics.record_request(&client);
+    }
+
+    let jobstats_filter = match jobstats_filter(&state, &params) {
+        Ok(x) => x,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    let openmetrics = state.exemplars && accepts_openmetrics(&headers);
+
+    let jobstats = if params.jobstats && state.collectors.enabled(Collector::Jobstats) {
+        let limit = job_limit(&state, &params);
+        let rx = spawn_jobstats(&state, jobstats_filter, limit, openmetrics).await?;
+
+        Some(rx.map(jobstats_frames))
+    } else {
+        None
+    };
+
+    let budget = Budget::new(start, state.timeouts.scrape);
+
+    let collected = match &state.scrape_worker {
+        Some(worker) => worker.collect(&state, budget.remaining(), params.mgs).await,
+        None => Some(collect_sources(&state, &budget, params.mgs).await),
+    };
+
+    // Prometheus drops the body of a failed scrape, so all it records of a failed
+    // worker is `up` at 0. The body still goes out for anyone reading it by hand,
+    // while the background collection skips it like any failed scrape.
+    let status = match collected {
+        Some(_) => StatusCode::OK,
+        None => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    let (sources, mut skipped) = collected.unwrap_or_default();
+
+    // Streaming jobstats can take a while, so drop them if there is no time left to send them.
+    let jobstats = match jobstats {
+        Some(Some(_)) if budget.timeout(state.timeouts.jobstats).is_none() => {
+            skipped.push("jobstats");
+
+            None
+        }
+        x => x,
+    };
+
+    let mut source_up: Vec<_> = sources.iter().map(|(x, y)| (*x, y.is_some())).collect();
+
+    if let Some(jobstats) = &jobstats {
+        source_up.push(("jobstats", jobstats.is_some()));
+    }
+
+    let output: Vec<_> = sources
+        .into_iter()
+        .filter_map(|(_, x)| x)
+        .flatten()
+        .collect();
+
+    state
+        .exporter_metrics
+        .record_consistency_warnings(consistency::check(&output));
+
+    // Targets are not gone just because the worker failed to read them
+    if status.is_success() {
+        state.counters.observe(&output);
+        state.targets.observe(&output);
+        state.recovery.observe(&output);
+    }
+
+    // With a collection interval, this is the background collection, which keeps
+    // the records for Kafka, and for `/json` to serialize if it is ever asked for them
+    let scraped = state
+        .collect_interval
+        .map(|_| ScrapedRecords(Arc::new(output.clone())));
+
+    let encode_start = Instant::now();
+
+    let mut lustre_stats = build_lustre_stats_with_quota_names(output, &state.quota_names);
+
+    state.exporter_metrics.record_stage_duration(
+        "lustre_stats",
+        Phase::Encode,
+        encode_start.elapsed(),
+    );
+
+    if state.counters.save_due() {
+        let counters_state = Arc::clone(&state);
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = counters_state.counters.save() {
+                tracing::warn!("Could not persist counter state: {e}");
+            }
+        });
+    }
+
+    tracing::debug!(
+        elapsed_seconds = start.elapsed().as_secs_f64(),
+        "Scrape collected"
+    );
+
+    state
+        .exporter_metrics
+        .record_scrape_duration(start.elapsed());
+
+    let exporter_stats = state.exporter_metrics.render();
+
+    let modules = match &state.from_dir {
+        Some(dir) => dir.join("modules"),
+        None => PathBuf::from(host::MODULES_ROOT),
+    };
+
+    if !skipped.is_empty() {
+        tracing::warn!(
+            "Scrape budget of {:?} nearly exhausted, skipped {}",
+            state.timeouts.scrape,
+            skipped.join(" ")
+        );
+    }
+
+    for x in [
+        render_scrape_status(&source_up, &skipped),
+        exporter_stats,
+        state.exporter_metrics.render_build_info(),
+        host::render_module_versions(&modules),
+        render_runtime(&tokio::runtime::Handle::current().metrics()),
+        state.counters.render(),
+        state.targets.render(),
+        state.recovery.render(),
+    ] {
+        if !x.is_empty() {
+            lustre_stats.push('\n');
+            lustre_stats.push_str(&x);
+        }
+    }
+
+    if openmetrics {
+        // OpenMetrics does not allow empty lines
+        lustre_stats = lustre_stats
+            .lines()
+            .filter(|x| !x.is_empty())
+            .flat_map(|x| [x, "\n"])
+            .collect();
+    }
+
+    let eof =
+        tokio_stream::iter(openmetrics.then(|| Ok(Bytes::from_static(OPENMETRICS_EOF.as_bytes()))));
+
+    let body = if let Some(Some(stream)) = jobstats {
+        let merged =
+            tokio_stream::StreamExt::chain(tokio_stream::once(Ok(lustre_stats.into())), stream);
+
+        Body::from_stream(tokio_stream::StreamExt::chain(merged, eof))
+    } else {
+        tracing::debug!("Jobstats collection disabled");
+
+        if openmetrics {
+            lustre_stats.push_str(OPENMETRICS_EOF);
+        }
+
+        Body::from(lustre_stats)
+    };
+
+    let mut response_builder = Response::builder().status(status);
+
+    if openmetrics {
+        response_builder = response_builder.header(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE);
+    }
+
+    let mut resp = response_builder.body(body)?;
+
+    if let Some(x) = scraped {
+        resp.extensions_mut().insert(x);
+    }
+
+    Ok(resp)
+}
+
+/// Returns the parsed records of a scrape as JSON, for tooling that wants structured data.
+///
+/// Jobstats are streamed straight to Prometheus text and are not included. With
+/// `--collect-interval`, the records of the latest background collection are
+/// returned instead, and query params are ignored like on `/metrics`.
+async fn json(State(state): State<Arc<AppState>>, Query(params): Query<JsonParams>) -> Response {
+    if state.collect_interval.is_some() {
+        return match state.latest_collection() {
+            Some(x) => Json(x.records.as_slice()).into_response(),
+            None => no_collection_yet(),
+        };
+    }
+
+    let budget = Budget::new(Instant::now(), state.timeouts.scrape);
+
+    let (sources, skipped) = collect_sources(&state, &budget, params.mgs).await;
+
+    if !skipped.is_empty() {
+        tracing::warn!(
+            "Scrape budget of {:?} nearly exhausted, skipped {}",
+            state.timeouts.scrape,
+            skipped.join(" ")
+        );
+    }
+
+    Json(
+        sources
+            .into_iter()
+            .filter_map(|(_, x)| x)
+            .flatten()
+            .collect::<Vec<_>>(),
+    )
+    .into_response()
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Family {
+    name: String,
+    series: u64,
+}
+
+/// Adds the number of series of each metric family in the rendered `stats` to `counts`.
+fn count_series(counts: &mut BTreeMap<String, u64>, stats: &str) {
+    for line in stats.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.split(['{', ' ']).next() {
+            *counts.entry(name.to_string()).or_default() += 1;
+        }
+    }
+}
+
+/// The params, stats and Lustre releases the parsers of this build cover.
+async fn support() -> Json<SupportMatrix> {
+    Json(support_matrix())
+}
+
+/// Lists each metric family a scrape would export with its number of series,
+/// largest first, to find what is driving up cardinality.
+///
+/// With `--collect-interval`, the latest background collection is counted
+/// instead, and query params are ignored like on `/metrics`.
+async fn families(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<Params>,
+) -> Result<Response, Error> {
+    if state.collect_interval.is_some() {
+        let Some(collection) = state.latest_collection() else {
+            return Ok(no_collection_yet());
+        };
+
+        let mut counts = BTreeMap::new();
+
+        count_series(&mut counts, &String::from_utf8_lossy(&collection.body));
+
+        return Ok(Json(sorted_families(counts)).into_response());
+    }
+
+    let jobstats_filter = match jobstats_filter(&state, &params) {
+        Ok(x) => x,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    let jobstats = if params.jobstats && state.collectors.enabled(Collector::Jobstats) {
+        spawn_jobstats(&state, jobstats_filter, job_limit(&state, &params), false).await?
+    } else {
+        None
+    };
+
+    let budget = Budget::new(Instant::now(), state.timeouts.scrape);
+
+    let (sources, _) = collect_sources(&state, &budget, params.mgs).await;
+
+    let output = sources
+        .into_iter()
+        .filter_map(|(_, x)| x)
+        .flatten()
+        .collect();
+
+    let mut counts = BTreeMap::new();
+
+    count_series(
+        &mut counts,
+        &build_lustre_stats_with_quota_names(output, &state.quota_names),
+    );
+
+    if let Some(mut rx) = jobstats {
+        // Jobstats series are sent in pieces, so only count complete lines
+        let mut line = String::new();
+
+        while let Some(x) = rx.recv().await {
+            line.push_str(&x);
+
+            if line.ends_with('\n') {
+                count_series(&mut counts, &line);
+
+                line.clear();
+            }
+        }
+    }
+
+    Ok(Json(sorted_families(counts)).into_response())
+}
+
+/// The families counted in `counts`, largest first.
+fn sorted_families(counts: BTreeMap<String, u64>) -> Vec<Family> {
+    let mut families: Vec<_> = counts
+        .into_iter()
+        .map(|(name, series)| Family { name, series })
+        .collect();
+
+    families.sort_by_key(|x| Reverse(x.series));
+
+    families
+}
+
+/// Collects every source except jobstats, skipping the optional ones once `budget` runs low.
+///
+/// Returns the records of each source, `None` if it failed, and the names of the skipped sources.
+pub(crate) async fn collect_sources(
+    state: &AppState,
+    budget: &Budget,
+    mgs: bool,
+) -> (Vec<(&'static str, Option<Vec<Record>>)>, Vec<&'static str>) {
+    let lctl = async {
+        state
+            .collect_params(
+                "lctl",
+                state.timeouts.lctl,
+                false,
+                state.collectors.params(),
+                |stdout| {
+                    let (lctl_output, skipped) = parse_lctl_output_lossy(stdout)?;
+
+                    for x in &skipped {
+                        tracing::warn!("Skipped unparsable param {}: {}", x.param, x.snippet);
+
+                        state.exporter_metrics.record_parse_error(&x.param);
+                    }
+
+                    state.exporter_metrics.record_skipped_params(skipped.len());
+
+                    Ok(lctl_output)
+                },
+            )
+            .await
+    };
+
+    // recovery_status is not part of the lctl params, so it is read on its own.
+    let recovery_status = async {
+        let Some(timeout) = budget.timeout(state.timeouts.lctl) else {
+            return Err("recovery_status");
+        };
+
+        let records = state
+            .collect_params(
+                "recovery_status",
+                timeout,
+                false,
+                recovery_status_parser::params(),
+                |stdout| Ok(parse_recovery_status_output(stdout)?),
+            )
+            .await;
+
+        Ok(("recovery_status", records))
+    };
+
+    let lnetctl = |(source, args, parse, group): (
+        &'static str,
+        &'static [&'static str],
+        LnetctlParser,
+        Collector,
+    )| async move {
+        if !state.collectors.enabled(Collector::Lnet) || !state.collectors.enabled(group) {
+            return None;
+        }
+
+        let Some(timeout) = budget.timeout(state.timeouts.lnetctl) else {
+            return Some(Err(source));
+        };
+
+        let records = state
+            .collect(
+                source,
+                timeout,
+                Command::new("lnetctl").args(args),
+                |stdout| Ok(parse(std::str::from_utf8(stdout)?)?),
+            )
+            .await;
+
+        Some(Ok((source, records)))
+    };
+
+    let [net, stats, peer, route, routing, global, udsp] = LNETCTL_SOURCES.map(lnetctl);
+
+    // The commands are independent, so run them side by side rather than
+    // paying for each in turn. Every one still records its own duration.
+    let (
+        lctl,
+        recovery_status,
+        lnetctl_net,
+        lnetctl_stats,
+        lnetctl_peer,
+        lnetctl_route,
+        lnetctl_routing,
+        lnetctl_global,
+        lnetctl_udsp,
+    ) = tokio::join!(
+        lctl,
+        recovery_status,
+        net,
+        stats,
+        peer,
+        route,
+        routing,
+        global,
+        udsp
+    );
+
+    let mut sources = vec![("lctl", lctl)];
+    let mut skipped = vec![];
+
+    for x in [
+        Some(recovery_status),
+        lnetctl_net,
+        lnetctl_stats,
+        lnetctl_peer,
+        lnetctl_route,
+        lnetctl_routing,
+        lnetctl_global,
+        lnetctl_udsp,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        match x {
+            Ok(x) => sources.push(x),
+            Err(source) => skipped.push(source),
+        }
+    }
+
+    let arcstats = match &state.from_dir {
+        Some(dir) => dir.join("zfs_arcstats.txt"),
+        None => PathBuf::from(zfs_parser::ARCSTATS),
+    };
+
+    // Only ZFS backed servers have an ARC, so skip the ZFS sources everywhere else.
+    match tokio::fs::read(&arcstats).await {
+        Ok(arcstats) => {
+            let start = Instant::now();
+
+            let records = parse_arcstats_output(&arcstats);
+
+            state.exporter_metrics.record_stage_duration(
+                "zfs_arcstats",
+                Phase::Parse,
+                start.elapsed(),
+            );
+
+            let arcstats = match records {
+                Ok(x) => {
+                    state
+                        .exporter_metrics
+                        .record_parsed("zfs_arcstats", arcstats.len(), x.len());
+
+                    Some(x)
+                }
+                Err(e) => {
+                    tracing::warn!("Could not parse zfs_arcstats output: {e}");
+
+                    None
+                }
+            };
+
+            sources.push(("zfs_arcstats", arcstats));
+
+            if let Some(timeout) = budget.timeout(state.timeouts.lctl) {
+                let zpool = state
+                    .collect(
+                        "zpool",
+                        timeout,
+                        Command::new("zpool").args(zfs_parser::ZPOOL_FRAGMENTATION_ARGS),
+                        |stdout| Ok(parse_zpool_fragmentation_output(stdout)?),
+                    )
+                    .await;
+
+                sources.push(("zpool", zpool));
+            } else {
+                skipped.push("zpool");
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => {
+            tracing::warn!("Could not read {}: {e}", arcstats.display());
+
+            sources.push(("zfs_arcstats", None));
+        }
+    }
+
+    if mgs && state.collectors.enabled(Collector::Mgs) {
+        if let Some(timeout) = budget.timeout(state.timeouts.lctl) {
+            let mgs_fs = state
+                .collect_params("mgs_fs", timeout, true, mgs_fs_parser::params(), |stdout| {
+                    // Nodes without an MGS have nothing to report, so a parse failure is not fatal here.
+                    match parse_mgs_fs_output(stdout) {
+                        Ok(x) => Ok(x),
+                        Err(e) => {
+                            tracing::debug!("Could not parse mgs fs output: {e}");
+
+                            Ok(vec![])
+                        }
+                    }
+                })
+                .await;
+
+            let filesystems: Vec<_> = mgs_fs
+                .iter()
+                .flatten()
+                .filter_map(|x| match x {
+                    Record::Target(TargetStats::FsNames(x)) => Some(
+                        x.value
+                            .iter()
+                            .map(|fs| (x.target.clone(), fs.clone()))
+                            .collect::<Vec<_>>(),
+                    ),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+
+            sources.push(("mgs_fs", mgs_fs));
+
+            if !filesystems.is_empty() {
+                collect_mgs_filesystems(state, budget, &filesystems, &mut sources, &mut skipped)
+                    .await;
+            }
+        } else {
+            skipped.push("mgs_fs");
+        }
+    }
+
+    for (_, records) in &mut sources {
+        if let Some(records) = records {
+            records.retain(|x| state.collectors.wants_record(x));
+        }
+    }
+
+    (sources, skipped)
+}
+
+/// Collects the imperative recovery state and pool count of each filesystem registered with an MGS.
+async fn collect_mgs_filesystems(
+    state: &AppState,
+    budget: &Budget,
+    filesystems: &[(Target, FsName)],
+    sources: &mut Vec<(&'static str, Option<Vec<Record>>)>,
+    skipped: &mut Vec<&'static str>,
+) {
+    if let Some(timeout) = budget.timeout(state.timeouts.lctl) {
+        let params = filesystems
+            .iter()
+            .flat_map(|(target, fs)| mgs_live_parser::params(target, std::slice::from_ref(fs)))
+            .collect();
+
+        let mgs_live = state
+            .collect_params("mgs_live", timeout, false, params, |stdout| {
+                Ok(parse_mgs_live_output(stdout)?)
+            })
+            .await;
+
+        sources.push(("mgs_live", mgs_live));
+    } else {
+        skipped.push("mgs_live");
+    }
+
+    // Pools are listed by `lctl pool_list`, which has no param to read instead,
+    // and is run per filesystem so it has no single saved output either.
+    if state.params.use_procfs() || state.from_dir.is_some() {
+        return;
+    }
+
+    let mut pools = Some(vec![]);
+
+    for (target, fs) in filesystems {
+        let Some(timeout) = budget.timeout(state.timeouts.lctl) else {
+            skipped.push("mgs_pools");
+
+            return;
+        };
+
+        let x = state
+            .collect(
+                "mgs_pools",
+                timeout,
+                Command::new("lctl").args(mgs_live_parser::pool_list_args(fs)),
+                |stdout| Ok(parse_pool_list_output(target, fs, stdout)?),
+            )
+            .await;
+
+        pools = pools.zip(x).map(|(mut xs, mut x)| {
+            xs.append(&mut x);
+
+            xs
+        });
+    }
+
+    sources.push(("mgs_pools", pools));
+}
+
+/// Reads the `job_stats` of a single OST or MDT and returns the
+/// stats of the requested job as JSON.
+async fn jobstats_lookup(
+    State(state): State<Arc<AppState>>,
+    Path(target): Path<String>,
+    Query(params): Query<JobstatsLookupParams>,
+) -> Result<Response, Error> {
+    // The target ends up in an lctl parameter path, so don't let globs or dots through.
+    if !target
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Ok((StatusCode::BAD_REQUEST, format!("Invalid target {target}")).into_response());
+    }
+
+    let param = match TargetVariant::try_from(&Target(target.clone())) {
+        Ok(TargetVariant::Ost) => format!("obdfilter.{target}.job_stats"),
+        Ok(TargetVariant::Mdt) => format!("mdt.{target}.job_stats"),
+        _ => {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                format!("{target} is not an OST or MDT target"),
+            )
+                .into_response())
+        }
+    };
+
+    let Some(lctl) = state
+        .get_param("jobstats_lookup", state.timeouts.lctl, false, vec![param])
+        .await?
+    else {
+        return Ok((
+            StatusCode::GATEWAY_TIMEOUT,
+            format!("Timed out reading job stats on {target}"),
+        )
+            .into_response());
+    };
+
+    let jobid = params.jobid;
+
+    let job = tokio::task::spawn_blocking(move || find_job(lctl.as_slice(), &jobid)).await??;
+
+    match job {
+        Some(x) => Ok(Json(x).into_response()),
+        None => Ok((
+            StatusCode::NOT_FOUND,
+            format!("No job stats found on {target}"),
+        )
+            .into_response()),
+    }
+}
+
+/// Whether `headers` carry the bearer token configured for the debug routes.
+fn authorized(state: &AppState, headers: &HeaderMap) -> bool {
+    let Some(token) = &state.debug_token else {
+        return false;
+    };
+
+    let Some(x) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    // Compare every byte so the time taken does not tell how much of the token matched
+    x.len() == token.len()
+        && x.bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Appends the stdout of a source to a dump under a `# <command>` header, or why there is none.
+fn dump_section(out: &mut Vec<u8>, command: &str, stdout: Result<Option<Vec<u8>>, Error>) {
+    out.extend_from_slice(format!("# {command}\n").as_bytes());
+
+    match stdout {
+        Ok(Some(x)) => {
+            out.extend_from_slice(&x);
+
+            if !x.ends_with(b"\n") {
+                out.push(b'\n');
+            }
+        }
+        Ok(None) => out.extend_from_slice(b"# timed out\n"),
+        Err(e) => out.extend_from_slice(format!("# failed: {e}\n").as_bytes()),
+    }
+
+    out.push(b'\n');
+}
+
+/// Returns the raw output of the params and commands a scrape reads, so support can collect
+/// diagnostics without a shell on the node.
+async fn dump(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if state.debug_token.is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            "Set --debug-token to enable /debug/dump",
+        )
+            .into_response();
+    }
+
+    if !authorized(&state, &headers) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+        )
+            .into_response();
+    }
+
+    let mut params = vec![state.collectors.params(), recovery_status_parser::params()];
+
+    if state.collectors.enabled(Collector::Jobstats) {
+        params.push(JOBSTATS_PARAMS.map(String::from).to_vec());
+    }
+
+    let mut out = vec![];
+
+    for x in params {
+        let command = format!("lctl get_param {}", x.join(" "));
+
+        let stdout = state.get_param("dump", state.timeouts.lctl, false, x).await;
+
+        dump_section(&mut out, &command, stdout);
+    }
+
+    if state.collectors.enabled(Collector::Lnet) {
+        for (_, args, _, group) in &LNETCTL_SOURCES {
+            if !state.collectors.enabled(*group) {
+                continue;
+            }
+
+            let stdout = state
+                .output(
+                    "dump",
+                    state.timeouts.lnetctl,
+                    Command::new("lnetctl").args(*args),
+                )
+                .await
+                .map(|x| x.map(|x| x.stdout));
+
+            dump_section(&mut out, &format!("lnetctl {}", args.join(" ")), stdout);
+        }
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"lustrefs-exporter-dump.txt\"",
+            ),
+        ],
+        out,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        app, count_series, fetch_scrape, jobstats_frames, scrape, scrape_once, serve, serve_unix,
+        AppState, Budget, Collection, Compression, Config, History, ScrapedRecords,
+        JOBSTATS_FLUSH_LINES, MIN_SOURCE_BUDGET,
+    };
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header, StatusCode},
+    };
+    use compact_str::format_compact;
+    use std::{
+        collections::BTreeMap,
+        env, fs,
+        io::Read,
+        net::{Ipv6Addr, SocketAddr},
+        os::unix::fs::PermissionsExt,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{UnixListener, UnixStream},
+        sync::mpsc,
+    };
+    use tokio_stream::StreamExt;
+    use tower::ServiceExt;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn jobstats_frames_flush_by_size_and_time() {
+        let (tx, rx) = mpsc::channel(1_024);
+
+        for x in 0..JOBSTATS_FLUSH_LINES + 1 {
+            tx.send(format_compact!("{x}\n")).await.unwrap();
+        }
+
+        let mut frames = Box::pin(jobstats_frames(rx));
+
+        let first = frames.next().await.unwrap().unwrap();
+
+        assert_eq!(
+            first.iter().filter(|x| **x == b'\n').count(),
+            JOBSTATS_FLUSH_LINES
+        );
+
+        // The last line is flushed on its own once the interval passes, while the sender is still open.
+        let second = tokio::time::timeout(Duration::from_secs(5), frames.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            second.as_ref(),
+            format!("{JOBSTATS_FLUSH_LINES}\n").as_bytes()
+        );
+
+        drop(tx);
+
+        assert!(frames.next().await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_from_dir() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-from-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../lustre-collector/src/fixtures/valid/lustre-2.14.0_ddn133/2.14.0_ddn133_quota.txt"
+            ),
+            dir.join("lctl.txt"),
+        )
+        .unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../lustre-collector/src/fixtures/lnetctl_net_show.txt"
+            ),
+            dir.join("lnetctl_net.txt"),
+        )
+        .unwrap();
+
+        let state = Arc::new(AppState {
+            from_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+
+        let output = scrape_once(&app(Arc::clone(&state)), "test").await;
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        let output = String::from_utf8(output.unwrap().unwrap().to_vec()).unwrap();
+
+        assert!(output.contains("lustre_quota_"));
+        assert!(output.contains(r#"lustre_lnet_ni_fatal_error{nid="172.16.1.24@o2ib"} 1"#));
+
+        assert!(state.exporter_metrics.render().contains(&format!(
+            r#"lustre_exporter_response_bytes{{encoding="identity"}} {}"#,
+            output.len()
+        )));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_gzip() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-gzip-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../lustre-collector/src/fixtures/valid/lustre-2.14.0_ddn133/2.14.0_ddn133_quota.txt"
+            ),
+            dir.join("lctl.txt"),
+        )
+        .unwrap();
+
+        let state = Arc::new(AppState {
+            from_dir: Some(dir.clone()),
+            compression_min_bytes: 1024,
+            ..Default::default()
+        });
+
+        let request = Request::get("/metrics")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+
+        let response = app(Arc::clone(&state)).oneshot(request).await.unwrap();
+
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let sent = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut output = String::new();
+        flate2::read::GzDecoder::new(&sent[..])
+            .read_to_string(&mut output)
+            .unwrap();
+
+        assert!(output.contains("lustre_quota_"));
+        assert!(sent.len() < output.len());
+
+        assert!(state.exporter_metrics.render().contains(&format!(
+            r#"lustre_exporter_response_bytes{{encoding="gzip"}} {}"#,
+            sent.len()
+        )));
+    }
+
+    #[test]
+    fn parse_compression() {
+        assert_eq!("none".parse(), Ok(Compression::None));
+        assert_eq!("gzip".parse(), Ok(Compression::Gzip(6)));
+        assert_eq!("gzip:1".parse(), Ok(Compression::Gzip(1)));
+        assert_eq!("zstd".parse(), Ok(Compression::Zstd(3)));
+        assert_eq!("zstd:19".parse(), Ok(Compression::Zstd(19)));
+        assert!("gzip:10".parse::<Compression>().is_err());
+        assert!("zstd:0".parse::<Compression>().is_err());
+        assert!("none:1".parse::<Compression>().is_err());
+        assert!("br".parse::<Compression>().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_zstd() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-zstd-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../lustre-collector/src/fixtures/valid/lustre-2.14.0_ddn133/2.14.0_ddn133_quota.txt"
+            ),
+            dir.join("lctl.txt"),
+        )
+        .unwrap();
+
+        let state = Arc::new(AppState {
+            from_dir: Some(dir.clone()),
+            compression: Compression::Zstd(3),
+            compression_min_bytes: 1024,
+            ..Default::default()
+        });
+
+        let get = |path: &'static str, accept_encoding: Option<&'static str>| {
+            let mut request = Request::get(path);
+
+            if let Some(x) = accept_encoding {
+                request = request.header(header::ACCEPT_ENCODING, x);
+            }
+
+            app(Arc::clone(&state)).oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = get("/metrics", Some("gzip, zstd")).await.unwrap();
+
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "zstd");
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+
+        // Scrapers like Prometheus only accept gzip
+        let response = get("/metrics", Some("gzip")).await.unwrap();
+
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Bodies too small to compress, and ones for scrapers that do not accept
+        // compression, still vary on it for caches in between
+        for accept_encoding in [Some("gzip"), None] {
+            let response = get("/config", accept_encoding).await.unwrap();
+
+            assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+            assert_eq!(response.headers()[header::VARY], "accept-encoding");
+        }
+    }
+
+    #[test]
+    fn lctl_stderr_classes() {
+        let state = AppState::default();
+
+        state.record_lctl_stderr(
+            "lctl",
+            b"error: get_param: param_path 'mdt/*/job_stats': No such file or directory
+error: get_param: read('/proc/fs/lustre/health_check'): Permission denied
+
+error: get_param: read('/sys/fs/lustre/osc/fs-OST0000-osc/state'): No such device
+lctl: something unexpected
+",
+        );
+
+        let rendered = state.exporter_metrics.render();
+
+        for x in ["not_found", "permission", "module_not_loaded", "other"] {
+            assert!(rendered.contains(&format!(
+                r#"lustre_exporter_lctl_stderr_lines_total{{class="{x}"}} 1"#
+            )));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dump_requires_token() {
+        async fn get(state: &Arc<AppState>, token: Option<&str>) -> (StatusCode, String) {
+            let mut request = Request::get("/debug/dump");
+
+            if let Some(x) = token {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {x}"));
+            }
+
+            let response = app(Arc::clone(state))
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-dump-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        // Every section of the dump is read as the `dump` source
+        fs::write(dir.join("dump.txt"), "memused=1000\n").unwrap();
+
+        let state = Arc::new(AppState {
+            from_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+
+        assert_eq!(get(&state, Some("secret")).await.0, StatusCode::NOT_FOUND);
+
+        let state = Arc::new(AppState {
+            debug_token: Some("secret".to_string()),
+            from_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+
+        assert_eq!(get(&state, None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            get(&state, Some("secreT")).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let (status, body) = get(&state, Some("secret")).await;
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("# lctl get_param "));
+        assert!(body.contains("\nmemused=1000\n\n# lctl get_param "));
+        assert!(body.contains("# lnetctl net show -v 4\nmemused=1000\n\n"));
+        assert!(!body.contains("# timed out\n"));
+        assert!(!body.contains("# failed"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_latest_snapshot() {
+        let state = Arc::new(AppState {
+            collect_interval: Some(Duration::from_secs(30)),
+            ..Default::default()
+        });
+
+        let app = app(Arc::clone(&state));
+
+        assert_eq!(scrape_once(&app, "test").await.unwrap(), None);
+
+        state
+            .snapshot
+            .send_replace(Some(Collection::new("lustre_health_healthy 1\n".into())));
+
+        assert_eq!(
+            scrape_once(&app, "test").await.unwrap().as_deref(),
+            Some(b"lustre_health_healthy 1\n".as_slice())
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_json_from_snapshot() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-json-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lctl.txt"), "memused=1000\n").unwrap();
+
+        let state = Arc::new(AppState {
+            collect_interval: Some(Duration::from_secs(30)),
+            from_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+
+        let get_json = || async {
+            let response = app(Arc::clone(&state))
+                .oneshot(Request::get("/json").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        assert_eq!(get_json().await.0, StatusCode::SERVICE_UNAVAILABLE);
+
+        // The background collection scrapes through the scrape handler directly
+        let collector = axum::Router::new()
+            .route("/metrics", axum::routing::get(scrape))
+            .with_state(Arc::clone(&state));
+
+        let (_, records) = fetch_scrape(&collector, "test").await.unwrap().unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        let ScrapedRecords(records) = records.unwrap();
+
+        let mut collection = Collection::new("lustre_mem_used 1000\n".into());
+        collection.records = records.clone();
+
+        state.snapshot.send_replace(Some(collection));
+
+        let (status, body) = get_json().await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::to_string(&*records).unwrap());
+        assert!(body.contains("memused"));
+    }
+
+    #[tokio::test]
+    async fn sd_reuses_roles() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-sd-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../lustre-collector/src/fixtures/valid/valid.txt"
+            ),
+            dir.join("lctl.txt"),
+        )
+        .unwrap();
+
+        let state = Arc::new(AppState {
+            from_dir: Some(dir.clone()),
+            hostname: "node1".to_string(),
+            ..Default::default()
+        });
+
+        let get_sd = || async {
+            let response = app(Arc::clone(&state))
+                .oneshot(Request::get("/sd").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()[0].clone()
+        };
+
+        let x = get_sd().await;
+
+        assert_eq!(x["targets"][0], "node1:0");
+        assert_eq!(x["labels"]["__meta_lustre_roles"], ",ost,mdt,");
+
+        // Until the roles expire, polls do not collect again
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(get_sd().await["labels"]["__meta_lustre_roles"], ",ost,mdt,");
+
+        // The latest background collection is used as soon as there is one
+        state
+            .snapshot
+            .send_replace(Some(Collection::new("".into())));
+
+        assert_eq!(get_sd().await["labels"]["__meta_lustre_roles"], ",");
+    }
+
+    #[tokio::test]
+    async fn serve_families_from_snapshot() {
+        let state = Arc::new(AppState {
+            collect_interval: Some(Duration::from_secs(30)),
+            ..Default::default()
+        });
+
+        let get_families = || async {
+            let response = app(Arc::clone(&state))
+                .oneshot(Request::get("/debug/families").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        assert_eq!(get_families().await.0, StatusCode::SERVICE_UNAVAILABLE);
+
+        state.snapshot.send_replace(Some(Collection::new(
+            "# TYPE lustre_mem_used gauge\nlustre_mem_used 2\nlustre_health_healthy 1\nlustre_mem_used{x=\"y\"} 3\n"
+                .into(),
+        )));
+
+        assert_eq!(
+            get_families().await,
+            (
+                StatusCode::OK,
+                r#"[{"name":"lustre_mem_used","series":2},{"name":"lustre_health_healthy","series":1}]"#
+                    .to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_history() {
+        async fn get(state: &Arc<AppState>, uri: &str) -> (StatusCode, String) {
+            let response = app(Arc::clone(state))
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        }
+
+        let state = Arc::new(AppState::default());
+
+        assert_eq!(
+            get(&state, "/history?metrics=lustre_health_healthy")
+                .await
+                .0,
+            StatusCode::NOT_FOUND
+        );
+
+        let state = Arc::new(AppState {
+            collect_interval: Some(Duration::from_secs(30)),
+            history: History::new(10),
+            ..Default::default()
+        });
+
+        state
+            .history
+            .push(u64::MAX, b"lustre_health_healthy 1\nlustre_mem_used 2\n");
+
+        assert_eq!(
+            get(&state, "/history?minutes=10").await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            get(&state, "/history?minutes=10&metrics=lustre_health_healthy").await,
+            (
+                StatusCode::OK,
+                format!(
+                    r#"[{{"name":"lustre_health_healthy","labels":{{}},"values":[[{},1.0]]}}]"#,
+                    u64::MAX
+                )
+            )
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_latest_snapshot_not_modified() {
+        let state = Arc::new(AppState {
+            collect_interval: Some(Duration::from_secs(30)),
+            ..Default::default()
+        });
+
+        let app = app(Arc::clone(&state));
+
+        let get = |if_none_match: Option<&str>| {
+            let mut request = axum::http::Request::get("/metrics");
+
+            if let Some(x) = if_none_match {
+                request = request.header(header::IF_NONE_MATCH, x);
+            }
+
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        state
+            .snapshot
+            .send_replace(Some(Collection::new("lustre_health_healthy 1\n".into())));
+
+        let response = get(None).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let response = get(Some(&etag)).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        assert!(axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            get(Some(&format!("\"0\", W/{etag}")))
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::NOT_MODIFIED
+        );
+
+        state
+            .snapshot
+            .send_replace(Some(Collection::new("lustre_health_healthy 0\n".into())));
+
+        assert_eq!(get(Some(&etag)).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_on_unix_socket() {
+        let path = env::temp_dir().join(format!("lustrefs-exporter-{}.sock", std::process::id()));
+
+        // A stale socket from an earlier run is replaced
+        let stale = UnixListener::bind(&path).unwrap();
+
+        drop(stale);
+
+        let server = serve_unix(Arc::new(AppState::default()), &path, 0o600)
+            .await
+            .unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+
+        stream
+            .write_all(b"GET /config HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+
+        stream.read_to_string(&mut response).await.unwrap();
+
+        server.abort();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(r#""bound_addrs":[]"#));
+    }
+
+    async fn get_config(addr: SocketAddr) -> Config {
+        let body = tokio::task::spawn_blocking(move || {
+            ureq::get(&format!("http://{addr}/config"))
+                .call()
+                .unwrap()
+                .into_string()
+                .unwrap()
+        })
+        .await
+        .unwrap();
+
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_on_any_port() {
+        let (addrs, server) = serve(
+            Arc::new(AppState::default()),
+            &[SocketAddr::from(([127, 0, 0, 1], 0))],
+        )
+        .await
+        .unwrap();
+
+        assert_ne!(addrs[0].port(), 0);
+
+        let config = get_config(addrs[0]).await;
+
+        server.abort();
+
+        assert_eq!(config.bound_addrs, addrs);
+        assert_eq!(config.collectors.len(), 7);
+    }
+
+    /// Whether `::1` can be bound, which it can't on hosts or containers without IPv6.
+    fn ipv6_available() -> bool {
+        std::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).is_ok()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_on_ipv4_and_ipv6() {
+        if !ipv6_available() {
+            return;
+        }
+
+        let (addrs, server) = serve(
+            Arc::new(AppState::default()),
+            &[
+                SocketAddr::from(([127, 0, 0, 1], 0)),
+                SocketAddr::from((Ipv6Addr::LOCALHOST, 0)),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addrs.len(), 2);
+        assert_eq!(addrs[0].port(), addrs[1].port());
+
+        for &addr in &addrs {
+            assert_eq!(get_config(addr).await.bound_addrs, addrs);
+        }
+
+        server.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_on_ipv6_wildcard_is_dual_stack() {
+        if !ipv6_available() {
+            return;
+        }
+
+        let (addrs, server) = serve(
+            Arc::new(AppState::default()),
+            &[SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))],
+        )
+        .await
+        .unwrap();
+
+        let port = addrs[0].port();
+
+        get_config(SocketAddr::from((Ipv6Addr::LOCALHOST, port))).await;
+        get_config(SocketAddr::from(([127, 0, 0, 1], port))).await;
+
+        server.abort();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_on_ipv4_and_ipv6_wildcards() {
+        if !ipv6_available() {
+            return;
+        }
+
+        // The IPv6 wildcard must not take over IPv4 when both are requested
+        let (addrs, server) = serve(
+            Arc::new(AppState::default()),
+            &[
+                SocketAddr::from(([0, 0, 0, 0], 0)),
+                SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(addrs[0].port(), addrs[1].port());
+
+        get_config(SocketAddr::from(([127, 0, 0, 1], addrs[0].port()))).await;
+        get_config(SocketAddr::from((Ipv6Addr::LOCALHOST, addrs[0].port()))).await;
+
+        server.abort();
+    }
+
+    #[test]
+    fn budget_caps_timeout_to_remaining_time() {
+        let budget = Budget::new(Instant::now(), Duration::from_secs(60));
+
+        assert_eq!(
+            budget.timeout(Duration::from_secs(5)),
+            Some(Duration::from_secs(5))
+        );
+        assert!(budget.timeout(Duration::from_secs(120)).unwrap() <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn budget_skips_when_nearly_exhausted() {
+        let budget = Budget::new(Instant::now(), MIN_SOURCE_BUDGET / 2);
+
+        assert_eq!(budget.timeout(Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn count_series_by_family() {
+        let mut counts = BTreeMap::new();
+
+        count_series(
+            &mut counts,
+            r#"# HELP lustre_health_healthy Indicates whether the Lustre server is healthy or not. 1 is healthy, 0 is unhealthy.
+# TYPE lustre_health_healthy gauge
+lustre_health_healthy 1
+
+# HELP lustre_free_bytes The number of bytes available to the filesystem.
+# TYPE lustre_free_bytes gauge
+lustre_free_bytes{component="ost",target="fs-OST0000"} 1000
+lustre_free_bytes{component="ost",target="fs-OST0001"} 2000
+"#,
+        );
+
+        count_series(
+            &mut counts,
+            "lustre_job_stats_total{operation=\"getattr\",component=\"ost\",target=\"fs-OST0000\",jobid=\"dd.0\"} 3\n",
+        );
+
+        assert_eq!(
+            counts,
+            BTreeMap::from([
+                ("lustre_free_bytes".to_string(), 2),
+                ("lustre_health_healthy".to_string(), 1),
+                ("lustre_job_stats_total".to_string(), 1),
+            ])
+        );
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use lustre_collector::{BrwStatsBucket, ImportStat, OscRpcStats, TargetStats};
+use prometheus_exporter_base::prelude::*;
+
+use crate::{escape_label_value, Metric, StatsMapExt};
+
+static RPC_IN_FLIGHT: Metric = Metric {
+    name: "lustre_client_rpc_in_flight",
+    help: "Number of RPCs a client import currently has in flight to its OST.",
+    r#type: MetricType::Gauge,
+};
+
+static RPC_PENDING_PAGES: Metric = Metric {
+    name: "lustre_client_rpc_pending_pages",
+    help: "Number of pages a client import has waiting to be sent to its OST.",
+    r#type: MetricType::Gauge,
+};
+
+static RPC_PAGES: Metric = Metric {
+    name: "lustre_client_rpc_pages",
+    help: "Pages in each RPC sent by a client import, counted in the power of two bucket at or below its size.",
+    r#type: MetricType::Histogram,
+};
+
+static RPC_QUEUE_DEPTH: Metric = Metric {
+    name: "lustre_client_rpc_queue_depth",
+    help: "RPCs a client import already had in flight when it sent each RPC.",
+    r#type: MetricType::Histogram,
+};
+
+static RPC_OFFSET_PAGES: Metric = Metric {
+    name: "lustre_client_rpc_offset_pages",
+    help: "Offset in the object of each RPC sent by a client import, in pages, counted in the power of two bucket at or below it.",
+    r#type: MetricType::Histogram,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&RPC_IN_FLIGHT, "osc.*.rpc_stats"),
+    (&RPC_PENDING_PAGES, "osc.*.rpc_stats"),
+    (&RPC_PAGES, "osc.*.rpc_stats"),
+    (&RPC_QUEUE_DEPTH, "osc.*.rpc_stats"),
+    (&RPC_OFFSET_PAGES, "osc.*.rpc_stats"),
+];
+
+pub fn build_osc_rpc_stats(
+    x: &ImportStat<OscRpcStats>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let ImportStat {
+        kind,
+        target,
+        value,
+        ..
+    } = x;
+
+    for (metric, operation, value) in [
+        (RPC_IN_FLIGHT, "read", value.read_rpcs_in_flight),
+        (RPC_IN_FLIGHT, "write", value.write_rpcs_in_flight),
+        (RPC_PENDING_PAGES, "read", value.pending_read_pages),
+        (RPC_PENDING_PAGES, "write", value.pending_write_pages),
+    ] {
+        stats_map.get_mut_metric(metric).render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("component", &**kind)
+                .with_label("target", target.0.as_str())
+                .with_label("operation", operation)
+                .with_value(value),
+        );
+    }
+}
+
+/// The RPC histograms of each `osc` import, rendered as they are observed.
+///
+/// `prometheus_exporter_base` only renders plain samples, so the `_bucket`
+/// and `_count` series are written out here, one family per histogram.
+#[derive(Debug, Default)]
+pub struct ClientRpcHistograms {
+    families: BTreeMap<&'static str, String>,
+}
+
+impl ClientRpcHistograms {
+    pub fn observe(&mut self, x: &TargetStats) {
+        let TargetStats::OscRpcStats(ImportStat {
+            kind,
+            target,
+            value,
+            ..
+        }) = x
+        else {
+            return;
+        };
+
+        let target = escape_label_value(&target.0);
+
+        for x in &value.histograms {
+            let metric = match x.name.as_str() {
+                "pages" => &RPC_PAGES,
+                "rpcs_in_flight" => &RPC_QUEUE_DEPTH,
+                "offset" => &RPC_OFFSET_PAGES,
+                _ => continue,
+            };
+
+            let out = self.families.entry(metric.name).or_insert_with(|| {
+                format!(
+                    "# HELP {0} {1}\n# TYPE {0} histogram\n",
+                    metric.name, metric.help
+                )
+            });
+
+            for (operation, count) in [
+                ("read", (|b| b.read) as fn(&BrwStatsBucket) -> u64),
+                ("write", |b| b.write),
+            ] {
+                let name = metric.name;
+                let labels =
+                    format!(r#"component="{kind}",target="{target}",operation="{operation}""#);
+
+                let mut cumulative = 0;
+
+                for b in &x.buckets {
+                    cumulative += count(b);
+
+                    _ = writeln!(
+                        out,
+                        "{name}_bucket{{{labels},le=\"{}\"}} {cumulative}",
+                        b.name
+                    );
+                }
+
+                _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {cumulative}");
+                _ = writeln!(out, "{name}_count{{{labels}}} {cumulative}");
+            }
+        }
+    }
+
+    /// The rendered families, sorted by name.
+    pub fn into_families(self) -> impl Iterator<Item = String> {
+        self.families.into_values()
+    }
+}
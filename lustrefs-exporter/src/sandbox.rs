@@ -0,0 +1,382 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Narrows down what the exporter and the commands it runs may do, as it runs
+//! as root on storage servers.
+//!
+//! A seccomp filter covers the exporter and everything it starts. It is a
+//! denylist, not an allowlist: it only refuses the syscalls in
+//! [`DENIED_SYSCALLS`], which a collector never makes, namely
+//!
+//! - loading and unloading kernel modules and kernels: `init_module`,
+//!   `finit_module`, `delete_module`, `kexec_load`
+//! - mounting, swapping and changing roots or namespaces: `mount`, `umount2`,
+//!   `pivot_root`, `chroot`, `setns`, `unshare`, `swapon`, `swapoff`
+//! - tracing or writing to other processes: `ptrace`, `process_vm_writev`,
+//!   `perf_event_open`, `bpf`, `userfaultfd`
+//! - the kernel keyring: `add_key`, `request_key`, `keyctl`
+//! - setting the clock, names and accounting of the host, and rebooting it:
+//!   `clock_settime`, `settimeofday`, `sethostname`, `setdomainname`, `acct`,
+//!   `reboot`
+//!
+//! along with every x32 syscall. Everything else, including opening, reading
+//! and writing files, stays allowed, as lctl, lnetctl and zpool inherit the
+//! filter and make far more syscalls than the exporter does.
+//!
+//! Landlock then stops the exporter from writing to the filesystem, except
+//! under `/dev` and the dirs it was told to write to, and the commands it runs
+//! from writing anywhere but under `/dev`, where lctl, lnetctl and zpool talk
+//! to the kernel. Reading is left alone.
+//!
+//! Only built with the `sandbox` feature, which is on by default and can be
+//! turned off to build for targets seccompiler does not support.
+
+use crate::Error;
+use clap::ValueEnum;
+use landlock::{
+    AccessFs, CompatLevel, Compatible, PathBeneath, PathFd, Ruleset, RulesetAttr, RulesetCreated,
+    RulesetCreatedAttr, ABI,
+};
+use seccompiler::{sock_filter, BpfProgram, SeccompAction, SeccompFilter, TargetArch};
+use serde::{Deserialize, Serialize};
+use std::{
+    env::consts::ARCH, io, os::unix::process::CommandExt, path::Path, process::Command,
+    sync::OnceLock,
+};
+
+/// How much the exporter is sandboxed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sandbox {
+    /// Deny a fixed list of syscalls a collector never makes, like mounting or
+    /// loading modules, and stop the exporter writing outside /dev and its state,
+    /// sink and socket dirs, and commands writing outside /dev. The exporter
+    /// does not start if the kernel cannot enforce it
+    #[value(alias = "strict")]
+    #[serde(alias = "strict")]
+    Denylist,
+    /// Only log the syscalls denylist denies to the audit log. Landlock has no
+    /// such mode, so writes are not restricted
+    Permissive,
+    /// Run unrestricted
+    #[default]
+    Off,
+}
+
+/// The syscalls a collector has no use for, listed by kind in the module docs.
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_acct,
+    libc::SYS_add_key,
+    libc::SYS_bpf,
+    libc::SYS_chroot,
+    libc::SYS_clock_settime,
+    libc::SYS_delete_module,
+    libc::SYS_finit_module,
+    libc::SYS_init_module,
+    libc::SYS_kexec_load,
+    libc::SYS_keyctl,
+    libc::SYS_mount,
+    libc::SYS_perf_event_open,
+    libc::SYS_pivot_root,
+    libc::SYS_process_vm_writev,
+    libc::SYS_ptrace,
+    libc::SYS_reboot,
+    libc::SYS_request_key,
+    libc::SYS_setdomainname,
+    libc::SYS_sethostname,
+    libc::SYS_setns,
+    libc::SYS_settimeofday,
+    libc::SYS_swapoff,
+    libc::SYS_swapon,
+    libc::SYS_umount2,
+    libc::SYS_unshare,
+    libc::SYS_userfaultfd,
+];
+
+/// x32 syscalls share the arch of x86_64, with this bit set in their number.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// The Landlock ABI the ruleset is written against. Its write access covers
+/// writing to files and making or removing anything in a directory, which is
+/// all a command needs denied to leave the filesystem untouched.
+const LANDLOCK_ABI: ABI = ABI::V1;
+
+/// A seccomp filter answering the syscalls in [`DENIED_SYSCALLS`] with `action`.
+///
+/// seccompiler kills the process on a syscall made for another arch, but does
+/// not look at x32 syscalls, which would get past it under numbers of their
+/// own, so on x86_64 those are answered with `action` ahead of its filter.
+fn seccomp_filter(arch: TargetArch, action: SeccompAction) -> Result<BpfProgram, String> {
+    let rules = DENIED_SYSCALLS.iter().map(|x| (*x, vec![])).collect();
+
+    let filter = SeccompFilter::new(rules, SeccompAction::Allow, action.clone(), arch)
+        .map_err(|e| e.to_string())?;
+
+    let filter = BpfProgram::try_from(filter).map_err(|e| e.to_string())?;
+
+    if arch != TargetArch::x86_64 {
+        return Ok(filter);
+    }
+
+    let stmt = |code: u32, k| sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+
+    // `nr` is the first field of `struct seccomp_data`
+    let mut x32 = vec![
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0),
+        sock_filter {
+            code: (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16,
+            jt: 0,
+            jf: 1,
+            k: X32_SYSCALL_BIT,
+        },
+        stmt(libc::BPF_RET | libc::BPF_K, action.into()),
+    ];
+
+    x32.extend(filter);
+
+    Ok(x32)
+}
+
+/// The ruleset the commands are restricted with, built once by [`Sandbox::restrict_self`].
+static COMMAND_RULESET: OnceLock<RulesetCreated> = OnceLock::new();
+
+/// A Landlock ruleset denying writes to the filesystem, except to files under
+/// `/dev` and anything under `writable`, which fails on kernels without
+/// Landlock if `level` is a hard requirement.
+fn landlock_ruleset(level: CompatLevel, writable: &[&Path]) -> Result<RulesetCreated, String> {
+    let fd = |path: &Path| PathFd::new(path).map_err(|e| format!("{}: {e}", path.display()));
+
+    let mut rules = vec![PathBeneath::new(
+        fd(Path::new("/dev"))?,
+        AccessFs::WriteFile,
+    )];
+
+    for &path in writable {
+        rules.push(PathBeneath::new(
+            fd(path)?,
+            AccessFs::from_write(LANDLOCK_ABI),
+        ));
+    }
+
+    Ruleset::default()
+        .set_compatibility(level)
+        .handle_access(AccessFs::from_write(LANDLOCK_ABI))
+        .and_then(|x| x.create())
+        .and_then(|x| x.add_rules(rules.into_iter().map(Ok::<_, landlock::RulesetError>)))
+        .map_err(|e| e.to_string())
+}
+
+/// Builds the ruleset of [`Sandbox::apply`], once.
+fn init_command_ruleset() -> Result<(), String> {
+    if COMMAND_RULESET.get().is_none() {
+        let _ = COMMAND_RULESET.set(landlock_ruleset(CompatLevel::HardRequirement, &[])?);
+    }
+
+    Ok(())
+}
+
+impl Sandbox {
+    /// Installs the seccomp filter on the exporter, which the commands it starts
+    /// inherit, and with [`Sandbox::Denylist`] stops the exporter writing outside
+    /// `/dev` and `writable`, and builds the ruleset of the commands.
+    ///
+    /// Landlock only restricts the thread calling this and the threads it starts
+    /// afterwards, so this is called before the runtime starts its workers.
+    /// Only writes are restricted, so the startup files are still read after it.
+    pub fn restrict_self(self, writable: &[&Path]) -> Result<(), Error> {
+        let action = match self {
+            Self::Denylist => SeccompAction::Errno(libc::EPERM as u32),
+            Self::Permissive => SeccompAction::Log,
+            Self::Off => return Ok(()),
+        };
+
+        let unsupported = |e: String| match self {
+            Self::Denylist => Err(Error::Config(format!("Cannot sandbox with denylist: {e}"))),
+            _ => {
+                tracing::warn!("Sandboxing without {e}");
+
+                Ok(())
+            }
+        };
+
+        if self == Self::Denylist {
+            landlock_ruleset(CompatLevel::HardRequirement, writable)
+                .and_then(|x| x.restrict_self().map_err(|e| e.to_string()))
+                .and_then(|_| init_command_ruleset())
+                .or_else(|e| unsupported(format!("Landlock: {e}")))?;
+        }
+
+        let Ok(arch) = TargetArch::try_from(ARCH) else {
+            return unsupported("a seccomp filter on this architecture".to_string());
+        };
+
+        let filter = seccomp_filter(arch, action).map_err(Error::Config)?;
+
+        seccompiler::apply_filter_all_threads(&filter)
+            .or_else(|e| unsupported(format!("a seccomp filter: {e}")))
+    }
+
+    /// Stops `cmd` from writing to the filesystem outside `/dev`, with [`Sandbox::Denylist`].
+    pub fn apply(self, cmd: &mut Command) {
+        if self != Self::Denylist {
+            return;
+        }
+
+        let Some(ruleset) = COMMAND_RULESET.get() else {
+            return;
+        };
+
+        // The child restricts itself with its own copy of the ruleset fd
+        let mut ruleset = match ruleset.try_clone() {
+            Ok(x) => Some(x),
+            Err(e) => {
+                tracing::warn!("Running {:?} without Landlock: {e}", cmd.get_program());
+
+                return;
+            }
+        };
+
+        // Only the forked child runs this, once, and it only makes syscalls
+        let restrict = move || match ruleset.take() {
+            Some(x) => x
+                .restrict_self()
+                .map(|_| ())
+                .map_err(|_| io::ErrorKind::PermissionDenied.into()),
+            None => Ok(()),
+        };
+
+        // SAFETY: `restrict` only makes syscalls, which is safe between fork and exec
+        unsafe {
+            cmd.pre_exec(restrict);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn commands_only_write_to_dev() {
+        if init_command_ruleset().is_err() {
+            return;
+        }
+
+        let path =
+            env::temp_dir().join(format!("lustrefs-exporter-sandbox-{}", std::process::id()));
+
+        let write = |sandbox: Sandbox| {
+            let mut cmd = Command::new("sh");
+
+            cmd.arg("-c")
+                .arg(format!("echo x > /dev/null && echo x > {}", path.display()));
+
+            sandbox.apply(&mut cmd);
+
+            cmd.status().unwrap().success()
+        };
+
+        assert!(!write(Sandbox::Denylist));
+        assert!(!path.exists());
+
+        assert!(write(Sandbox::Permissive));
+        assert!(path.exists());
+
+        fs::remove_file(&path).unwrap();
+
+        assert!(write(Sandbox::Off));
+        assert!(path.exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn exporter_only_writes_to_dev_and_writable() {
+        let dir = env::temp_dir().join(format!(
+            "lustrefs-exporter-sandbox-dir-{}",
+            std::process::id()
+        ));
+        let outside = dir.with_extension("outside");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let Ok(ruleset) = landlock_ruleset(CompatLevel::HardRequirement, &[&dir]) else {
+            fs::remove_dir(&dir).unwrap();
+
+            return;
+        };
+
+        // Landlock only restricts the thread calling it
+        let written = std::thread::spawn({
+            let dir = dir.clone();
+            let outside = outside.clone();
+
+            move || {
+                ruleset.restrict_self().unwrap();
+
+                (
+                    fs::write(dir.join("state.json"), "{}").is_ok(),
+                    fs::write("/dev/null", "x").is_ok(),
+                    fs::write(&outside, "x").is_ok(),
+                )
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(written, (true, true, false));
+        assert!(!outside.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn strict_is_still_accepted() {
+        assert_eq!(Sandbox::from_str("strict", true), Ok(Sandbox::Denylist));
+        assert_eq!(
+            serde_json::from_str::<Sandbox>(r#""strict""#).unwrap(),
+            Sandbox::Denylist
+        );
+    }
+
+    #[test]
+    fn seccomp_filter_denies_x32_syscalls_first() {
+        let filter = seccomp_filter(TargetArch::x86_64, SeccompAction::Log).unwrap();
+
+        assert_eq!(filter[1].k, X32_SYSCALL_BIT);
+        assert_eq!(filter[2].k, libc::SECCOMP_RET_LOG);
+
+        // Then the arch check seccompiler starts its filter with
+        assert_eq!(filter[4].k, 0xc000_003e);
+        assert_eq!(filter.last().unwrap().k, libc::SECCOMP_RET_ALLOW);
+
+        let filter = seccomp_filter(TargetArch::aarch64, SeccompAction::Log).unwrap();
+
+        assert_eq!(filter[1].k, 0xc000_00b7);
+    }
+
+    #[test]
+    fn seccomp_filter_denies_every_syscall() {
+        let filter = seccomp_filter(TargetArch::aarch64, SeccompAction::Log).unwrap();
+
+        for x in DENIED_SYSCALLS {
+            let i = filter
+                .iter()
+                .position(|y| {
+                    y.code == (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16
+                        && y.k == *x as u32
+                })
+                .unwrap();
+
+            // A match jumps over the jump past the action
+            assert_eq!(filter[i + 3].k, libc::SECCOMP_RET_LOG);
+        }
+    }
+}
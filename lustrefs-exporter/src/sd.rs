@@ -0,0 +1,103 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Prometheus HTTP service discovery of the exporter, labelled with the
+//! Lustre roles of the host, so a central Prometheus can tell OSSs, MDSs,
+//! MGSs and clients apart without an inventory of its own.
+//!
+//! The labels are `__meta_lustre_ost`, `__meta_lustre_mdt`, `__meta_lustre_mgs`
+//! and `__meta_lustre_client`, each `true` or `false`, and `__meta_lustre_roles`
+//! listing the roles between commas, like `,mdt,mgs,`, for matching in relabel rules.
+
+use crate::collectors::Collector;
+use lustre_collector::Record;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The roles a host can have, with the collector their records belong to.
+const ROLES: [(&str, Collector); 4] = [
+    ("ost", Collector::Ost),
+    ("mdt", Collector::Mdt),
+    ("mgs", Collector::Mgs),
+    ("client", Collector::Llite),
+];
+
+/// The Lustre roles of a host, as found in the records of a scrape.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Roles(BTreeSet<Collector>);
+
+impl Roles {
+    pub fn of(records: &[Record]) -> Self {
+        Self(records.iter().filter_map(Collector::for_record).collect())
+    }
+}
+
+/// Reads the hostname of the node once, for the target of service discovery
+/// requests without a `Host` header.
+pub fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|x| x.trim().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
+
+/// A target group of the HTTP service discovery response.
+#[derive(Debug, Serialize)]
+pub struct TargetGroup {
+    pub targets: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// The target group of the exporter at `target`, labelled with the `roles` of its host.
+pub fn target_group(target: String, roles: &Roles) -> TargetGroup {
+    let Roles(found) = roles;
+
+    let mut labels: BTreeMap<_, _> = ROLES
+        .iter()
+        .map(|(role, x)| {
+            (
+                format!("__meta_lustre_{role}"),
+                found.contains(x).to_string(),
+            )
+        })
+        .collect();
+
+    let roles: String = ROLES
+        .iter()
+        .filter(|(_, x)| found.contains(x))
+        .map(|(role, _)| format!("{role},"))
+        .collect();
+
+    labels.insert("__meta_lustre_roles".to_string(), format!(",{roles}"));
+
+    TargetGroup {
+        targets: vec![target],
+        labels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use insta::assert_snapshot;
+
+    #[test]
+    fn test_target_group() {
+        let records = lustre_collector::parse_lctl_output(include_bytes!(
+            "../../lustre-collector/src/fixtures/valid/valid.txt"
+        ))
+        .unwrap();
+
+        let x = target_group("node1:32221".to_string(), &Roles::of(&records));
+
+        assert_snapshot!(serde_json::to_string_pretty(&x).unwrap());
+    }
+
+    #[test]
+    fn test_no_roles() {
+        let x = target_group("node:32221".to_string(), &Roles::default());
+
+        assert_eq!(x.labels["__meta_lustre_roles"], ",");
+        assert_eq!(x.labels["__meta_lustre_ost"], "false");
+    }
+}
@@ -15,6 +15,15 @@ static LDLM_CBD_STATS_SAMPLES: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (
+        &LDLM_CANCELD_STATS_SAMPLES,
+        "ldlm.services.ldlm_canceld.stats",
+    ),
+    (&LDLM_CBD_STATS_SAMPLES, "ldlm.services.ldlm_cbd.stats"),
+];
+
 pub fn build_service_stats(
     x: LustreServiceStats,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
@@ -0,0 +1,585 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Writes scrapes as newline delimited JSON, one object per sample, for sites
+//! that ship metrics through log pipelines like Vector or Fluent Bit, or pushes
+//! them to the `/api/v1/import` endpoint of VictoriaMetrics, for sites that run
+//! VictoriaMetrics clusters rather than Prometheus.
+
+use crate::{
+    routes::{app, scrape_once, AppState},
+    Error,
+};
+use axum::body::Bytes;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::Instant;
+
+/// Wait before the first retry of a failed write, doubled on each failure after it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How long a push to VictoriaMetrics may take before it is retried.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct Sample<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) labels: BTreeMap<&'a str, String>,
+    pub(crate) value: f64,
+    /// Milliseconds since the epoch
+    pub(crate) timestamp: u64,
+}
+
+/// Unescapes a label value, the inverse of the `\\`, `\"` and `\n` escapes of the text format.
+fn unescape(x: &str) -> String {
+    let mut out = String::with_capacity(x.len());
+    let mut chars = x.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// Splits `{a="1",b="2"}` style labels, returning them and the rest of the line.
+fn parse_labels(x: &str) -> Option<(BTreeMap<&str, String>, &str)> {
+    let mut labels = BTreeMap::new();
+    let mut rest = x.strip_prefix('{')?;
+
+    loop {
+        rest = rest.trim_start_matches(',');
+
+        if let Some(x) = rest.strip_prefix('}') {
+            return Some((labels, x));
+        }
+
+        let (name, x) = rest.split_once("=\"")?;
+
+        // Find the closing quote, skipping escaped ones
+        let mut chars = x.char_indices();
+
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i,
+                (_, '\\') => {
+                    chars.next();
+                }
+                _ => {}
+            }
+        };
+
+        labels.insert(name, unescape(&x[..end]));
+
+        rest = &x[end + 1..];
+    }
+}
+
+/// Parses a sample line of the Prometheus text format.
+pub(crate) fn parse_sample(line: &str, timestamp: u64) -> Option<Sample<'_>> {
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let end = line.find(['{', ' '])?;
+    let (name, rest) = line.split_at(end);
+
+    let (labels, rest) = if rest.starts_with('{') {
+        parse_labels(rest)?
+    } else {
+        (BTreeMap::new(), rest)
+    };
+
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+
+    Some(Sample {
+        name,
+        labels,
+        value,
+        timestamp,
+    })
+}
+
+/// Appends samples to a file, rotating it once it grows past `max_bytes`.
+#[derive(Debug)]
+pub struct NdjsonSink {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    /// Rotated files to keep, as `<path>.1` up to `<path>.<keep>`
+    pub keep: usize,
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut x = path.as_os_str().to_owned();
+    x.push(format!(".{n}"));
+
+    x.into()
+}
+
+impl NdjsonSink {
+    fn rotate(&self) -> io::Result<()> {
+        let len = match fs::metadata(&self.path) {
+            Ok(x) => x.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if len < self.max_bytes {
+            return Ok(());
+        }
+
+        if self.keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        for n in (1..self.keep).rev() {
+            match fs::rename(rotated(&self.path, n), rotated(&self.path, n + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+
+        fs::rename(&self.path, rotated(&self.path, 1))
+    }
+
+    /// Writes each sample of the rendered `stats` as a line of JSON.
+    pub fn write(&self, stats: &str, timestamp: u64) -> Result<(), Error> {
+        self.rotate()?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        let mut w = BufWriter::new(file);
+
+        for sample in stats.lines().filter_map(|x| parse_sample(x, timestamp)) {
+            serde_json::to_writer(&mut w, &sample)?;
+            w.write_all(b"\n")?;
+        }
+
+        w.flush()?;
+
+        Ok(())
+    }
+}
+
+/// A series of the VictoriaMetrics JSON line import format.
+#[derive(Debug, Serialize)]
+struct ImportLine<'a> {
+    metric: BTreeMap<&'a str, String>,
+    values: [f64; 1],
+    timestamps: [u64; 1],
+}
+
+/// Renders the samples of `stats` in the VictoriaMetrics JSON line import format.
+fn import_lines(stats: &str, timestamp: u64) -> Result<Vec<u8>, Error> {
+    let mut body = vec![];
+
+    for sample in stats.lines().filter_map(|x| parse_sample(x, timestamp)) {
+        // JSON has no NaN or infinities to import them as
+        if !sample.value.is_finite() {
+            continue;
+        }
+
+        let mut metric = sample.labels;
+        metric.insert("__name__", sample.name.to_string());
+
+        serde_json::to_writer(
+            &mut body,
+            &ImportLine {
+                metric,
+                values: [sample.value],
+                timestamps: [sample.timestamp],
+            },
+        )?;
+        body.push(b'\n');
+    }
+
+    Ok(body)
+}
+
+/// Parses the url of a server metrics are pushed to, which must be `http` or `https`.
+pub fn parse_push_url(x: &str) -> Result<String, String> {
+    match x.split_once("://") {
+        Some(("http" | "https", rest)) if !rest.is_empty() => Ok(x.to_string()),
+        Some((scheme, _)) => Err(format!(
+            "{x}: unsupported scheme {scheme}, expected http or https"
+        )),
+        None => Err(format!("{x}: missing scheme, expected http:// or https://")),
+    }
+}
+
+/// Posts samples to VictoriaMetrics.
+#[derive(Debug)]
+pub struct VictoriaMetricsSink {
+    /// The base url of the import API, like `http://vm:8428` for a single node,
+    /// or `https://vminsert:8480/insert/0/prometheus` for a cluster
+    pub url: String,
+}
+
+impl VictoriaMetricsSink {
+    fn import_url(&self) -> String {
+        format!("{}/api/v1/import", self.url.trim_end_matches('/'))
+    }
+
+    /// Posts the samples of the rendered `stats` to `/api/v1/import`.
+    pub fn write(&self, stats: &str, timestamp: u64) -> Result<(), Error> {
+        let body = import_lines(stats, timestamp)?;
+
+        ureq::post(&self.import_url())
+            .timeout(PUSH_TIMEOUT)
+            .set("Content-Type", "application/x-ndjson")
+            .send_bytes(&body)
+            .map_err(Box::new)?;
+
+        Ok(())
+    }
+}
+
+/// Where a sink writes its scrapes.
+#[derive(Debug)]
+pub enum Target {
+    Ndjson(NdjsonSink),
+    VictoriaMetrics(VictoriaMetricsSink),
+}
+
+impl Target {
+    /// The name of the sink in the exporter metrics.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Ndjson(_) => "ndjson",
+            Self::VictoriaMetrics(_) => "victoria-metrics",
+        }
+    }
+
+    fn write(&self, stats: &str, timestamp: u64) -> Result<(), Error> {
+        match self {
+            Self::Ndjson(x) => x.write(stats, timestamp),
+            Self::VictoriaMetrics(x) => x.write(stats, timestamp),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ndjson(x) => write!(f, "{}", x.path.display()),
+            Self::VictoriaMetrics(x) => write!(f, "{}", x.import_url()),
+        }
+    }
+}
+
+/// Scrapes waiting to be written, oldest first, kept across failed writes so
+/// a transient error does not lose whole intervals.
+#[derive(Debug)]
+struct RetryQueue {
+    pending: VecDeque<(Bytes, u64)>,
+    capacity: usize,
+    backoff: Duration,
+    /// When to retry after a failed write, `None` if the last write succeeded
+    retry_at: Option<Instant>,
+}
+
+impl RetryQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            pending: VecDeque::new(),
+            capacity: capacity.max(1),
+            backoff: INITIAL_BACKOFF,
+            retry_at: None,
+        }
+    }
+
+    /// Queues a scrape, dropping the oldest one if the queue is full.
+    ///
+    /// Returns whether a scrape was dropped.
+    fn push(&mut self, body: Bytes, timestamp: u64) -> bool {
+        let dropped = self.pending.len() >= self.capacity;
+
+        if dropped {
+            self.pending.pop_front();
+        }
+
+        self.pending.push_back((body, timestamp));
+
+        dropped
+    }
+
+    /// Whether the queued scrapes should be written now.
+    fn due(&self, now: Instant) -> bool {
+        match self.retry_at {
+            Some(x) => now >= x,
+            None => true,
+        }
+    }
+
+    fn failed(&mut self, now: Instant) {
+        self.retry_at = Some(now + self.backoff);
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+
+    fn succeeded(&mut self) {
+        self.retry_at = None;
+        self.backoff = INITIAL_BACKOFF;
+    }
+}
+
+/// Writes the queued scrapes in order, stopping at the first failure so it can be retried.
+async fn flush(sink: &Arc<Target>, queue: &mut RetryQueue) -> Result<(), Error> {
+    while let Some((body, timestamp)) = queue.pending.front().cloned() {
+        let writer = Arc::clone(sink);
+
+        tokio::task::spawn_blocking(move || writer.write(str::from_utf8(&body)?, timestamp))
+            .await??;
+
+        queue.pending.pop_front();
+    }
+
+    Ok(())
+}
+
+/// Scrapes `/metrics` every `interval` and writes the samples to `target`, instead of serving them.
+///
+/// Scrapes that could not be written are retried with exponential backoff,
+/// keeping up to `queue` of them before the oldest are dropped.
+pub async fn run(
+    state: Arc<AppState>,
+    target: Target,
+    interval: Duration,
+    queue: usize,
+) -> Result<(), Error> {
+    let sink = Arc::new(target);
+    let name = sink.name();
+    let user_agent = format!("lustrefs-exporter-{name}-sink");
+    let app = app(Arc::clone(&state));
+
+    let mut queue = RetryQueue::new(queue);
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        let retry_at = queue.retry_at;
+
+        tokio::select! {
+            _ = ticker.tick() => {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+
+                let Some(body) = scrape_once(&app, &user_agent).await? else {
+                    continue;
+                };
+
+                if queue.push(body, timestamp) {
+                    tracing::warn!("Sink queue is full, dropping the oldest scrape");
+
+                    state.exporter_metrics.record_sink_dropped(name);
+                }
+            }
+            _ = tokio::time::sleep_until(retry_at.unwrap_or_else(Instant::now)), if retry_at.is_some() => {}
+        }
+
+        if !queue.due(Instant::now()) {
+            continue;
+        }
+
+        match flush(&sink, &mut queue).await {
+            Ok(()) => queue.succeeded(),
+            Err(e) => {
+                queue.failed(Instant::now());
+
+                tracing::warn!(
+                    "Could not write to {sink}: {e}, retrying {} queued scrapes in {:?}",
+                    queue.pending.len(),
+                    queue
+                        .retry_at
+                        .map(|x| x - Instant::now())
+                        .unwrap_or_default()
+                );
+            }
+        }
+
+        state
+            .exporter_metrics
+            .record_sink_queued(name, queue.pending.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        import_lines, parse_push_url, parse_sample, NdjsonSink, RetryQueue, VictoriaMetricsSink,
+        INITIAL_BACKOFF,
+    };
+    use axum::{body::Bytes, routing::post, Router};
+    use std::{env, fs};
+    use tokio::{net::TcpListener, sync::mpsc, time::Instant};
+
+    #[test]
+    fn test_parse_sample() {
+        let sample = parse_sample(
+            r#"lustre_job_stats{jobid="dd.0 \"x\"",target="fs-OST0000"} 42"#,
+            1000,
+        )
+        .unwrap();
+
+        insta::assert_snapshot!(serde_json::to_string(&sample).unwrap());
+
+        assert!(parse_sample("# TYPE lustre_mem_used gauge", 1000).is_none());
+        assert_eq!(
+            parse_sample("lustre_mem_used 10", 1000).unwrap().value,
+            10.0
+        );
+    }
+
+    #[test]
+    fn test_parse_push_url() {
+        assert_eq!(
+            parse_push_url("http://vm:8428"),
+            Ok("http://vm:8428".to_string())
+        );
+        assert!(parse_push_url("https://vminsert:8480/insert/0/prometheus").is_ok());
+        assert!(parse_push_url("vm:8428").is_err());
+        assert!(parse_push_url("ftp://vm:8428").is_err());
+        assert!(parse_push_url("http://").is_err());
+    }
+
+    #[test]
+    fn test_import_lines() {
+        let body = import_lines(
+            "# TYPE lustre_job_stats counter\nlustre_job_stats{jobid=\"dd.0\",target=\"fs-OST0000\"} 42\nlustre_mem_used NaN\nlustre_mem_used_max 10\n",
+            1000,
+        )
+        .unwrap();
+
+        insta::assert_snapshot!(String::from_utf8(body).unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_victoria_metrics_write() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let app = Router::new().route(
+            "/insert/0/prometheus/api/v1/import",
+            post(move |body: String| async move { tx.send(body).unwrap() }),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let sink = VictoriaMetricsSink {
+            url: format!("http://{addr}/insert/0/prometheus/"),
+        };
+
+        tokio::task::spawn_blocking(move || sink.write("lustre_mem_used 10\n", 1000))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let body = rx.recv().await.unwrap();
+
+        let missing = VictoriaMetricsSink {
+            url: format!("http://{addr}"),
+        };
+
+        let result =
+            tokio::task::spawn_blocking(move || missing.write("lustre_mem_used 10\n", 1000))
+                .await
+                .unwrap();
+
+        server.abort();
+
+        assert_eq!(
+            body,
+            r#"{"metric":{"__name__":"lustre_mem_used"},"values":[10.0],"timestamps":[1000]}"#
+                .to_owned()
+                + "\n"
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-sink-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        let sink = NdjsonSink {
+            path: dir.join("metrics.ndjson"),
+            max_bytes: 1,
+            keep: 2,
+        };
+
+        for x in 1..=4 {
+            sink.write(&format!("lustre_mem_used {x}\n"), 1000).unwrap();
+        }
+
+        let read = |x: &str| fs::read_to_string(dir.join(x)).unwrap();
+
+        let (current, first, second) = (
+            read("metrics.ndjson"),
+            read("metrics.ndjson.1"),
+            read("metrics.ndjson.2"),
+        );
+        let third = dir.join("metrics.ndjson.3").exists();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(current.contains(r#""value":4.0"#));
+        assert!(first.contains(r#""value":3.0"#));
+        assert!(second.contains(r#""value":2.0"#));
+        assert!(!third);
+    }
+
+    #[test]
+    fn test_retry_queue() {
+        let mut queue = RetryQueue::new(2);
+
+        assert!(!queue.push(Bytes::from_static(b"a 1\n"), 1));
+        assert!(!queue.push(Bytes::from_static(b"a 2\n"), 2));
+        assert!(queue.push(Bytes::from_static(b"a 3\n"), 3));
+        assert_eq!(
+            queue.pending.iter().map(|(_, x)| *x).collect::<Vec<_>>(),
+            [2, 3]
+        );
+
+        let now = Instant::now();
+
+        assert!(queue.due(now));
+
+        queue.failed(now);
+        queue.failed(now);
+
+        assert!(!queue.due(now));
+        assert_eq!(queue.retry_at, Some(now + INITIAL_BACKOFF * 2));
+        assert!(queue.due(now + INITIAL_BACKOFF * 2));
+
+        queue.succeeded();
+
+        assert!(queue.due(now));
+        assert_eq!(queue.backoff, INITIAL_BACKOFF);
+    }
+}
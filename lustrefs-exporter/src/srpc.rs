@@ -0,0 +1,61 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use std::{collections::BTreeMap, ops::Deref};
+
+use lustre_collector::{ImportStat, SrpcInfo};
+use prometheus_exporter_base::prelude::*;
+
+use crate::{Metric, StatsMapExt};
+
+static SRPC_FLAVOR_INFO: Metric = Metric {
+    name: "lustre_srpc_flavor_info",
+    help: "Security flavor RPCs and bulk data are sent with by a client import. Always 1.",
+    r#type: MetricType::Gauge,
+};
+
+static SRPC_CONTEXTS: Metric = Metric {
+    name: "lustre_srpc_contexts",
+    help: "Number of security contexts currently held by a client import.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&SRPC_FLAVOR_INFO, "{mdc,osc,mgc}.*.srpc_info"),
+    (&SRPC_CONTEXTS, "{mdc,osc,mgc}.*.srpc_info"),
+];
+
+pub fn build_srpc_info_stats(
+    x: ImportStat<SrpcInfo>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let ImportStat {
+        kind,
+        target,
+        value,
+        ..
+    } = x;
+
+    stats_map
+        .get_mut_metric(SRPC_FLAVOR_INFO)
+        .render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("component", kind.deref())
+                .with_label("target", target.deref())
+                .with_label("flavor", value.rpc_flavor.as_str())
+                .with_label("bulk_flavor", value.bulk_flavor.as_str())
+                .with_label("flags", value.flags.trim_end_matches(','))
+                .with_value(1),
+        );
+
+    stats_map
+        .get_mut_metric(SRPC_CONTEXTS)
+        .render_and_append_instance(
+            &PrometheusInstance::new()
+                .with_label("component", kind.deref())
+                .with_label("target", target.deref())
+                .with_value(value.contexts),
+        );
+}
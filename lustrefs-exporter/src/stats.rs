@@ -1,7 +1,7 @@
-use crate::{LabelProm, Metric, StatsMapExt};
-use lustre_collector::{ExportStats, MdsStat, Stat, Target, TargetStat};
+use crate::{Metric, StatsMapExt, TargetLabels};
+use lustre_collector::{ExportStats, MdsStat, Stat, Target, TargetStat, TargetVariant};
 use prometheus_exporter_base::prelude::*;
-use std::{collections::BTreeMap, ops::Deref};
+use std::collections::BTreeMap;
 
 static READ_SAMPLES: Metric = Metric {
     name: "lustre_read_samples_total",
@@ -50,99 +50,39 @@ pub fn build_ost_stats(
     target: Target,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
 ) {
-    let kind = lustre_collector::TargetVariant::Ost;
+    let labels = TargetLabels::new(TargetVariant::Ost, &target);
+
     for s in x {
-        match s.name.as_str() {
-            "read_bytes" => {
-                stats_map
-                    .get_mut_metric(READ_SAMPLES)
-                    .render_and_append_instance(
-                        &PrometheusInstance::new()
-                            .with_label("component", kind.to_prom_label())
-                            .with_label("operation", "read")
-                            .with_label("target", target.deref())
-                            .with_value(s.samples),
-                    );
-                s.min.map(|v| {
-                    stats_map
-                        .get_mut_metric(READ_MIN_SIZE_BYTES)
-                        .render_and_append_instance(
-                            &PrometheusInstance::new()
-                                .with_label("component", kind.to_prom_label())
-                                .with_label("operation", "read")
-                                .with_label("target", target.deref())
-                                .with_value(v),
-                        )
-                });
-                s.max.map(|v| {
-                    stats_map
-                        .get_mut_metric(READ_MAX_SIZE_BYTES)
-                        .render_and_append_instance(
-                            &PrometheusInstance::new()
-                                .with_label("component", kind.to_prom_label())
-                                .with_label("operation", "read")
-                                .with_label("target", target.deref())
-                                .with_value(v),
-                        )
-                });
-                s.sum.map(|v| {
-                    stats_map
-                        .get_mut_metric(READ_BYTES)
-                        .render_and_append_instance(
-                            &PrometheusInstance::new()
-                                .with_label("component", kind.to_prom_label())
-                                .with_label("operation", "read")
-                                .with_label("target", target.deref())
-                                .with_value(v),
-                        )
-                });
-            }
-            "write_bytes" => {
-                stats_map
-                    .get_mut_metric(WRITE_SAMPLES)
-                    .render_and_append_instance(
-                        &PrometheusInstance::new()
-                            .with_label("component", kind.to_prom_label())
-                            .with_label("operation", "write")
-                            .with_label("target", target.deref())
-                            .with_value(s.samples),
-                    );
-                s.min.map(|v| {
-                    stats_map
-                        .get_mut_metric(WRITE_MIN_SIZE_BYTES)
-                        .render_and_append_instance(
-                            &PrometheusInstance::new()
-                                .with_label("component", kind.to_prom_label())
-                                .with_label("operation", "write")
-                                .with_label("target", target.deref())
-                                .with_value(v),
-                        )
-                });
-                s.max.map(|v| {
-                    stats_map
-                        .get_mut_metric(WRITE_MAX_SIZE_BYTES)
-                        .render_and_append_instance(
-                            &PrometheusInstance::new()
-                                .with_label("component", kind.to_prom_label())
-                                .with_label("operation", "write")
-                                .with_label("target", target.deref())
-                                .with_value(v),
-                        )
-                });
-                s.sum.map(|v| {
-                    stats_map
-                        .get_mut_metric(WRITE_BYTES)
-                        .render_and_append_instance(
-                            &PrometheusInstance::new()
-                                .with_label("component", kind.to_prom_label())
-                                .with_label("operation", "write")
-                                .with_label("target", target.deref())
-                                .with_value(v),
-                        )
-                });
-            }
+        let (operation, samples, min, max, sum) = match s.name.as_str() {
+            "read_bytes" => (
+                "read",
+                READ_SAMPLES,
+                READ_MIN_SIZE_BYTES,
+                READ_MAX_SIZE_BYTES,
+                READ_BYTES,
+            ),
+            "write_bytes" => (
+                "write",
+                WRITE_SAMPLES,
+                WRITE_MIN_SIZE_BYTES,
+                WRITE_MAX_SIZE_BYTES,
+                WRITE_BYTES,
+            ),
             _x => {
                 // Ignore
+                continue;
+            }
+        };
+
+        stats_map
+            .get_mut_metric(samples)
+            .render_and_append_instance(&labels.operation_inst(operation, s.samples));
+
+        for (metric, value) in [(min, s.min), (max, s.max), (sum, s.sum)] {
+            if let Some(v) = value {
+                stats_map
+                    .get_mut_metric(metric)
+                    .render_and_append_instance(&labels.operation_inst(operation, v));
             }
         }
     }
@@ -159,17 +99,12 @@ pub fn build_mdt_stats(
     target: Target,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
 ) {
-    let kind = lustre_collector::TargetVariant::Mdt;
+    let labels = TargetLabels::new(TargetVariant::Mdt, &target);
+
     for s in x {
         stats_map
             .get_mut_metric(MDT_STATS_SAMPLES)
-            .render_and_append_instance(
-                &PrometheusInstance::new()
-                    .with_label("component", kind.to_prom_label())
-                    .with_label("operation", s.name.deref())
-                    .with_label("target", target.deref())
-                    .with_value(s.samples),
-            );
+            .render_and_append_instance(&labels.operation_inst(&s.name, s.samples));
     }
 }
 
@@ -185,12 +120,38 @@ pub fn build_stats(
     } = x;
 
     match kind {
-        lustre_collector::TargetVariant::Ost => build_ost_stats(value, target, stats_map),
-        lustre_collector::TargetVariant::Mgt => { /*TODO*/ }
-        lustre_collector::TargetVariant::Mdt => build_mdt_stats(value, target, stats_map),
+        TargetVariant::Ost => build_ost_stats(value, target, stats_map),
+        TargetVariant::Mgt => { /*TODO*/ }
+        TargetVariant::Mdt => build_mdt_stats(value, target, stats_map),
     }
 }
 
+static SNAPSHOT_TIMESTAMP: Metric = Metric {
+    name: "lustre_stats_snapshot_timestamp_seconds",
+    help: "When the target last took a snapshot of the stats param, in seconds since the epoch. Stops advancing if the target stops updating its stats.",
+    r#type: MetricType::Gauge,
+};
+
+pub fn build_snapshot_time_stats(
+    x: TargetStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let TargetStat {
+        kind,
+        target,
+        param,
+        value,
+    } = x;
+
+    stats_map
+        .get_mut_metric(SNAPSHOT_TIMESTAMP)
+        .render_and_append_instance(
+            &TargetLabels::new(kind, &target)
+                .inst(value as f64 / 1_000.0)
+                .with_label("param", param.0.as_str()),
+        );
+}
+
 static MDS_STATS: Metric = Metric {
     name: "lustre_mds_mdt_stats",
     help: "MDS mdt stats",
@@ -280,6 +241,29 @@ static EXPORT_STATS: Metric = Metric {
     r#type: MetricType::Counter,
 };
 
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&READ_SAMPLES, "obdfilter.*OST*.stats"),
+    (&READ_MIN_SIZE_BYTES, "obdfilter.*OST*.stats"),
+    (&READ_MAX_SIZE_BYTES, "obdfilter.*OST*.stats"),
+    (&READ_BYTES, "obdfilter.*OST*.stats"),
+    (&WRITE_SAMPLES, "obdfilter.*OST*.stats"),
+    (&WRITE_MIN_SIZE_BYTES, "obdfilter.*OST*.stats"),
+    (&WRITE_MAX_SIZE_BYTES, "obdfilter.*OST*.stats"),
+    (&WRITE_BYTES, "obdfilter.*OST*.stats"),
+    (&MDT_STATS_SAMPLES, "mdt.*.md_stats"),
+    (&SNAPSHOT_TIMESTAMP, "obdfilter.*OST*.stats, mdt.*.md_stats"),
+    (&MDS_STATS, "mds.MDS.mdt.stats"),
+    (&MDS_FLD_STATS, "mds.MDS.mdt_fld.stats"),
+    (&MDS_IO_STATS, "mds.MDS.mdt_io.stats"),
+    (&MDS_OUT_STATS, "mds.MDS.mdt_out.stats"),
+    (&MDS_READPAGE_STATS, "mds.MDS.mdt_readpage.stats"),
+    (&MDS_SEQM_STATS, "mds.MDS.mdt_seqm.stats"),
+    (&MDS_SEQS_STATS, "mds.MDS.mdt_seqs.stats"),
+    (&MDS_SETATTR_STATS, "mds.MDS.mdt_setattr.stats"),
+    (&EXPORT_STATS, "*.*.exports.*.stats"),
+];
+
 pub fn build_export_stats(
     x: TargetStat<Vec<ExportStats>>,
     stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
@@ -291,6 +275,8 @@ pub fn build_export_stats(
         target,
     } = x;
 
+    let labels = TargetLabels::new(kind, &target);
+
     for e in export_stats {
         let ExportStats { nid, stats } = e;
         for s in stats {
@@ -305,13 +291,11 @@ pub fn build_export_stats(
                 _ => continue,
             };
 
-            let stat = PrometheusInstance::new()
-                .with_label("component", kind.to_prom_label())
-                .with_label("target", target.deref())
+            let stat = labels
+                .inst(samples)
                 .with_label("nid", nid.as_str())
                 .with_label("name", name.as_str())
-                .with_label("units", units.as_str())
-                .with_value(samples);
+                .with_label("units", units.as_str());
 
             metric.render_and_append_instance(&stat);
         }
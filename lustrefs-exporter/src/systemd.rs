@@ -0,0 +1,52 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Socket activation and readiness notifications, so the exporter can be
+//! started by systemd on the first scrape instead of idling on every node.
+//!
+//! Outside of systemd there is nothing to inherit or notify, and all of these are no-ops.
+
+use crate::Error;
+use sd_notify::NotifyState;
+use std::time::Duration;
+use tokio::{net::TcpListener, task::JoinHandle};
+
+/// Takes the listening socket passed by systemd in `LISTEN_FDS`, if any.
+pub fn listener() -> Result<Option<TcpListener>, Error> {
+    let Some(listener) = listenfd::ListenFd::from_env().take_tcp_listener(0)? else {
+        return Ok(None);
+    };
+
+    listener.set_nonblocking(true)?;
+
+    Ok(Some(TcpListener::from_std(listener)?))
+}
+
+/// Tells systemd the exporter has started, for `Type=notify` units.
+pub fn notify_ready() {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+        tracing::warn!("Could not notify systemd of readiness: {e}");
+    }
+}
+
+/// Pings the systemd watchdog at half of `WatchdogSec`, if the unit sets it.
+pub fn spawn_watchdog() -> Option<JoinHandle<()>> {
+    let mut usec = 0;
+
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return None;
+    }
+
+    let mut ticker = tokio::time::interval(Duration::from_micros(usec) / 2);
+
+    Some(tokio::spawn(async move {
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+                tracing::warn!("Could not ping the systemd watchdog: {e}");
+            }
+        }
+    }))
+}
@@ -0,0 +1,164 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{LabelProm, Metric, StatsMapExt};
+use lustre_collector::{Record, TargetStat, TargetStats, TargetVariant};
+use prometheus_exporter_base::prelude::*;
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// How many scrapes a missing target is reported as down for by default.
+pub const DEFAULT_TARGET_RETENTION: usize = 10;
+
+static TARGET_UP: Metric = Metric {
+    name: "lustre_target_up",
+    help: "Whether the target was found during this scrape. Targets seen during recent scrapes but missing from this one are 0.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[(&TARGET_UP, "exporter")];
+
+/// Remembers the targets of recent scrapes, so that a target disappearing
+/// turns its `lustre_target_up` series to 0 instead of just ending it.
+#[derive(Debug)]
+pub struct TargetRegistry {
+    /// How many scrapes a missing target is kept for
+    retention: usize,
+    /// Number of scrapes since each target was last seen, keyed by component and target
+    targets: Mutex<BTreeMap<(String, String), usize>>,
+}
+
+impl Default for TargetRegistry {
+    fn default() -> Self {
+        Self::new(DEFAULT_TARGET_RETENTION)
+    }
+}
+
+/// The kind and name of the server target `record` was read from, for the
+/// osd records every mounted OST, MDT and MGT has. Client imports are not targets.
+fn server_target(record: &Record) -> Option<(TargetVariant, &str)> {
+    match record {
+        Record::Target(
+            TargetStats::FilesFree(TargetStat { kind, target, .. })
+            | TargetStats::FilesTotal(TargetStat { kind, target, .. })
+            | TargetStats::KBytesAvail(TargetStat { kind, target, .. })
+            | TargetStats::KBytesFree(TargetStat { kind, target, .. })
+            | TargetStats::KBytesTotal(TargetStat { kind, target, .. })
+            | TargetStats::FsType(TargetStat { kind, target, .. }),
+        ) => Some((*kind, target.0.as_str())),
+        _ => None,
+    }
+}
+
+impl TargetRegistry {
+    pub fn new(retention: usize) -> Self {
+        Self {
+            retention,
+            targets: Mutex::default(),
+        }
+    }
+
+    /// Records the server targets found in the `records` of a scrape, and
+    /// forgets the ones that have been missing for more than `retention` scrapes.
+    pub fn observe(&self, records: &[Record]) {
+        let mut targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+
+        for missed in targets.values_mut() {
+            *missed += 1;
+        }
+
+        for (kind, target) in records.iter().filter_map(server_target) {
+            let component = kind.to_prom_label();
+
+            match targets.get_mut(&(component.to_string(), target.to_string())) {
+                Some(x) => *x = 0,
+                None => {
+                    targets.insert((component.to_string(), target.to_string()), 0);
+                }
+            }
+        }
+
+        targets.retain(|_, missed| *missed <= self.retention);
+    }
+
+    pub fn render(&self) -> String {
+        let mut stats_map = BTreeMap::new();
+
+        let targets = self.targets.lock().unwrap_or_else(|e| e.into_inner());
+
+        for ((component, target), missed) in targets.iter() {
+            stats_map
+                .get_mut_metric(TARGET_UP)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("component", component.as_str())
+                        .with_label("target", target.as_str())
+                        .with_value(u8::from(*missed == 0)),
+                );
+        }
+
+        stats_map
+            .values()
+            .map(|x| x.render())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TargetRegistry;
+    use lustre_collector::{parse_lctl_output, Record};
+
+    fn records(input: &str) -> Vec<Record> {
+        parse_lctl_output(input.as_bytes()).unwrap()
+    }
+
+    const BOTH: &str = "osd-ldiskfs.fs-OST0000.kbytestotal=1000
+osd-ldiskfs.fs-OST0001.kbytestotal=2000
+osd-ldiskfs.fs-MDT0000.filestotal=4096
+ldlm.namespaces.fs-OST0002-osc-ffff9239aa0c4800.lru_size=400
+";
+
+    const ONE: &str = "osd-ldiskfs.fs-OST0000.kbytestotal=1000
+osd-ldiskfs.fs-MDT0000.filestotal=4096
+";
+
+    #[test]
+    fn test_missing_targets() {
+        let registry = TargetRegistry::new(2);
+
+        assert_eq!(registry.render(), "");
+
+        registry.observe(&records(BOTH));
+        registry.observe(&records(ONE));
+
+        insta::assert_snapshot!(registry.render());
+
+        registry.observe(&records(ONE));
+
+        assert!(registry
+            .render()
+            .contains(r#"lustre_target_up{component="ost",target="fs-OST0001"} 0"#));
+
+        registry.observe(&records(ONE));
+
+        assert!(!registry.render().contains("fs-OST0001"));
+
+        registry.observe(&records(BOTH));
+
+        assert!(registry
+            .render()
+            .contains(r#"lustre_target_up{component="ost",target="fs-OST0001"} 1"#));
+    }
+
+    #[test]
+    fn test_client_imports_are_not_targets() {
+        let registry = TargetRegistry::default();
+
+        registry.observe(&records(BOTH));
+
+        assert!(!registry.render().contains("OST0002"));
+    }
+}
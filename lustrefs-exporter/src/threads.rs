@@ -0,0 +1,112 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt};
+use lustre_collector::{TargetStat, TargetStats};
+use prometheus_exporter_base::prelude::*;
+use std::collections::BTreeMap;
+
+static THREADS_MIN: Metric = Metric {
+    name: "lustre_threads_min",
+    help: "Minimum number of threads a service keeps running.",
+    r#type: MetricType::Gauge,
+};
+
+static THREADS_MAX: Metric = Metric {
+    name: "lustre_threads_max",
+    help: "Maximum number of threads a service may start.",
+    r#type: MetricType::Gauge,
+};
+
+static THREADS_STARTED: Metric = Metric {
+    name: "lustre_threads_started",
+    help: "Number of threads a service has currently started.",
+    r#type: MetricType::Gauge,
+};
+
+static THREADS_UTILIZATION: Metric = Metric {
+    name: "lustre_threads_utilization_ratio",
+    help: "Ratio of the threads a service has started to the most it may start, from 0 to 1. Services start threads as requests queue up, so a ratio near 1 means the pool is close to exhausted.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&THREADS_MIN, "{mgs,ost,mds}.*.*.threads_min"),
+    (&THREADS_MAX, "{mgs,ost,mds}.*.*.threads_max"),
+    (&THREADS_STARTED, "{mgs,ost,mds}.*.*.threads_started"),
+    (&THREADS_UTILIZATION, "{mgs,ost,mds}.*.*.threads_max"),
+];
+
+/// The thread counts of each service, which come from separate records.
+#[derive(Debug, Default)]
+pub struct ServiceThreads {
+    /// Minimum, maximum and started threads, keyed by target and service
+    services: BTreeMap<(String, String), [Option<u64>; 3]>,
+}
+
+impl ServiceThreads {
+    pub fn observe(&mut self, x: &TargetStats) {
+        let (
+            i,
+            TargetStat {
+                target,
+                param,
+                value,
+                ..
+            },
+        ) = match x {
+            TargetStats::ThreadsMin(x) => (0, x),
+            TargetStats::ThreadsMax(x) => (1, x),
+            TargetStats::ThreadsStarted(x) => (2, x),
+            _ => return,
+        };
+
+        // Services under `ost.OSS` and `mds.MDS` are named in the param, like `ost_io.threads_max`.
+        // The MGS only runs the `mgs` service.
+        let service = match param.0.split_once('.') {
+            Some((service, _)) => service.to_string(),
+            None => target.to_lowercase(),
+        };
+
+        self.services
+            .entry((target.0.clone(), service))
+            .or_default()[i] = Some(*value);
+    }
+}
+
+pub fn build_threads_stats(
+    x: ServiceThreads,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    for ((target, service), values) in x.services {
+        let inst = || {
+            PrometheusInstance::new()
+                .with_label("service", service.as_str())
+                .with_label("target", target.as_str())
+        };
+
+        for (metric, value) in [THREADS_MIN, THREADS_MAX, THREADS_STARTED]
+            .into_iter()
+            .zip(values)
+        {
+            if let Some(value) = value {
+                stats_map
+                    .get_mut_metric(metric)
+                    .render_and_append_instance(&inst().with_value(value));
+            }
+        }
+
+        if let [_, Some(max @ 1..), Some(started)] = values {
+            stats_map
+                .get_mut_metric(THREADS_UTILIZATION)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("service", service.as_str())
+                        .with_label("target", target.as_str())
+                        .with_value(started as f64 / max as f64),
+                );
+        }
+    }
+}
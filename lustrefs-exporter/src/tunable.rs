@@ -0,0 +1,87 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use std::collections::BTreeMap;
+
+use lustre_collector::TargetStat;
+use prometheus_exporter_base::prelude::*;
+
+use crate::{Metric, StatsMapExt, ToMetricInst};
+
+static BRW_SIZE_MB: Metric = Metric {
+    name: "lustre_tunable_brw_size_mb",
+    help: "Maximum bulk RPC size in megabytes the target accepts.",
+    r#type: MetricType::Gauge,
+};
+
+static MAX_DIRTY_MB: Metric = Metric {
+    name: "lustre_tunable_max_dirty_mb",
+    help: "Maximum megabytes of dirty data the target lets clients cache.",
+    r#type: MetricType::Gauge,
+};
+
+static READCACHE_MAX_FILESIZE_BYTES: Metric = Metric {
+    name: "lustre_tunable_readcache_max_filesize_bytes",
+    help: "Size in bytes of the largest files the target keeps in its read cache.",
+    r#type: MetricType::Gauge,
+};
+
+static SYNC_JOURNAL: Metric = Metric {
+    name: "lustre_tunable_sync_journal",
+    help: "Whether the target commits its journal synchronously. 0 is asynchronous journal commit.",
+    r#type: MetricType::Gauge,
+};
+
+static IDENTITY_EXPIRE_SECONDS: Metric = Metric {
+    name: "lustre_tunable_identity_expire_seconds",
+    help: "Seconds the MDT keeps a user's identity cached before looking it up again.",
+    r#type: MetricType::Gauge,
+};
+
+static IDENTITY_ACQUIRE_EXPIRE_SECONDS: Metric = Metric {
+    name: "lustre_tunable_identity_acquire_expire_seconds",
+    help: "Seconds the MDT waits for the identity upcall before failing the lookup.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&BRW_SIZE_MB, "obdfilter.*OST*.brw_size"),
+    (&MAX_DIRTY_MB, "obdfilter.*OST*.max_dirty_mb"),
+    (
+        &READCACHE_MAX_FILESIZE_BYTES,
+        "obdfilter.*OST*.readcache_max_filesize",
+    ),
+    (&SYNC_JOURNAL, "obdfilter.*OST*.sync_journal"),
+    (&IDENTITY_EXPIRE_SECONDS, "mdt.*MDT*.identity_expire"),
+    (
+        &IDENTITY_ACQUIRE_EXPIRE_SECONDS,
+        "mdt.*MDT*.identity_acquire_expire",
+    ),
+];
+
+/// Exports a performance tunable of a target, so that configuration drift
+/// across targets shows up. Tunables are named after their param.
+pub fn build_tunable_stats(
+    x: &TargetStat<u64>,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let metric = match x.param.0.as_str() {
+        "brw_size" => BRW_SIZE_MB,
+        "max_dirty_mb" => MAX_DIRTY_MB,
+        "readcache_max_filesize" => READCACHE_MAX_FILESIZE_BYTES,
+        "sync_journal" => SYNC_JOURNAL,
+        "identity_expire" => IDENTITY_EXPIRE_SECONDS,
+        "identity_acquire_expire" => IDENTITY_ACQUIRE_EXPIRE_SECONDS,
+        x => {
+            tracing::debug!("Unhandled tunable: {x}");
+
+            return;
+        }
+    };
+
+    stats_map
+        .get_mut_metric(metric)
+        .render_and_append_instance(&x.to_metric_inst());
+}
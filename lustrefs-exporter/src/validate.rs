@@ -0,0 +1,459 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Checks rendered metrics against the Prometheus text and OpenMetrics formats.
+//!
+//! `prometheus_exporter_base` renders whatever names and label values it is
+//! given, so this catches what it lets through: illegal names, broken label
+//! escaping, repeated metadata and counters without a `_total` suffix.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+};
+
+/// Suffixes a sample name may add to the name of its family.
+const SUFFIXES: [&str; 6] = ["_total", "_created", "_bucket", "_count", "_sum", "_info"];
+
+const TYPES: [&str; 8] = [
+    "counter",
+    "gauge",
+    "histogram",
+    "summary",
+    "untyped",
+    "unknown",
+    "info",
+    "stateset",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Violation {
+    InvalidMetricName(String),
+    InvalidLabelName {
+        metric: String,
+        label: String,
+    },
+    /// An unterminated value, or an escape other than `\\`, `\"` and `\n`
+    InvalidLabelValue {
+        metric: String,
+        label: String,
+    },
+    DuplicateLabel {
+        metric: String,
+        label: String,
+    },
+    InvalidValue {
+        metric: String,
+        value: String,
+    },
+    InvalidType {
+        family: String,
+        r#type: String,
+    },
+    DuplicateHelp(String),
+    DuplicateType(String),
+    /// Metadata after the first sample of its family
+    MetadataAfterSamples(String),
+    /// A family continued after another one started
+    InterleavedFamily(String),
+    DuplicateSeries(String),
+    /// A counter sample not ending in `_total`
+    CounterSuffix(String),
+    /// A line that is neither a sample nor metadata
+    InvalidLine(String),
+    /// Only reported for OpenMetrics
+    EmptyLine,
+    /// Only reported for OpenMetrics
+    MissingEof,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMetricName(x) => write!(f, "invalid metric name {x}"),
+            Self::InvalidLabelName { metric, label } => {
+                write!(f, "invalid label name {label} on {metric}")
+            }
+            Self::InvalidLabelValue { metric, label } => {
+                write!(f, "badly escaped value of label {label} on {metric}")
+            }
+            Self::DuplicateLabel { metric, label } => {
+                write!(f, "label {label} repeated on {metric}")
+            }
+            Self::InvalidValue { metric, value } => write!(f, "invalid value {value} of {metric}"),
+            Self::InvalidType { family, r#type } => write!(f, "invalid type {type} of {family}"),
+            Self::DuplicateHelp(x) => write!(f, "HELP repeated for {x}"),
+            Self::DuplicateType(x) => write!(f, "TYPE repeated for {x}"),
+            Self::MetadataAfterSamples(x) => write!(f, "metadata after the samples of {x}"),
+            Self::InterleavedFamily(x) => write!(f, "samples of {x} are not grouped together"),
+            Self::DuplicateSeries(x) => write!(f, "series {x} repeated"),
+            Self::CounterSuffix(x) => write!(f, "counter {x} does not end in _total"),
+            Self::InvalidLine(x) => write!(f, "invalid line {x}"),
+            Self::EmptyLine => write!(f, "empty line"),
+            Self::MissingEof => write!(f, "missing # EOF"),
+        }
+    }
+}
+
+fn is_metric_name(x: &str) -> bool {
+    let mut bytes = x.bytes();
+
+    matches!(bytes.next(), Some(b'a'..=b'z' | b'A'..=b'Z' | b'_' | b':'))
+        && bytes.all(|x| x.is_ascii_alphanumeric() || x == b'_' || x == b':')
+}
+
+fn is_label_name(x: &str) -> bool {
+    let mut bytes = x.bytes();
+
+    matches!(bytes.next(), Some(b'a'..=b'z' | b'A'..=b'Z' | b'_'))
+        && bytes.all(|x| x.is_ascii_alphanumeric() || x == b'_')
+}
+
+/// Splits an escaped label value off the start of `x`, which follows its opening quote.
+///
+/// Returns `None` if the value is not terminated or has an illegal escape.
+fn split_label_value(x: &str) -> Option<(&str, &str)> {
+    let mut chars = x.char_indices();
+
+    loop {
+        match chars.next()? {
+            (i, '"') => return Some((&x[..i], &x[i + 1..])),
+            (_, '\\') => match chars.next()? {
+                (_, '\\' | '"' | 'n') => {}
+                _ => return None,
+            },
+            _ => {}
+        }
+    }
+}
+
+/// The state of a family while validating.
+#[derive(Debug, Default)]
+struct Family {
+    r#type: Option<String>,
+    help: bool,
+    samples: bool,
+}
+
+#[derive(Debug, Default)]
+struct Validator {
+    families: HashMap<String, Family>,
+    /// The family of the previous line
+    current: Option<String>,
+    /// Families that another family started after
+    closed: HashSet<String>,
+    series: HashSet<String>,
+    violations: BTreeSet<Violation>,
+}
+
+impl Validator {
+    /// The family a sample named `name` belongs to.
+    fn family_of(&self, name: &str) -> String {
+        if self.families.contains_key(name) {
+            return name.to_string();
+        }
+
+        SUFFIXES
+            .iter()
+            .filter_map(|x| name.strip_suffix(x))
+            .find(|x| self.families.contains_key(*x))
+            .unwrap_or(name)
+            .to_string()
+    }
+
+    fn enter(&mut self, family: &str) {
+        if self.current.as_deref() == Some(family) {
+            return;
+        }
+
+        if let Some(x) = self.current.take() {
+            self.closed.insert(x);
+        }
+
+        if self.closed.contains(family) {
+            self.violations
+                .insert(Violation::InterleavedFamily(family.to_string()));
+        }
+
+        self.current = Some(family.to_string());
+    }
+
+    fn metadata(&mut self, line: &str) {
+        let mut parts = line.splitn(4, ' ');
+
+        let (Some(kind), Some(name)) = (parts.nth(1), parts.next()) else {
+            return;
+        };
+
+        if !matches!(kind, "HELP" | "TYPE" | "UNIT") {
+            return;
+        }
+
+        if !is_metric_name(name) {
+            self.violations
+                .insert(Violation::InvalidMetricName(name.to_string()));
+        }
+
+        self.enter(name);
+
+        let family = self.families.entry(name.to_string()).or_default();
+
+        if family.samples {
+            self.violations
+                .insert(Violation::MetadataAfterSamples(name.to_string()));
+        }
+
+        match kind {
+            "HELP" if family.help => {
+                self.violations
+                    .insert(Violation::DuplicateHelp(name.to_string()));
+            }
+            "HELP" => family.help = true,
+            "TYPE" if family.r#type.is_some() => {
+                self.violations
+                    .insert(Violation::DuplicateType(name.to_string()));
+            }
+            "TYPE" => {
+                let r#type = parts.next().unwrap_or_default().trim();
+
+                if !TYPES.contains(&r#type) {
+                    self.violations.insert(Violation::InvalidType {
+                        family: name.to_string(),
+                        r#type: r#type.to_string(),
+                    });
+                }
+
+                family.r#type = Some(r#type.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn sample(&mut self, line: &str, openmetrics: bool) {
+        // Exemplars are only part of OpenMetrics, and are not validated here
+        let line = match line.split_once(" # ") {
+            Some((x, _)) if openmetrics => x,
+            _ => line,
+        };
+
+        let end = line.find(['{', ' ']).unwrap_or(line.len());
+        let (name, mut rest) = line.split_at(end);
+
+        if !is_metric_name(name) {
+            self.violations
+                .insert(Violation::InvalidMetricName(name.to_string()));
+
+            return;
+        }
+
+        let mut labels = BTreeSet::new();
+
+        if let Some(x) = rest.strip_prefix('{') {
+            rest = x;
+
+            loop {
+                rest = rest.trim_start_matches(',');
+
+                if let Some(x) = rest.strip_prefix('}') {
+                    rest = x;
+
+                    break;
+                }
+
+                let Some((label, x)) = rest.split_once("=\"") else {
+                    self.violations
+                        .insert(Violation::InvalidLine(line.to_string()));
+
+                    return;
+                };
+
+                if !is_label_name(label) || label.starts_with("__") {
+                    self.violations.insert(Violation::InvalidLabelName {
+                        metric: name.to_string(),
+                        label: label.to_string(),
+                    });
+                }
+
+                if !labels.insert(label) {
+                    self.violations.insert(Violation::DuplicateLabel {
+                        metric: name.to_string(),
+                        label: label.to_string(),
+                    });
+                }
+
+                let Some((_, x)) = split_label_value(x) else {
+                    self.violations.insert(Violation::InvalidLabelValue {
+                        metric: name.to_string(),
+                        label: label.to_string(),
+                    });
+
+                    return;
+                };
+
+                rest = x;
+            }
+        }
+
+        let series = &line[..line.len() - rest.len()];
+
+        let mut fields = rest.split_whitespace();
+
+        match fields.next() {
+            Some(x) if x.parse::<f64>().is_ok() => {}
+            x => {
+                self.violations.insert(Violation::InvalidValue {
+                    metric: name.to_string(),
+                    value: x.unwrap_or_default().to_string(),
+                });
+            }
+        }
+
+        if !self.series.insert(series.to_string()) {
+            self.violations
+                .insert(Violation::DuplicateSeries(series.to_string()));
+        }
+
+        let family = self.family_of(name);
+
+        self.enter(&family);
+
+        let x = self.families.entry(family.clone()).or_default();
+
+        x.samples = true;
+
+        if x.r#type.as_deref() == Some("counter")
+            && !name.ends_with("_total")
+            && !name.ends_with("_created")
+        {
+            self.violations.insert(Violation::CounterSuffix(family));
+        }
+    }
+}
+
+/// Validates `text` as the Prometheus text format, or as OpenMetrics with `openmetrics`.
+///
+/// Each violation is reported once, however often it occurs.
+pub fn validate(text: &str, openmetrics: bool) -> BTreeSet<Violation> {
+    let mut validator = Validator::default();
+    let mut eof = false;
+
+    for line in text.lines() {
+        if line.is_empty() {
+            if openmetrics {
+                validator.violations.insert(Violation::EmptyLine);
+            }
+        } else if line == "# EOF" {
+            eof = true;
+        } else if line.starts_with('#') {
+            validator.metadata(line);
+        } else {
+            validator.sample(line, openmetrics);
+        }
+    }
+
+    if openmetrics && !eof {
+        validator.violations.insert(Violation::MissingEof);
+    }
+
+    validator.violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Violation};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_valid_output() {
+        let output = r#"# HELP lustre_job_stats_total Number of operations.
+# TYPE lustre_job_stats_total counter
+lustre_job_stats_total{operation="getattr",jobid="dd \"quoted\" \\ back\nslash"} 3
+lustre_job_stats_total{operation="setattr",jobid="dd"} 1 1720516680
+# HELP lustre_mem_used Bytes used.
+# TYPE lustre_mem_used gauge
+lustre_mem_used 1.5e3
+lustre_mem_nan NaN
+"#;
+
+        assert_eq!(validate(output, false), BTreeSet::new());
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        let violations = validate(
+            r#"lustre_a{jobid="back\slash"} 1
+lustre_b{jobid="unterminated} 1
+lustre_c{jobid="a\\",target="b\""} 1
+lustre_d{jobid="x",jobid="y"} 1
+lustre_e{__name="x",1st="y"} 1
+"#,
+            false,
+        );
+
+        assert_eq!(
+            violations,
+            BTreeSet::from([
+                Violation::InvalidLabelValue {
+                    metric: "lustre_a".into(),
+                    label: "jobid".into()
+                },
+                Violation::InvalidLabelValue {
+                    metric: "lustre_b".into(),
+                    label: "jobid".into()
+                },
+                Violation::DuplicateLabel {
+                    metric: "lustre_d".into(),
+                    label: "jobid".into()
+                },
+                Violation::InvalidLabelName {
+                    metric: "lustre_e".into(),
+                    label: "__name".into()
+                },
+                Violation::InvalidLabelName {
+                    metric: "lustre_e".into(),
+                    label: "1st".into()
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_metadata_and_families() {
+        let violations = validate(
+            r#"# HELP lustre_reads Reads.
+# HELP lustre_reads Reads again.
+# TYPE lustre_reads counter
+lustre_reads 1
+# TYPE lustre_reads counter
+# TYPE lustre_writes_total counter
+lustre_writes_total 1
+lustre_reads{x="y"} 2
+lustre_reads{x="y"} 3
+0lustre 1
+lustre_free{x="y"} lots
+"#,
+            false,
+        );
+
+        insta::assert_snapshot!(violations
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+
+    #[test]
+    fn test_openmetrics() {
+        let output = "# TYPE lustre_job_write_bytes counter\nlustre_job_write_bytes_total{jobid=\"dd\"} 1 # {jobid=\"dd\"} 1 1720516680\n\n";
+
+        assert_eq!(
+            validate(output, true),
+            BTreeSet::from([Violation::EmptyLine, Violation::MissingEof])
+        );
+        assert_eq!(
+            validate(&format!("{}\n# EOF\n", output.trim_end()), true),
+            BTreeSet::new()
+        );
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+//! Collects scrapes in a child process, so an `lctl` that hangs or a parser
+//! running out of memory takes down the worker instead of the HTTP server.
+//!
+//! The server writes a [`WorkerRequest`] per scrape to the stdin of the worker
+//! as a line of JSON, and reads the parsed records back as a line of JSON from
+//! its stdout, along with the self-metrics recorded while collecting them. A
+//! worker that fails or outlasts the scrape is killed along with the commands
+//! it started, and a new one is started for the next scrape.
+
+use crate::{
+    exporter_metrics::{intern, ScrapeMetrics},
+    routes::{collect_sources, AppState, Budget},
+    Error,
+};
+use lustre_collector::Record;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    io,
+    path::PathBuf,
+    process::Stdio,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+};
+
+/// The subcommand running the worker side of a [`ScrapeWorker`].
+pub const SUBCOMMAND: &str = "scrape-worker";
+
+/// How long a worker may run past the scrape budget before it is considered hung.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// The records of each source, `None` if it failed, and the names of the skipped sources.
+pub(crate) type Sources = (Vec<(&'static str, Option<Vec<Record>>)>, Vec<&'static str>);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WorkerRequest {
+    mgs: bool,
+    budget: Duration,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WorkerResponse {
+    sources: Vec<(String, Option<Vec<Record>>)>,
+    skipped: Vec<String>,
+    #[serde(default)]
+    metrics: ScrapeMetrics,
+}
+
+impl WorkerResponse {
+    /// Adds the self-metrics of the worker to those of `state`, and returns the sources.
+    fn into_sources(self, state: &AppState) -> Sources {
+        state.exporter_metrics.record_scrape_metrics(self.metrics);
+
+        (
+            self.sources
+                .into_iter()
+                .map(|(name, records)| (intern(name), records))
+                .collect(),
+            self.skipped.into_iter().map(intern).collect(),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Process {
+    // Killed when dropped
+    child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+impl Drop for Process {
+    /// Kills the process group of the worker, so the commands it started go with it.
+    fn drop(&mut self) {
+        // The worker is not waited for until dropped, so its pid is not reused yet
+        if let Some(pid) = self.child.id().and_then(|x| libc::pid_t::try_from(x).ok()) {
+            unsafe { libc::kill(-pid, libc::SIGKILL) };
+        }
+    }
+}
+
+impl Process {
+    async fn collect(&mut self, request: &WorkerRequest) -> Result<WorkerResponse, Error> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+
+        self.stdin.write_all(&line).await?;
+        self.stdin.flush().await?;
+
+        match self.stdout.next_line().await? {
+            Some(x) => Ok(serde_json::from_str(&x)?),
+            None => {
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "scrape worker exited").into())
+            }
+        }
+    }
+}
+
+/// A child process collecting the scrapes of the server, one at a time.
+#[derive(Debug)]
+pub struct ScrapeWorker {
+    program: PathBuf,
+    args: Vec<OsString>,
+    process: tokio::sync::Mutex<Option<Process>>,
+}
+
+impl ScrapeWorker {
+    /// A worker started as `program` with `args`, once the first scrape needs it.
+    pub fn new(program: impl Into<PathBuf>, args: impl IntoIterator<Item = OsString>) -> Self {
+        Self {
+            program: program.into(),
+            args: args.into_iter().collect(),
+            process: Default::default(),
+        }
+    }
+
+    fn spawn(&self) -> Result<Process, Error> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("scrape worker has no stdin or stdout").into());
+        };
+
+        tracing::debug!(pid = child.id(), "Started scrape worker");
+
+        Ok(Process {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+        })
+    }
+
+    /// Collects a scrape in the worker, waiting for any scrape already running in it.
+    ///
+    /// Returns `None` if the worker failed or did not answer within `budget`, in
+    /// which case it is killed and started again on the next scrape, or if the
+    /// scrape before this one kept it busy past `budget`.
+    pub(crate) async fn collect(
+        &self,
+        state: &AppState,
+        budget: Duration,
+        mgs: bool,
+    ) -> Option<Sources> {
+        let deadline = tokio::time::Instant::now() + budget + GRACE;
+
+        let Ok(mut process) = tokio::time::timeout_at(deadline, self.process.lock()).await else {
+            tracing::warn!(
+                "Scrape worker was still busy after {:?}, skipping this scrape",
+                budget + GRACE
+            );
+
+            return None;
+        };
+
+        let request = WorkerRequest { mgs, budget };
+
+        let result = async {
+            let process = match &mut *process {
+                Some(x) => x,
+                x => x.insert(self.spawn()?),
+            };
+
+            process.collect(&request).await
+        };
+
+        let reason = match tokio::time::timeout_at(deadline, result).await {
+            Ok(Ok(x)) => return Some(x.into_sources(state)),
+            Ok(Err(e)) => {
+                tracing::warn!("Scrape worker failed, restarting it: {e}");
+
+                "failed"
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "Scrape worker did not answer within {:?}, restarting it",
+                    budget + GRACE
+                );
+
+                "timeout"
+            }
+        };
+
+        *process = None;
+
+        state.exporter_metrics.record_scrape_worker_restart(reason);
+
+        None
+    }
+}
+
+/// Collects the scrape asked for by `request`, with the self-metrics recorded meanwhile.
+async fn answer(state: &AppState, request: &WorkerRequest) -> WorkerResponse {
+    let budget = Budget::new(Instant::now(), request.budget);
+
+    let (sources, skipped) = collect_sources(state, &budget, request.mgs).await;
+
+    WorkerResponse {
+        sources: sources
+            .into_iter()
+            .map(|(name, records)| (name.to_string(), records))
+            .collect(),
+        skipped: skipped.into_iter().map(str::to_string).collect(),
+        metrics: state.exporter_metrics.take_scrape_metrics(),
+    }
+}
+
+/// Runs the worker side of a [`ScrapeWorker`], collecting a scrape for each
+/// request read from stdin until it is closed.
+pub async fn run(state: Arc<AppState>) -> Result<(), Error> {
+    let mut requests = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(x) = requests.next_line().await? {
+        let request: WorkerRequest = serde_json::from_str(&x)?;
+
+        let mut line = serde_json::to_vec(&answer(&state, &request).await)?;
+        line.push(b'\n');
+
+        stdout.write_all(&line).await?;
+        stdout.flush().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn sh(script: &str) -> ScrapeWorker {
+        ScrapeWorker::new("sh", ["-c".into(), script.into()])
+    }
+
+    fn fixture() -> Vec<Record> {
+        lustre_collector::parse_lctl_output(include_bytes!(
+            "../../lustre-collector/src/fixtures/valid/valid.txt"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn records_survive_the_pipe() {
+        let response = WorkerResponse {
+            sources: vec![("lctl".to_string(), Some(fixture()))],
+            skipped: vec!["zpool".to_string()],
+            metrics: Default::default(),
+        };
+
+        let response: WorkerResponse =
+            serde_json::from_slice(&serde_json::to_vec(&response).unwrap()).unwrap();
+
+        let (sources, skipped) = response.into_sources(&AppState::default());
+
+        assert_eq!(sources, [("lctl", Some(fixture()))]);
+        assert_eq!(skipped, ["zpool"]);
+    }
+
+    #[tokio::test]
+    async fn answers_scrapes() {
+        let state = AppState::default();
+
+        let worker = sh(
+            r#"while read x; do echo '{"sources":[["lctl",[]],["lnetctl_net",null]],"skipped":["zpool"]}'; done"#,
+        );
+
+        for _ in 0..2 {
+            let (sources, skipped) = worker
+                .collect(&state, Duration::from_secs(5), false)
+                .await
+                .unwrap();
+
+            assert_eq!(sources, [("lctl", Some(vec![])), ("lnetctl_net", None)]);
+            assert_eq!(skipped, ["zpool"]);
+        }
+
+        assert!(!state
+            .exporter_metrics
+            .render()
+            .contains("lustre_exporter_scrape_worker_restarts_total"));
+    }
+
+    #[tokio::test]
+    async fn counts_parse_errors_of_the_worker() {
+        let dir = env::temp_dir().join(format!("lustrefs-exporter-worker-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../lustre-collector/src/fixtures/vanished_params.txt"
+            ),
+            dir.join("lctl.txt"),
+        )
+        .unwrap();
+
+        // What a worker answers when lctl prints params that vanished mid-read
+        let worker_state = AppState {
+            from_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+
+        let request = WorkerRequest {
+            mgs: false,
+            budget: Duration::from_secs(5),
+        };
+
+        let response = dir.join("response.json");
+
+        fs::write(
+            &response,
+            serde_json::to_vec(&answer(&worker_state, &request).await).unwrap(),
+        )
+        .unwrap();
+
+        let state = AppState::default();
+
+        let worker = sh(&format!(
+            "while read x; do cat '{}'; echo; done",
+            response.display()
+        ));
+
+        let (sources, _) = worker
+            .collect(&state, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(sources
+            .iter()
+            .any(|(name, records)| *name == "lctl" && records.is_some()));
+
+        let x = state.exporter_metrics.render();
+
+        assert!(x.contains(
+            r#"lustre_exporter_parse_errors_total{param="obdfilter.ai400-OST0001.stats"} 1"#
+        ));
+        assert!(x.contains("lustre_exporter_skipped_params_total 3\n"));
+        assert!(worker_state.exporter_metrics.render().is_empty());
+    }
+
+    #[tokio::test]
+    async fn kills_the_commands_of_a_hung_worker() {
+        let state = AppState::default();
+
+        let pid = env::temp_dir().join(format!(
+            "lustrefs-exporter-worker-pid-{}",
+            std::process::id()
+        ));
+
+        let worker = sh(&format!(
+            "read x; sleep 600 & echo $! > '{}'; wait",
+            pid.display()
+        ));
+
+        assert!(worker
+            .collect(&state, Duration::from_secs(1), false)
+            .await
+            .is_none());
+
+        let stat = format!("/proc/{}/stat", fs::read_to_string(&pid).unwrap().trim());
+
+        fs::remove_file(&pid).unwrap();
+
+        // Gone, or dead and waiting to be reaped by whoever inherited it
+        for _ in 0..50 {
+            match fs::read_to_string(&stat) {
+                Ok(x) if !x.contains(") Z ") => tokio::time::sleep(Duration::from_millis(20)).await,
+                _ => return,
+            }
+        }
+
+        panic!("sleep outlived the scrape worker");
+    }
+
+    #[tokio::test]
+    async fn restarts_after_failing() {
+        let state = AppState::default();
+
+        let worker = sh("read x; exit 1");
+
+        assert!(worker
+            .collect(&state, Duration::from_secs(5), false)
+            .await
+            .is_none());
+        assert!(worker
+            .collect(&state, Duration::from_secs(5), false)
+            .await
+            .is_none());
+
+        assert!(state
+            .exporter_metrics
+            .render()
+            .contains(r#"lustre_exporter_scrape_worker_restarts_total{reason="failed"} 2"#));
+    }
+
+    #[tokio::test]
+    async fn restarts_after_hanging() {
+        let state = AppState::default();
+
+        let worker = sh("read x; exec sleep 600");
+
+        assert!(worker
+            .collect(&state, Duration::from_secs(1), false)
+            .await
+            .is_none());
+
+        assert!(state
+            .exporter_metrics
+            .render()
+            .contains(r#"lustre_exporter_scrape_worker_restarts_total{reason="timeout"} 1"#));
+    }
+}
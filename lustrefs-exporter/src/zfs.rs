@@ -0,0 +1,74 @@
+// Copyright (c) 2024 DDN. All rights reserved.
+// Use of this source code is governed by a MIT-style
+// license that can be found in the LICENSE file.
+
+use crate::{Metric, StatsMapExt};
+use lustre_collector::ZfsStats;
+use prometheus_exporter_base::prelude::*;
+use std::collections::BTreeMap;
+
+static ARC_HITS: Metric = Metric {
+    name: "lustre_osd_zfs_arc_hits_total",
+    help: "Total number of ZFS ARC lookups that were served from the cache.",
+    r#type: MetricType::Counter,
+};
+
+static ARC_MISSES: Metric = Metric {
+    name: "lustre_osd_zfs_arc_misses_total",
+    help: "Total number of ZFS ARC lookups that had to go to disk.",
+    r#type: MetricType::Counter,
+};
+
+static ARC_SIZE: Metric = Metric {
+    name: "lustre_osd_zfs_arc_size_bytes",
+    help: "Current size of the ZFS ARC in bytes.",
+    r#type: MetricType::Gauge,
+};
+
+static ARC_MAX_SIZE: Metric = Metric {
+    name: "lustre_osd_zfs_arc_max_size_bytes",
+    help: "Maximum size the ZFS ARC may grow to in bytes.",
+    r#type: MetricType::Gauge,
+};
+
+static POOL_FRAGMENTATION: Metric = Metric {
+    name: "lustre_osd_zfs_pool_fragmentation_percent",
+    help: "Free space fragmentation of the ZFS pool in percent.",
+    r#type: MetricType::Gauge,
+};
+
+/// The metric families built here, with the param or command they are read from.
+pub(crate) static METADATA: &[(&Metric, &str)] = &[
+    (&ARC_HITS, "/proc/spl/kstat/zfs/arcstats"),
+    (&ARC_MISSES, "/proc/spl/kstat/zfs/arcstats"),
+    (&ARC_SIZE, "/proc/spl/kstat/zfs/arcstats"),
+    (&ARC_MAX_SIZE, "/proc/spl/kstat/zfs/arcstats"),
+    (&POOL_FRAGMENTATION, "zpool list"),
+];
+
+pub fn build_zfs_stats(
+    x: ZfsStats,
+    stats_map: &mut BTreeMap<&'static str, PrometheusMetric<'static>>,
+) {
+    let (metric, stat) = match x {
+        ZfsStats::ArcHits(x) => (ARC_HITS, x),
+        ZfsStats::ArcMisses(x) => (ARC_MISSES, x),
+        ZfsStats::ArcSize(x) => (ARC_SIZE, x),
+        ZfsStats::ArcMaxSize(x) => (ARC_MAX_SIZE, x),
+        ZfsStats::PoolFragmentation(x) => {
+            stats_map
+                .get_mut_metric(POOL_FRAGMENTATION)
+                .render_and_append_instance(
+                    &PrometheusInstance::new()
+                        .with_label("pool", x.pool.as_str())
+                        .with_value(x.value),
+                );
+
+            return;
+        }
+    };
+
+    stats_map
+        .get_mut_metric(metric)
+        .render_and_append_instance(&PrometheusInstance::new().with_value(stat.value));
+}